@@ -0,0 +1 @@
+//! Benchmarks only, see `messages.rs`.
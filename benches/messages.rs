@@ -0,0 +1,117 @@
+//! Benchmarks of message bodies run against the off-chain environment.
+//!
+//! These measure the contract logic only, not gas. Run with
+//! `cargo bench -p unit_test_bug_benches`.
+
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use ink::primitives::{AccountId, Hash};
+use unit_test_bug::{
+    test_support::{advance_blocks, deploy, set_caller},
+    FlipError, UnitTestBug,
+};
+
+/// Iterations run against one contract before the environment is reset.
+///
+/// The off-chain environment keeps every emitted event, so long runs in one
+/// environment would measure its growing event log as much as the contract.
+const ITERATIONS_PER_ENV: u64 = 1_000;
+
+/// Times `iters` runs of `routine`, deploying a fresh contract with `setup`
+/// every `ITERATIONS_PER_ENV` runs outside the measurement.
+fn time_fresh(
+    iters: u64,
+    setup: impl Fn() -> UnitTestBug,
+    mut routine: impl FnMut(&mut UnitTestBug),
+) -> Duration {
+    let mut total = Duration::ZERO;
+    let mut remaining = iters;
+    while remaining > 0 {
+        let batch = remaining.min(ITERATIONS_PER_ENV);
+        let mut contract = setup();
+        let start = Instant::now();
+        for _ in 0..batch {
+            routine(&mut contract);
+        }
+        total += start.elapsed();
+        remaining -= batch;
+    }
+    total
+}
+
+/// Repeated flips in one block, overwriting the block's checkpoint.
+fn flip(c: &mut Criterion) {
+    c.bench_function("flip", |b| {
+        b.iter_custom(|iters| {
+            time_fresh(iters, || deploy(false), |contract| {
+                contract.flip().expect("flip succeeds");
+            })
+        })
+    });
+}
+
+/// `n` consecutive flips by `n` distinct accounts, filling the leaderboard.
+///
+/// The contract has no batched `flip_n` message, so this runs `n` flips.
+fn flip_n(c: &mut Criterion) {
+    let mut group = c.benchmark_group("flip_n");
+    for n in [1u8, 10, 50] {
+        let flippers: Vec<AccountId> = (0..n).map(|i| AccountId::from([i + 1; 32])).collect();
+        group.bench_with_input(BenchmarkId::from_parameter(n), &flippers, |b, flippers| {
+            b.iter_custom(|iters| {
+                time_fresh(iters, || deploy(false), |contract| {
+                    for flipper in flippers {
+                        set_caller(*flipper);
+                        contract.flip().expect("flip succeeds");
+                    }
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+/// One flip per block, each appending a new checkpoint.
+fn history_write(c: &mut Criterion) {
+    c.bench_function("history_write", |b| {
+        b.iter_custom(|iters| {
+            time_fresh(iters, || deploy(false), |contract| {
+                advance_blocks(1);
+                contract.flip().expect("flip succeeds");
+            })
+        })
+    });
+}
+
+/// Airdrop claims walking a proof of each depth to a mismatching root.
+///
+/// The whole proof is hashed before the claim is rejected, and a rejected
+/// claim changes no state, so every iteration does the same work.
+fn merkle_verify(c: &mut Criterion) {
+    let setup = || {
+        let mut contract = deploy(false);
+        contract
+            .set_airdrop(Hash::from([0xaa; 32]), 1)
+            .expect("owner configures the airdrop");
+        contract
+    };
+    let mut group = c.benchmark_group("merkle_verify");
+    for depth in [4u8, 8, 16] {
+        let proof: Vec<Hash> = (0..depth).map(|i| Hash::from([i; 32])).collect();
+        group.bench_with_input(BenchmarkId::from_parameter(depth), &proof, |b, proof| {
+            b.iter_custom(|iters| {
+                time_fresh(iters, setup, |contract| {
+                    assert_eq!(
+                        contract.claim_airdrop(7, proof.clone()),
+                        Err(FlipError::InvalidProof)
+                    );
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, flip, flip_n, history_write, merkle_verify);
+criterion_main!(benches);
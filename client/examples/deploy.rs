@@ -0,0 +1,60 @@
+//! Deploys the contract, flips it once and prints what happened.
+//!
+//! Build the contract with `cargo contract build` first, then run
+//! `cargo run -p unit_test_bug_client --example deploy -- <config.json>`,
+//! with a config like `examples/deploy.json`.
+
+use std::{error::Error, fs, path::PathBuf};
+
+use serde::Deserialize;
+use subxt_signer::{sr25519::Keypair, SecretUri};
+use unit_test_bug_client::{NetworkProfile, UnitTestBugClient};
+
+#[derive(Deserialize)]
+struct DeployConfig {
+    /// `local`, `rococo-contracts` or the endpoint of another node.
+    network: String,
+    /// Secret URI of the deploying account, e.g. `//Alice` on dev chains.
+    suri: String,
+    /// Argument of the `new` constructor.
+    init_value: bool,
+    /// The contract's `.wasm`, as `cargo contract build` writes it.
+    code: PathBuf,
+    /// Salt telling apart instances of the same code.
+    #[serde(default)]
+    salt: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let path = std::env::args()
+        .nth(1)
+        .ok_or("usage: deploy <config.json>")?;
+    let config: DeployConfig = serde_json::from_slice(&fs::read(path)?)?;
+    let signer = Keypair::from_uri(&config.suri.parse::<SecretUri>()?)?;
+    let network: NetworkProfile = config.network.parse()?;
+    let api = UnitTestBugClient::connect(&network).await?;
+
+    let client = UnitTestBugClient::instantiate(
+        api,
+        &signer,
+        fs::read(&config.code)?,
+        config.init_value,
+        config.salt.into_bytes(),
+        network.storage_deposit_limit(),
+    )
+    .await?;
+    println!("deployed at {:?}", client.address());
+
+    let fee = client.flip_fee(client.address()).await?;
+    println!("flipping for {}", network.format_balance(fee));
+    match client.flip(&signer, fee).await? {
+        Ok(events) => {
+            for event in events {
+                println!("{event:?}");
+            }
+        }
+        Err(error) => println!("flip failed: {error:?}"),
+    }
+    Ok(())
+}
@@ -0,0 +1,857 @@
+//! Typed client for the `unit_test_bug` contract, for off-chain services.
+//!
+//! [`UnitTestBugClient`] encodes messages with their ink! selectors, dry-runs
+//! them through the `ContractsApi` runtime API and submits the ones changing
+//! state as `Contracts` extrinsics, with the gas and deposit the dry run needed.
+
+use std::fmt;
+
+use ink::{env::Environment, primitives::AccountId, LangError};
+use pallet_contracts_primitives::{ContractExecResult, ContractInstantiateResult};
+use scale::{Decode, Encode};
+use shared_types::{events::FLIPPED_V2_INDEX, limits::MAX_HISTORY_PAGE, CustomEnvironment};
+use sp_runtime::DispatchError;
+use subxt::{
+    blocks::ExtrinsicEvents,
+    events::StaticEvent,
+    ext::{scale_decode, scale_encode},
+    utils::MultiAddress,
+    OnlineClient, PolkadotConfig,
+};
+use subxt_signer::sr25519::Keypair;
+
+pub use pallet_contracts_primitives::StorageDeposit;
+pub use shared_types::{network::NetworkProfile, FlippedV2};
+pub use unit_test_bug::{Checkpoint, FlipError, MutationReceipt};
+
+type Balance = <CustomEnvironment as Environment>::Balance;
+type BlockNumber = <CustomEnvironment as Environment>::BlockNumber;
+
+/// Error talking to the node or the contract.
+#[derive(Debug)]
+pub enum Error {
+    /// The node could not be reached, or rejected a request or extrinsic.
+    Subxt(Box<subxt::Error>),
+    /// The runtime failed the call, e.g. because the contract trapped.
+    Dispatch(DispatchError),
+    /// The contract did not dispatch the input, e.g. on an unknown selector.
+    Lang(LangError),
+    /// The contract returned data not matching the message's return type.
+    Decode(scale::Error),
+    /// Instantiation succeeded without reporting the new contract.
+    MissingContract,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Subxt(error) => write!(f, "node error: {error}"),
+            Self::Dispatch(error) => write!(f, "call failed: {error:?}"),
+            Self::Lang(error) => write!(f, "contract rejected the input: {error:?}"),
+            Self::Decode(error) => write!(f, "unexpected contract output: {error}"),
+            Self::MissingContract => f.write_str("no `Contracts::Instantiated` event"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<subxt::Error> for Error {
+    fn from(error: subxt::Error) -> Self {
+        Self::Subxt(Box::new(error))
+    }
+}
+
+impl From<scale::Error> for Error {
+    fn from(error: scale::Error) -> Self {
+        Self::Decode(error)
+    }
+}
+
+/// Gas and storage deposit a call needs, from its dry run.
+///
+/// The dry run runs against the current state, so a call submitted after the
+/// state changed may need more.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Estimate {
+    /// Reference time the call requires, which may exceed what it consumes.
+    pub ref_time: u64,
+    /// Proof size the call requires.
+    pub proof_size: u64,
+    /// What the call charges or refunds for storage.
+    pub storage_deposit: StorageDeposit<Balance>,
+}
+
+impl Estimate {
+    fn new(gas_required: sp_weights::Weight, storage_deposit: StorageDeposit<Balance>) -> Self {
+        Self {
+            ref_time: gas_required.ref_time(),
+            proof_size: gas_required.proof_size(),
+            storage_deposit,
+        }
+    }
+
+    /// Deposit limit covering the call: its charge, or `None` for a refund.
+    pub fn storage_deposit_limit(&self) -> Option<Balance> {
+        match self.storage_deposit {
+            StorageDeposit::Charge(amount) => Some(amount),
+            StorageDeposit::Refund(_) => None,
+        }
+    }
+
+    fn gas_limit(&self) -> Weight {
+        Weight {
+            ref_time: self.ref_time,
+            proof_size: self.proof_size,
+        }
+    }
+}
+
+/// A deployed `unit_test_bug` contract.
+#[derive(Clone)]
+pub struct UnitTestBugClient {
+    api: OnlineClient<PolkadotConfig>,
+    address: AccountId,
+    /// Deposit limit of submitted calls, the dry run's charge if `None`.
+    storage_deposit_limit: Option<Balance>,
+}
+
+impl UnitTestBugClient {
+    /// Client for the contract at `address`.
+    pub fn at(api: OnlineClient<PolkadotConfig>, address: AccountId) -> Self {
+        Self {
+            api,
+            address,
+            storage_deposit_limit: None,
+        }
+    }
+
+    /// Connects to the network of `profile`.
+    pub async fn connect(profile: &NetworkProfile) -> Result<OnlineClient<PolkadotConfig>, Error> {
+        Ok(OnlineClient::from_url(profile.endpoint()).await?)
+    }
+
+    /// Submits calls with `limit` as their deposit limit, e.g. the one of a
+    /// [`NetworkProfile`], instead of what their dry runs charge.
+    pub fn with_storage_deposit_limit(mut self, limit: Option<Balance>) -> Self {
+        self.storage_deposit_limit = limit;
+        self
+    }
+
+    /// Uploads `code` and instantiates it with `new(init_value)`.
+    ///
+    /// The instantiation and later calls are limited to `storage_deposit_limit`
+    /// if set.
+    pub async fn instantiate(
+        api: OnlineClient<PolkadotConfig>,
+        signer: &Keypair,
+        code: Vec<u8>,
+        init_value: bool,
+        salt: Vec<u8>,
+        storage_deposit_limit: Option<Balance>,
+    ) -> Result<Self, Error> {
+        let data = (ink::selector_bytes!("new"), init_value).encode();
+        let request = InstantiateRequest {
+            origin: account_id(signer),
+            value: 0,
+            gas_limit: None,
+            storage_deposit_limit: None,
+            code: Code::Upload(code.clone()),
+            data: data.clone(),
+            salt: salt.clone(),
+        };
+        let dry_run: ContractInstantiateResult<AccountId, Balance, ()> = api
+            .rpc()
+            .state_call("ContractsApi_instantiate", Some(&request.encode()), None)
+            .await?;
+        let estimate = Estimate::new(dry_run.gas_required, dry_run.storage_deposit);
+        let output = dry_run.result.map_err(Error::Dispatch)?;
+        decode_output::<()>(&output.result.data)?;
+
+        let payload = subxt::tx::Payload::new(
+            "Contracts",
+            "instantiate_with_code",
+            InstantiateWithCode {
+                value: 0,
+                gas_limit: estimate.gas_limit(),
+                storage_deposit_limit: storage_deposit_limit
+                    .or_else(|| estimate.storage_deposit_limit()),
+                code,
+                data,
+                salt,
+            },
+        )
+        .unvalidated();
+        let events = submit(&api, &payload, signer).await?;
+        let instantiated = events
+            .find_first::<Instantiated>()?
+            .ok_or(Error::MissingContract)?;
+        Ok(Self::at(api, instantiated.contract).with_storage_deposit_limit(storage_deposit_limit))
+    }
+
+    /// Account of the contract.
+    pub fn address(&self) -> AccountId {
+        self.address
+    }
+
+    /// The current value, see the `get` message.
+    pub async fn get(&self, origin: AccountId) -> Result<bool, Error> {
+        self.query(origin, ink::selector_bytes!("get").encode())
+            .await
+    }
+
+    /// The value at the end of `block`, see the `value_at` message.
+    pub async fn value_at(
+        &self,
+        origin: AccountId,
+        block: BlockNumber,
+    ) -> Result<Option<bool>, Error> {
+        self.query(origin, (ink::selector_bytes!("value_at"), block).encode())
+            .await
+    }
+
+    /// Number of recorded checkpoints, see the `checkpoint_count` message.
+    pub async fn checkpoint_count(&self, origin: AccountId) -> Result<u32, Error> {
+        self.query(origin, ink::selector_bytes!("checkpoint_count").encode())
+            .await
+    }
+
+    /// Every kept checkpoint, oldest first, read through `history_page` in
+    /// pages of `MAX_HISTORY_PAGE`.
+    ///
+    /// Each page is read from the state of its own dry run, so checkpoints
+    /// pruned or written in between may be missed or included.
+    pub async fn history(
+        &self,
+        origin: AccountId,
+    ) -> Result<Result<Vec<Checkpoint>, FlipError>, Error> {
+        let mut start: u32 = self
+            .query(origin, ink::selector_bytes!("first_checkpoint").encode())
+            .await?;
+        let mut history = Vec::new();
+        loop {
+            let input = (
+                ink::selector_bytes!("history_page"),
+                start,
+                MAX_HISTORY_PAGE,
+            )
+                .encode();
+            let page: Vec<Checkpoint> = match self.query(origin, input).await? {
+                Ok(page) => page,
+                Err(error) => return Ok(Err(error)),
+            };
+            let last = page.len() < MAX_HISTORY_PAGE as usize;
+            start += page.len() as u32;
+            history.extend(page);
+            if last {
+                return Ok(Ok(history));
+            }
+        }
+    }
+
+    /// The contract owner, see the `owner` message.
+    pub async fn owner(&self, origin: AccountId) -> Result<AccountId, Error> {
+        self.query(origin, ink::selector_bytes!("owner").encode())
+            .await
+    }
+
+    /// The fee a flip costs, see the `flip_fee` message.
+    pub async fn flip_fee(&self, origin: AccountId) -> Result<Balance, Error> {
+        self.query(origin, ink::selector_bytes!("flip_fee").encode())
+            .await
+    }
+
+    /// Flips the value, paying `value`, and returns the emitted `FlippedV2`.
+    ///
+    /// Nothing is submitted if the dry run returns a [`FlipError`].
+    pub async fn flip(
+        &self,
+        signer: &Keypair,
+        value: Balance,
+    ) -> Result<Result<Vec<FlippedV2>, FlipError>, Error> {
+        let input = ink::selector_bytes!("flip").encode();
+        Ok(self
+            .transact::<MutationReceipt>(signer, value, input, None)
+            .await?
+            .map(|(_, events)| self.flipped(&events)))
+    }
+
+    /// What flipping as `origin`, paying `value`, needs.
+    pub async fn estimate_flip(
+        &self,
+        origin: AccountId,
+        value: Balance,
+    ) -> Result<Result<Estimate, FlipError>, Error> {
+        let input = ink::selector_bytes!("flip").encode();
+        Ok(self
+            .estimate::<MutationReceipt>(origin, value, input)
+            .await?
+            .map(|(_, estimate)| estimate))
+    }
+
+    /// The receipt flipping as `origin`, paying `value`, would return.
+    pub async fn preview_flip(
+        &self,
+        origin: AccountId,
+        value: Balance,
+    ) -> Result<Result<MutationReceipt, FlipError>, Error> {
+        let input = ink::selector_bytes!("flip").encode();
+        Ok(self
+            .estimate::<MutationReceipt>(origin, value, input)
+            .await?
+            .map(|(receipt, _)| receipt))
+    }
+
+    /// Like [`Self::flip`], limited to `limits` instead of the dry run's estimate.
+    pub async fn flip_within(
+        &self,
+        signer: &Keypair,
+        value: Balance,
+        limits: &Estimate,
+    ) -> Result<Result<Vec<FlippedV2>, FlipError>, Error> {
+        let input = ink::selector_bytes!("flip").encode();
+        Ok(self
+            .transact::<MutationReceipt>(signer, value, input, Some(limits))
+            .await?
+            .map(|(_, events)| self.flipped(&events)))
+    }
+
+    /// Flips the value on behalf of `owner`, see the `flip_for` message.
+    pub async fn flip_for(
+        &self,
+        signer: &Keypair,
+        value: Balance,
+        owner: AccountId,
+    ) -> Result<Result<Vec<FlippedV2>, FlipError>, Error> {
+        let input = (ink::selector_bytes!("flip_for"), owner).encode();
+        Ok(self
+            .transact::<MutationReceipt>(signer, value, input, None)
+            .await?
+            .map(|(_, events)| self.flipped(&events)))
+    }
+
+    async fn dry_run(
+        &self,
+        origin: AccountId,
+        value: Balance,
+        input_data: Vec<u8>,
+    ) -> Result<ContractExecResult<Balance, ()>, Error> {
+        let request = CallRequest {
+            origin,
+            dest: self.address,
+            value,
+            gas_limit: None,
+            storage_deposit_limit: None,
+            input_data,
+        };
+        Ok(self
+            .api
+            .rpc()
+            .state_call("ContractsApi_call", Some(&request.encode()), None)
+            .await?)
+    }
+
+    /// Dry-runs a read-only message and decodes its return value.
+    async fn query<T: Decode>(&self, origin: AccountId, input: Vec<u8>) -> Result<T, Error> {
+        let dry_run = self.dry_run(origin, 0, input).await?;
+        let output = dry_run.result.map_err(Error::Dispatch)?;
+        decode_output(&output.data)
+    }
+
+    /// Dry-runs a message returning `Result<T, FlipError>` and returns what
+    /// it would return with what it needs.
+    async fn estimate<T: Decode>(
+        &self,
+        origin: AccountId,
+        value: Balance,
+        input: Vec<u8>,
+    ) -> Result<Result<(T, Estimate), FlipError>, Error> {
+        let dry_run = self.dry_run(origin, value, input).await?;
+        let estimate = Estimate::new(dry_run.gas_required, dry_run.storage_deposit);
+        let output = dry_run.result.map_err(Error::Dispatch)?;
+        Ok(decode_output::<Result<T, FlipError>>(&output.data)?
+            .map(|returned| (returned, estimate)))
+    }
+
+    /// Dry-runs a message returning `Result<T, FlipError>` and submits it if
+    /// the contract returns `Ok`, within `limits` or the dry run's estimate.
+    async fn transact<T: Decode>(
+        &self,
+        signer: &Keypair,
+        value: Balance,
+        input: Vec<u8>,
+        limits: Option<&Estimate>,
+    ) -> Result<Result<(T, ExtrinsicEvents<PolkadotConfig>), FlipError>, Error> {
+        let (returned, estimate) = match self
+            .estimate(account_id(signer), value, input.clone())
+            .await?
+        {
+            Ok(estimated) => estimated,
+            Err(error) => return Ok(Err(error)),
+        };
+        let limits = limits.unwrap_or(&estimate);
+
+        let payload = subxt::tx::Payload::new(
+            "Contracts",
+            "call",
+            Call {
+                dest: MultiAddress::Id(self.address),
+                value,
+                gas_limit: limits.gas_limit(),
+                storage_deposit_limit: self
+                    .storage_deposit_limit
+                    .or_else(|| limits.storage_deposit_limit()),
+                data: input,
+            },
+        )
+        .unvalidated();
+        let events = submit(&self.api, &payload, signer).await?;
+        Ok(Ok((returned, events)))
+    }
+
+    /// The `FlippedV2` events this contract emitted in `events`.
+    fn flipped(&self, events: &ExtrinsicEvents<PolkadotConfig>) -> Vec<FlippedV2> {
+        events
+            .find::<ContractEmitted>()
+            .filter_map(Result::ok)
+            .filter(|emitted| emitted.contract == self.address)
+            .filter_map(|emitted| match emitted.data.split_first() {
+                Some((&FLIPPED_V2_INDEX, mut data)) => FlippedV2::decode(&mut data).ok(),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+fn account_id(signer: &Keypair) -> AccountId {
+    AccountId::from(signer.public_key().0)
+}
+
+/// Decodes the output of a message or constructor, which ink! wraps in a
+/// `Result` reporting whether the input was dispatched.
+fn decode_output<T: Decode>(mut data: &[u8]) -> Result<T, Error> {
+    Result::<T, LangError>::decode(&mut data)?.map_err(Error::Lang)
+}
+
+async fn submit(
+    api: &OnlineClient<PolkadotConfig>,
+    payload: &impl subxt::tx::TxPayload,
+    signer: &Keypair,
+) -> Result<ExtrinsicEvents<PolkadotConfig>, Error> {
+    Ok(api
+        .tx()
+        .sign_and_submit_then_watch_default(payload, signer)
+        .await?
+        .wait_for_finalized_success()
+        .await?)
+}
+
+/// `sp_weights::Weight` as the `Contracts` calls take it.
+#[derive(Debug, Clone, Copy, Encode, scale_encode::EncodeAsType)]
+#[encode_as_type(crate_path = "subxt::ext::scale_encode")]
+struct Weight {
+    #[codec(compact)]
+    ref_time: u64,
+    #[codec(compact)]
+    proof_size: u64,
+}
+
+/// Arguments of `ContractsApi_call`.
+#[derive(Encode)]
+struct CallRequest {
+    origin: AccountId,
+    dest: AccountId,
+    value: Balance,
+    gas_limit: Option<Weight>,
+    storage_deposit_limit: Option<Balance>,
+    input_data: Vec<u8>,
+}
+
+/// Arguments of `ContractsApi_instantiate`.
+#[derive(Encode)]
+struct InstantiateRequest {
+    origin: AccountId,
+    value: Balance,
+    gas_limit: Option<Weight>,
+    storage_deposit_limit: Option<Balance>,
+    code: Code,
+    data: Vec<u8>,
+    salt: Vec<u8>,
+}
+
+#[derive(Encode)]
+enum Code {
+    Upload(Vec<u8>),
+}
+
+/// The `Contracts::call` extrinsic.
+#[derive(Encode, scale_encode::EncodeAsType)]
+#[encode_as_type(crate_path = "subxt::ext::scale_encode")]
+struct Call {
+    dest: MultiAddress<AccountId, ()>,
+    #[codec(compact)]
+    value: Balance,
+    gas_limit: Weight,
+    storage_deposit_limit: Option<Balance>,
+    data: Vec<u8>,
+}
+
+/// The `Contracts::instantiate_with_code` extrinsic.
+#[derive(Encode, scale_encode::EncodeAsType)]
+#[encode_as_type(crate_path = "subxt::ext::scale_encode")]
+struct InstantiateWithCode {
+    #[codec(compact)]
+    value: Balance,
+    gas_limit: Weight,
+    storage_deposit_limit: Option<Balance>,
+    code: Vec<u8>,
+    data: Vec<u8>,
+    salt: Vec<u8>,
+}
+
+#[derive(Decode, scale_decode::DecodeAsType)]
+#[decode_as_type(crate_path = "subxt::ext::scale_decode")]
+struct Instantiated {
+    #[allow(dead_code)]
+    deployer: AccountId,
+    contract: AccountId,
+}
+
+impl StaticEvent for Instantiated {
+    const PALLET: &'static str = "Contracts";
+    const EVENT: &'static str = "Instantiated";
+}
+
+#[derive(Decode, scale_decode::DecodeAsType)]
+#[decode_as_type(crate_path = "subxt::ext::scale_decode")]
+struct ContractEmitted {
+    contract: AccountId,
+    data: Vec<u8>,
+}
+
+impl StaticEvent for ContractEmitted {
+    const PALLET: &'static str = "Contracts";
+    const EVENT: &'static str = "ContractEmitted";
+}
+
+#[cfg(all(test, feature = "e2e-tests"))]
+mod e2e_tests {
+    use contract_build::{BuildArtifacts, BuildMode, ExecuteArgs, ManifestPath, Verbosity};
+    use ink_e2e::TestNodeProcess;
+    use subxt_signer::sr25519::dev;
+
+    use super::*;
+
+    /// Builds the contract's code as `cargo contract build` does.
+    pub(super) fn contract_code() -> Vec<u8> {
+        let manifest_path =
+            ManifestPath::new(concat!(env!("CARGO_MANIFEST_DIR"), "/../Cargo.toml"))
+                .expect("manifest path is valid");
+        let args = ExecuteArgs {
+            manifest_path,
+            verbosity: Verbosity::Quiet,
+            build_mode: BuildMode::Debug,
+            build_artifact: BuildArtifacts::CodeOnly,
+            ..ExecuteArgs::default()
+        };
+        let result = contract_build::execute(args).expect("contract builds");
+        std::fs::read(result.dest_wasm.expect("code is generated")).expect("code is readable")
+    }
+
+    async fn node() -> TestNodeProcess<PolkadotConfig> {
+        let path = option_env!("CONTRACTS_NODE").unwrap_or("substrate-contracts-node");
+        TestNodeProcess::build(path)
+            .spawn()
+            .await
+            .expect("contracts node starts")
+    }
+
+    #[tokio::test]
+    async fn flip_round_trip() {
+        let node = node().await;
+        let alice = dev::alice();
+        let client = UnitTestBugClient::instantiate(
+            node.client(),
+            &alice,
+            contract_code(),
+            false,
+            Vec::new(),
+            None,
+        )
+        .await
+        .expect("contract instantiates");
+        let origin = account_id(&alice);
+        assert!(!client.get(origin).await.expect("get succeeds"));
+        assert_eq!(client.owner(origin).await.expect("owner succeeds"), origin);
+
+        let flipped = client
+            .flip(&alice, 0)
+            .await
+            .expect("flip is submitted")
+            .expect("flip succeeds");
+        assert_eq!(flipped.len(), 1);
+        assert_eq!(flipped[0].account, origin);
+        assert!(flipped[0].value);
+        assert!(client.get(origin).await.expect("get succeeds"));
+        assert_eq!(
+            client
+                .checkpoint_count(origin)
+                .await
+                .expect("count succeeds"),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn flip_estimate_is_sufficient() {
+        let node = node().await;
+        let alice = dev::alice();
+        let client = UnitTestBugClient::instantiate(
+            node.client(),
+            &alice,
+            contract_code(),
+            false,
+            Vec::new(),
+            None,
+        )
+        .await
+        .expect("contract instantiates");
+
+        let bob = dev::bob();
+        let estimate = client
+            .estimate_flip(account_id(&bob), 0)
+            .await
+            .expect("dry run succeeds")
+            .expect("flip succeeds");
+        // The first flip of a block stores a checkpoint.
+        assert!(matches!(estimate.storage_deposit, StorageDeposit::Charge(charge) if charge > 0));
+
+        let flipped = client
+            .flip_within(&bob, 0, &estimate)
+            .await
+            .expect("flip within the estimate is submitted")
+            .expect("flip succeeds");
+        assert_eq!(flipped.len(), 1);
+        assert!(client.get(account_id(&bob)).await.expect("get succeeds"));
+    }
+
+    #[tokio::test]
+    async fn contract_errors_are_not_submitted() {
+        let node = node().await;
+        let alice = dev::alice();
+        let client = UnitTestBugClient::instantiate(
+            node.client(),
+            &alice,
+            contract_code(),
+            false,
+            Vec::new(),
+            None,
+        )
+        .await
+        .expect("contract instantiates");
+
+        let bob = dev::bob();
+        let result = client
+            .flip_for(&bob, 0, account_id(&alice))
+            .await
+            .expect("dry run succeeds");
+        assert_eq!(result, Err(FlipError::NotApproved));
+        assert!(!client.get(account_id(&alice)).await.expect("get succeeds"));
+    }
+}
+
+/// Drives the contract with subxt's dynamic API and raw runtime calls only,
+/// as generic Substrate tooling would, without this client or `ink_e2e`.
+///
+/// Needs a running node of the network named by `CONTRACTS_NETWORK`, see
+/// [`NetworkProfile::from_env`]. Other tests and earlier runs may share the
+/// node, so each test signs with its own account and instantiates with its
+/// own salt.
+#[cfg(all(test, feature = "e2e-tests"))]
+mod subxt_tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use sp_runtime::traits::{BlakeTwo256, Hash as _};
+    use subxt::{
+        blocks::ExtrinsicEvents, dynamic::Value, tx::TxPayload, OnlineClient, PolkadotConfig,
+    };
+    use subxt_signer::{
+        sr25519::{dev, Keypair},
+        SecretUri,
+    };
+
+    use super::*;
+
+    /// Gas limit of every call, well above what any of them needs, as the
+    /// test does not dry-run the extrinsics.
+    const REF_TIME_LIMIT: u128 = 100_000_000_000;
+    /// Proof size limit of every call, see [`REF_TIME_LIMIT`].
+    const PROOF_SIZE_LIMIT: u128 = 1_000_000;
+
+    fn gas_limit() -> Value {
+        Value::named_composite([
+            ("ref_time", Value::u128(REF_TIME_LIMIT)),
+            ("proof_size", Value::u128(PROOF_SIZE_LIMIT)),
+        ])
+    }
+
+    /// The network's deposit limit as the `Contracts` calls take it.
+    fn deposit_limit(network: &NetworkProfile) -> Value {
+        match network.storage_deposit_limit() {
+            Some(limit) => Value::unnamed_variant("Some", [Value::u128(limit)]),
+            None => Value::unnamed_variant("None", []),
+        }
+    }
+
+    async fn submit(
+        api: &OnlineClient<PolkadotConfig>,
+        payload: &impl TxPayload,
+        signer: &Keypair,
+    ) -> ExtrinsicEvents<PolkadotConfig> {
+        api.tx()
+            .sign_and_submit_then_watch_default(payload, signer)
+            .await
+            .expect("extrinsic is submitted")
+            .wait_for_finalized_success()
+            .await
+            .expect("extrinsic succeeds")
+    }
+
+    /// Tokens each test's signer is funded with.
+    const SIGNER_FUNDS: u128 = 1_000;
+
+    /// Derives an account from alice for the test `name` in this run and funds it.
+    async fn test_signer(
+        api: &OnlineClient<PolkadotConfig>,
+        network: &NetworkProfile,
+        name: &str,
+    ) -> Keypair {
+        let uri: SecretUri = format!("//Alice//{name}//{}", unique())
+            .parse()
+            .expect("derivation path is valid");
+        let signer = Keypair::from_uri(&uri).expect("account derives");
+        let transfer = subxt::dynamic::tx(
+            "Balances",
+            "transfer_keep_alive",
+            vec![
+                Value::unnamed_variant("Id", [Value::from_bytes(signer.public_key().0)]),
+                Value::u128(network.units(SIGNER_FUNDS)),
+            ],
+        );
+        submit(api, &transfer, &dev::alice()).await;
+        signer
+    }
+
+    /// A value no other test or run uses.
+    fn unique() -> String {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("clock is after the epoch")
+            .as_nanos();
+        format!("{}-{nanos}", std::process::id())
+    }
+
+    /// Field bytes of every `Contracts` event named `variant` in `events`.
+    fn contracts_events(events: &ExtrinsicEvents<PolkadotConfig>, variant: &str) -> Vec<Vec<u8>> {
+        events
+            .iter()
+            .map(|event| event.expect("event decodes"))
+            .filter(|event| event.pallet_name() == "Contracts" && event.variant_name() == variant)
+            .map(|event| event.field_bytes().to_vec())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn upload_instantiate_call_and_decode_events() {
+        let network = NetworkProfile::from_env().expect("CONTRACTS_NETWORK names a network");
+        let api = UnitTestBugClient::connect(&network)
+            .await
+            .expect("node is reachable");
+        let signer = test_signer(&api, &network, "upload_instantiate_call").await;
+        let origin: [u8; 32] = signer.public_key().0;
+
+        let code = super::e2e_tests::contract_code();
+        let code_hash: [u8; 32] = BlakeTwo256::hash(&code).into();
+        let upload = subxt::dynamic::tx(
+            "Contracts",
+            "upload_code",
+            vec![
+                Value::from_bytes(code),
+                deposit_limit(&network),
+                Value::unnamed_variant("Enforced", []),
+            ],
+        );
+        let events = submit(&api, &upload, &signer).await;
+        // Code uploaded before, e.g. by an earlier run, is not stored again.
+        for stored in contracts_events(&events, "CodeStored") {
+            assert_eq!(<[u8; 32]>::decode(&mut &stored[..]), Ok(code_hash));
+        }
+
+        let instantiate = subxt::dynamic::tx(
+            "Contracts",
+            "instantiate",
+            vec![
+                Value::u128(0),
+                gas_limit(),
+                deposit_limit(&network),
+                Value::from_bytes(code_hash),
+                Value::from_bytes((ink::selector_bytes!("new"), false).encode()),
+                Value::from_bytes(unique()),
+            ],
+        );
+        let events = submit(&api, &instantiate, &signer).await;
+        let instantiated = contracts_events(&events, "Instantiated");
+        let (deployer, contract) =
+            <([u8; 32], [u8; 32])>::decode(&mut &instantiated[0][..]).expect("accounts decode");
+        assert_eq!(deployer, origin);
+
+        let flip = subxt::dynamic::tx(
+            "Contracts",
+            "call",
+            vec![
+                Value::unnamed_variant("Id", [Value::from_bytes(contract)]),
+                Value::u128(0),
+                gas_limit(),
+                deposit_limit(&network),
+                Value::from_bytes(ink::selector_bytes!("flip")),
+            ],
+        );
+        let events = submit(&api, &flip, &signer).await;
+        let flipped: Vec<FlippedV2> = contracts_events(&events, "ContractEmitted")
+            .into_iter()
+            .filter_map(|fields| {
+                let (emitter, data) =
+                    <([u8; 32], Vec<u8>)>::decode(&mut &fields[..]).expect("event decodes");
+                match data.split_first() {
+                    Some((&FLIPPED_V2_INDEX, mut data)) if emitter == contract => {
+                        Some(FlippedV2::decode(&mut data).expect("FlippedV2 decodes"))
+                    }
+                    _ => None,
+                }
+            })
+            .collect();
+        assert_eq!(flipped.len(), 1);
+        assert_eq!(flipped[0].account, AccountId::from(origin));
+        assert!(flipped[0].value);
+
+        // `ContractsApi_call(origin, dest, value, gas_limit, storage_deposit_limit, input_data)`
+        let get = (
+            origin,
+            contract,
+            0u128,
+            None::<()>,
+            None::<u128>,
+            ink::selector_bytes!("get").to_vec(),
+        );
+        let dry_run: ContractExecResult<u128, ()> = api
+            .rpc()
+            .state_call("ContractsApi_call", Some(&get.encode()), None)
+            .await
+            .expect("dry run succeeds");
+        let output = dry_run.result.expect("get succeeds");
+        assert_eq!(
+            Result::<bool, LangError>::decode(&mut &output.data[..]),
+            Ok(Ok(true))
+        );
+    }
+}
@@ -1,156 +1,15189 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
-#[ink::contract]
+pub use shared_types::{
+    AssetsError, ChainRandomness, ContractsError, CustomEnvironment, EpochStats, FetchRandom,
+    FlashBorrower, IFlipQuery, PalletAssets, PalletContracts, PriceOracle, PriceQuote,
+    RandomReadErr, Randomness, UpstreamError,
+};
+
+pub use self::unit_test_bug::{
+    airdrop_leaf, AdminOp, ApiVersion, AssetFee, AuditEntry, BatchMode, Bid, BlsValidatorSet,
+    Checkpoint, Config, Escrow, EscrowState, ExportChunk, ExportCursor, ExportItem, ExportSection,
+    FeeDiscounts, FeeTier, FlipError, FlipPolicy, FlipResult, ForwardedCall, Id, InvariantReport,
+    LeaderboardEntry, MutationReceipt, PendingConfig, PolicyMode, PolicyParams, PrivilegedOp,
+    Proposal, Psp22Gate, QueuedFlip, Schedule, SessionKey, Settings, SettingsPatch, SigningDomain,
+    Subscription, UnitTestBug, UnitTestBugRef, UsdFee, Violation, ALL_FEATURES, API_FEATURE_BLS,
+    API_FEATURE_DEBUG, API_FEATURE_ZK, API_VERSION, AUDIT_LOG_SIZE, BLOB_CHUNK_SIZE,
+    BPS_DENOMINATOR, DEFAULT_EPOCH_LENGTH, FEATURE_FLASH_FLIP, FEATURE_LOTTERY, KEEPER_REWARD,
+    LEADERBOARD_SIZE, LOTTERY_PRIZE_PERCENT, MAX_BATCH_SIZE_LIMIT, MAX_BLOB_SIZE_LIMIT,
+    MAX_BLS_VALIDATORS, MAX_COOLDOWN_BLOCKS, MAX_FEE_TIERS, MAX_HISTORY_PAGE, MAX_LABEL_LEN,
+    MAX_POLICY_EXEMPTIONS, MAX_QUEUED_FLIPS, MAX_RESPONSE_SIZE, MAX_TIMELOCK_DELAY,
+    PARTICIPANT_RETENTION_EPOCHS, SESSION_FLIP, SIGNING_DOMAIN_VERSION, STORAGE_DEPOSIT_PER_BYTE,
+    STORAGE_DEPOSIT_PER_ITEM,
+};
+
+/// Writes a line to the contracts pallet debug buffer in `debug` builds.
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "debug")]
+        ink::env::debug_println!($($arg)*);
+    };
+}
+
+/// Writes the context of a message call to the debug buffer in `debug`
+/// builds, see `UnitTestBug::trace_call`. Other builds get no code at all.
+macro_rules! trace_call {
+    ($contract:expr, $message:literal, $args:expr) => {
+        #[cfg(feature = "debug")]
+        $contract.trace_call($message, &$args);
+    };
+}
+
+#[ink::contract(env = crate::CustomEnvironment)]
 mod unit_test_bug {
+    use ink::{
+        codegen::TraitCallBuilder,
+        env::{
+            call::{build_call, ExecutionInput, Selector},
+            hash::{Blake2x256, Keccak256, Sha2x256},
+        },
+        prelude::{string::String, vec::Vec},
+        storage::{traits::ManualKey, Lazy, Mapping},
+    };
+
+    use crate::{
+        ChainRandomness, EpochStats, FlashBorrower, IFlipQuery, PriceOracle, PriceQuote,
+        RandomReadErr, Randomness, UpstreamError,
+    };
+
+    /// Storage cell of the flipped value.
+    ///
+    /// The core fields live on fixed keys so that adding or reordering fields
+    /// in a later version can never move them. These keys must not change.
+    type ValueCell = Lazy<bool, ManualKey<0x0000_0100>>;
+    /// Storage cell of the checkpoint history.
+    type CheckpointsCell = Mapping<u32, Checkpoint, ManualKey<0x0000_0101>>;
+    /// Storage cell of the checkpoint history length.
+    type CheckpointCountCell = Lazy<u32, ManualKey<0x0000_0102>>;
+    /// Blob chunks keyed by blob and chunk index.
+    type BlobChunks = Mapping<(u32, u32), Vec<u8>>;
+    /// Receipts keyed by caller and idempotency key.
+    type IdempotentReceipts = Mapping<(AccountId, [u8; 16]), MutationReceipt>;
 
+    /// Why a message failed.
+    ///
+    /// Each variant has a stable status code, its index plus one, for
+    /// clients that do not decode SCALE enums. See `shared_types::errors`.
     #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
     #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    #[allow(clippy::enum_variant_names)]
     pub enum FlipError {
        // A flip error to cause revert
        FlipError,
+        /// The caller is not the contract owner.
+        NotOwner,
+        /// The transferred value does not cover the flip fee.
+        InsufficientFee,
+        /// Subscriptions are not on sale because no price is configured.
+        SubscriptionsDisabled,
+        /// The transferred value does not buy a single block of subscription.
+        InsufficientPayment,
+        /// The bid does not exceed the current highest bid.
+        BidTooLow,
+        /// Sending funds out of the contract failed.
+        TransferFailed,
+        /// Another account won exclusive flip rights for the current epoch.
+        NotRightsHolder,
+        /// No escrow exists under the given id.
+        EscrowNotFound,
+        /// The escrow is not in a state that allows this operation.
+        InvalidEscrowState,
+        /// The caller has no role in the escrow that permits this operation.
+        NotEscrowParty,
+        /// An escrow must hold a non-zero deposit.
+        EmptyEscrow,
+        /// No vested fees are available to claim.
+        NothingToClaim,
+        /// The airdrop index was already claimed.
+        AlreadyClaimed,
+        /// The Merkle proof does not lead to the airdrop root.
+        InvalidProof,
+        /// No airdrop is configured or its pool cannot cover another claim.
+        AirdropExhausted,
+        /// The lottery epoch has not ended yet.
+        LotteryNotFinished,
+        /// The lottery of this epoch was already drawn.
+        AlreadyDrawn,
+        /// Nobody flipped during the lottery epoch.
+        NoTickets,
+        /// The randomness chain extension failed.
+        RandomnessUnavailable,
+        /// Only the trusted forwarder may relay calls.
+        NotTrustedForwarder,
+        /// The permit signature does not recover to the claimed signer.
+        InvalidSignature,
+        /// The caller is not an authorised session key of the account.
+        SessionKeyNotFound,
+        /// The session key is past its expiry block.
+        SessionExpired,
+        /// The session key's permissions do not cover the call.
+        OutOfScope,
+        /// The proposed configuration fails validation.
+        InvalidConfig,
+        /// No configuration change is queued.
+        NoPendingConfig,
+        /// The queued configuration change is still timelocked.
+        TimelockActive,
+        /// Flipping is paused.
+        Paused,
+        /// The caller flipped too recently.
+        CooldownActive,
+        /// The caller's wrapped balance is too low.
+        InsufficientWrapped,
+        /// The caller holds less than the gate's minimum of the gating token.
+        BelowTokenMinimum,
+        /// The gating token could not be queried, and why.
+        TokenQueryFailed(Option<UpstreamError>),
+        /// No PSP34 collection grants flip permits.
+        NoNftCollection,
+        /// The caller does not own the presented NFT.
+        NotNftHolder,
+        /// The caller is not approved to flip for the account.
+        NotApproved,
+        /// No treasury account is configured.
+        NoTreasury,
+        /// The runtime rejected a dispatched call.
+        RuntimeCallFailed,
+        /// No asset is configured for paying flip fees.
+        NoAssetFee,
+        /// The `pallet-assets` chain extension rejected a transfer.
+        AssetTransferFailed,
+        /// The price oracle could not be queried, and why, or has no price.
+        OracleUnavailable(Option<UpstreamError>),
+        /// The oracle's price is older than the fee allows.
+        StalePrice,
+        /// No Groth16 verifying key is configured.
+        NoVerifyingKey,
+        /// The Groth16 verifying key does not deserialize.
+        InvalidVerifyingKey,
+        /// The contract was built without the `zk` feature.
+        ZkUnsupported,
+        /// No BLS validator set is configured.
+        NoValidatorSet,
+        /// A validator key does not deserialize or the threshold is out of range.
+        InvalidValidatorSet,
+        /// Signer indices are out of range or not strictly ascending.
+        InvalidSigner,
+        /// Fewer validators signed than the threshold requires.
+        BelowThreshold,
+        /// The contract was built without the `bls` feature.
+        BlsUnsupported,
+        /// The blob is larger than `config.max_blob_size`.
+        BlobTooLarge,
+        /// The label is longer than `MAX_LABEL_LEN` bytes.
+        LabelTooLong,
+        /// The flip policy has an empty schedule or too many exemptions.
+        InvalidPolicy,
+        /// The flip policy does not admit the caller now.
+        PolicyDenied,
+        /// The caller used up `config.max_calls_per_block` in this block.
+        RateLimited,
+        /// Returned if the caller is not the guardian.
+        NotGuardian,
+        /// More operations than `config.max_batch_size` were batched.
+        BatchTooLarge,
+        /// The PSP22 token refused or failed the transfer, and why.
+        TokenTransferFailed(Option<UpstreamError>),
+        /// A flash flip cannot lend to the contract itself.
+        InvalidBorrower,
+        /// The flash flip callback trapped or is not implemented, and why.
+        FlashCallFailed(Option<UpstreamError>),
+        /// The borrower rejected the flash flip.
+        FlashRejected,
+        /// The invariants did not hold after a flash flip.
+        InvariantViolated,
+        /// An account cannot refer itself.
+        SelfReferral,
+        /// The caller's referrer was settled by an earlier flip.
+        AlreadyReferred,
+        /// Fee discount tiers are unordered, too many, or discount over 100%.
+        InvalidFeeDiscounts,
+        /// No proposal has the given id.
+        ProposalNotFound,
+        /// The proposal is not accepting votes in this block.
+        VotingNotOpen,
+        /// The caller has already voted on the proposal.
+        AlreadyVoted,
+        /// The caller held no wrapped balance at the relevant block.
+        NoStake,
+        /// The proposal's voting period has not ended yet.
+        VotingNotOver,
+        /// The proposal did not win more aye than nay weight.
+        ProposalRejected,
+        /// The proposal has already been executed.
+        ProposalExecuted,
+        /// The response would not fit in `MAX_RESPONSE_SIZE` bytes.
+        ResponseTooLarge,
+        /// The `flip_until` deadline is not after the current block's timestamp.
+        DeadlineInPast,
+        /// `initialize` was called on a contract already set up.
+        AlreadyInitialized,
+        /// The callee's code hash is not on the allowlist.
+        UntrustedCallee,
+        /// The flip queue already holds `MAX_QUEUED_FLIPS` flips.
+        QueueFull,
+        /// The message's feature is disabled in `Config::features`.
+        FeatureDisabled,
     }
 
-    /// Defines the storage of your contract.
-    /// Add new fields to the below struct in order
-    /// to add new static storage fields to your contract.
-    #[ink(storage)]
-    pub struct UnitTestBug {
-        /// Stores a single `bool` value on the storage.
+    /// Result of a message that can fail.
+    pub type FlipResult<T> = Result<T, FlipError>;
+
+    impl FlipError {
+        /// Returns the stable status code of the error.
+        ///
+        /// ```
+        /// # use unit_test_bug::FlipError;
+        /// assert_eq!(FlipError::NotOwner.code(), 2);
+        /// assert_eq!(shared_types::errors::error_name(2), Some("NotOwner"));
+        /// ```
+        pub fn code(self) -> u16 {
+            scale::Encode::using_encoded(&self, |encoded| u16::from(encoded[0]) + 1)
+        }
+    }
+
+    pub use shared_types::limits::{
+        AUDIT_LOG_SIZE, BLOB_CHUNK_SIZE, LEADERBOARD_SIZE, MAX_BATCH_SIZE_LIMIT,
+        MAX_BLOB_SIZE_LIMIT, MAX_BLS_VALIDATORS, MAX_COOLDOWN_BLOCKS, MAX_FEE_TIERS,
+        MAX_HISTORY_PAGE, MAX_LABEL_LEN, MAX_POLICY_EXEMPTIONS, MAX_QUEUED_FLIPS,
+        MAX_RESPONSE_SIZE, MAX_TIMELOCK_DELAY,
+    };
+
+    /// Basis points in a whole, the scale of `FeeTier::discount_bps`.
+    pub const BPS_DENOMINATOR: u16 = 10_000;
+
+    /// Storage deposit per stored item on `substrate-contracts-node`.
+    pub const STORAGE_DEPOSIT_PER_ITEM: Balance = 100_000_000_000;
+
+    /// Storage deposit per stored byte on `substrate-contracts-node`.
+    pub const STORAGE_DEPOSIT_PER_BYTE: Balance = 5_000_000;
+
+    /// Session key permission to relay `flip`.
+    pub const SESSION_FLIP: u32 = 1 << 0;
+
+    /// Version of the permit signing scheme, bumped on any change to [`SigningDomain`].
+    pub const SIGNING_DOMAIN_VERSION: u32 = 1;
+
+    /// `ApiVersion::features` bit set when built with `zk`.
+    pub const API_FEATURE_ZK: u32 = 1 << 0;
+
+    /// `ApiVersion::features` bit set when built with `bls`.
+    pub const API_FEATURE_BLS: u32 = 1 << 1;
+
+    /// `ApiVersion::features` bit set when built with `debug`.
+    pub const API_FEATURE_DEBUG: u32 = 1 << 2;
+
+    /// Version and features of this build, kept in step with the crate version.
+    pub const API_VERSION: ApiVersion = ApiVersion {
+        major: 0,
+        minor: 1,
+        patch: 0,
+        features: if cfg!(feature = "zk") { API_FEATURE_ZK } else { 0 }
+            | if cfg!(feature = "bls") { API_FEATURE_BLS } else { 0 }
+            | if cfg!(feature = "debug") { API_FEATURE_DEBUG } else { 0 },
+    };
+
+    /// Share of the fee pot, in percent, paid to a lottery winner.
+    pub const LOTTERY_PRIZE_PERCENT: Balance = 50;
+
+    /// Number of blocks per epoch unless configured otherwise.
+    pub const DEFAULT_EPOCH_LENGTH: BlockNumber = 100;
+
+    /// Epochs without a flip or wrap after which `prune_participants` drops an account.
+    pub const PARTICIPANT_RETENTION_EPOCHS: u32 = 4;
+
+    /// `Config::features` bit enabling `draw`.
+    pub const FEATURE_LOTTERY: u32 = 1 << 0;
+
+    /// `Config::features` bit enabling `flash_flip`.
+    pub const FEATURE_FLASH_FLIP: u32 = 1 << 1;
+
+    /// Every bit `Config::features` may set.
+    pub const ALL_FEATURES: u32 = FEATURE_LOTTERY | FEATURE_FLASH_FLIP;
+
+    /// Part of a queued flip's deposit paid to the keeper who executes it.
+    pub const KEEPER_REWARD: Balance = 10;
+
+    /// A prepaid subscription granting fee-free flips.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct Subscription {
+        /// Block in which the subscription was first bought.
+        pub started_at: BlockNumber,
+        /// First block in which the subscription is no longer valid.
+        pub expires_at: BlockNumber,
+        /// Total value paid for this subscription so far.
+        pub paid: Balance,
+    }
+
+    /// A snapshot of the stored value taken at the block it changed in.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct Checkpoint {
+        /// Block number in which the value was written.
+        pub block: BlockNumber,
+        /// The value as of the end of that block.
+        pub value: bool,
+    }
+
+    /// What a call flipping the value did, returned as well as announced by
+    /// `FlippedV2` so that a dry run previews it.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct MutationReceipt {
+        /// `seq` of the `FlippedV2` the call emitted.
+        pub seq: u64,
+        /// The value after the call.
+        pub new_value: bool,
+        /// `ref_time` the message had used by the time it returned, zero off
+        /// chain, where the test engine does not meter it.
+        pub gas_hint: u64,
+    }
+
+    /// The value and how long it had been `true` in total at a timestamp.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    struct TwabObservation {
+        /// Timestamp of the block the value was written in.
+        at: Timestamp,
+        /// The value from `at` on.
         value: bool,
+        /// Milliseconds the value was `true` before `at`.
+        true_time: Timestamp,
     }
 
-    impl UnitTestBug {
-        /// Constructor that initializes the `bool` value to the given `init_value`.
-        #[ink(constructor)]
-        pub fn new(init_value: bool) -> Self {
-            Self { value: init_value }
+    /// A bid for exclusive flip rights during `epoch`.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct Bid {
+        /// Account placing the bid.
+        pub bidder: AccountId,
+        /// Value locked with the bid.
+        pub amount: Balance,
+        /// Epoch the bid competes for.
+        pub epoch: u32,
+    }
+
+    /// Lifecycle of an escrow.
+    ///
+    /// `Open` may move to `Released` or `Disputed`; `Disputed` is resolved by the
+    /// arbiter into `Released` or `Refunded`. Both of those are final.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub enum EscrowState {
+        /// Funds are held and the depositor may release them.
+        Open,
+        /// A party objected and only the arbiter can settle.
+        Disputed,
+        /// Funds went to the beneficiary.
+        Released,
+        /// Funds went back to the depositor.
+        Refunded,
+    }
+
+    /// Funds held by the contract until released or arbitrated.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct Escrow {
+        /// Account that funded the escrow.
+        pub depositor: AccountId,
+        /// Account receiving the funds on release.
+        pub beneficiary: AccountId,
+        /// Account settling disputes.
+        pub arbiter: AccountId,
+        /// Value held.
+        pub amount: Balance,
+        /// Current lifecycle state.
+        pub state: EscrowState,
+    }
+
+    /// Hashes two Merkle nodes in sorted order, so proofs need no direction bits.
+    fn hash_pair(a: Hash, b: Hash) -> Hash {
+        let (low, high) = if a.as_ref() <= b.as_ref() {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        let mut output = [0u8; 32];
+        ink::env::hash_encoded::<Blake2x256, _>(&(low, high), &mut output);
+        Hash::from(output)
+    }
+
+    /// Returns the Merkle leaf committing `account` to airdrop slot `index`.
+    pub fn airdrop_leaf(index: u32, account: AccountId) -> Hash {
+        let mut output = [0u8; 32];
+        ink::env::hash_encoded::<Blake2x256, _>(&(index, account), &mut output);
+        Hash::from(output)
+    }
+
+    /// A ranked account on the current epoch's leaderboard.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct LeaderboardEntry {
+        /// The ranked account.
+        pub account: AccountId,
+        /// Its number of successful flips in the epoch.
+        pub flips: u64,
+    }
+
+    /// A flip waiting in the queue for a keeper to execute it.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct QueuedFlip {
+        /// Account the flip is made for.
+        pub by: AccountId,
+        /// Block after which the flip may be executed.
+        pub execute_after: BlockNumber,
+        /// Value paid towards the flip fee, credited back if the flip fails.
+        pub paid: Balance,
+    }
+
+    /// Wrapped balance of an account as of the end of `block`.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct StakeCheckpoint {
+        /// Block of the last change.
+        pub block: BlockNumber,
+        /// Wrapped balance after the change.
+        pub balance: Balance,
+    }
+
+    /// A proposal to set the value, decided by wrapped-balance-weighted votes.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct Proposal {
+        /// Account that made the proposal.
+        pub proposer: AccountId,
+        /// Value to set if the proposal passes.
+        pub value: bool,
+        /// Block whose closing wrapped balances weigh the votes.
+        pub snapshot: BlockNumber,
+        /// Last block in which votes are accepted.
+        pub voting_ends: BlockNumber,
+        /// Weight voted in favour.
+        pub ayes: Balance,
+        /// Weight voted against.
+        pub nays: Balance,
+        /// Whether the proposal has been executed.
+        pub executed: bool,
+    }
+
+    /// Operations a trusted forwarder may relay on behalf of another account.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    #[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+    pub enum ForwardedCall {
+        /// Relays `flip`.
+        Flip,
+    }
+
+    /// Semantic version of the contract API and the optional features compiled in.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct ApiVersion {
+        pub major: u16,
+        pub minor: u16,
+        pub patch: u16,
+        /// `API_FEATURE_*` bits of the features the code was built with.
+        pub features: u32,
+    }
+
+    impl ApiVersion {
+        /// Whether every bit of `feature` is set.
+        pub fn supports(&self, feature: u32) -> bool {
+            self.features & feature == feature
         }
+    }
 
-        /// Constructor that initializes the `bool` value to `false`.
+    /// Context every permit signature commits to.
+    ///
+    /// Binding the contract address and chain id keeps a signature from being
+    /// replayed against another deployment or another chain, and the per-signer
+    /// nonce keeps it from being replayed against the same one.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct SigningDomain {
+        /// Address of the verifying contract.
+        pub contract: AccountId,
+        /// Genesis hash of the chain the contract is deployed on.
+        pub chain_id: Hash,
+        /// Signing scheme version.
+        pub version: u32,
+        /// Next unused nonce of the signer.
+        pub nonce: u64,
+    }
+
+    impl ForwardedCall {
+        /// Returns the session key permission bit needed to relay this call.
+        pub fn permission(&self) -> u32 {
+            match self {
+                ForwardedCall::Flip => SESSION_FLIP,
+            }
+        }
+    }
+
+    /// An ephemeral key allowed to act for an account within limits.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct SessionKey {
+        /// Bitmask of `SESSION_*` permissions.
+        pub permissions: u32,
+        /// First block in which the key is no longer valid.
+        pub expires_at: BlockNumber,
+    }
+
+    /// Preferences an account keeps with the contract.
+    #[derive(Debug, Default, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct Settings {
+        /// Whether the account opts into automatic flips.
+        pub auto_flip: bool,
+        /// Contract to notify about the account's activity.
+        pub notify_hook: Option<AccountId>,
+        /// Fee tier the account selected.
+        pub fee_tier: u8,
+    }
+
+    /// A partial update of [`Settings`]; `None` fields are left unchanged.
+    #[derive(Debug, Default, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct SettingsPatch {
+        /// New `auto_flip` value.
+        pub auto_flip: Option<bool>,
+        /// New `notify_hook`; `Some(None)` clears it.
+        pub notify_hook: Option<Option<AccountId>>,
+        /// New `fee_tier` value.
+        pub fee_tier: Option<u8>,
+    }
+
+    impl Settings {
+        /// Returns these settings with every field set in `patch` replaced.
+        pub fn patched(self, patch: SettingsPatch) -> Self {
+            Self {
+                auto_flip: patch.auto_flip.unwrap_or(self.auto_flip),
+                notify_hook: patch.notify_hook.unwrap_or(self.notify_hook),
+                fee_tier: patch.fee_tier.unwrap_or(self.fee_tier),
+            }
+        }
+    }
+
+    /// Global parameters, changed only through the configuration timelock.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    #[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+    pub struct Config {
+        /// Value non-subscribers must transfer with every `flip`.
+        pub flip_fee: Balance,
+        /// Price of one block of subscription, zero disables `subscribe`.
+        pub subscription_price: Balance,
+        /// Minimum number of blocks between two flips by the same account.
+        pub cooldown_blocks: BlockNumber,
+        /// Largest number of items a batch operation may process.
+        pub max_batch_size: u32,
+        /// Whether flipping is paused.
+        pub paused: bool,
+        /// Blocks a queued configuration change waits before it can be applied.
+        pub timelock_delay: BlockNumber,
+        /// Largest blob, in bytes, `store_blob` accepts.
+        pub max_blob_size: u32,
+        /// Whether flips also emit the deprecated `Flipped` event.
+        pub emit_legacy_events: bool,
+        /// Most flips, `subscribe`, `bid`, `unwrap` and `claim_airdrop` calls one
+        /// account may make per block, zero for no limit.
+        pub max_calls_per_block: u32,
+        /// Consecutive failed privileged operations that trip the circuit
+        /// breaker, zero disables it.
+        pub breaker_threshold: u32,
+        /// Blocks a proposal accepts votes for after its snapshot block.
+        pub voting_period: BlockNumber,
+        /// Most `ref_time` a price oracle query or flash borrower callback may
+        /// use, zero for all the call has left.
         ///
-        /// Constructors can delegate to other constructors.
-        #[ink(constructor)]
-        pub fn default() -> Self {
-            Self::new(Default::default())
+        /// ink! 4 calls take no `proof_size` limit; that needs ink! 5.
+        pub call_ref_time_limit: u64,
+        /// Most checkpoints kept in the history, zero for no limit. The oldest
+        /// is evicted when a new one would exceed it.
+        pub max_history: u32,
+        /// Experimental messages enabled, as `FEATURE_*` bits.
+        pub features: u32,
+    }
+
+    impl Default for Config {
+        fn default() -> Self {
+            Self {
+                flip_fee: 0,
+                subscription_price: 0,
+                cooldown_blocks: 0,
+                max_batch_size: 16,
+                paused: false,
+                timelock_delay: 10,
+                max_blob_size: 1024,
+                emit_legacy_events: true,
+                max_calls_per_block: 0,
+                breaker_threshold: 0,
+                voting_period: 100,
+                call_ref_time_limit: 0,
+                max_history: 0,
+                features: ALL_FEATURES,
+            }
         }
+    }
 
-        /// A message that can be called on instantiated contracts.
-        /// This one flips the value of the stored `bool` from `true`
-        /// to `false` and vice versa.
-        #[ink(message)]
-        pub fn flip_with_error(&mut self) -> Result<(), FlipError>{
-            self.value = !self.value;
-            // Revert should occur and self.value remains unchanged
-            Err(FlipError::FlipError)
+    impl Config {
+        /// Checks every field against its allowed range.
+        pub fn validate(&self) -> FlipResult<()> {
+            let valid = (1..=MAX_BATCH_SIZE_LIMIT).contains(&self.max_batch_size)
+                && self.cooldown_blocks <= MAX_COOLDOWN_BLOCKS
+                && self.timelock_delay <= MAX_TIMELOCK_DELAY
+                && self.max_blob_size <= MAX_BLOB_SIZE_LIMIT
+                && self.voting_period > 0
+                && self.features & !ALL_FEATURES == 0;
+            if !valid {
+                return Err(FlipError::InvalidConfig);
+            }
+            Ok(())
         }
 
-        /// Simply returns the current value of our `bool`.
-        #[ink(message)]
-        pub fn get(&self) -> bool {
-            self.value
+        /// Fails unless every bit of `feature` is enabled.
+        pub fn ensure_feature(&self, feature: u32) -> FlipResult<()> {
+            if self.features & feature != feature {
+                return Err(FlipError::FeatureDisabled);
+            }
+            Ok(())
         }
     }
 
-    /// Unit tests in Rust are normally defined within such a `#[cfg(test)]`
-    /// module and test functions are marked with a `#[test]` attribute.
-    /// The below code is technically just normal Rust code.
-    #[cfg(test)]
-    mod tests {
+    /// Who may flip, and when.
+    #[derive(Debug, Default, PartialEq, Eq, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    #[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+    pub struct FlipPolicy {
+        /// Which callers are admitted.
+        pub mode: PolicyMode,
+        /// Limits and exemptions applied on top of `mode`.
+        pub params: PolicyParams,
+    }
 
-        /// Imports all the definitions from the outer scope so we can use them here.
-        use super::*;
+    /// Which callers a flip policy admits.
+    #[derive(Debug, Default, PartialEq, Eq, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    #[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+    pub enum PolicyMode {
+        /// Anyone may flip.
+        #[default]
+        Open,
+        /// Only the owner may flip.
+        OwnerOnly,
+        /// Anyone may flip while the schedule is open.
+        Scheduled(Schedule),
+    }
 
-        /// We test if the default constructor does its job.
-        #[ink::test]
-        fn default_works() {
-            let unit_test_bug = UnitTestBug::default();
-            assert_eq!(unit_test_bug.get(), false);
+    /// Recurring window of blocks in which flipping is open.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    #[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+    pub struct Schedule {
+        /// First block of the first window.
+        pub start: BlockNumber,
+        /// Blocks from the start of one window to the start of the next.
+        pub period: BlockNumber,
+        /// Blocks each window stays open.
+        pub open_for: BlockNumber,
+    }
+
+    /// Limits and exemptions of a flip policy.
+    #[derive(Debug, Default, PartialEq, Eq, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct PolicyParams {
+        /// Lifetime flips allowed per account, unlimited if `None`.
+        pub max_flips_per_account: Option<u64>,
+        /// Accounts the policy does not apply to.
+        pub exempt: Vec<AccountId>,
+    }
+
+    impl FlipPolicy {
+        /// Checks the schedule and the exemption list.
+        pub fn validate(&self) -> FlipResult<()> {
+            let schedule_valid = match &self.mode {
+                PolicyMode::Scheduled(schedule) => {
+                    schedule.open_for > 0 && schedule.open_for <= schedule.period
+                }
+                PolicyMode::Open | PolicyMode::OwnerOnly => true,
+            };
+            if !schedule_valid || self.params.exempt.len() > MAX_POLICY_EXEMPTIONS {
+                return Err(FlipError::InvalidPolicy);
+            }
+            Ok(())
         }
 
-        /// We test a simple use case of our contract.
-        #[ink::test]
-        fn it_works() {
-            let mut unit_test_bug = UnitTestBug::new(false);
-            assert_eq!(unit_test_bug.get(), false);
-            // Error is returned, revert should occur, and value should remain as false
-            assert_eq!(unit_test_bug.flip_with_error(), Err(FlipError::FlipError));
-            // This test is going to FAIL because the revert did not occur
-            assert_eq!(unit_test_bug.get(), false);
+        /// Returns whether `caller`, with `flips` earlier flips, may flip at block `now`.
+        pub fn admits(
+            &self,
+            caller: AccountId,
+            owner: AccountId,
+            now: BlockNumber,
+            flips: u64,
+        ) -> bool {
+            if self.params.exempt.contains(&caller) {
+                return true;
+            }
+            let mode_admits = match &self.mode {
+                PolicyMode::Open => true,
+                PolicyMode::OwnerOnly => caller == owner,
+                PolicyMode::Scheduled(schedule) => schedule.is_open(now),
+            };
+            mode_admits
+                && self
+                    .params
+                    .max_flips_per_account
+                    .is_none_or(|max| flips < max)
+        }
+    }
+
+    impl Schedule {
+        /// Returns whether block `now` falls inside a window.
+        pub fn is_open(&self, now: BlockNumber) -> bool {
+            now >= self.start && (now - self.start) % self.period < self.open_for
         }
     }
 
+    /// Fee discounts earned by flipping often.
+    #[derive(Debug, Default, PartialEq, Eq, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    #[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+    pub struct FeeDiscounts {
+        /// Tiers in strictly increasing `min_flips` order.
+        pub tiers: Vec<FeeTier>,
+    }
 
-    /// This is how you'd write end-to-end (E2E) or integration tests for ink! contracts.
+    /// Discount granted from a number of earlier flips on.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    #[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+    pub struct FeeTier {
+        /// Earlier flips needed to reach the tier.
+        pub min_flips: u64,
+        /// Share of the fee waived, in basis points.
+        pub discount_bps: u16,
+    }
+
+    impl FeeDiscounts {
+        /// Checks the tiers are ordered and never lower the discount.
+        ///
+        /// Together this keeps fees non-increasing as an account flips more.
+        pub fn validate(&self) -> FlipResult<()> {
+            let ordered = self.tiers.windows(2).all(|pair| {
+                pair[0].min_flips < pair[1].min_flips
+                    && pair[0].discount_bps <= pair[1].discount_bps
+            });
+            if !ordered
+                || self.tiers.len() > MAX_FEE_TIERS
+                || self
+                    .tiers
+                    .iter()
+                    .any(|tier| tier.discount_bps > BPS_DENOMINATOR)
+            {
+                return Err(FlipError::InvalidFeeDiscounts);
+            }
+            Ok(())
+        }
+
+        /// Returns `fee` less the discount of an account with `flips` earlier flips.
+        pub fn apply(&self, fee: Balance, flips: u64) -> Balance {
+            let Some(tier) = self.tiers.iter().rev().find(|tier| flips >= tier.min_flips) else {
+                return fee;
+            };
+            let bps = Balance::from(tier.discount_bps);
+            let whole = Balance::from(BPS_DENOMINATOR);
+            fee - (fee / whole * bps + fee % whole * bps / whole)
+        }
+    }
+
+    /// A configuration change waiting out the timelock.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct PendingConfig {
+        /// The configuration to apply.
+        pub config: Config,
+        /// First block in which it can be applied.
+        pub eta: BlockNumber,
+    }
+
+    /// Emitted on every flip while `config.emit_legacy_events` is set.
     ///
-    /// When running these you need to make sure that you:
-    /// - Compile the tests with the `e2e-tests` feature flag enabled (`--features e2e-tests`)
-    /// - Are running a Substrate node which contains `pallet-contracts` in the background
-    #[cfg(all(test, feature = "e2e-tests"))]
-    mod e2e_tests {
-        /// Imports all the definitions from the outer scope so we can use them here.
-        use super::*;
+    /// Deprecated in favour of [`FlippedV2`]. Decodes as `shared_types::Flipped`,
+    /// so unlike the other events it carries no `seq`.
+    #[ink(event)]
+    pub struct Flipped {
+        /// The value after the flip.
+        pub value: bool,
+    }
 
-        /// A helper function used for calling contract messages.
-        use ink_e2e::build_message;
+    /// Emitted on every flip. Decodes as `shared_types::FlippedV2`.
+    #[ink(event)]
+    pub struct FlippedV2 {
+        /// The account the flip is recorded for.
+        #[ink(topic)]
+        pub account: AccountId,
+        /// The value after the flip.
+        pub value: bool,
+        /// Block the flip happened in.
+        pub block: BlockNumber,
+        /// Position among the contract's state-change events, counting from zero.
+        pub seq: u64,
+    }
 
-        /// The End-to-End test `Result` type.
-        type E2EResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+    /// Emitted when an account grants or revokes a delegate's right to flip for it.
+    #[ink(event)]
+    pub struct Approval {
+        /// The account flips are made for.
+        #[ink(topic)]
+        pub account: AccountId,
+        /// The account allowed to flip for `account`.
+        pub delegate: AccountId,
+        /// Whether the right was granted or revoked.
+        pub allowed: bool,
+        /// Position among the contract's state-change events, counting from zero.
+        pub seq: u64,
+    }
 
-        /// We test that we can upload and instantiate the contract using its default constructor.
-        #[ink_e2e::test]
-        async fn default_works(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
-            // Given
-            let constructor = UnitTestBugRef::default();
+    /// A privileged operation recorded in the audit log.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct AuditEntry {
+        /// Position in the log, counting every entry ever recorded.
+        pub index: u32,
+        /// The account that made the change.
+        pub by: AccountId,
+        /// Selector of the message that made the change.
+        pub what: [u8; 4],
+        /// Block the change was made in.
+        pub block: BlockNumber,
+        /// Blake2x256 hash of the SCALE encoding of what was changed, before.
+        pub old: Hash,
+        /// Blake2x256 hash of the SCALE encoding of what was changed, after.
+        pub new: Hash,
+    }
 
-            // When
-            let contract_account_id = client
-                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
-                .await
-                .expect("instantiate failed")
-                .account_id;
+    /// How `batch_flip_for` treats a failing item.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    #[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+    pub enum BatchMode {
+        /// The first failing item fails, and so reverts, the whole batch.
+        AllOrNothing,
+        /// Failing items are reported and skipped, the others take effect.
+        BestEffort,
+    }
 
-            // Then
-            let get = build_message::<UnitTestBugRef>(contract_account_id.clone())
-                .call(|unit_test_bug| unit_test_bug.get());
-            let get_result = client.call_dry_run(&ink_e2e::alice(), &get, 0, None).await;
-            assert!(matches!(get_result.return_value(), false));
+    /// An owner setting change batched through `multicall`.
+    #[derive(Debug, PartialEq, Eq, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    #[allow(clippy::enum_variant_names)]
+    pub enum AdminOp {
+        /// `set_treasury`.
+        SetTreasury(Option<AccountId>),
+        /// `set_guardian`.
+        SetGuardian(Option<AccountId>),
+        /// `set_trusted_forwarder`.
+        SetTrustedForwarder(Option<AccountId>),
+        /// `set_chain_id`.
+        SetChainId(Hash),
+        /// `set_label`.
+        SetLabel(String),
+        /// `set_flip_policy`.
+        SetFlipPolicy(FlipPolicy),
+    }
 
-            Ok(())
-        }
+    /// An owner operation whose failures count towards the circuit breaker.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    #[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+    pub enum PrivilegedOp {
+        /// `claim`.
+        Claim,
+        /// `forward_donations`.
+        ForwardDonations,
+        /// `withdraw_asset_fees` for the asset id.
+        WithdrawAssetFees(u32),
+    }
 
-        /// We test that we can read and write a value from the on-chain contract contract.
-        #[ink_e2e::test]
-        async fn it_works(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
-            // Given
-            let constructor = UnitTestBugRef::new(false);
-            let contract_account_id = client
-                .instantiate("unit_test_bug", &ink_e2e::bob(), constructor, 0, None)
-                .await
-                .expect("instantiate failed")
-                .account_id;
+    /// Emitted when repeated privileged failures trip the circuit breaker.
+    #[ink(event)]
+    pub struct BreakerTripped {
+        /// Consecutive failures that tripped it.
+        pub failures: u32,
+        /// Position among the contract's state-change events, counting from zero.
+        pub seq: u64,
+    }
 
-            let get = build_message::<UnitTestBugRef>(contract_account_id.clone())
-                .call(|unit_test_bug| unit_test_bug.get());
-            let get_result = client.call_dry_run(&ink_e2e::bob(), &get, 0, None).await;
-            assert!(matches!(get_result.return_value(), false));
+    /// Emitted when a configuration change is applied.
+    #[ink(event)]
+    pub struct ConfigChanged {
+        /// Configuration before the change.
+        pub old: Config,
+        /// Configuration after the change.
+        pub new: Config,
+        /// Position among the contract's state-change events, counting from zero.
+        pub seq: u64,
+    }
 
-            // When
-            let flip = build_message::<UnitTestBugRef>(contract_account_id.clone())
-                .call(|unit_test_bug| unit_test_bug.flip_with_error());
+    /// Emitted when the first flip of a new epoch resets the epoch statistics.
+    #[ink(event)]
+    pub struct EpochRolledOver {
+        /// Epoch of the statistics that were reset.
+        pub from: u32,
+        /// The new epoch.
+        pub to: u32,
+        /// Position among the contract's state-change events, counting from zero.
+        pub seq: u64,
+    }
 
-            // Call flip. Result should still be false as error is returned
-            let _flip_result = client
-                .call(&ink_e2e::bob(), flip, 0, None)
-                .await;
+    /// Emitted once per completed `batch_flip_for`.
+    #[ink(event)]
+    pub struct BatchFlipped {
+        /// The account that submitted the batch.
+        #[ink(topic)]
+        pub account: AccountId,
+        /// Items that flipped.
+        pub succeeded: u32,
+        /// Items skipped in `BestEffort` mode.
+        pub failed: u32,
+        /// Position among the contract's state-change events, counting from zero.
+        pub seq: u64,
+    }
 
-            // Then
-            let get = build_message::<UnitTestBugRef>(contract_account_id.clone())
-                .call(|unit_test_bug| unit_test_bug.get());
-            let get_result = client.call_dry_run(&ink_e2e::bob(), &get, 0, None).await;
-            // This test does pass properly in e2e tests
-            assert!(matches!(get_result.return_value(), false));
+    /// Emitted by every `prune_participants` call.
+    #[ink(event)]
+    pub struct Pruned {
+        /// Participants removed by the call.
+        pub count: u32,
+        /// Position among the contract's state-change events, counting from zero.
+        pub seq: u64,
+    }
 
-            Ok(())
+    /// Emitted for each checkpoint dropped to stay within `max_history`.
+    #[ink(event)]
+    pub struct Evicted {
+        /// Index of the evicted checkpoint.
+        pub index: u32,
+        /// Block the evicted checkpoint was written in.
+        pub block: BlockNumber,
+        /// Position among the contract's state-change events, counting from zero.
+        pub seq: u64,
+    }
+
+    /// A broken internal consistency rule found by `verify_invariants`.
+    #[derive(Debug, PartialEq, Eq, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Violation {
+        /// Value owed to users and the owner exceeds the contract balance.
+        ReservesExceedBalance { reserved: Balance, balance: Balance },
+        /// A checkpoint below `checkpoint_count` is missing.
+        MissingCheckpoint(u32),
+        /// A checkpoint is stored at or past `checkpoint_count`.
+        ExtraCheckpoint(u32),
+        /// The latest checkpoint disagrees with the current value.
+        StaleCheckpoint,
+        /// Checkpoints are not in strictly increasing block order.
+        UnorderedCheckpoints(u32),
+        /// The leaderboard holds more than `LEADERBOARD_SIZE` entries.
+        LeaderboardTooLong,
+        /// The leaderboard is not sorted by flip count, highest first.
+        LeaderboardUnsorted,
+        /// A leaderboard entry disagrees with the account's flip count in the epoch.
+        LeaderboardMismatch(AccountId),
+        /// The owner has claimed more fees than have vested.
+        ClaimedExceedsVested,
+        /// `total_wrapped` disagrees with the sum of the wrapped balances.
+        WrappedTotalMismatch { total: Balance, sum: Balance },
+        /// `total_credits` disagrees with the sum of the credits.
+        CreditTotalMismatch { total: Balance, sum: Balance },
+        /// The epoch's flip count disagrees with the sum of its accounts' flips.
+        EpochFlipsMismatch { recorded: u64, sum: u64 },
+        /// The epoch's flipper count disagrees with the accounts that flipped in it.
+        EpochFlippersMismatch { recorded: u32, count: u32 },
+        /// More checkpoints are kept than `max_history` allows.
+        HistoryTooLong { kept: u32, max_history: u32 },
+    }
+
+    /// Result of `verify_invariants`, empty when the storage is consistent.
+    #[derive(Debug, Default, PartialEq, Eq, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct InvariantReport {
+        /// Every rule found broken, in the order checked.
+        pub violations: Vec<Violation>,
+    }
+
+    impl InvariantReport {
+        /// Whether no rule is broken.
+        pub fn is_ok(&self) -> bool {
+            self.violations.is_empty()
         }
     }
+
+    /// A storage section exported by `export_storage`, in export order.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    #[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+    pub enum ExportSection {
+        /// Value checkpoints, oldest first.
+        History,
+        /// Accounts that have flipped or wrapped, with their flip counts.
+        Participants,
+        /// Wrapped balances of the participants.
+        Balances,
+    }
+
+    /// Position within the storage export.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    #[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+    pub struct ExportCursor {
+        /// Section to continue in.
+        pub section: ExportSection,
+        /// Index of the next entry within `section`.
+        pub index: u32,
+    }
+
+    impl Default for ExportCursor {
+        fn default() -> Self {
+            Self {
+                section: ExportSection::History,
+                index: 0,
+            }
+        }
+    }
+
+    /// One exported storage entry.
+    #[derive(Debug, PartialEq, Eq, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum ExportItem {
+        /// The checkpoint at `index` in the history.
+        Checkpoint { index: u32, checkpoint: Checkpoint },
+        /// The participant at `index` and their flip count.
+        Participant {
+            index: u32,
+            account: AccountId,
+            flips: u64,
+        },
+        /// A participant's wrapped balance.
+        Balance {
+            account: AccountId,
+            wrapped: Balance,
+        },
+    }
+
+    /// A page of the storage export.
+    #[derive(Debug, PartialEq, Eq, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct ExportChunk {
+        /// Entries in export order.
+        pub items: Vec<ExportItem>,
+        /// Where the next page starts, `None` once everything has been exported.
+        pub next: Option<ExportCursor>,
+    }
+
+    /// Requires flippers to hold at least `min_balance` of the PSP22 `token`.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct Psp22Gate {
+        /// The PSP22 token contract.
+        pub token: AccountId,
+        /// Smallest balance that grants flipping.
+        pub min_balance: Balance,
+    }
+
+    /// PSP34 token id, mirroring the standard's `Id`.
+    #[derive(Debug, PartialEq, Eq, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    #[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+    pub enum Id {
+        U8(u8),
+        U16(u16),
+        U32(u32),
+        U64(u64),
+        U128(u128),
+        Bytes(Vec<u8>),
+    }
+
+    /// How the fee of a flip is covered.
+    #[derive(Clone, Copy)]
+    enum Payment {
+        /// Native value, added to the fee pot, that must cover the flip fee.
+        Native(Balance),
+        /// A `pallet-assets` payment collected by the caller of `flip_admitted`.
+        Asset,
+    }
+
+    /// Flip fee payable in a `pallet-assets` asset instead of the native token.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    #[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+    pub struct AssetFee {
+        /// The `pallet-assets` asset id.
+        pub asset_id: u32,
+        /// Amount of the asset charged per flip.
+        pub amount: Balance,
+    }
+
+    /// Flip fee fixed in US dollars and converted at the oracle's price.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct UsdFee {
+        /// Contract implementing `PriceOracle`.
+        pub oracle: AccountId,
+        /// Fee in US cents.
+        pub usd_cents: Balance,
+        /// Oldest price, in blocks, the fee may be converted at.
+        pub max_age: BlockNumber,
+    }
+
+    impl UsdFee {
+        /// Converts the fee to native units at `quote`, rounding up.
+        pub fn native_amount(&self, quote: &PriceQuote) -> Balance {
+            self.usd_cents
+                .saturating_mul(quote.units_per_usd)
+                .div_ceil(100)
+        }
+    }
+
+    /// Validators whose aggregated BLS signature authorizes an attested flip.
+    ///
+    /// Keys are trusted as configured: the owner must have checked each
+    /// validator's proof of possession to rule out rogue-key aggregation.
+    #[derive(Debug, PartialEq, Eq, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    #[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+    pub struct BlsValidatorSet {
+        /// Compressed BLS12-381 G2 public keys.
+        pub keys: Vec<Vec<u8>>,
+        /// Number of distinct validators that must sign.
+        pub threshold: u32,
+    }
+
+    /// The subset of the runtime's `RuntimeCall` the contract dispatches.
+    #[derive(scale::Encode)]
+    enum RuntimeCall {
+        /// `pallet_balances` sits at index 4 of `construct_runtime!` in
+        /// `substrate-contracts-node`. Other runtimes may place it elsewhere.
+        #[codec(index = 4)]
+        Balances(BalancesCall),
+    }
+
+    /// The subset of `pallet_balances::Call` the contract dispatches.
+    #[derive(scale::Encode)]
+    enum BalancesCall {
+        #[codec(index = 0)]
+        Transfer {
+            dest: MultiAddress,
+            #[codec(compact)]
+            value: Balance,
+        },
+    }
+
+    /// Mirrors the `Id` variant of `sp_runtime::MultiAddress`.
+    #[derive(scale::Encode)]
+    enum MultiAddress {
+        #[codec(index = 0)]
+        Id(AccountId),
+    }
+
+    /// `arbitrary::Arbitrary` for the message arguments holding accounts or hashes.
+    ///
+    /// ink!'s `AccountId` and `Hash` have no implementation of their own, so
+    /// these are written out; the other argument types derive it.
+    #[cfg(feature = "fuzzing")]
+    mod fuzzing {
+        use arbitrary::{Arbitrary, Result, Unstructured};
+
+        use super::*;
+
+        fn account(u: &mut Unstructured) -> Result<AccountId> {
+            Ok(AccountId::from(<[u8; 32]>::arbitrary(u)?))
+        }
+
+        fn maybe_account(u: &mut Unstructured) -> Result<Option<AccountId>> {
+            Ok(if u.arbitrary()? { Some(account(u)?) } else { None })
+        }
+
+        impl<'a> Arbitrary<'a> for PolicyParams {
+            fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+                let exempt = u.arbitrary_len::<[u8; 32]>()?;
+                Ok(Self {
+                    max_flips_per_account: u.arbitrary()?,
+                    exempt: (0..exempt).map(|_| account(u)).collect::<Result<_>>()?,
+                })
+            }
+        }
+
+        impl<'a> Arbitrary<'a> for Settings {
+            fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+                Ok(Self {
+                    auto_flip: u.arbitrary()?,
+                    notify_hook: maybe_account(u)?,
+                    fee_tier: u.arbitrary()?,
+                })
+            }
+        }
+
+        impl<'a> Arbitrary<'a> for SettingsPatch {
+            fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+                let notify_hook = if u.arbitrary()? {
+                    Some(maybe_account(u)?)
+                } else {
+                    None
+                };
+                Ok(Self {
+                    auto_flip: u.arbitrary()?,
+                    notify_hook,
+                    fee_tier: u.arbitrary()?,
+                })
+            }
+        }
+
+        impl<'a> Arbitrary<'a> for Psp22Gate {
+            fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+                Ok(Self {
+                    token: account(u)?,
+                    min_balance: u.arbitrary()?,
+                })
+            }
+        }
+
+        impl<'a> Arbitrary<'a> for UsdFee {
+            fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+                Ok(Self {
+                    oracle: account(u)?,
+                    usd_cents: u.arbitrary()?,
+                    max_age: u.arbitrary()?,
+                })
+            }
+        }
+
+        impl<'a> Arbitrary<'a> for AdminOp {
+            fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+                Ok(match u.int_in_range(0..=5)? {
+                    0 => AdminOp::SetTreasury(maybe_account(u)?),
+                    1 => AdminOp::SetGuardian(maybe_account(u)?),
+                    2 => AdminOp::SetTrustedForwarder(maybe_account(u)?),
+                    3 => AdminOp::SetChainId(Hash::from(<[u8; 32]>::arbitrary(u)?)),
+                    4 => AdminOp::SetLabel(u.arbitrary()?),
+                    _ => AdminOp::SetFlipPolicy(u.arbitrary()?),
+                })
+            }
+        }
+    }
+
+    /// Defines the storage of your contract.
+    /// Add new fields to the below struct in order
+    /// to add new static storage fields to your contract.
+    #[ink(storage)]
+    pub struct UnitTestBug {
+        /// Stores a single `bool` value on the storage.
+        value: ValueCell,
+        /// Value history ordered by block number, one entry per block at most.
+        ///
+        /// ink! 4 has no `StorageVec`, so this `Mapping` indexed below
+        /// `checkpoint_count` stands in for one.
+        checkpoints: CheckpointsCell,
+        /// Number of entries stored in `checkpoints`.
+        checkpoint_count: CheckpointCountCell,
+        /// Index of the oldest checkpoint kept by `prune_history` and `max_history`.
+        first_checkpoint: u32,
+        /// Timestamp from which `value` reads as reverted, set by `flip_until`.
+        value_expiry: Option<Timestamp>,
+        /// Whether a constructor or `initialize` has set the contract up.
+        initialized: bool,
+        /// Account allowed to configure the contract.
+        owner: AccountId,
+        /// Global parameters.
+        config: Config,
+        /// Configuration change waiting out the timelock.
+        pending_config: Option<PendingConfig>,
+        /// Block of each account's latest flip, tracked for the cooldown.
+        last_flip: Mapping<AccountId, BlockNumber>,
+        /// Fees and subscription payments collected so far.
+        fee_pot: Balance,
+        /// Subscription records by subscriber.
+        subscriptions: Mapping<AccountId, Subscription>,
+        /// Number of blocks per epoch.
+        epoch_length: BlockNumber,
+        /// Highest bid of the running auction, for the epoch after the current one.
+        highest_bid: Option<Bid>,
+        /// Winning bid of the latest settled auction.
+        rights: Option<Bid>,
+        /// Escrows by id.
+        escrows: Mapping<u32, Escrow>,
+        /// Id assigned to the next escrow.
+        next_escrow_id: u32,
+        /// Fees unlocked per block for the owner to claim.
+        vesting_rate: Balance,
+        /// Block from which `vesting_rate` applies.
+        vesting_start: BlockNumber,
+        /// Fees unlocked before `vesting_start` under earlier rates.
+        vested_before_start: Balance,
+        /// Fees claimed by the owner so far.
+        fees_claimed: Balance,
+        /// Merkle root over all eligible `(index, account)` leaves.
+        airdrop_root: Option<Hash>,
+        /// Value paid out per successful claim.
+        airdrop_amount: Balance,
+        /// Value set aside for airdrop claims.
+        airdrop_pool: Balance,
+        /// Claimed airdrop indexes, packed 128 per word.
+        claimed_bitmap: Mapping<u32, u128>,
+        /// Lottery tickets by `(epoch, ticket number)`, one per successful flip.
+        lottery_tickets: Mapping<(u32, u32), AccountId>,
+        /// Number of tickets issued per epoch.
+        ticket_counts: Mapping<u32, u32>,
+        /// Drawn winner of each epoch.
+        lottery_winners: Mapping<u32, AccountId>,
+        /// Successful flips per account, over all epochs.
+        flip_counts: Mapping<AccountId, u64>,
+        /// Top flippers of `epoch_stats.epoch`, best first, ties ranked by who
+        /// reached the count first.
+        leaderboard: Vec<LeaderboardEntry>,
+        /// Contract allowed to relay calls on behalf of other accounts.
+        trusted_forwarder: Option<AccountId>,
+        /// Genesis hash committed to in permit signatures.
+        chain_id: Hash,
+        /// Next unused permit nonce per signer.
+        permit_nonces: Mapping<AccountId, u64>,
+        /// Session keys by `(account, key)`.
+        session_keys: Mapping<(AccountId, AccountId), SessionKey>,
+        /// Per-account settings, absent for accounts using the defaults.
+        settings: Mapping<AccountId, Settings>,
+        /// Wrapped-native credit of each account, backed 1:1 by native deposits.
+        wrapped: Mapping<AccountId, Balance>,
+        /// Sum of every account's wrapped credit.
+        total_wrapped: Balance,
+        /// Every account that has flipped or wrapped, in order of first appearance.
+        participants: Mapping<u32, AccountId>,
+        /// Position of each account in `participants`.
+        participant_ids: Mapping<AccountId, u32>,
+        /// Number of entries in `participants`.
+        participant_count: u32,
+        /// Latest epoch each participant flipped or wrapped in.
+        participant_epochs: Mapping<AccountId, u32>,
+        /// Index in `participants` the next `prune_participants` call starts at.
+        prune_cursor: u32,
+        /// The latest `AUDIT_LOG_SIZE` audit entries, keyed by index modulo the size.
+        audit_entries: Mapping<u32, AuditEntry>,
+        /// Number of audit entries ever recorded.
+        audit_count: u32,
+        /// Token balance required to flip, if any.
+        psp22_gate: Option<Psp22Gate>,
+        /// PSP34 collection whose holders may flip past the token gate.
+        psp34_collection: Option<AccountId>,
+        /// Delegates each account has approved to flip for it, keyed by `(owner, delegate)`.
+        approvals: Mapping<(AccountId, AccountId), ()>,
+        /// Account donations are forwarded to.
+        treasury: Option<AccountId>,
+        /// Donations received and not yet forwarded.
+        donations: Balance,
+        /// Asset accepted for flip fees, if any.
+        asset_fee: Option<AssetFee>,
+        /// Asset fees collected per asset id and not yet withdrawn.
+        asset_fee_pots: Mapping<u32, Balance>,
+        /// Dollar-denominated flip fee overriding `config.flip_fee`, if any.
+        usd_fee: Option<UsdFee>,
+        /// Compressed Groth16 verifying key gating `flip_with_zk_proof`.
+        zk_verifying_key: Option<Vec<u8>>,
+        /// Validators attesting flips through `flip_with_bls_attestation`.
+        bls_validators: Option<BlsValidatorSet>,
+        /// Number of attested flips so far, committed to by the next attestation.
+        bls_nonce: u64,
+        /// Length of each blob stored by the owner.
+        blob_lengths: Mapping<u32, u32>,
+        /// Blob contents split into `BLOB_CHUNK_SIZE` chunks, keyed by blob and chunk index.
+        blob_chunks: BlobChunks,
+        /// Human-readable name of the deployment.
+        label: String,
+        /// Who may flip, and when.
+        policy: FlipPolicy,
+        /// Rate-limited calls made by each account in each block.
+        call_counts: Mapping<(AccountId, BlockNumber), u32>,
+        /// Account allowed to reset the circuit breaker.
+        guardian: Option<AccountId>,
+        /// Consecutive failed `run_privileged` operations.
+        privileged_failures: u32,
+        /// Whether the circuit breaker has paused the contract.
+        breaker_tripped: bool,
+        /// Who referred each account, recorded on its first flip.
+        referrers: Mapping<AccountId, AccountId>,
+        /// Number of accounts each account has referred.
+        referral_counts: Mapping<AccountId, u32>,
+        /// Fee discounts by flip count.
+        fee_discounts: FeeDiscounts,
+        /// Statistics of the latest epoch anyone flipped in.
+        epoch_stats: EpochStats,
+        /// Successful flips per `(epoch, account)`.
+        epoch_flip_counts: Mapping<(u32, AccountId), u64>,
+        /// Changes of each account's wrapped balance by `(account, index)`, oldest first.
+        stake_checkpoints: Mapping<(AccountId, u32), StakeCheckpoint>,
+        /// Number of stake checkpoints per account.
+        stake_checkpoint_counts: Mapping<AccountId, u32>,
+        /// Value proposals by id.
+        proposals: Mapping<u32, Proposal>,
+        /// Number of proposals made, and the id of the next one.
+        proposal_count: u32,
+        /// Cast votes by `(proposal, voter)`, `true` for aye.
+        votes: Mapping<(u32, AccountId), bool>,
+        /// Value observations by timestamp for `true_ratio_since`, oldest first.
+        twab_observations: Mapping<u32, TwabObservation>,
+        /// Number of entries stored in `twab_observations`.
+        twab_count: u32,
+        /// Code hashes contracts must run for the contract to call them.
+        trusted_code_hashes: Mapping<Hash, ()>,
+        /// Number of entries in `trusted_code_hashes`, zero trusting every callee.
+        trusted_code_hash_count: u32,
+        /// Flips waiting for a keeper, ordered by `execute_after`.
+        flip_queue: Vec<QueuedFlip>,
+        /// Value owed to each account, withdrawn with `withdraw_credit`.
+        credits: Mapping<AccountId, Balance>,
+        /// Sum of `credits`.
+        total_credits: Balance,
+        /// `seq` of the next state-change event.
+        event_seq: u64,
+        /// Receipts of `execute_idempotent` calls by `(caller, idempotency_key)`.
+        idempotent_receipts: IdempotentReceipts,
+    }
+
+    impl UnitTestBug {
+        /// Constructor that initializes the `bool` value to the given `init_value`.
+        #[ink(constructor)]
+        pub fn new(init_value: bool) -> Self {
+            Self::with_config(init_value, Config::default())
+        }
+
+        /// Constructor that also sets the initial configuration.
+        ///
+        /// Panics if `config` fails validation.
+        #[ink(constructor)]
+        pub fn with_config(init_value: bool, config: Config) -> Self {
+            config.validate().expect("initial config must be valid");
+            let mut instance = Self::blank(Self::env().caller(), config);
+            instance.set_up(init_value);
+            instance
+        }
+
+        /// Constructor that initializes the `bool` value to `false`.
+        ///
+        /// Constructors can delegate to other constructors.
+        #[ink(constructor)]
+        #[allow(clippy::should_implement_trait)]
+        pub fn default() -> Self {
+            Self::new(Default::default())
+        }
+
+        /// Returns the encoded root storage of a contract owned by `owner`
+        /// that never ran a constructor, for a proxy delegating to this code
+        /// to hold at its own root. `owner` then sets it up with `initialize`.
+        #[cfg(feature = "std")]
+        pub fn proxy_storage(owner: AccountId) -> Vec<u8> {
+            let mut storage = Vec::new();
+            ink::storage::traits::Storable::encode(
+                &Self::blank(owner, Config::default()),
+                &mut storage,
+            );
+            storage
+        }
+
+        /// Sets up a contract whose constructor never ran, as behind a proxy.
+        ///
+        /// Only the owner the proxy storage names may call it, and only once,
+        /// so no one can take over a proxy deployed without setting it up.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, FlipError};
+        /// let mut contract = deploy(false);
+        /// // Constructors already set the contract up.
+        /// assert_eq!(contract.initialize(true), Err(FlipError::AlreadyInitialized));
+        /// ```
+        #[ink(message)]
+        pub fn initialize(&mut self, init_value: bool) -> FlipResult<()> {
+            if self.initialized {
+                return Err(FlipError::AlreadyInitialized);
+            }
+            self.ensure_owner()?;
+            self.set_up(init_value);
+            Ok(())
+        }
+
+        /// Does the work of a constructor on `blank` state.
+        fn set_up(&mut self, init_value: bool) {
+            self.initialized = true;
+            self.vesting_start = self.env().block_number();
+            self.value.set(&init_value);
+            self.write_checkpoint();
+            self.epoch_stats.epoch = self.current_epoch();
+        }
+
+        /// State before a constructor or `initialize` ran.
+        fn blank(owner: AccountId, config: Config) -> Self {
+            Self {
+                initialized: false,
+                value: Lazy::default(),
+                checkpoints: Mapping::default(),
+                checkpoint_count: Lazy::default(),
+                first_checkpoint: 0,
+                owner,
+                config,
+                pending_config: None,
+                last_flip: Mapping::default(),
+                fee_pot: 0,
+                subscriptions: Mapping::default(),
+                epoch_length: DEFAULT_EPOCH_LENGTH,
+                highest_bid: None,
+                rights: None,
+                escrows: Mapping::default(),
+                next_escrow_id: 0,
+                vesting_rate: 0,
+                vesting_start: 0,
+                vested_before_start: 0,
+                fees_claimed: 0,
+                airdrop_root: None,
+                airdrop_amount: 0,
+                airdrop_pool: 0,
+                claimed_bitmap: Mapping::default(),
+                lottery_tickets: Mapping::default(),
+                ticket_counts: Mapping::default(),
+                lottery_winners: Mapping::default(),
+                flip_counts: Mapping::default(),
+                leaderboard: Vec::new(),
+                trusted_forwarder: None,
+                chain_id: Hash::default(),
+                permit_nonces: Mapping::default(),
+                session_keys: Mapping::default(),
+                settings: Mapping::default(),
+                wrapped: Mapping::default(),
+                total_wrapped: 0,
+                participants: Mapping::default(),
+                participant_ids: Mapping::default(),
+                participant_count: 0,
+                participant_epochs: Mapping::default(),
+                prune_cursor: 0,
+                audit_entries: Mapping::default(),
+                audit_count: 0,
+                psp22_gate: None,
+                psp34_collection: None,
+                approvals: Mapping::default(),
+                treasury: None,
+                donations: 0,
+                asset_fee: None,
+                asset_fee_pots: Mapping::default(),
+                usd_fee: None,
+                zk_verifying_key: None,
+                bls_validators: None,
+                bls_nonce: 0,
+                blob_lengths: Mapping::default(),
+                blob_chunks: Mapping::default(),
+                label: String::new(),
+                policy: FlipPolicy::default(),
+                call_counts: Mapping::default(),
+                guardian: None,
+                privileged_failures: 0,
+                breaker_tripped: false,
+                referrers: Mapping::default(),
+                referral_counts: Mapping::default(),
+                fee_discounts: FeeDiscounts::default(),
+                epoch_stats: EpochStats::default(),
+                epoch_flip_counts: Mapping::default(),
+                stake_checkpoints: Mapping::default(),
+                stake_checkpoint_counts: Mapping::default(),
+                proposals: Mapping::default(),
+                proposal_count: 0,
+                votes: Mapping::default(),
+                twab_observations: Mapping::default(),
+                twab_count: 0,
+                value_expiry: None,
+                trusted_code_hashes: Mapping::default(),
+                trusted_code_hash_count: 0,
+                flip_queue: Vec::new(),
+                credits: Mapping::default(),
+                total_credits: 0,
+                event_seq: 0,
+                idempotent_receipts: Mapping::default(),
+            }
+        }
+
+        /// Flips the stored `bool` and records a checkpoint for the current block.
+        ///
+        /// Callers without an active subscription must transfer at least the flip fee.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::deploy;
+        /// let mut contract = deploy(false);
+        /// let receipt = contract.flip().expect("flip succeeds");
+        /// assert!(receipt.new_value);
+        /// assert!(contract.get());
+        /// ```
+        #[ink(message, payable)]
+        pub fn flip(&mut self) -> FlipResult<MutationReceipt> {
+            trace_call!(self, "flip", ());
+            let gas = self.gas_left();
+            let seq = self.flip_as(self.env().caller())?;
+            Ok(self.receipt(seq, gas))
+        }
+
+        /// Flips the stored `bool` until timestamp `deadline`, from which it reads
+        /// as reverted again.
+        ///
+        /// The revert is evaluated lazily when reading and stored by the next
+        /// write. Flipping again before the deadline drops it.
+        ///
+        /// ```
+        /// # use ink::env::{test::set_block_timestamp, DefaultEnvironment};
+        /// # use unit_test_bug::test_support::deploy;
+        /// let mut contract = deploy(false);
+        /// contract.flip_until(1_000).unwrap();
+        /// assert!(contract.get());
+        /// set_block_timestamp::<DefaultEnvironment>(1_000);
+        /// assert!(!contract.get());
+        /// ```
+        #[ink(message, payable)]
+        pub fn flip_until(&mut self, deadline: Timestamp) -> FlipResult<MutationReceipt> {
+            trace_call!(self, "flip_until", deadline);
+            let gas = self.gas_left();
+            if deadline <= self.env().block_timestamp() {
+                return Err(FlipError::DeadlineInPast);
+            }
+            let seq = self.flip_as(self.env().caller())?;
+            self.value_expiry = Some(deadline);
+            Ok(self.receipt(seq, gas))
+        }
+
+        /// Flips on behalf of `caller`, who may differ from the immediate caller when relayed.
+        ///
+        /// Returns the `seq` of the `FlippedV2` emitted, as do the helpers below.
+        fn flip_as(&mut self, caller: AccountId) -> FlipResult<u64> {
+            let seq = self.flip_paying(caller, self.env().transferred_value())?;
+            self.debug_check_invariants();
+            Ok(seq)
+        }
+
+        /// Flips on behalf of `caller`, who has paid `paid` towards the fee.
+        fn flip_paying(&mut self, caller: AccountId, paid: Balance) -> FlipResult<u64> {
+            self.check_psp22_gate(caller)?;
+            self.flip_admitted(caller, Payment::Native(paid))
+        }
+
+        /// Flips on behalf of `caller`, who has already passed a holding gate.
+        fn flip_admitted(&mut self, caller: AccountId, payment: Payment) -> FlipResult<u64> {
+            self.consume_call_quota(caller)?;
+            self.settle_auction();
+            if self.rights_holder().is_some_and(|holder| holder != caller) {
+                return Err(FlipError::NotRightsHolder);
+            }
+            if self.config.paused || self.breaker_tripped {
+                return Err(FlipError::Paused);
+            }
+            let now = self.env().block_number();
+            if !self
+                .policy
+                .admits(caller, self.owner, now, self.flip_count(caller))
+            {
+                return Err(FlipError::PolicyDenied);
+            }
+            if let Some(last) = self.last_flip.get(caller) {
+                if now < last.saturating_add(self.config.cooldown_blocks) {
+                    return Err(FlipError::CooldownActive);
+                }
+            }
+            if let Payment::Native(paid) = payment {
+                if !self.is_subscribed(caller) && paid < self.flip_fee_for(caller)? {
+                    return Err(FlipError::InsufficientFee);
+                }
+                self.fee_pot += paid;
+            }
+            self.last_flip.insert(caller, &now);
+            self.write_value(!self.read_value());
+            trace!("flip: value is now {}", self.read_value());
+            let seq = self.emit_flipped(caller);
+            self.write_checkpoint();
+            self.issue_ticket(caller);
+            self.record_flip(caller);
+            Ok(seq)
+        }
+
+        /// A message that can be called on instantiated contracts.
+        /// This one flips the value of the stored `bool` from `true`
+        /// to `false` and vice versa.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{call_reverting, deploy}, FlipError};
+        /// let mut contract = deploy(false);
+        /// let result = call_reverting(&mut contract, |contract| contract.flip_with_error());
+        /// assert_eq!(result, Err(FlipError::FlipError));
+        /// assert!(!contract.get());
+        /// ```
+        #[ink(message)]
+        pub fn flip_with_error(&mut self) -> FlipResult<()>{
+            self.write_value(!self.read_value());
+            trace!("flip_with_error: value is now {}, reverting", self.read_value());
+            // Revert should occur and self.value remains unchanged
+            Err(FlipError::FlipError)
+        }
+
+        /// Simply returns the current value of our `bool`.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::deploy;
+        /// let contract = deploy(true);
+        /// assert!(contract.get());
+        /// ```
+        #[ink(message)]
+        pub fn get(&self) -> bool {
+            self.read_value()
+        }
+
+        /// Returns the value as it stood at the end of `block`.
+        ///
+        /// Returns `None` for blocks before the first kept checkpoint.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{advance_blocks, deploy};
+        /// let mut contract = deploy(false);
+        /// advance_blocks(1);
+        /// contract.flip().unwrap();
+        /// assert_eq!(contract.value_at(0), Some(false));
+        /// assert_eq!(contract.value_at(1), Some(true));
+        /// ```
+        #[ink(message)]
+        pub fn value_at(&self, block: BlockNumber) -> Option<bool> {
+            // Find the first checkpoint written after `block`; the one before it holds the answer.
+            let first = self.first_checkpoint;
+            let (mut low, mut high) = (first, self.checkpoint_count());
+            while low < high {
+                let mid = low + (high - low) / 2;
+                if self.checkpoint(mid).block <= block {
+                    low = mid + 1;
+                } else {
+                    high = mid;
+                }
+            }
+            (low > first).then(|| self.checkpoint(low - 1).value)
+        }
+
+        /// Returns up to `limit` checkpoints, oldest first, starting at `start`.
+        ///
+        /// Indices before the first kept checkpoint are skipped and a `limit` of
+        /// zero reads one. A page that would not fit the output buffer is
+        /// refused with `ResponseTooLarge` instead of trapping, which a `limit`
+        /// of at most `MAX_HISTORY_PAGE` never is.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{advance_blocks, deploy};
+        /// let mut contract = deploy(false);
+        /// advance_blocks(1);
+        /// contract.flip().unwrap();
+        /// let page = contract.history_page(1, u32::MAX).unwrap();
+        /// assert_eq!(page.len(), 1);
+        /// assert!(page[0].value);
+        /// ```
+        #[ink(message)]
+        pub fn history_page(&self, start: u32, limit: u32) -> FlipResult<Vec<Checkpoint>> {
+            let start = start.max(self.first_checkpoint);
+            let end = start
+                .saturating_add(limit.max(1))
+                .min(self.checkpoint_count());
+            let len = end.saturating_sub(start);
+            if len > 0 {
+                // Checkpoints all encode to the same size, so the page is
+                // measured before any more of them are read.
+                let size = scale::Encode::encoded_size(&scale::Compact(len))
+                    + len as usize * scale::Encode::encoded_size(&self.checkpoint(start));
+                // `MessageResult` and `Result` add a byte each around the page.
+                if size + 2 > MAX_RESPONSE_SIZE {
+                    return Err(FlipError::ResponseTooLarge);
+                }
+            }
+            Ok((start..end).map(|index| self.checkpoint(index)).collect())
+        }
+
+        /// Returns the number of recorded checkpoints.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{advance_blocks, deploy};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.checkpoint_count(), 1);
+        /// advance_blocks(1);
+        /// contract.flip().unwrap();
+        /// assert_eq!(contract.checkpoint_count(), 2);
+        /// ```
+        #[ink(message)]
+        pub fn checkpoint_count(&self) -> u32 {
+            self.checkpoint_count.get().unwrap_or_default()
+        }
+
+        /// Returns the contract owner.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy};
+        /// let contract = deploy(false);
+        /// assert_eq!(contract.owner(), accounts().alice);
+        /// ```
+        #[ink(message)]
+        pub fn owner(&self) -> AccountId {
+            self.owner
+        }
+
+        /// Returns the API version and features of the running code.
+        ///
+        /// This follows `set_code_hash` upgrades, so clients can tell deployed
+        /// instances apart by what they support.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, API_VERSION};
+        /// let contract = deploy(false);
+        /// assert_eq!(contract.api_version(), API_VERSION);
+        /// ```
+        #[ink(message)]
+        pub fn api_version(&self) -> ApiVersion {
+            API_VERSION
+        }
+
+        /// Returns the flip fee set in the configuration.
+        ///
+        /// A configured USD fee takes precedence, see `quote_flip_fee`.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy_with_config, Config};
+        /// let contract = deploy_with_config(false, Config { flip_fee: 10, ..Config::default() });
+        /// assert_eq!(contract.flip_fee(), 10);
+        /// ```
+        #[ink(message)]
+        pub fn flip_fee(&self) -> Balance {
+            self.config.flip_fee
+        }
+
+        /// Returns the fees collected so far.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy, pay_as};
+        /// let mut contract = deploy(false);
+        /// pay_as(accounts().bob, 10);
+        /// contract.flip().unwrap();
+        /// assert_eq!(contract.fee_pot(), 10);
+        /// ```
+        #[ink(message)]
+        pub fn fee_pot(&self) -> Balance {
+            self.fee_pot
+        }
+
+        /// Counts a call by `caller` against `config.max_calls_per_block`.
+        fn consume_call_quota(&mut self, caller: AccountId) -> FlipResult<()> {
+            if self.config.max_calls_per_block == 0 {
+                return Ok(());
+            }
+            let key = (caller, self.env().block_number());
+            let calls = self.call_counts.get(key).unwrap_or(0);
+            if calls >= self.config.max_calls_per_block {
+                return Err(FlipError::RateLimited);
+            }
+            self.call_counts.insert(key, &(calls + 1));
+            Ok(())
+        }
+
+        fn ensure_owner(&self) -> FlipResult<()> {
+            if self.env().caller() != self.owner {
+                return Err(FlipError::NotOwner);
+            }
+            Ok(())
+        }
+
+        /// Emits `FlippedV2`, and `Flipped` during the transition window,
+        /// returning the `seq` taken.
+        fn emit_flipped(&mut self, by: AccountId) -> u64 {
+            let value = self.read_value();
+            let seq = self.next_seq();
+            self.env().emit_event(FlippedV2 {
+                account: by,
+                value,
+                block: self.env().block_number(),
+                seq,
+            });
+            if self.config.emit_legacy_events {
+                self.env().emit_event(Flipped { value });
+            }
+            seq
+        }
+
+        /// Receipt of a message that started with `gas_at_start` left and
+        /// emitted the `FlippedV2` numbered `seq`.
+        fn receipt(&self, seq: u64, gas_at_start: u64) -> MutationReceipt {
+            MutationReceipt {
+                seq,
+                new_value: self.read_value(),
+                gas_hint: gas_at_start.saturating_sub(self.gas_left()),
+            }
+        }
+
+        /// Writes the caller, `message`, the value transferred and a digest of
+        /// its `args` to the debug buffer, so that a revert or panic following
+        /// it has context.
+        ///
+        /// The digest is the start of the Blake2x256 hash of the SCALE-encoded
+        /// arguments, which is the call data after the selector.
+        #[cfg(feature = "debug")]
+        fn trace_call(&self, message: &str, args: &impl scale::Encode) {
+            let mut digest = [0u8; 32];
+            ink::env::hash_encoded::<Blake2x256, _>(args, &mut digest);
+            trace!(
+                "{message}: caller {}, value {}, args {}",
+                crate::fmt::hex(self.env().caller().as_ref()).as_str(),
+                crate::fmt::decimal(self.env().transferred_value()).as_str(),
+                crate::fmt::hex(&digest[..8]).as_str()
+            );
+        }
+
+        /// `ref_time` left to the call, zero in the off-chain test engine,
+        /// which does not meter it.
+        fn gas_left(&self) -> u64 {
+            if cfg!(feature = "std") {
+                0
+            } else {
+                self.env().gas_left()
+            }
+        }
+
+        /// Takes the sequence number of the next state-change event.
+        fn next_seq(&mut self) -> u64 {
+            let seq = self.event_seq;
+            self.event_seq += 1;
+            seq
+        }
+
+        /// Returns the `seq` the next state-change event will carry.
+        ///
+        /// Consumers that saw every event up to `seq` n - 1 have missed none.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::deploy;
+        /// let mut contract = deploy(false);
+        /// let next = contract.next_event_seq();
+        /// contract.flip().unwrap();
+        /// assert_eq!(contract.next_event_seq(), next + 1);
+        /// ```
+        #[ink(message)]
+        pub fn next_event_seq(&self) -> u64 {
+            self.event_seq
+        }
+
+        /// The value, reverted if its `flip_until` deadline has passed.
+        fn read_value(&self) -> bool {
+            self.stored_value() != self.value_expired().is_some()
+        }
+
+        fn stored_value(&self) -> bool {
+            self.value.get().unwrap_or_default()
+        }
+
+        /// The `flip_until` deadline, if it has passed.
+        fn value_expired(&self) -> Option<Timestamp> {
+            self.value_expiry
+                .filter(|&deadline| deadline <= self.env().block_timestamp())
+        }
+
+        /// Stores `value`, dropping any pending `flip_until` deadline.
+        fn write_value(&mut self, value: bool) {
+            self.settle_expiry();
+            self.value.set(&value);
+            self.value_expiry = None;
+        }
+
+        /// Stores the reverted value of a passed `flip_until` deadline and
+        /// observes it at the deadline for `true_ratio_since`.
+        ///
+        /// Checkpoints are keyed by block, so the revert only shows in `value_at`
+        /// from the next written checkpoint on.
+        fn settle_expiry(&mut self) {
+            if let Some(deadline) = self.value_expired() {
+                self.value.set(&self.read_value());
+                self.value_expiry = None;
+                self.observe_value_at(deadline);
+            }
+        }
+
+        fn checkpoint(&self, index: u32) -> Checkpoint {
+            self.checkpoints
+                .get(index)
+                .expect("checkpoints below `checkpoint_count` are always present")
+        }
+
+        /// Records the current value, overwriting the last checkpoint if it is from this block.
+        fn write_checkpoint(&mut self) {
+            let block = self.env().block_number();
+            let checkpoint = Checkpoint {
+                block,
+                value: self.read_value(),
+            };
+            let count = self.checkpoint_count();
+            let index = match count.checked_sub(1) {
+                Some(last) if self.checkpoint(last).block == block => last,
+                _ => {
+                    self.checkpoint_count.set(&(count + 1));
+                    count
+                }
+            };
+            self.checkpoints.insert(index, &checkpoint);
+            self.evict_history();
+            self.observe_value();
+        }
+
+        /// Drops the oldest checkpoints until the history fits `max_history`.
+        fn evict_history(&mut self) {
+            let max_history = self.config.max_history;
+            if max_history == 0 {
+                return;
+            }
+            while self.checkpoint_count() - self.first_checkpoint > max_history {
+                let index = self.first_checkpoint;
+                let block = self.checkpoint(index).block;
+                self.checkpoints.remove(index);
+                self.first_checkpoint += 1;
+                let seq = self.next_seq();
+                self.env().emit_event(Evicted { index, block, seq });
+            }
+        }
+
+        /// Returns the share of time since timestamp `since`, in basis points,
+        /// that the value has been `true`.
+        ///
+        /// Returns `None` if `since` is not in the past or predates the contract.
+        ///
+        /// ```
+        /// # use ink::env::{test::set_block_timestamp, DefaultEnvironment};
+        /// # use unit_test_bug::test_support::deploy;
+        /// let mut contract = deploy(false);
+        /// set_block_timestamp::<DefaultEnvironment>(1_000);
+        /// contract.flip().unwrap();
+        /// set_block_timestamp::<DefaultEnvironment>(4_000);
+        /// // `true` for 3 of the last 4 seconds.
+        /// assert_eq!(contract.true_ratio_since(0), Some(7_500));
+        /// ```
+        #[ink(message)]
+        pub fn true_ratio_since(&self, since: Timestamp) -> Option<u16> {
+            let now = self.env().block_timestamp();
+            if since >= now {
+                return None;
+            }
+            let true_time = self.true_time_at(now)? - self.true_time_at(since)?;
+            let ratio = u128::from(true_time) * u128::from(BPS_DENOMINATOR) / u128::from(now - since);
+            Some(ratio as u16)
+        }
+
+        /// Milliseconds the value was `true` before timestamp `at`, `None` before
+        /// the first observation.
+        fn true_time_at(&self, at: Timestamp) -> Option<Timestamp> {
+            // Find the first observation after `at`; the one before it is in force.
+            let (mut low, mut high) = (0, self.twab_count);
+            while low < high {
+                let mid = low + (high - low) / 2;
+                if self.twab_observation(mid).at <= at {
+                    low = mid + 1;
+                } else {
+                    high = mid;
+                }
+            }
+            let index = low.checked_sub(1)?;
+            let observation = self.twab_observation(index);
+            // A passed `flip_until` deadline reverts the latest value without observing it.
+            match self.value_expired() {
+                Some(deadline) if index + 1 == self.twab_count && deadline < at => {
+                    let reverted = if observation.value { 0 } else { at - deadline };
+                    Some(observation.true_time_at(deadline) + reverted)
+                }
+                _ => Some(observation.true_time_at(at)),
+            }
+        }
+
+        fn twab_observation(&self, index: u32) -> TwabObservation {
+            self.twab_observations
+                .get(index)
+                .expect("observations below `twab_count` are always present")
+        }
+
+        /// Records the current value, overwriting the last observation if it is from this timestamp.
+        fn observe_value(&mut self) {
+            self.observe_value_at(self.env().block_timestamp());
+        }
+
+        /// Records the current value as held from `now` on.
+        fn observe_value_at(&mut self, now: Timestamp) {
+            let (index, true_time) = match self.twab_count.checked_sub(1) {
+                Some(last) => {
+                    let observation = self.twab_observation(last);
+                    if observation.at == now {
+                        (last, observation.true_time)
+                    } else {
+                        (self.twab_count, observation.true_time_at(now))
+                    }
+                }
+                None => (0, 0),
+            };
+            if index == self.twab_count {
+                self.twab_count += 1;
+            }
+            let observation = TwabObservation {
+                at: now,
+                value: self.read_value(),
+                true_time,
+            };
+            self.twab_observations.insert(index, &observation);
+        }
+    }
+
+    impl TwabObservation {
+        /// Milliseconds the value was `true` before `at`, assuming no later change.
+        fn true_time_at(&self, at: Timestamp) -> Timestamp {
+            let since = if self.value { at.saturating_sub(self.at) } else { 0 };
+            self.true_time + since
+        }
+    }
+
+    impl UnitTestBug {
+        /// Buys fee-free flipping for as many blocks as the transferred value pays for.
+        ///
+        /// An active subscription is extended from its current expiry, an expired
+        /// one restarts at the current block. Any remainder below one block's price
+        /// is kept in the fee pot.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy_with_config, pay_as}, Config};
+        /// let config = Config { subscription_price: 10, ..Config::default() };
+        /// let mut contract = deploy_with_config(false, config);
+        /// pay_as(accounts().bob, 30);
+        /// let subscription = contract.subscribe().unwrap();
+        /// assert_eq!(subscription.expires_at, 3);
+        /// ```
+        #[ink(message, payable)]
+        pub fn subscribe(&mut self) -> FlipResult<Subscription> {
+            self.consume_call_quota(self.env().caller())?;
+            if self.config.subscription_price == 0 {
+                return Err(FlipError::SubscriptionsDisabled);
+            }
+            let paid = self.env().transferred_value();
+            let blocks = paid / self.config.subscription_price;
+            if blocks == 0 {
+                return Err(FlipError::InsufficientPayment);
+            }
+            let blocks = BlockNumber::try_from(blocks).unwrap_or(BlockNumber::MAX);
+            let caller = self.env().caller();
+            let now = self.env().block_number();
+            let subscription = match self.subscriptions.get(caller) {
+                Some(current) if current.expires_at > now => Subscription {
+                    expires_at: current.expires_at.saturating_add(blocks),
+                    paid: current.paid + paid,
+                    ..current
+                },
+                _ => Subscription {
+                    started_at: now,
+                    expires_at: now.saturating_add(blocks),
+                    paid,
+                },
+            };
+            self.subscriptions.insert(caller, &subscription);
+            self.fee_pot += paid;
+            self.debug_check_invariants();
+            Ok(subscription)
+        }
+
+        /// Returns the subscription record of `account`, including expired ones.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy_with_config, pay_as}, Config};
+        /// let config = Config { subscription_price: 10, ..Config::default() };
+        /// let mut contract = deploy_with_config(false, config);
+        /// let bob = accounts().bob;
+        /// assert_eq!(contract.subscription_of(bob), None);
+        /// pay_as(bob, 30);
+        /// contract.subscribe().unwrap();
+        /// assert_eq!(contract.subscription_of(bob).map(|subscription| subscription.paid), Some(30));
+        /// ```
+        #[ink(message)]
+        pub fn subscription_of(&self, account: AccountId) -> Option<Subscription> {
+            self.subscriptions.get(account)
+        }
+
+        /// Returns whether `account` holds a subscription valid in the current block.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, advance_blocks, deploy_with_config, pay_as}, Config};
+        /// let config = Config { subscription_price: 10, ..Config::default() };
+        /// let mut contract = deploy_with_config(false, config);
+        /// let bob = accounts().bob;
+        /// pay_as(bob, 10);
+        /// contract.subscribe().unwrap();
+        /// assert!(contract.is_subscribed(bob));
+        /// advance_blocks(1);
+        /// assert!(!contract.is_subscribed(bob));
+        /// ```
+        #[ink(message)]
+        pub fn is_subscribed(&self, account: AccountId) -> bool {
+            self.subscriptions
+                .get(account)
+                .is_some_and(|subscription| subscription.expires_at > self.env().block_number())
+        }
+
+        /// Returns the price of one block of subscription.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{deploy, deploy_with_config}, Config};
+        /// assert_eq!(deploy(false).subscription_price(), 0);
+        /// let config = Config { subscription_price: 10, ..Config::default() };
+        /// assert_eq!(deploy_with_config(false, config).subscription_price(), 10);
+        /// ```
+        #[ink(message)]
+        pub fn subscription_price(&self) -> Balance {
+            self.config.subscription_price
+        }
+    }
+
+    impl UnitTestBug {
+        /// Bids for exclusive flip rights during the next epoch.
+        ///
+        /// The bid must exceed the current highest bid, which is credited back
+        /// to its bidder. Once its epoch starts, the winning bid moves into the fee pot
+        /// and only the winner may flip until the epoch ends.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, pay_as}, FlipError};
+        /// let mut contract = deploy(false);
+        /// pay_as(accounts().bob, 50);
+        /// assert_eq!(contract.bid().map(|bid| bid.epoch), Ok(1));
+        /// pay_as(accounts().charlie, 50);
+        /// assert_eq!(contract.bid(), Err(FlipError::BidTooLow));
+        /// ```
+        #[ink(message, payable)]
+        pub fn bid(&mut self) -> FlipResult<Bid> {
+            self.consume_call_quota(self.env().caller())?;
+            self.settle_auction();
+            let amount = self.env().transferred_value();
+            let epoch = self.current_epoch() + 1;
+            let outbid = self.highest_bid.filter(|bid| bid.epoch == epoch);
+            if amount <= outbid.map_or(0, |bid| bid.amount) {
+                return Err(FlipError::BidTooLow);
+            }
+            if let Some(previous) = outbid {
+                self.credit(previous.bidder, previous.amount);
+            }
+            let bid = Bid {
+                bidder: self.env().caller(),
+                amount,
+                epoch,
+            };
+            self.highest_bid = Some(bid);
+            self.debug_check_invariants();
+            Ok(bid)
+        }
+
+        /// Returns the highest bid for the next epoch, if any.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy, pay_as};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.highest_bid(), None);
+        /// pay_as(accounts().bob, 50);
+        /// contract.bid().unwrap();
+        /// assert_eq!(contract.highest_bid().map(|bid| bid.bidder), Some(accounts().bob));
+        /// ```
+        #[ink(message)]
+        pub fn highest_bid(&self) -> Option<Bid> {
+            self.highest_bid
+                .filter(|bid| bid.epoch > self.current_epoch())
+        }
+
+        /// Returns the account holding exclusive flip rights in the current epoch.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, advance_blocks, deploy, pay_as}, DEFAULT_EPOCH_LENGTH};
+        /// let mut contract = deploy(false);
+        /// pay_as(accounts().bob, 50);
+        /// contract.bid().unwrap();
+        /// assert_eq!(contract.rights_holder(), None);
+        /// advance_blocks(DEFAULT_EPOCH_LENGTH);
+        /// assert_eq!(contract.rights_holder(), Some(accounts().bob));
+        /// ```
+        #[ink(message)]
+        pub fn rights_holder(&self) -> Option<AccountId> {
+            let epoch = self.current_epoch();
+            [self.rights, self.highest_bid]
+                .into_iter()
+                .flatten()
+                .find(|bid| bid.epoch == epoch)
+                .map(|bid| bid.bidder)
+        }
+
+        /// Returns the number of the epoch containing the current block.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{advance_blocks, deploy}, DEFAULT_EPOCH_LENGTH};
+        /// let contract = deploy(false);
+        /// assert_eq!(contract.current_epoch(), 0);
+        /// advance_blocks(DEFAULT_EPOCH_LENGTH);
+        /// assert_eq!(contract.current_epoch(), 1);
+        /// ```
+        #[ink(message)]
+        pub fn current_epoch(&self) -> u32 {
+            self.env().block_number() / self.epoch_length
+        }
+
+        /// Moves a bid whose epoch has started from the auction into the fee pot.
+        fn settle_auction(&mut self) {
+            if let Some(bid) = self
+                .highest_bid
+                .filter(|bid| bid.epoch <= self.current_epoch())
+            {
+                self.fee_pot += bid.amount;
+                self.rights = Some(bid);
+                self.highest_bid = None;
+            }
+        }
+    }
+
+    impl UnitTestBug {
+        /// Holds the transferred value until the caller releases it to `beneficiary`.
+        ///
+        /// Either party may raise a dispute, after which `arbiter` decides where the
+        /// funds go. Returns the id of the new escrow.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, pay_as}, EscrowState};
+        /// let mut contract = deploy(false);
+        /// let accounts = accounts();
+        /// pay_as(accounts.bob, 100);
+        /// let id = contract.create_escrow(accounts.charlie, accounts.django).unwrap();
+        /// assert_eq!(contract.escrow(id).map(|escrow| escrow.state), Some(EscrowState::Open));
+        /// ```
+        #[ink(message, payable)]
+        pub fn create_escrow(
+            &mut self,
+            beneficiary: AccountId,
+            arbiter: AccountId,
+        ) -> FlipResult<u32> {
+            let amount = self.env().transferred_value();
+            if amount == 0 {
+                return Err(FlipError::EmptyEscrow);
+            }
+            let id = self.next_escrow_id;
+            let escrow = Escrow {
+                depositor: self.env().caller(),
+                beneficiary,
+                arbiter,
+                amount,
+                state: EscrowState::Open,
+            };
+            self.escrows.insert(id, &escrow);
+            self.next_escrow_id += 1;
+            self.debug_check_invariants();
+            Ok(id)
+        }
+
+        /// Credits an open escrow to its beneficiary. Only the depositor may release.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy, pay_as};
+        /// let mut contract = deploy(false);
+        /// let accounts = accounts();
+        /// pay_as(accounts.bob, 100);
+        /// let id = contract.create_escrow(accounts.charlie, accounts.django).unwrap();
+        /// assert_eq!(contract.release(id), Ok(()));
+        /// assert_eq!(contract.credit_of(accounts.charlie), 100);
+        /// ```
+        #[ink(message)]
+        pub fn release(&mut self, id: u32) -> FlipResult<()> {
+            let escrow = self.escrow_in_state(id, EscrowState::Open)?;
+            if self.env().caller() != escrow.depositor {
+                return Err(FlipError::NotEscrowParty);
+            }
+            self.settle_escrow(id, escrow, EscrowState::Released)
+        }
+
+        /// Freezes an open escrow until the arbiter resolves it.
+        ///
+        /// Either the depositor or the beneficiary may dispute.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, pay_as, set_caller}, EscrowState};
+        /// let mut contract = deploy(false);
+        /// let accounts = accounts();
+        /// pay_as(accounts.bob, 100);
+        /// let id = contract.create_escrow(accounts.charlie, accounts.django).unwrap();
+        /// set_caller(accounts.charlie);
+        /// assert_eq!(contract.dispute(id), Ok(()));
+        /// assert_eq!(contract.escrow(id).map(|escrow| escrow.state), Some(EscrowState::Disputed));
+        /// ```
+        #[ink(message)]
+        pub fn dispute(&mut self, id: u32) -> FlipResult<()> {
+            let mut escrow = self.escrow_in_state(id, EscrowState::Open)?;
+            let caller = self.env().caller();
+            if caller != escrow.depositor && caller != escrow.beneficiary {
+                return Err(FlipError::NotEscrowParty);
+            }
+            escrow.state = EscrowState::Disputed;
+            self.escrows.insert(id, &escrow);
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Settles a disputed escrow: `true` pays the beneficiary, `false` refunds the depositor.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, pay_as, set_caller}, EscrowState};
+        /// let mut contract = deploy(false);
+        /// let accounts = accounts();
+        /// pay_as(accounts.bob, 100);
+        /// let id = contract.create_escrow(accounts.charlie, accounts.django).unwrap();
+        /// contract.dispute(id).unwrap();
+        /// set_caller(accounts.django);
+        /// assert_eq!(contract.resolve(id, false), Ok(()));
+        /// assert_eq!(contract.escrow(id).map(|escrow| escrow.state), Some(EscrowState::Refunded));
+        /// ```
+        #[ink(message)]
+        pub fn resolve(&mut self, id: u32, verdict: bool) -> FlipResult<()> {
+            let escrow = self.escrow_in_state(id, EscrowState::Disputed)?;
+            if self.env().caller() != escrow.arbiter {
+                return Err(FlipError::NotEscrowParty);
+            }
+            let outcome = if verdict {
+                EscrowState::Released
+            } else {
+                EscrowState::Refunded
+            };
+            self.settle_escrow(id, escrow, outcome)
+        }
+
+        /// Returns the escrow stored under `id`.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy, pay_as};
+        /// let mut contract = deploy(false);
+        /// let accounts = accounts();
+        /// assert_eq!(contract.escrow(0), None);
+        /// pay_as(accounts.bob, 100);
+        /// contract.create_escrow(accounts.charlie, accounts.django).unwrap();
+        /// assert_eq!(contract.escrow(0).map(|escrow| escrow.amount), Some(100));
+        /// ```
+        #[ink(message)]
+        pub fn escrow(&self, id: u32) -> Option<Escrow> {
+            self.escrows.get(id)
+        }
+
+        fn escrow_in_state(&self, id: u32, state: EscrowState) -> FlipResult<Escrow> {
+            let escrow = self.escrows.get(id).ok_or(FlipError::EscrowNotFound)?;
+            if escrow.state != state {
+                return Err(FlipError::InvalidEscrowState);
+            }
+            Ok(escrow)
+        }
+
+        /// Credits the escrow according to `outcome` and records the final state.
+        fn settle_escrow(
+            &mut self,
+            id: u32,
+            mut escrow: Escrow,
+            outcome: EscrowState,
+        ) -> FlipResult<()> {
+            let recipient = match outcome {
+                EscrowState::Refunded => escrow.depositor,
+                _ => escrow.beneficiary,
+            };
+            self.credit(recipient, escrow.amount);
+            escrow.state = outcome;
+            self.escrows.insert(id, &escrow);
+            self.debug_check_invariants();
+            Ok(())
+        }
+    }
+
+    impl UnitTestBug {
+        /// Returns the fees the owner can claim right now.
+        ///
+        /// Fees unlock linearly at `vesting_rate` per block and can never exceed
+        /// what the fee pot actually holds.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, advance_blocks, deploy, pay_as, set_caller};
+        /// let mut contract = deploy(false);
+        /// pay_as(accounts().bob, 100);
+        /// contract.flip().unwrap();
+        /// set_caller(accounts().alice);
+        /// contract.set_vesting_rate(5).unwrap();
+        /// advance_blocks(2);
+        /// assert_eq!(contract.claimable(), 10);
+        /// ```
+        #[ink(message)]
+        pub fn claimable(&self) -> Balance {
+            self.vested()
+                .saturating_sub(self.fees_claimed)
+                .min(self.fee_pot)
+        }
+
+        /// Transfers all claimable fees to the owner and returns the amount.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, advance_blocks, deploy, pay_as, set_caller};
+        /// let mut contract = deploy(false);
+        /// pay_as(accounts().bob, 100);
+        /// contract.flip().unwrap();
+        /// set_caller(accounts().alice);
+        /// contract.set_vesting_rate(5).unwrap();
+        /// advance_blocks(2);
+        /// assert_eq!(contract.claim(), Ok(10));
+        /// assert_eq!(contract.fee_pot(), 90);
+        /// ```
+        #[ink(message)]
+        pub fn claim(&mut self) -> FlipResult<Balance> {
+            self.ensure_owner()?;
+            let amount = self.claimable();
+            if amount == 0 {
+                return Err(FlipError::NothingToClaim);
+            }
+            self.env()
+                .transfer(self.owner, amount)
+                .map_err(|_| FlipError::TransferFailed)?;
+            self.fee_pot -= amount;
+            let claimed = self.fees_claimed;
+            self.fees_claimed += amount;
+            self.audit(
+                ink::selector_bytes!("claim"),
+                Self::audit_hash(&claimed),
+                Self::audit_hash(&self.fees_claimed),
+            );
+            self.debug_check_invariants();
+            Ok(amount)
+        }
+
+        /// Returns the fees claimed by the owner so far.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, advance_blocks, deploy, pay_as, set_caller};
+        /// let mut contract = deploy(false);
+        /// pay_as(accounts().bob, 100);
+        /// contract.flip().unwrap();
+        /// set_caller(accounts().alice);
+        /// contract.set_vesting_rate(5).unwrap();
+        /// advance_blocks(2);
+        /// contract.claim().unwrap();
+        /// assert_eq!(contract.fees_claimed(), 10);
+        /// ```
+        #[ink(message)]
+        pub fn fees_claimed(&self) -> Balance {
+            self.fees_claimed
+        }
+
+        /// Returns the number of fees unlocked per block.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::deploy;
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.vesting_rate(), 0);
+        /// contract.set_vesting_rate(5).unwrap();
+        /// assert_eq!(contract.vesting_rate(), 5);
+        /// ```
+        #[ink(message)]
+        pub fn vesting_rate(&self) -> Balance {
+            self.vesting_rate
+        }
+
+        /// Changes the unlock rate from the current block on.
+        ///
+        /// Amounts already unlocked under the previous rate stay unlocked.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, set_caller}, FlipError};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.set_vesting_rate(5), Ok(()));
+        /// set_caller(accounts().bob);
+        /// assert_eq!(contract.set_vesting_rate(50), Err(FlipError::NotOwner));
+        /// ```
+        #[ink(message)]
+        pub fn set_vesting_rate(&mut self, rate: Balance) -> FlipResult<()> {
+            self.ensure_owner()?;
+            self.vested_before_start = self.vested();
+            self.vesting_start = self.env().block_number();
+            self.audit(
+                ink::selector_bytes!("set_vesting_rate"),
+                Self::audit_hash(&self.vesting_rate),
+                Self::audit_hash(&rate),
+            );
+            self.vesting_rate = rate;
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Total fees unlocked by the schedule up to the current block.
+        fn vested(&self) -> Balance {
+            let elapsed = self.env().block_number().saturating_sub(self.vesting_start);
+            self.vesting_rate
+                .saturating_mul(Balance::from(elapsed))
+                .saturating_add(self.vested_before_start)
+        }
+    }
+
+    impl UnitTestBug {
+        /// Configures the airdrop and adds the transferred value to its pool.
+        ///
+        /// Claimed indexes stay claimed when the root is replaced.
+        ///
+        /// ```
+        /// # use ink::primitives::Hash;
+        /// # use unit_test_bug::test_support::{deploy, set_value_transferred};
+        /// let mut contract = deploy(false);
+        /// set_value_transferred(100);
+        /// assert_eq!(contract.set_airdrop(Hash::from([1; 32]), 10), Ok(()));
+        /// assert_eq!(contract.airdrop_pool(), 100);
+        /// ```
+        #[ink(message, payable)]
+        pub fn set_airdrop(&mut self, root: Hash, amount: Balance) -> FlipResult<()> {
+            self.ensure_owner()?;
+            self.audit(
+                ink::selector_bytes!("set_airdrop"),
+                Self::audit_hash(&(self.airdrop_root, self.airdrop_amount)),
+                Self::audit_hash(&(Some(root), amount)),
+            );
+            self.airdrop_root = Some(root);
+            self.airdrop_amount = amount;
+            self.airdrop_pool += self.env().transferred_value();
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Pays the airdrop amount to the caller if `proof` shows they own slot `index`.
+        ///
+        /// Nothing is marked unless the payout succeeds, so a failed claim can be retried.
+        ///
+        /// ```
+        /// # use unit_test_bug::{airdrop_leaf, test_support::{accounts, deploy, set_caller, set_value_transferred}};
+        /// let mut contract = deploy(false);
+        /// let bob = accounts().bob;
+        /// // A tree with a single leaf has that leaf as its root and needs no proof.
+        /// set_value_transferred(100);
+        /// contract.set_airdrop(airdrop_leaf(0, bob), 10).unwrap();
+        /// set_caller(bob);
+        /// assert_eq!(contract.claim_airdrop(0, Vec::new()), Ok(()));
+        /// assert_eq!(contract.airdrop_pool(), 90);
+        /// ```
+        #[ink(message)]
+        pub fn claim_airdrop(&mut self, index: u32, proof: Vec<Hash>) -> FlipResult<()> {
+            self.consume_call_quota(self.env().caller())?;
+            let root = self.airdrop_root.ok_or(FlipError::AirdropExhausted)?;
+            if self.is_claimed(index) {
+                return Err(FlipError::AlreadyClaimed);
+            }
+            let caller = self.env().caller();
+            let computed = proof
+                .into_iter()
+                .fold(airdrop_leaf(index, caller), hash_pair);
+            if computed != root {
+                return Err(FlipError::InvalidProof);
+            }
+            let amount = self.airdrop_amount;
+            if self.airdrop_pool < amount {
+                return Err(FlipError::AirdropExhausted);
+            }
+            self.env()
+                .transfer(caller, amount)
+                .map_err(|_| FlipError::TransferFailed)?;
+            self.airdrop_pool -= amount;
+            let (word, bit) = (index / 128, index % 128);
+            let bits = self.claimed_bitmap.get(word).unwrap_or(0);
+            self.claimed_bitmap.insert(word, &(bits | (1 << bit)));
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Returns whether airdrop slot `index` has been claimed.
+        ///
+        /// ```
+        /// # use unit_test_bug::{airdrop_leaf, test_support::{accounts, deploy, set_caller, set_value_transferred}};
+        /// let mut contract = deploy(false);
+        /// let bob = accounts().bob;
+        /// set_value_transferred(100);
+        /// contract.set_airdrop(airdrop_leaf(0, bob), 10).unwrap();
+        /// assert!(!contract.is_claimed(0));
+        /// set_caller(bob);
+        /// contract.claim_airdrop(0, Vec::new()).unwrap();
+        /// assert!(contract.is_claimed(0));
+        /// ```
+        #[ink(message)]
+        pub fn is_claimed(&self, index: u32) -> bool {
+            let (word, bit) = (index / 128, index % 128);
+            self.claimed_bitmap.get(word).unwrap_or(0) & (1 << bit) != 0
+        }
+
+        /// Returns the value left for airdrop claims.
+        ///
+        /// ```
+        /// # use ink::primitives::Hash;
+        /// # use unit_test_bug::test_support::{deploy, set_value_transferred};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.airdrop_pool(), 0);
+        /// set_value_transferred(100);
+        /// contract.set_airdrop(Hash::from([1; 32]), 10).unwrap();
+        /// assert_eq!(contract.airdrop_pool(), 100);
+        /// ```
+        #[ink(message)]
+        pub fn airdrop_pool(&self) -> Balance {
+            self.airdrop_pool
+        }
+    }
+
+    impl UnitTestBug {
+        /// Draws the winner of a finished epoch's lottery and credits them their prize.
+        ///
+        /// Anyone may trigger the draw. The winner is picked uniformly among the
+        /// epoch's tickets using the randomness chain extension and is credited
+        /// `LOTTERY_PRIZE_PERCENT` of the fee pot. Needs `FEATURE_LOTTERY`.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, FlipError};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.draw(0), Err(FlipError::LotteryNotFinished));
+        /// ```
+        #[ink(message)]
+        pub fn draw(&mut self, epoch: u32) -> FlipResult<(AccountId, Balance)> {
+            self.config.ensure_feature(FEATURE_LOTTERY)?;
+            if epoch >= self.current_epoch() {
+                return Err(FlipError::LotteryNotFinished);
+            }
+            if self.lottery_winners.contains(epoch) {
+                return Err(FlipError::AlreadyDrawn);
+            }
+            let tickets = self.tickets(epoch);
+            if tickets == 0 {
+                return Err(FlipError::NoTickets);
+            }
+            let mut subject = [0u8; 32];
+            ink::env::hash_encoded::<Blake2x256, _>(&(b"lottery", epoch), &mut subject);
+            let random = Self::random(subject).map_err(|_| FlipError::RandomnessUnavailable)?;
+            let roll = u32::from_le_bytes([random[0], random[1], random[2], random[3]]);
+            let winner = self
+                .lottery_tickets
+                .get((epoch, roll % tickets))
+                .expect("tickets below the epoch count are always present");
+            let prize = self.fee_pot * LOTTERY_PRIZE_PERCENT / 100;
+            self.fee_pot -= prize;
+            self.credit(winner, prize);
+            self.lottery_winners.insert(epoch, &winner);
+            self.debug_check_invariants();
+            Ok((winner, prize))
+        }
+
+        /// Randomness for `subject` from the chain, or off-chain from the
+        /// source `test_support::set_randomness` installed, if any.
+        fn random(subject: [u8; 32]) -> Result<[u8; 32], RandomReadErr> {
+            #[cfg(feature = "std")]
+            if let Some(random) = crate::test_support::installed_random(subject) {
+                return random;
+            }
+            ChainRandomness.random(subject)
+        }
+
+        /// Returns the number of lottery tickets issued in `epoch`.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::deploy;
+        /// let mut contract = deploy(false);
+        /// contract.flip().unwrap();
+        /// assert_eq!(contract.tickets(0), 1);
+        /// ```
+        #[ink(message)]
+        pub fn tickets(&self, epoch: u32) -> u32 {
+            self.ticket_counts.get(epoch).unwrap_or(0)
+        }
+
+        /// Returns the drawn winner of `epoch`, if any.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::deploy;
+        /// let mut contract = deploy(false);
+        /// contract.flip().unwrap();
+        /// assert_eq!(contract.lottery_winner(0), None);
+        /// ```
+        #[ink(message)]
+        pub fn lottery_winner(&self, epoch: u32) -> Option<AccountId> {
+            self.lottery_winners.get(epoch)
+        }
+
+        fn issue_ticket(&mut self, holder: AccountId) {
+            let epoch = self.current_epoch();
+            let count = self.tickets(epoch);
+            self.lottery_tickets.insert((epoch, count), &holder);
+            self.ticket_counts.insert(epoch, &(count + 1));
+        }
+    }
+
+    impl UnitTestBug {
+        /// Returns the top flippers of the current epoch, best first.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, set_caller}, LeaderboardEntry};
+        /// let mut contract = deploy(false);
+        /// let bob = accounts().bob;
+        /// set_caller(bob);
+        /// contract.flip().unwrap();
+        /// assert_eq!(contract.leaderboard(), vec![LeaderboardEntry { account: bob, flips: 1 }]);
+        /// ```
+        #[ink(message)]
+        pub fn leaderboard(&self) -> Vec<LeaderboardEntry> {
+            if self.epoch_stats.epoch != self.current_epoch() {
+                return Vec::new();
+            }
+            self.leaderboard.clone()
+        }
+
+        /// Returns the number of successful flips by `account` over all epochs.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy};
+        /// let mut contract = deploy(false);
+        /// contract.flip().unwrap();
+        /// contract.flip().unwrap();
+        /// assert_eq!(contract.flip_count(accounts().alice), 2);
+        /// ```
+        #[ink(message)]
+        pub fn flip_count(&self, account: AccountId) -> u64 {
+            self.flip_counts.get(account).unwrap_or(0)
+        }
+
+        /// Returns the number of successful flips by `account` in `epoch`.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, advance_blocks, deploy}, DEFAULT_EPOCH_LENGTH};
+        /// let mut contract = deploy(false);
+        /// let alice = accounts().alice;
+        /// contract.flip().unwrap();
+        /// advance_blocks(DEFAULT_EPOCH_LENGTH);
+        /// contract.flip().unwrap();
+        /// assert_eq!(contract.epoch_flip_count(0, alice), 1);
+        /// assert_eq!(contract.flip_count(alice), 2);
+        /// ```
+        #[ink(message)]
+        pub fn epoch_flip_count(&self, epoch: u32, account: AccountId) -> u64 {
+            self.epoch_flip_counts.get((epoch, account)).unwrap_or(0)
+        }
+
+        /// Returns the flip statistics of the current epoch.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, set_caller}, EpochStats};
+        /// let mut contract = deploy(false);
+        /// contract.flip().unwrap();
+        /// set_caller(accounts().bob);
+        /// contract.flip().unwrap();
+        /// assert_eq!(contract.epoch_stats(), EpochStats { epoch: 0, flips: 2, flippers: 2 });
+        /// ```
+        #[ink(message)]
+        pub fn epoch_stats(&self) -> EpochStats {
+            let epoch = self.current_epoch();
+            if self.epoch_stats.epoch != epoch {
+                return EpochStats {
+                    epoch,
+                    ..EpochStats::default()
+                };
+            }
+            self.epoch_stats
+        }
+
+        /// Counts a successful flip and moves the account up the leaderboard.
+        fn record_flip(&mut self, account: AccountId) {
+            self.flip_counts
+                .insert(account, &(self.flip_count(account) + 1));
+            self.register_participant(account);
+
+            self.roll_epoch();
+            let epoch = self.epoch_stats.epoch;
+            let flips = self.epoch_flip_count(epoch, account) + 1;
+            self.epoch_flip_counts.insert((epoch, account), &flips);
+            self.epoch_stats.flips += 1;
+            if flips == 1 {
+                self.epoch_stats.flippers += 1;
+            }
+
+            let entry = LeaderboardEntry { account, flips };
+            let mut rank = match self.leaderboard.iter().position(|e| e.account == account) {
+                Some(rank) => rank,
+                None if self.leaderboard.len() < LEADERBOARD_SIZE => {
+                    self.leaderboard.push(entry);
+                    self.leaderboard.len() - 1
+                }
+                None => {
+                    let last = LEADERBOARD_SIZE - 1;
+                    if self.leaderboard[last].flips >= flips {
+                        return;
+                    }
+                    last
+                }
+            };
+            self.leaderboard[rank] = entry;
+            // Only overtake strictly lower counts so earlier entrants win ties.
+            while rank > 0 && self.leaderboard[rank - 1].flips < flips {
+                self.leaderboard.swap(rank - 1, rank);
+                rank -= 1;
+            }
+        }
+
+        /// Resets the epoch statistics and leaderboard once a new epoch has begun.
+        ///
+        /// Lottery tickets are kept per epoch and need no reset.
+        fn roll_epoch(&mut self) {
+            let epoch = self.current_epoch();
+            if self.epoch_stats.epoch == epoch {
+                return;
+            }
+            let seq = self.next_seq();
+            self.env().emit_event(EpochRolledOver {
+                from: self.epoch_stats.epoch,
+                to: epoch,
+                seq,
+            });
+            self.epoch_stats = EpochStats {
+                epoch,
+                ..EpochStats::default()
+            };
+            self.leaderboard.clear();
+        }
+    }
+
+    impl UnitTestBug {
+        /// Sets the contract allowed to relay calls, `None` disables relaying.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, set_caller}, FlipError};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.set_trusted_forwarder(Some(accounts().charlie)), Ok(()));
+        /// set_caller(accounts().bob);
+        /// assert_eq!(contract.set_trusted_forwarder(None), Err(FlipError::NotOwner));
+        /// ```
+        #[ink(message)]
+        pub fn set_trusted_forwarder(
+            &mut self,
+            forwarder: Option<AccountId>,
+        ) -> FlipResult<()> {
+            self.ensure_owner()?;
+            self.audit(
+                ink::selector_bytes!("set_trusted_forwarder"),
+                Self::audit_hash(&self.trusted_forwarder),
+                Self::audit_hash(&forwarder),
+            );
+            self.trusted_forwarder = forwarder;
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Returns the contract allowed to relay calls.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.trusted_forwarder(), None);
+        /// contract.set_trusted_forwarder(Some(accounts().charlie)).unwrap();
+        /// assert_eq!(contract.trusted_forwarder(), Some(accounts().charlie));
+        /// ```
+        #[ink(message)]
+        pub fn trusted_forwarder(&self) -> Option<AccountId> {
+            self.trusted_forwarder
+        }
+
+        /// Executes `call` with `from` as the effective caller.
+        ///
+        /// Only the trusted forwarder may call this; it is responsible for having
+        /// authenticated `from`, and any value it transfers is treated as paid by `from`.
+        /// This lets relayers pay for gas on behalf of users.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, set_caller}, ForwardedCall};
+        /// let mut contract = deploy(false);
+        /// let accounts = accounts();
+        /// contract.set_trusted_forwarder(Some(accounts.charlie)).unwrap();
+        /// set_caller(accounts.charlie);
+        /// assert!(contract.execute_forwarded(accounts.bob, ForwardedCall::Flip).is_ok());
+        /// assert_eq!(contract.flip_count(accounts.bob), 1);
+        /// ```
+        #[ink(message, payable)]
+        pub fn execute_forwarded(
+            &mut self,
+            from: AccountId,
+            call: ForwardedCall,
+        ) -> FlipResult<MutationReceipt> {
+            trace_call!(self, "execute_forwarded", (from, call));
+            let gas = self.gas_left();
+            if self.trusted_forwarder != Some(self.env().caller()) {
+                return Err(FlipError::NotTrustedForwarder);
+            }
+            let seq = self.dispatch(from, call)?;
+            Ok(self.receipt(seq, gas))
+        }
+
+        /// Executes `call` for the caller, once per `idempotency_key`, so that a
+        /// wallet can resubmit a call it does not know went through.
+        ///
+        /// A key the caller already used returns the receipt of its first call
+        /// instead of executing `call` again, and credits any value transferred
+        /// with the retry for `withdraw_credit`. Keys of failed calls stay unused.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, ForwardedCall};
+        /// let mut contract = deploy(false);
+        /// let first = contract.execute_idempotent(ForwardedCall::Flip, Some([7; 16]));
+        /// let retry = contract.execute_idempotent(ForwardedCall::Flip, Some([7; 16]));
+        /// assert_eq!(retry, first);
+        /// assert!(contract.get());
+        /// ```
+        #[ink(message, payable)]
+        pub fn execute_idempotent(
+            &mut self,
+            call: ForwardedCall,
+            idempotency_key: Option<[u8; 16]>,
+        ) -> FlipResult<MutationReceipt> {
+            trace_call!(self, "execute_idempotent", (call, idempotency_key));
+            let gas = self.gas_left();
+            let caller = self.env().caller();
+            let Some(key) = idempotency_key else {
+                let seq = self.dispatch(caller, call)?;
+                return Ok(self.receipt(seq, gas));
+            };
+            if let Some(receipt) = self.idempotent_receipts.get((caller, key)) {
+                self.credit(caller, self.env().transferred_value());
+                self.debug_check_invariants();
+                return Ok(receipt);
+            }
+            let seq = self.dispatch(caller, call)?;
+            let receipt = self.receipt(seq, gas);
+            self.idempotent_receipts.insert((caller, key), &receipt);
+            Ok(receipt)
+        }
+
+        /// Returns the receipt `execute_idempotent` stored for `account` under
+        /// `idempotency_key`, `None` if the key is unused.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy}, ForwardedCall};
+        /// let mut contract = deploy(false);
+        /// let alice = accounts().alice;
+        /// assert_eq!(contract.idempotent_receipt(alice, [7; 16]), None);
+        /// let receipt = contract.execute_idempotent(ForwardedCall::Flip, Some([7; 16]));
+        /// assert_eq!(contract.idempotent_receipt(alice, [7; 16]), receipt.ok());
+        /// ```
+        #[ink(message)]
+        pub fn idempotent_receipt(
+            &self,
+            account: AccountId,
+            idempotency_key: [u8; 16],
+        ) -> Option<MutationReceipt> {
+            self.idempotent_receipts.get((account, idempotency_key))
+        }
+
+        /// Executes a relayed `call` with `from` as the effective caller.
+        fn dispatch(&mut self, from: AccountId, call: ForwardedCall) -> FlipResult<u64> {
+            match call {
+                ForwardedCall::Flip => self.flip_as(from),
+            }
+        }
+    }
+
+    impl UnitTestBug {
+        /// Executes `call` for `signer`, authorised by their ECDSA signature over
+        /// [`Self::permit_hash`] instead of by the caller.
+        ///
+        /// Anyone may submit the permit. Each permit is valid once, for this
+        /// contract on this chain only.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy}, FlipError, ForwardedCall};
+        /// let mut contract = deploy(false);
+        /// let result = contract.execute_with_permit(accounts().bob, ForwardedCall::Flip, [0; 65]);
+        /// assert_eq!(result, Err(FlipError::InvalidSignature));
+        /// ```
+        #[ink(message, payable)]
+        pub fn execute_with_permit(
+            &mut self,
+            signer: AccountId,
+            call: ForwardedCall,
+            signature: [u8; 65],
+        ) -> FlipResult<MutationReceipt> {
+            trace_call!(self, "execute_with_permit", (signer, call, signature));
+            let gas = self.gas_left();
+            let nonce = self.permit_nonce(signer);
+            let hash = self.permit_hash(signer, call, nonce);
+            let public_key = self
+                .env()
+                .ecdsa_recover(&signature, &hash)
+                .map_err(|_| FlipError::InvalidSignature)?;
+            let mut recovered = [0u8; 32];
+            ink::env::hash_bytes::<Blake2x256>(&public_key, &mut recovered);
+            if AccountId::from(recovered) != signer {
+                return Err(FlipError::InvalidSignature);
+            }
+            let seq = self.dispatch(signer, call)?;
+            self.permit_nonces.insert(signer, &(nonce + 1));
+            self.debug_check_invariants();
+            Ok(self.receipt(seq, gas))
+        }
+
+        /// Returns the hash `signer` must sign to permit `call` with `nonce`.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy}, ForwardedCall};
+        /// let contract = deploy(false);
+        /// let bob = accounts().bob;
+        /// assert_ne!(
+        ///     contract.permit_hash(bob, ForwardedCall::Flip, 0),
+        ///     contract.permit_hash(bob, ForwardedCall::Flip, 1)
+        /// );
+        /// ```
+        #[ink(message)]
+        pub fn permit_hash(&self, signer: AccountId, call: ForwardedCall, nonce: u64) -> [u8; 32] {
+            let domain = SigningDomain {
+                nonce,
+                ..self.signing_domain(signer)
+            };
+            let mut hash = [0u8; 32];
+            ink::env::hash_encoded::<Blake2x256, _>(
+                &(b"unit_test_bug:permit", domain, signer, call),
+                &mut hash,
+            );
+            hash
+        }
+
+        /// Returns the domain the next permit of `signer` must be signed for.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy}, SIGNING_DOMAIN_VERSION};
+        /// let contract = deploy(false);
+        /// let domain = contract.signing_domain(accounts().bob);
+        /// assert_eq!(domain.version, SIGNING_DOMAIN_VERSION);
+        /// assert_eq!(domain.nonce, 0);
+        /// ```
+        #[ink(message)]
+        pub fn signing_domain(&self, signer: AccountId) -> SigningDomain {
+            SigningDomain {
+                contract: self.env().account_id(),
+                chain_id: self.chain_id,
+                version: SIGNING_DOMAIN_VERSION,
+                nonce: self.permit_nonce(signer),
+            }
+        }
+
+        /// Returns the next unused permit nonce of `signer`.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy};
+        /// let contract = deploy(false);
+        /// assert_eq!(contract.permit_nonce(accounts().bob), 0);
+        /// ```
+        #[ink(message)]
+        pub fn permit_nonce(&self, signer: AccountId) -> u64 {
+            self.permit_nonces.get(signer).unwrap_or(0)
+        }
+
+        /// Sets the genesis hash committed to in permits.
+        ///
+        /// ```
+        /// # use ink::primitives::Hash;
+        /// # use unit_test_bug::test_support::{accounts, deploy};
+        /// let mut contract = deploy(false);
+        /// let chain_id = Hash::from([7; 32]);
+        /// assert_eq!(contract.set_chain_id(chain_id), Ok(()));
+        /// assert_eq!(contract.signing_domain(accounts().bob).chain_id, chain_id);
+        /// ```
+        #[ink(message)]
+        pub fn set_chain_id(&mut self, chain_id: Hash) -> FlipResult<()> {
+            self.ensure_owner()?;
+            self.audit(
+                ink::selector_bytes!("set_chain_id"),
+                Self::audit_hash(&self.chain_id),
+                Self::audit_hash(&chain_id),
+            );
+            self.chain_id = chain_id;
+            self.debug_check_invariants();
+            Ok(())
+        }
+    }
+
+    impl UnitTestBug {
+        /// Lets `key` act for the caller with `permissions` until `expires_at`.
+        ///
+        /// Authorising an existing key replaces its permissions and expiry.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, set_caller}, SessionKey, SESSION_FLIP};
+        /// let mut contract = deploy(false);
+        /// let accounts = accounts();
+        /// set_caller(accounts.bob);
+        /// assert_eq!(contract.authorize_session_key(accounts.charlie, SESSION_FLIP, 10), Ok(()));
+        /// assert_eq!(
+        ///     contract.session_key(accounts.bob, accounts.charlie),
+        ///     Some(SessionKey { permissions: SESSION_FLIP, expires_at: 10 })
+        /// );
+        /// ```
+        #[ink(message)]
+        pub fn authorize_session_key(
+            &mut self,
+            key: AccountId,
+            permissions: u32,
+            expires_at: BlockNumber,
+        ) -> FlipResult<()> {
+            if expires_at <= self.env().block_number() {
+                return Err(FlipError::SessionExpired);
+            }
+            let session = SessionKey {
+                permissions,
+                expires_at,
+            };
+            self.session_keys
+                .insert((self.env().caller(), key), &session);
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Removes `key` from the caller's session keys.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy}, FlipError, SESSION_FLIP};
+        /// let mut contract = deploy(false);
+        /// let charlie = accounts().charlie;
+        /// contract.authorize_session_key(charlie, SESSION_FLIP, 10).unwrap();
+        /// assert_eq!(contract.revoke_session_key(charlie), Ok(()));
+        /// assert_eq!(contract.revoke_session_key(charlie), Err(FlipError::SessionKeyNotFound));
+        /// ```
+        #[ink(message)]
+        pub fn revoke_session_key(&mut self, key: AccountId) -> FlipResult<()> {
+            let entry = (self.env().caller(), key);
+            if !self.session_keys.contains(entry) {
+                return Err(FlipError::SessionKeyNotFound);
+            }
+            self.session_keys.remove(entry);
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Returns the session key `key` of `account`, including expired ones.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy}, SESSION_FLIP};
+        /// let mut contract = deploy(false);
+        /// let accounts = accounts();
+        /// assert_eq!(contract.session_key(accounts.alice, accounts.charlie), None);
+        /// contract.authorize_session_key(accounts.charlie, SESSION_FLIP, 10).unwrap();
+        /// assert!(contract.session_key(accounts.alice, accounts.charlie).is_some());
+        /// ```
+        #[ink(message)]
+        pub fn session_key(&self, account: AccountId, key: AccountId) -> Option<SessionKey> {
+            self.session_keys.get((account, key))
+        }
+
+        /// Executes `call` for `account`, called by one of its session keys.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, set_caller}, ForwardedCall, SESSION_FLIP};
+        /// let mut contract = deploy(false);
+        /// let accounts = accounts();
+        /// set_caller(accounts.bob);
+        /// contract.authorize_session_key(accounts.charlie, SESSION_FLIP, 10).unwrap();
+        /// set_caller(accounts.charlie);
+        /// assert!(contract.execute_as_session(accounts.bob, ForwardedCall::Flip).is_ok());
+        /// assert_eq!(contract.flip_count(accounts.bob), 1);
+        /// ```
+        #[ink(message, payable)]
+        pub fn execute_as_session(
+            &mut self,
+            account: AccountId,
+            call: ForwardedCall,
+        ) -> FlipResult<MutationReceipt> {
+            trace_call!(self, "execute_as_session", (account, call));
+            let gas = self.gas_left();
+            let session = self
+                .session_keys
+                .get((account, self.env().caller()))
+                .ok_or(FlipError::SessionKeyNotFound)?;
+            if session.expires_at <= self.env().block_number() {
+                return Err(FlipError::SessionExpired);
+            }
+            if session.permissions & call.permission() == 0 {
+                return Err(FlipError::OutOfScope);
+            }
+            let seq = self.dispatch(account, call)?;
+            Ok(self.receipt(seq, gas))
+        }
+    }
+
+    impl UnitTestBug {
+        /// Returns the settings of `account`, or the defaults if it never set any.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy}, Settings};
+        /// let contract = deploy(false);
+        /// assert_eq!(contract.get_settings(accounts().bob), Settings::default());
+        /// ```
+        #[ink(message)]
+        pub fn get_settings(&self, account: AccountId) -> Settings {
+            self.settings.get(account).unwrap_or_default()
+        }
+
+        /// Applies `patch` to the caller's settings and returns the result.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy}, SettingsPatch};
+        /// let mut contract = deploy(false);
+        /// let patch = SettingsPatch { fee_tier: Some(2), ..SettingsPatch::default() };
+        /// assert_eq!(contract.update_settings(patch).fee_tier, 2);
+        /// assert_eq!(contract.get_settings(accounts().alice).fee_tier, 2);
+        /// ```
+        #[ink(message)]
+        pub fn update_settings(&mut self, patch: SettingsPatch) -> Settings {
+            let caller = self.env().caller();
+            let settings = self.get_settings(caller).patched(patch);
+            self.settings.insert(caller, &settings);
+            self.debug_check_invariants();
+            settings
+        }
+    }
+
+    impl UnitTestBug {
+        /// Returns the active configuration.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, Config};
+        /// let contract = deploy(false);
+        /// assert_eq!(contract.config(), Config::default());
+        /// ```
+        #[ink(message)]
+        pub fn config(&self) -> Config {
+            self.config
+        }
+
+        /// Returns the queued configuration change, if any.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, Config};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.pending_config(), None);
+        /// contract.propose_config(Config { flip_fee: 5, ..Config::default() }).unwrap();
+        /// assert_eq!(contract.pending_config().map(|pending| pending.config.flip_fee), Some(5));
+        /// ```
+        #[ink(message)]
+        pub fn pending_config(&self) -> Option<PendingConfig> {
+            self.pending_config
+        }
+
+        /// Queues `config` to replace the active configuration after the timelock.
+        ///
+        /// Replaces any change already queued. Returns the first block in which
+        /// the change can be applied.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, Config};
+        /// let mut contract = deploy(false);
+        /// let eta = contract.propose_config(Config { flip_fee: 5, ..Config::default() });
+        /// assert_eq!(eta, Ok(Config::default().timelock_delay));
+        /// ```
+        #[ink(message)]
+        pub fn propose_config(&mut self, config: Config) -> FlipResult<BlockNumber> {
+            self.ensure_owner()?;
+            config.validate()?;
+            let eta = self
+                .env()
+                .block_number()
+                .saturating_add(self.config.timelock_delay);
+            let pending = Some(PendingConfig { config, eta });
+            self.audit(
+                ink::selector_bytes!("propose_config"),
+                Self::audit_hash(&self.pending_config),
+                Self::audit_hash(&pending),
+            );
+            self.pending_config = pending;
+            self.debug_check_invariants();
+            Ok(eta)
+        }
+
+        /// Applies the queued configuration once its timelock has passed.
+        ///
+        /// Anyone may apply a change that is due.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{advance_blocks, deploy}, Config, FlipError};
+        /// let mut contract = deploy(false);
+        /// let eta = contract.propose_config(Config { flip_fee: 5, ..Config::default() }).unwrap();
+        /// assert_eq!(contract.apply_config(), Err(FlipError::TimelockActive));
+        /// advance_blocks(eta);
+        /// assert_eq!(contract.apply_config(), Ok(()));
+        /// assert_eq!(contract.flip_fee(), 5);
+        /// ```
+        #[ink(message)]
+        pub fn apply_config(&mut self) -> FlipResult<()> {
+            let pending = self.pending_config.ok_or(FlipError::NoPendingConfig)?;
+            if self.env().block_number() < pending.eta {
+                return Err(FlipError::TimelockActive);
+            }
+            pending.config.validate()?;
+            let old = self.config;
+            self.audit(
+                ink::selector_bytes!("apply_config"),
+                Self::audit_hash(&old),
+                Self::audit_hash(&pending.config),
+            );
+            self.config = pending.config;
+            self.pending_config = None;
+            let seq = self.next_seq();
+            self.env().emit_event(ConfigChanged {
+                old,
+                new: pending.config,
+                seq,
+            });
+            // A lowered `max_history` takes effect at once.
+            self.evict_history();
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Drops the queued configuration change.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, Config, FlipError};
+        /// let mut contract = deploy(false);
+        /// contract.propose_config(Config { flip_fee: 5, ..Config::default() }).unwrap();
+        /// assert_eq!(contract.cancel_config(), Ok(()));
+        /// assert_eq!(contract.cancel_config(), Err(FlipError::NoPendingConfig));
+        /// ```
+        #[ink(message)]
+        pub fn cancel_config(&mut self) -> FlipResult<()> {
+            self.ensure_owner()?;
+            let pending = self
+                .pending_config
+                .take()
+                .ok_or(FlipError::NoPendingConfig)?;
+            self.audit(
+                ink::selector_bytes!("cancel_config"),
+                Self::audit_hash(&Some(pending)),
+                Self::audit_hash(&None::<PendingConfig>),
+            );
+            self.debug_check_invariants();
+            Ok(())
+        }
+    }
+
+    impl UnitTestBug {
+        /// Credits the transferred value to the caller's wrapped balance.
+        ///
+        /// Returns the caller's new wrapped balance.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy, pay_as};
+        /// let mut contract = deploy(false);
+        /// pay_as(accounts().bob, 100);
+        /// assert_eq!(contract.wrap(), 100);
+        /// pay_as(accounts().bob, 50);
+        /// assert_eq!(contract.wrap(), 150);
+        /// ```
+        #[ink(message, payable)]
+        pub fn wrap(&mut self) -> Balance {
+            let caller = self.env().caller();
+            let amount = self.env().transferred_value();
+            let balance = self.wrapped_balance_of(caller) + amount;
+            self.register_participant(caller);
+            self.wrapped.insert(caller, &balance);
+            self.write_stake_checkpoint(caller, balance);
+            self.total_wrapped += amount;
+            self.debug_check_invariants();
+            balance
+        }
+
+        /// Burns `amount` of the caller's wrapped balance and returns it as native value.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, pay_as}, FlipError};
+        /// let mut contract = deploy(false);
+        /// let bob = accounts().bob;
+        /// pay_as(bob, 100);
+        /// contract.wrap();
+        /// assert_eq!(contract.unwrap(40), Ok(()));
+        /// assert_eq!(contract.wrapped_balance_of(bob), 60);
+        /// assert_eq!(contract.unwrap(100), Err(FlipError::InsufficientWrapped));
+        /// ```
+        #[ink(message)]
+        pub fn unwrap(&mut self, amount: Balance) -> FlipResult<()> {
+            let caller = self.env().caller();
+            self.consume_call_quota(caller)?;
+            let balance = self.wrapped_balance_of(caller);
+            if balance < amount {
+                return Err(FlipError::InsufficientWrapped);
+            }
+            self.env()
+                .transfer(caller, amount)
+                .map_err(|_| FlipError::TransferFailed)?;
+            self.debit_wrapped(caller, balance - amount, amount);
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Flips, paying the fee out of the caller's wrapped balance.
+        ///
+        /// Subscribers pay nothing, as with `flip`.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy_with_config, pay_as}, Config};
+        /// let mut contract = deploy_with_config(false, Config { flip_fee: 10, ..Config::default() });
+        /// let bob = accounts().bob;
+        /// pay_as(bob, 100);
+        /// contract.wrap();
+        /// assert!(contract.flip_with_wrapped().is_ok());
+        /// assert_eq!(contract.wrapped_balance_of(bob), 90);
+        /// ```
+        #[ink(message)]
+        pub fn flip_with_wrapped(&mut self) -> FlipResult<MutationReceipt> {
+            trace_call!(self, "flip_with_wrapped", ());
+            let gas = self.gas_left();
+            let caller = self.env().caller();
+            let fee = if self.is_subscribed(caller) {
+                0
+            } else {
+                self.flip_fee_for(caller)?
+            };
+            let balance = self.wrapped_balance_of(caller);
+            if balance < fee {
+                return Err(FlipError::InsufficientWrapped);
+            }
+            let seq = self.flip_paying(caller, fee)?;
+            self.debit_wrapped(caller, balance - fee, fee);
+            self.debug_check_invariants();
+            Ok(self.receipt(seq, gas))
+        }
+
+        /// Returns the wrapped balance of `account`.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy, pay_as};
+        /// let mut contract = deploy(false);
+        /// let bob = accounts().bob;
+        /// assert_eq!(contract.wrapped_balance_of(bob), 0);
+        /// pay_as(bob, 100);
+        /// contract.wrap();
+        /// assert_eq!(contract.wrapped_balance_of(bob), 100);
+        /// ```
+        #[ink(message)]
+        pub fn wrapped_balance_of(&self, account: AccountId) -> Balance {
+            self.wrapped.get(account).unwrap_or(0)
+        }
+
+        /// Returns the sum of every account's wrapped balance.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy, pay_as};
+        /// let mut contract = deploy(false);
+        /// pay_as(accounts().bob, 100);
+        /// contract.wrap();
+        /// pay_as(accounts().charlie, 50);
+        /// contract.wrap();
+        /// assert_eq!(contract.total_wrapped(), 150);
+        /// ```
+        #[ink(message)]
+        pub fn total_wrapped(&self) -> Balance {
+            self.total_wrapped
+        }
+
+        fn debit_wrapped(&mut self, account: AccountId, remaining: Balance, amount: Balance) {
+            if remaining == 0 {
+                self.wrapped.remove(account);
+            } else {
+                self.wrapped.insert(account, &remaining);
+            }
+            self.write_stake_checkpoint(account, remaining);
+            self.total_wrapped -= amount;
+        }
+
+        /// Returns the wrapped balance of `account` as it stood at the end of `block`.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, advance_blocks, deploy, pay_as};
+        /// let mut contract = deploy(false);
+        /// let bob = accounts().bob;
+        /// pay_as(bob, 100);
+        /// contract.wrap();
+        /// advance_blocks(1);
+        /// contract.unwrap(40).unwrap();
+        /// assert_eq!(contract.wrapped_balance_at(bob, 0), 100);
+        /// assert_eq!(contract.wrapped_balance_at(bob, 1), 60);
+        /// ```
+        #[ink(message)]
+        pub fn wrapped_balance_at(&self, account: AccountId, block: BlockNumber) -> Balance {
+            // Find the first checkpoint written after `block`; the one before it holds the answer.
+            let (mut low, mut high) = (0, self.stake_checkpoint_count(account));
+            while low < high {
+                let mid = low + (high - low) / 2;
+                if self.stake_checkpoint(account, mid).block <= block {
+                    low = mid + 1;
+                } else {
+                    high = mid;
+                }
+            }
+            low.checked_sub(1)
+                .map_or(0, |index| self.stake_checkpoint(account, index).balance)
+        }
+
+        fn stake_checkpoint_count(&self, account: AccountId) -> u32 {
+            self.stake_checkpoint_counts.get(account).unwrap_or(0)
+        }
+
+        fn stake_checkpoint(&self, account: AccountId, index: u32) -> StakeCheckpoint {
+            self.stake_checkpoints
+                .get((account, index))
+                .expect("stake checkpoints below the count are always present")
+        }
+
+        /// Records `balance` for `account`, overwriting its last checkpoint if it is from this block.
+        fn write_stake_checkpoint(&mut self, account: AccountId, balance: Balance) {
+            let block = self.env().block_number();
+            let count = self.stake_checkpoint_count(account);
+            let index = match count.checked_sub(1) {
+                Some(last) if self.stake_checkpoint(account, last).block == block => last,
+                _ => {
+                    self.stake_checkpoint_counts.insert(account, &(count + 1));
+                    count
+                }
+            };
+            self.stake_checkpoints
+                .insert((account, index), &StakeCheckpoint { block, balance });
+        }
+    }
+
+    impl UnitTestBug {
+        /// Checks the internal consistency of the storage and reports every broken rule.
+        ///
+        /// Walks every escrow, checkpoint and participant, so the cost grows with
+        /// the history and the number of accounts.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::deploy;
+        /// let mut contract = deploy(false);
+        /// contract.flip().unwrap();
+        /// assert!(contract.verify_invariants().is_ok());
+        /// ```
+        #[ink(message)]
+        pub fn verify_invariants(&self) -> InvariantReport {
+            let mut violations = Vec::new();
+
+            let balance = self.env().balance();
+            let reserved = self.reserved_balance();
+            if reserved > balance {
+                violations.push(Violation::ReservesExceedBalance { reserved, balance });
+            }
+
+            let count = self.checkpoint_count();
+            let mut previous: Option<Checkpoint> = None;
+            for index in self.first_checkpoint..count {
+                let Some(checkpoint) = self.checkpoints.get(index) else {
+                    violations.push(Violation::MissingCheckpoint(index));
+                    continue;
+                };
+                if previous.is_some_and(|previous| previous.block >= checkpoint.block) {
+                    violations.push(Violation::UnorderedCheckpoints(index));
+                }
+                previous = Some(checkpoint);
+            }
+            if self.checkpoints.contains(count) {
+                violations.push(Violation::ExtraCheckpoint(count));
+            }
+            if previous.map(|latest| latest.value) != Some(self.stored_value()) {
+                violations.push(Violation::StaleCheckpoint);
+            }
+            let kept = count - self.first_checkpoint;
+            let max_history = self.config.max_history;
+            if max_history != 0 && kept > max_history {
+                violations.push(Violation::HistoryTooLong { kept, max_history });
+            }
+
+            if self.leaderboard.len() > LEADERBOARD_SIZE {
+                violations.push(Violation::LeaderboardTooLong);
+            }
+            if self
+                .leaderboard
+                .windows(2)
+                .any(|pair| pair[0].flips < pair[1].flips)
+            {
+                violations.push(Violation::LeaderboardUnsorted);
+            }
+            for entry in &self.leaderboard {
+                if entry.flips != self.epoch_flip_count(self.epoch_stats.epoch, entry.account) {
+                    violations.push(Violation::LeaderboardMismatch(entry.account));
+                }
+            }
+
+            if self.fees_claimed > self.vested() {
+                violations.push(Violation::ClaimedExceedsVested);
+            }
+
+            // Every account holding wrapped value or credit, or flipping in the
+            // current epoch, is a participant.
+            let stats = self.epoch_stats();
+            let (mut wrapped, mut credits, mut flips, mut flippers) = (0, 0, 0, 0);
+            for index in 0..self.participant_count {
+                let account = self.participant(index);
+                wrapped += self.wrapped_balance_of(account);
+                credits += self.credit_of(account);
+                let account_flips = self.epoch_flip_count(stats.epoch, account);
+                flips += account_flips;
+                flippers += u32::from(account_flips > 0);
+            }
+            if wrapped != self.total_wrapped {
+                violations.push(Violation::WrappedTotalMismatch {
+                    total: self.total_wrapped,
+                    sum: wrapped,
+                });
+            }
+            if credits != self.total_credits {
+                violations.push(Violation::CreditTotalMismatch {
+                    total: self.total_credits,
+                    sum: credits,
+                });
+            }
+            if stats.flips != flips {
+                violations.push(Violation::EpochFlipsMismatch {
+                    recorded: stats.flips,
+                    sum: flips,
+                });
+            }
+            if stats.flippers != flippers {
+                violations.push(Violation::EpochFlippersMismatch {
+                    recorded: stats.flippers,
+                    count: flippers,
+                });
+            }
+
+            InvariantReport { violations }
+        }
+
+        /// Returns the value the contract holds on behalf of users and the owner.
+        fn reserved_balance(&self) -> Balance {
+            let escrowed: Balance = (0..self.next_escrow_id)
+                .filter_map(|id| self.escrows.get(id))
+                .filter(|escrow| matches!(escrow.state, EscrowState::Open | EscrowState::Disputed))
+                .map(|escrow| escrow.amount)
+                .sum();
+            let bid = self.highest_bid.map_or(0, |bid| bid.amount);
+            let queued: Balance = self
+                .flip_queue
+                .iter()
+                .map(|queued| queued.paid + KEEPER_REWARD)
+                .sum();
+            self.fee_pot
+                + self.airdrop_pool
+                + self.total_wrapped
+                + self.donations
+                + escrowed
+                + bid
+                + queued
+                + self.total_credits
+        }
+
+        /// Panics if any invariant is broken, in debug builds only.
+        #[cfg(debug_assertions)]
+        fn debug_check_invariants(&self) {
+            let report = self.verify_invariants();
+            assert!(
+                report.is_ok(),
+                "invariants violated: {:?}",
+                report.violations
+            );
+        }
+
+        #[cfg(not(debug_assertions))]
+        fn debug_check_invariants(&self) {}
+    }
+
+    impl UnitTestBug {
+        /// Exports up to `limit` storage entries starting at `cursor`.
+        ///
+        /// Pages run through the history, then the participants, then their
+        /// balances. `limit` is clamped to between one and the configured
+        /// `max_batch_size`.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, ExportCursor};
+        /// let contract = deploy(false);
+        /// // A fresh contract holds only its initial checkpoint.
+        /// let chunk = contract.export_storage(ExportCursor::default(), 10);
+        /// assert_eq!(chunk.items.len(), 1);
+        /// assert_eq!(chunk.next, None);
+        /// ```
+        #[ink(message)]
+        pub fn export_storage(&self, cursor: ExportCursor, limit: u32) -> ExportChunk {
+            let limit = limit.clamp(1, self.config.max_batch_size);
+            let mut items = Vec::new();
+            let mut cursor = Some(cursor);
+            while let Some(ExportCursor { section, index }) = cursor {
+                if items.len() as u32 == limit {
+                    break;
+                }
+                let index = match section {
+                    ExportSection::History => index.max(self.first_checkpoint),
+                    ExportSection::Participants | ExportSection::Balances => index,
+                };
+                let len = match section {
+                    ExportSection::History => self.checkpoint_count(),
+                    ExportSection::Participants | ExportSection::Balances => self.participant_count,
+                };
+                if index >= len {
+                    cursor = match section {
+                        ExportSection::History => Some(ExportSection::Participants),
+                        ExportSection::Participants => Some(ExportSection::Balances),
+                        ExportSection::Balances => None,
+                    }
+                    .map(|section| ExportCursor { section, index: 0 });
+                    continue;
+                }
+                items.push(self.export_item(section, index));
+                cursor = Some(ExportCursor {
+                    section,
+                    index: index + 1,
+                });
+            }
+            ExportChunk {
+                items,
+                next: cursor,
+            }
+        }
+
+        /// Returns the number of accounts that have flipped or wrapped.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy, pay_as};
+        /// let mut contract = deploy(false);
+        /// contract.flip().unwrap();
+        /// pay_as(accounts().bob, 100);
+        /// contract.wrap();
+        /// assert_eq!(contract.participant_count(), 2);
+        /// ```
+        #[ink(message)]
+        pub fn participant_count(&self) -> u32 {
+            self.participant_count
+        }
+
+        fn export_item(&self, section: ExportSection, index: u32) -> ExportItem {
+            match section {
+                ExportSection::History => ExportItem::Checkpoint {
+                    index,
+                    checkpoint: self.checkpoint(index),
+                },
+                ExportSection::Participants => {
+                    let account = self.participant(index);
+                    ExportItem::Participant {
+                        index,
+                        account,
+                        flips: self.flip_count(account),
+                    }
+                }
+                ExportSection::Balances => {
+                    let account = self.participant(index);
+                    ExportItem::Balance {
+                        account,
+                        wrapped: self.wrapped_balance_of(account),
+                    }
+                }
+            }
+        }
+
+        fn participant(&self, index: u32) -> AccountId {
+            self.participants
+                .get(index)
+                .expect("participants below `participant_count` are always present")
+        }
+
+        /// Removes up to `limit` participants that have neither flipped nor
+        /// wrapped in the last `PARTICIPANT_RETENTION_EPOCHS` epochs and hold no
+        /// wrapped balance or credit.
+        ///
+        /// Examines at most `limit` entries, clamped to between one and the
+        /// configured `max_batch_size`, starting where the previous call stopped
+        /// and over again once past the end. A removed entry is replaced by the
+        /// last one, which reorders `export_storage` pages. Returns the number
+        /// removed and emits `Pruned`, and the freed storage deposit is refunded
+        /// to the caller.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{advance_blocks, deploy}, DEFAULT_EPOCH_LENGTH, PARTICIPANT_RETENTION_EPOCHS};
+        /// let mut contract = deploy(false);
+        /// contract.flip().unwrap();
+        /// assert_eq!(contract.prune_participants(10), 0);
+        /// advance_blocks(DEFAULT_EPOCH_LENGTH * PARTICIPANT_RETENTION_EPOCHS);
+        /// assert_eq!(contract.prune_participants(10), 1);
+        /// assert_eq!(contract.participant_count(), 0);
+        /// ```
+        #[ink(message)]
+        pub fn prune_participants(&mut self, limit: u32) -> u32 {
+            let epoch = self.current_epoch();
+            let mut index = self.prune_cursor;
+            let mut count = 0;
+            for _ in 0..limit.clamp(1, self.config.max_batch_size) {
+                if index >= self.participant_count {
+                    index = 0;
+                    break;
+                }
+                let account = self.participant(index);
+                let active = self.participant_epochs.get(account).unwrap_or_default();
+                if active + PARTICIPANT_RETENTION_EPOCHS <= epoch
+                    && self.wrapped_balance_of(account) == 0
+                    && self.credit_of(account) == 0
+                {
+                    // The last entry moves into `index`, which is examined next.
+                    self.remove_participant(index, account);
+                    count += 1;
+                } else {
+                    index += 1;
+                }
+            }
+            self.prune_cursor = index;
+            let seq = self.next_seq();
+            self.env().emit_event(Pruned { count, seq });
+            self.debug_check_invariants();
+            count
+        }
+
+        fn remove_participant(&mut self, index: u32, account: AccountId) {
+            let last = self.participant_count - 1;
+            if index != last {
+                let moved = self.participant(last);
+                self.participants.insert(index, &moved);
+                self.participant_ids.insert(moved, &index);
+            }
+            self.participants.remove(last);
+            self.participant_ids.remove(account);
+            self.participant_epochs.remove(account);
+            self.participant_count = last;
+        }
+
+        fn register_participant(&mut self, account: AccountId) {
+            self.participant_epochs
+                .insert(account, &self.current_epoch());
+            self.add_participant(account);
+        }
+
+        /// Lists `account` as a participant without counting it as active.
+        fn add_participant(&mut self, account: AccountId) {
+            if self.participant_ids.contains(account) {
+                return;
+            }
+            let index = self.participant_count;
+            self.participants.insert(index, &account);
+            self.participant_ids.insert(account, &index);
+            self.participant_count = index + 1;
+        }
+    }
+
+    impl UnitTestBug {
+        /// Sets the PSP22 holding required to flip, `None` lifts the requirement.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, set_caller}, FlipError, Psp22Gate};
+        /// let mut contract = deploy(false);
+        /// let gate = Psp22Gate { token: accounts().django, min_balance: 100 };
+        /// assert_eq!(contract.set_psp22_gate(Some(gate)), Ok(()));
+        /// set_caller(accounts().bob);
+        /// assert_eq!(contract.set_psp22_gate(None), Err(FlipError::NotOwner));
+        /// ```
+        #[ink(message)]
+        pub fn set_psp22_gate(&mut self, gate: Option<Psp22Gate>) -> FlipResult<()> {
+            self.ensure_owner()?;
+            self.audit(
+                ink::selector_bytes!("set_psp22_gate"),
+                Self::audit_hash(&self.psp22_gate),
+                Self::audit_hash(&gate),
+            );
+            self.psp22_gate = gate;
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Returns the PSP22 holding required to flip, if any.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy}, Psp22Gate};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.psp22_gate(), None);
+        /// let gate = Psp22Gate { token: accounts().django, min_balance: 100 };
+        /// contract.set_psp22_gate(Some(gate)).unwrap();
+        /// assert_eq!(contract.psp22_gate(), Some(gate));
+        /// ```
+        #[ink(message)]
+        pub fn psp22_gate(&self) -> Option<Psp22Gate> {
+            self.psp22_gate
+        }
+
+        /// Fails unless `account` satisfies the PSP22 gate.
+        fn check_psp22_gate(&self, account: AccountId) -> FlipResult<()> {
+            let Some(gate) = self.psp22_gate else {
+                return Ok(());
+            };
+            self.ensure_trusted_callee(gate.token)?;
+            let balance = build_call::<Environment>()
+                .call(gate.token)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("PSP22::balance_of")))
+                        .push_arg(account),
+                )
+                .returns::<Balance>()
+                .try_invoke()
+                .map_err(|error| FlipError::TokenQueryFailed(Some(error.into())))?
+                .map_err(|error| FlipError::TokenQueryFailed(Some(error.into())))?;
+            if balance < gate.min_balance {
+                return Err(FlipError::BelowTokenMinimum);
+            }
+            Ok(())
+        }
+    }
+
+    impl UnitTestBug {
+        /// Transfers the contract's whole balance of PSP22 `token` to `to` and returns it.
+        ///
+        /// The contract never holds PSP22 tokens itself, so any balance was sent
+        /// to it by mistake.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, set_caller}, FlipError};
+        /// let mut contract = deploy(false);
+        /// let accounts = accounts();
+        /// set_caller(accounts.bob);
+        /// assert_eq!(contract.sweep_token(accounts.django, accounts.bob), Err(FlipError::NotOwner));
+        /// ```
+        #[ink(message)]
+        pub fn sweep_token(
+            &mut self,
+            token: AccountId,
+            to: AccountId,
+        ) -> FlipResult<Balance> {
+            self.ensure_owner()?;
+            self.ensure_trusted_callee(token)?;
+            let amount = build_call::<Environment>()
+                .call(token)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("PSP22::balance_of")))
+                        .push_arg(self.env().account_id()),
+                )
+                .returns::<Balance>()
+                .try_invoke()
+                .map_err(|error| FlipError::TokenQueryFailed(Some(error.into())))?
+                .map_err(|error| FlipError::TokenQueryFailed(Some(error.into())))?;
+            if amount == 0 {
+                return Err(FlipError::NothingToClaim);
+            }
+            build_call::<Environment>()
+                .call(token)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("PSP22::transfer")))
+                        .push_arg(to)
+                        .push_arg(amount)
+                        .push_arg(Vec::<u8>::new()),
+                )
+                .returns::<Result<(), u8>>()
+                .try_invoke()
+                .map_err(|error| FlipError::TokenTransferFailed(Some(error.into())))?
+                .map_err(|error| FlipError::TokenTransferFailed(Some(error.into())))?
+                .map_err(|code| {
+                    FlipError::TokenTransferFailed(Some(UpstreamError::Returned(code)))
+                })?;
+            self.audit(
+                ink::selector_bytes!("sweep_token"),
+                Self::audit_hash(&(token, amount)),
+                Self::audit_hash(&(to, amount)),
+            );
+            self.debug_check_invariants();
+            Ok(amount)
+        }
+    }
+
+    impl UnitTestBug {
+        /// Sets the PSP34 collection whose NFTs act as flip permits, `None` disables them.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, set_caller}, FlipError};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.set_psp34_collection(Some(accounts().django)), Ok(()));
+        /// set_caller(accounts().bob);
+        /// assert_eq!(contract.set_psp34_collection(None), Err(FlipError::NotOwner));
+        /// ```
+        #[ink(message)]
+        pub fn set_psp34_collection(
+            &mut self,
+            collection: Option<AccountId>,
+        ) -> FlipResult<()> {
+            self.ensure_owner()?;
+            self.audit(
+                ink::selector_bytes!("set_psp34_collection"),
+                Self::audit_hash(&self.psp34_collection),
+                Self::audit_hash(&collection),
+            );
+            self.psp34_collection = collection;
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Returns the PSP34 collection whose NFTs act as flip permits.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.psp34_collection(), None);
+        /// contract.set_psp34_collection(Some(accounts().django)).unwrap();
+        /// assert_eq!(contract.psp34_collection(), Some(accounts().django));
+        /// ```
+        #[ink(message)]
+        pub fn psp34_collection(&self) -> Option<AccountId> {
+            self.psp34_collection
+        }
+
+        /// Flips, presenting NFT `id` of the configured collection as a permit.
+        ///
+        /// Holding the NFT stands in for the PSP22 gate; every other rule of
+        /// `flip`, including the fee, still applies.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, FlipError, Id};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.flip_with_nft(Id::U8(1)), Err(FlipError::NoNftCollection));
+        /// ```
+        #[ink(message, payable)]
+        pub fn flip_with_nft(&mut self, id: Id) -> FlipResult<MutationReceipt> {
+            trace_call!(self, "flip_with_nft", id);
+            let gas = self.gas_left();
+            let caller = self.env().caller();
+            let collection = self.psp34_collection.ok_or(FlipError::NoNftCollection)?;
+            self.ensure_trusted_callee(collection)?;
+            let owner = build_call::<Environment>()
+                .call(collection)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("PSP34::owner_of")))
+                        .push_arg(id),
+                )
+                .returns::<Option<AccountId>>()
+                .try_invoke()
+                .map_err(|error| FlipError::TokenQueryFailed(Some(error.into())))?
+                .map_err(|error| FlipError::TokenQueryFailed(Some(error.into())))?;
+            if owner != Some(caller) {
+                return Err(FlipError::NotNftHolder);
+            }
+            let seq =
+                self.flip_admitted(caller, Payment::Native(self.env().transferred_value()))?;
+            self.debug_check_invariants();
+            Ok(self.receipt(seq, gas))
+        }
+    }
+
+    impl UnitTestBug {
+        /// Grants or revokes `delegate`'s right to flip on the caller's behalf.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy};
+        /// let mut contract = deploy(false);
+        /// let accounts = accounts();
+        /// contract.approve(accounts.charlie, true);
+        /// assert!(contract.is_approved(accounts.alice, accounts.charlie));
+        /// contract.approve(accounts.charlie, false);
+        /// assert!(!contract.is_approved(accounts.alice, accounts.charlie));
+        /// ```
+        #[ink(message)]
+        pub fn approve(&mut self, delegate: AccountId, allowed: bool) {
+            let owner = self.env().caller();
+            if allowed {
+                self.approvals.insert((owner, delegate), &());
+            } else {
+                self.approvals.remove((owner, delegate));
+            }
+            let seq = self.next_seq();
+            self.env().emit_event(Approval {
+                account: owner,
+                delegate,
+                allowed,
+                seq,
+            });
+            self.debug_check_invariants();
+        }
+
+        /// Returns whether `delegate` may flip on behalf of `owner`.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy, set_caller};
+        /// let mut contract = deploy(false);
+        /// let accounts = accounts();
+        /// set_caller(accounts.bob);
+        /// contract.approve(accounts.charlie, true);
+        /// assert!(contract.is_approved(accounts.bob, accounts.charlie));
+        /// assert!(!contract.is_approved(accounts.charlie, accounts.bob));
+        /// ```
+        #[ink(message)]
+        pub fn is_approved(&self, owner: AccountId, delegate: AccountId) -> bool {
+            self.approvals.contains((owner, delegate))
+        }
+
+        /// Flips on behalf of `owner`, who must have approved the caller.
+        ///
+        /// The flip counts as `owner`'s and the caller pays any fee.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, set_caller}, FlipError};
+        /// let mut contract = deploy(false);
+        /// let accounts = accounts();
+        /// set_caller(accounts.charlie);
+        /// assert_eq!(contract.flip_for(accounts.bob), Err(FlipError::NotApproved));
+        /// set_caller(accounts.bob);
+        /// contract.approve(accounts.charlie, true);
+        /// set_caller(accounts.charlie);
+        /// assert!(contract.flip_for(accounts.bob).is_ok());
+        /// assert_eq!(contract.flip_count(accounts.bob), 1);
+        /// ```
+        #[ink(message, payable)]
+        pub fn flip_for(&mut self, owner: AccountId) -> FlipResult<MutationReceipt> {
+            trace_call!(self, "flip_for", owner);
+            let gas = self.gas_left();
+            let caller = self.env().caller();
+            if caller != owner && !self.is_approved(owner, caller) {
+                return Err(FlipError::NotApproved);
+            }
+            let seq = self.flip_as(owner)?;
+            Ok(self.receipt(seq, gas))
+        }
+
+        /// Flips once for each of `owners`, who must each have approved the caller.
+        ///
+        /// The transferred value pays each item's fee in turn, and what is left
+        /// goes to the fee pot. Returns one result per owner, in order, and
+        /// emits a single `BatchFlipped` summing them up. In `BestEffort` mode a
+        /// failed item still counts against the owner's call quota.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, set_caller}, BatchMode, FlipError};
+        /// let mut contract = deploy(false);
+        /// let accounts = accounts();
+        /// set_caller(accounts.bob);
+        /// contract.approve(accounts.charlie, true);
+        /// set_caller(accounts.charlie);
+        /// let results = contract
+        ///     .batch_flip_for(vec![accounts.bob, accounts.django], BatchMode::BestEffort)
+        ///     .unwrap();
+        /// assert_eq!(results, [Ok(()), Err(FlipError::NotApproved)]);
+        /// ```
+        #[ink(message, payable)]
+        pub fn batch_flip_for(
+            &mut self,
+            owners: Vec<AccountId>,
+            mode: BatchMode,
+        ) -> FlipResult<Vec<FlipResult<()>>> {
+            if owners.len() > self.config.max_batch_size as usize {
+                return Err(FlipError::BatchTooLarge);
+            }
+            let caller = self.env().caller();
+            let mut budget = self.env().transferred_value();
+            let mut results = Vec::with_capacity(owners.len());
+            for owner in owners {
+                let result = self.batch_item(caller, owner, &mut budget);
+                if mode == BatchMode::AllOrNothing {
+                    result?;
+                }
+                results.push(result);
+            }
+            self.fee_pot += budget;
+            let succeeded = results.iter().filter(|result| result.is_ok()).count() as u32;
+            let seq = self.next_seq();
+            self.env().emit_event(BatchFlipped {
+                account: caller,
+                succeeded,
+                failed: results.len() as u32 - succeeded,
+                seq,
+            });
+            self.debug_check_invariants();
+            Ok(results)
+        }
+
+        /// Flips for `owner` in a batch, paying the fee out of `budget`.
+        fn batch_item(
+            &mut self,
+            caller: AccountId,
+            owner: AccountId,
+            budget: &mut Balance,
+        ) -> FlipResult<()> {
+            if caller != owner && !self.is_approved(owner, caller) {
+                return Err(FlipError::NotApproved);
+            }
+            let fee = if self.is_subscribed(owner) {
+                0
+            } else {
+                self.flip_fee_for(owner)?
+            };
+            if *budget < fee {
+                return Err(FlipError::InsufficientFee);
+            }
+            self.flip_paying(owner, fee)?;
+            *budget -= fee;
+            Ok(())
+        }
+    }
+
+    impl UnitTestBug {
+        /// Queues a flip for the caller, to be executed by a keeper once the
+        /// block number passes `execute_after`.
+        ///
+        /// The transferred value must cover `KEEPER_REWARD`, and what is left
+        /// pays the flip fee when the flip is executed. Flips due in the same
+        /// block are executed in the order they were queued.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, pay_as}, KEEPER_REWARD};
+        /// let mut contract = deploy(false);
+        /// pay_as(accounts().bob, KEEPER_REWARD);
+        /// assert_eq!(contract.queue_flip(5), Ok(()));
+        /// assert_eq!(contract.queued_flips()[0].execute_after, 5);
+        /// ```
+        #[ink(message, payable)]
+        pub fn queue_flip(&mut self, execute_after: BlockNumber) -> FlipResult<()> {
+            let deposit = self.env().transferred_value();
+            if deposit < KEEPER_REWARD {
+                return Err(FlipError::InsufficientFee);
+            }
+            if self.flip_queue.len() >= MAX_QUEUED_FLIPS {
+                return Err(FlipError::QueueFull);
+            }
+            let index = self
+                .flip_queue
+                .partition_point(|queued| queued.execute_after <= execute_after);
+            self.flip_queue.insert(
+                index,
+                QueuedFlip {
+                    by: self.env().caller(),
+                    execute_after,
+                    paid: deposit - KEEPER_REWARD,
+                },
+            );
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Executes up to `limit` due flips, oldest due first, and credits the
+        /// caller `KEEPER_REWARD` for each that succeeds.
+        ///
+        /// A flip that fails, for example on its fee or a cooldown, is dropped
+        /// and its whole deposit credited back, so queueing flips bound to fail
+        /// earns a keeper nothing. Returns the number of flips processed.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, advance_blocks, deploy, pay_as}, KEEPER_REWARD};
+        /// let mut contract = deploy(false);
+        /// pay_as(accounts().bob, KEEPER_REWARD);
+        /// contract.queue_flip(1).unwrap();
+        /// assert_eq!(contract.execute_due(10), Ok(0));
+        /// advance_blocks(2);
+        /// assert_eq!(contract.execute_due(10), Ok(1));
+        /// assert!(contract.get());
+        /// ```
+        #[ink(message)]
+        pub fn execute_due(&mut self, limit: u32) -> FlipResult<u32> {
+            if self.config.paused || self.breaker_tripped {
+                return Err(FlipError::Paused);
+            }
+            let now = self.env().block_number();
+            let due = self
+                .flip_queue
+                .iter()
+                .take(limit as usize)
+                .take_while(|queued| queued.execute_after < now)
+                .count();
+            let executed: Vec<QueuedFlip> = self.flip_queue.drain(..due).collect();
+            let mut succeeded: Balance = 0;
+            for queued in &executed {
+                if self.flip_paying(queued.by, queued.paid).is_ok() {
+                    succeeded += 1;
+                } else {
+                    self.credit(queued.by, queued.paid + KEEPER_REWARD);
+                }
+            }
+            self.credit(self.env().caller(), KEEPER_REWARD * succeeded);
+            self.debug_check_invariants();
+            Ok(due as u32)
+        }
+
+        /// Returns the queued flips, in the order they will be executed.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, pay_as}, KEEPER_REWARD};
+        /// let mut contract = deploy(false);
+        /// assert!(contract.queued_flips().is_empty());
+        /// pay_as(accounts().bob, KEEPER_REWARD + 3);
+        /// contract.queue_flip(5).unwrap();
+        /// let queued = contract.queued_flips();
+        /// assert_eq!((queued[0].by, queued[0].execute_after), (accounts().bob, 5));
+        /// assert_eq!(queued[0].paid, 3);
+        /// ```
+        #[ink(message)]
+        pub fn queued_flips(&self) -> Vec<QueuedFlip> {
+            self.flip_queue.clone()
+        }
+    }
+
+    impl UnitTestBug {
+        /// Transfers the caller's whole credit to them and returns it.
+        ///
+        /// Refunds, prizes and rewards owed by the contract are credited rather
+        /// than sent, so a recipient that cannot receive a transfer only holds
+        /// up its own withdrawal.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, pay_as}, FlipError};
+        /// let mut contract = deploy(false);
+        /// let accounts = accounts();
+        /// pay_as(accounts.bob, 50);
+        /// contract.bid().unwrap();
+        /// pay_as(accounts.charlie, 60);
+        /// contract.bid().unwrap();
+        /// assert_eq!(contract.withdraw_credit(), Err(FlipError::NothingToClaim));
+        /// pay_as(accounts.bob, 0);
+        /// assert_eq!(contract.withdraw_credit(), Ok(50));
+        /// assert_eq!(contract.credit_of(accounts.bob), 0);
+        /// ```
+        #[ink(message)]
+        pub fn withdraw_credit(&mut self) -> FlipResult<Balance> {
+            let caller = self.env().caller();
+            let amount = self.credit_of(caller);
+            if amount == 0 {
+                return Err(FlipError::NothingToClaim);
+            }
+            self.credits.remove(caller);
+            self.total_credits -= amount;
+            self.env()
+                .transfer(caller, amount)
+                .map_err(|_| FlipError::TransferFailed)?;
+            self.debug_check_invariants();
+            Ok(amount)
+        }
+
+        /// Returns the value credited to `account` and not yet withdrawn.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy, pay_as};
+        /// let mut contract = deploy(false);
+        /// let accounts = accounts();
+        /// pay_as(accounts.bob, 20);
+        /// contract.bid().unwrap();
+        /// assert_eq!(contract.credit_of(accounts.bob), 0);
+        /// pay_as(accounts.charlie, 30);
+        /// contract.bid().unwrap();
+        /// assert_eq!(contract.credit_of(accounts.bob), 20);
+        /// ```
+        #[ink(message)]
+        pub fn credit_of(&self, account: AccountId) -> Balance {
+            self.credits.get(account).unwrap_or(0)
+        }
+
+        /// Owes `amount` to `account`, to be withdrawn with `withdraw_credit`.
+        fn credit(&mut self, account: AccountId, amount: Balance) {
+            if amount == 0 {
+                return;
+            }
+            self.credits.insert(account, &(self.credit_of(account) + amount));
+            self.total_credits += amount;
+            self.add_participant(account);
+        }
+    }
+
+    impl UnitTestBug {
+        /// Sets the account donations are forwarded to, `None` holds them here.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, set_caller}, FlipError};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.set_treasury(Some(accounts().django)), Ok(()));
+        /// set_caller(accounts().bob);
+        /// assert_eq!(contract.set_treasury(None), Err(FlipError::NotOwner));
+        /// ```
+        #[ink(message)]
+        pub fn set_treasury(&mut self, treasury: Option<AccountId>) -> FlipResult<()> {
+            self.ensure_owner()?;
+            self.audit(
+                ink::selector_bytes!("set_treasury"),
+                Self::audit_hash(&self.treasury),
+                Self::audit_hash(&treasury),
+            );
+            self.treasury = treasury;
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Returns the account donations are forwarded to.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.treasury(), None);
+        /// contract.set_treasury(Some(accounts().django)).unwrap();
+        /// assert_eq!(contract.treasury(), Some(accounts().django));
+        /// ```
+        #[ink(message)]
+        pub fn treasury(&self) -> Option<AccountId> {
+            self.treasury
+        }
+
+        /// Accepts the transferred value as a donation to the treasury.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy, pay_as};
+        /// let mut contract = deploy(false);
+        /// pay_as(accounts().bob, 50);
+        /// contract.donate();
+        /// assert_eq!(contract.donations(), 50);
+        /// ```
+        #[ink(message, payable)]
+        pub fn donate(&mut self) {
+            self.donations += self.env().transferred_value();
+            self.debug_check_invariants();
+        }
+
+        /// Returns the donations waiting to be forwarded.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy, pay_as};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.donations(), 0);
+        /// pay_as(accounts().bob, 50);
+        /// contract.donate();
+        /// pay_as(accounts().charlie, 25);
+        /// contract.donate();
+        /// assert_eq!(contract.donations(), 75);
+        /// ```
+        #[ink(message)]
+        pub fn donations(&self) -> Balance {
+            self.donations
+        }
+
+        /// Forwards every held donation to the treasury through a runtime
+        /// `Balances::transfer` and returns the amount sent.
+        ///
+        /// Anyone may trigger the forwarding. The runtime must let contracts
+        /// dispatch balance transfers.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, FlipError};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.forward_donations(), Err(FlipError::NoTreasury));
+        /// ```
+        #[ink(message)]
+        pub fn forward_donations(&mut self) -> FlipResult<Balance> {
+            let treasury = self.treasury.ok_or(FlipError::NoTreasury)?;
+            let amount = self.donations;
+            if amount == 0 {
+                return Err(FlipError::NothingToClaim);
+            }
+            self.env()
+                .call_runtime(&RuntimeCall::Balances(BalancesCall::Transfer {
+                    dest: MultiAddress::Id(treasury),
+                    value: amount,
+                }))
+                .map_err(|_| FlipError::RuntimeCallFailed)?;
+            self.donations = 0;
+            self.debug_check_invariants();
+            Ok(amount)
+        }
+    }
+
+    impl UnitTestBug {
+        /// Sets the asset accepted by `flip_with_asset`, `None` disables asset fees.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, set_caller}, AssetFee, FlipError};
+        /// let mut contract = deploy(false);
+        /// let fee = AssetFee { asset_id: 1, amount: 10 };
+        /// assert_eq!(contract.set_asset_fee(Some(fee)), Ok(()));
+        /// set_caller(accounts().bob);
+        /// assert_eq!(contract.set_asset_fee(None), Err(FlipError::NotOwner));
+        /// ```
+        #[ink(message)]
+        pub fn set_asset_fee(&mut self, fee: Option<AssetFee>) -> FlipResult<()> {
+            self.ensure_owner()?;
+            self.audit(
+                ink::selector_bytes!("set_asset_fee"),
+                Self::audit_hash(&self.asset_fee),
+                Self::audit_hash(&fee),
+            );
+            self.asset_fee = fee;
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Returns the asset accepted by `flip_with_asset`.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, AssetFee};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.asset_fee(), None);
+        /// let fee = AssetFee { asset_id: 1, amount: 10 };
+        /// contract.set_asset_fee(Some(fee)).unwrap();
+        /// assert_eq!(contract.asset_fee(), Some(fee));
+        /// ```
+        #[ink(message)]
+        pub fn asset_fee(&self) -> Option<AssetFee> {
+            self.asset_fee
+        }
+
+        /// Returns the fees collected in `asset_id` and not yet withdrawn.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::deploy;
+        /// let contract = deploy(false);
+        /// assert_eq!(contract.asset_fee_pot(1), 0);
+        /// ```
+        #[ink(message)]
+        pub fn asset_fee_pot(&self, asset_id: u32) -> Balance {
+            self.asset_fee_pots.get(asset_id).unwrap_or(0)
+        }
+
+        /// Flips, paying the fee in the configured asset.
+        ///
+        /// The caller must first approve the contract for the fee in
+        /// `pallet-assets`. Subscribers pay nothing and fee discounts apply,
+        /// as with `flip`.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, FlipError};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.flip_with_asset(), Err(FlipError::NoAssetFee));
+        /// ```
+        #[ink(message)]
+        pub fn flip_with_asset(&mut self) -> FlipResult<MutationReceipt> {
+            trace_call!(self, "flip_with_asset", ());
+            let gas = self.gas_left();
+            let caller = self.env().caller();
+            let fee = self.asset_fee.ok_or(FlipError::NoAssetFee)?;
+            let amount = if self.is_subscribed(caller) {
+                0
+            } else {
+                self.fee_discounts
+                    .apply(fee.amount, self.flip_count(caller))
+            };
+            if Self::assets().balance(fee.asset_id, caller) < amount {
+                return Err(FlipError::InsufficientFee);
+            }
+            self.check_psp22_gate(caller)?;
+            let seq = self.flip_admitted(caller, Payment::Asset)?;
+            if amount > 0 {
+                Self::assets()
+                    .transfer_approved(fee.asset_id, caller, self.env().account_id(), amount)
+                    .map_err(|_| FlipError::AssetTransferFailed)?;
+                let pot = self.asset_fee_pot(fee.asset_id);
+                self.asset_fee_pots.insert(fee.asset_id, &(pot + amount));
+            }
+            self.debug_check_invariants();
+            Ok(self.receipt(seq, gas))
+        }
+
+        /// Sends every collected fee in `asset_id` to the owner and returns the amount.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, FlipError};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.withdraw_asset_fees(1), Err(FlipError::NothingToClaim));
+        /// ```
+        #[ink(message)]
+        pub fn withdraw_asset_fees(&mut self, asset_id: u32) -> FlipResult<Balance> {
+            self.ensure_owner()?;
+            let amount = self.asset_fee_pot(asset_id);
+            if amount == 0 {
+                return Err(FlipError::NothingToClaim);
+            }
+            Self::assets()
+                .transfer(asset_id, self.owner, amount)
+                .map_err(|_| FlipError::AssetTransferFailed)?;
+            self.asset_fee_pots.remove(asset_id);
+            self.audit(
+                ink::selector_bytes!("withdraw_asset_fees"),
+                Self::audit_hash(&(asset_id, amount)),
+                Self::audit_hash(&(asset_id, 0)),
+            );
+            self.debug_check_invariants();
+            Ok(amount)
+        }
+
+        fn assets() -> <crate::PalletAssets as ink::ChainExtensionInstance>::Instance {
+            <crate::PalletAssets as ink::ChainExtensionInstance>::instantiate()
+        }
+    }
+
+    impl UnitTestBug {
+        /// Returns the code hash of the contract at `account`, `None` if it is
+        /// not a contract.
+        ///
+        /// Lets callers check what code a counterpart runs before calling it.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy, set_contract_info};
+        /// # use ink::primitives::Hash;
+        /// let contract = deploy(false);
+        /// let code_hash = Hash::from([7; 32]);
+        /// set_contract_info(&[(accounts().django, code_hash, 100)]);
+        /// assert_eq!(contract.code_hash_of(accounts().django), Some(code_hash));
+        /// assert_eq!(contract.code_hash_of(accounts().eve), None);
+        /// ```
+        #[ink(message)]
+        pub fn code_hash_of(&self, account: AccountId) -> Option<Hash> {
+            Self::contracts().code_hash(account)
+        }
+
+        /// Adds `code_hash` to the allowlist of callee code, or removes it.
+        ///
+        /// Once the allowlist has an entry, every contract called, be it a
+        /// token, oracle or flash borrower, must run allowlisted code.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, set_caller}, FlipError};
+        /// # use ink::primitives::Hash;
+        /// let mut contract = deploy(false);
+        /// let code_hash = Hash::from([7; 32]);
+        /// assert_eq!(contract.set_code_hash_trusted(code_hash, true), Ok(()));
+        /// assert!(contract.is_code_hash_trusted(code_hash));
+        /// set_caller(accounts().bob);
+        /// let result = contract.set_code_hash_trusted(code_hash, false);
+        /// assert_eq!(result, Err(FlipError::NotOwner));
+        /// ```
+        #[ink(message)]
+        pub fn set_code_hash_trusted(&mut self, code_hash: Hash, trusted: bool) -> FlipResult<()> {
+            self.ensure_owner()?;
+            let was_trusted = self.is_code_hash_trusted(code_hash);
+            self.audit(
+                ink::selector_bytes!("set_code_hash_trusted"),
+                Self::audit_hash(&(code_hash, was_trusted)),
+                Self::audit_hash(&(code_hash, trusted)),
+            );
+            match (was_trusted, trusted) {
+                (false, true) => {
+                    self.trusted_code_hashes.insert(code_hash, &());
+                    self.trusted_code_hash_count += 1;
+                }
+                (true, false) => {
+                    self.trusted_code_hashes.remove(code_hash);
+                    self.trusted_code_hash_count -= 1;
+                }
+                _ => {}
+            }
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Returns whether `code_hash` is on the allowlist of callee code.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::deploy;
+        /// # use ink::primitives::Hash;
+        /// let mut contract = deploy(false);
+        /// let code_hash = Hash::from([7; 32]);
+        /// assert!(!contract.is_code_hash_trusted(code_hash));
+        /// contract.set_code_hash_trusted(code_hash, true).unwrap();
+        /// assert!(contract.is_code_hash_trusted(code_hash));
+        /// contract.set_code_hash_trusted(code_hash, false).unwrap();
+        /// assert!(!contract.is_code_hash_trusted(code_hash));
+        /// ```
+        #[ink(message)]
+        pub fn is_code_hash_trusted(&self, code_hash: Hash) -> bool {
+            self.trusted_code_hashes.contains(code_hash)
+        }
+
+        /// Fails unless `callee` runs allowlisted code, or the allowlist is empty.
+        fn ensure_trusted_callee(&self, callee: AccountId) -> FlipResult<()> {
+            if self.trusted_code_hash_count == 0 {
+                return Ok(());
+            }
+            match self.code_hash_of(callee) {
+                Some(code_hash) if self.is_code_hash_trusted(code_hash) => Ok(()),
+                _ => Err(FlipError::UntrustedCallee),
+            }
+        }
+
+        fn contracts() -> <crate::PalletContracts as ink::ChainExtensionInstance>::Instance {
+            <crate::PalletContracts as ink::ChainExtensionInstance>::instantiate()
+        }
+    }
+
+    impl UnitTestBug {
+        /// Fixes the flip fee in US dollars, `None` returns to `config.flip_fee`.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, set_caller}, FlipError, UsdFee};
+        /// let mut contract = deploy(false);
+        /// let fee = UsdFee { oracle: accounts().django, usd_cents: 50, max_age: 10 };
+        /// assert_eq!(contract.set_usd_fee(Some(fee)), Ok(()));
+        /// set_caller(accounts().bob);
+        /// assert_eq!(contract.set_usd_fee(None), Err(FlipError::NotOwner));
+        /// ```
+        #[ink(message)]
+        pub fn set_usd_fee(&mut self, fee: Option<UsdFee>) -> FlipResult<()> {
+            self.ensure_owner()?;
+            self.audit(
+                ink::selector_bytes!("set_usd_fee"),
+                Self::audit_hash(&self.usd_fee),
+                Self::audit_hash(&fee),
+            );
+            self.usd_fee = fee;
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Returns the dollar-denominated flip fee, if any.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy}, UsdFee};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.usd_fee(), None);
+        /// let fee = UsdFee { oracle: accounts().django, usd_cents: 50, max_age: 10 };
+        /// contract.set_usd_fee(Some(fee)).unwrap();
+        /// assert_eq!(contract.usd_fee(), Some(fee));
+        /// ```
+        #[ink(message)]
+        pub fn usd_fee(&self) -> Option<UsdFee> {
+            self.usd_fee
+        }
+
+        /// Returns the native value a non-subscriber must pay to flip right now.
+        ///
+        /// This is the fee before discounts, see `quote_flip_fee_for`.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy_with_config, Config};
+        /// let contract = deploy_with_config(false, Config { flip_fee: 10, ..Config::default() });
+        /// assert_eq!(contract.quote_flip_fee(), Ok(10));
+        /// ```
+        #[ink(message)]
+        pub fn quote_flip_fee(&self) -> FlipResult<Balance> {
+            self.native_flip_fee()
+        }
+
+        /// Returns the native value `account` must pay to flip right now,
+        /// after its fee discount.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy_with_config, set_value_transferred}, Config, FeeDiscounts, FeeTier};
+        /// let mut contract = deploy_with_config(false, Config { flip_fee: 10, ..Config::default() });
+        /// let half_off = FeeTier { min_flips: 1, discount_bps: 5_000 };
+        /// contract.set_fee_discounts(FeeDiscounts { tiers: vec![half_off] }).unwrap();
+        /// let alice = accounts().alice;
+        /// assert_eq!(contract.quote_flip_fee_for(alice), Ok(10));
+        /// set_value_transferred(10);
+        /// contract.flip().unwrap();
+        /// assert_eq!(contract.quote_flip_fee_for(alice), Ok(5));
+        /// ```
+        #[ink(message)]
+        pub fn quote_flip_fee_for(&self, account: AccountId) -> FlipResult<Balance> {
+            self.flip_fee_for(account)
+        }
+
+        fn flip_fee_for(&self, account: AccountId) -> FlipResult<Balance> {
+            let fee = self.native_flip_fee()?;
+            Ok(self.fee_discounts.apply(fee, self.flip_count(account)))
+        }
+
+        /// Returns the flip fee in native units, converting a USD fee at the
+        /// oracle's current price.
+        fn native_flip_fee(&self) -> FlipResult<Balance> {
+            let Some(fee) = self.usd_fee else {
+                return Ok(self.config.flip_fee);
+            };
+            self.ensure_trusted_callee(fee.oracle)?;
+            let oracle: ink::contract_ref!(PriceOracle) = fee.oracle.into();
+            let quote = oracle
+                .call()
+                .latest_price()
+                .gas_limit(self.config.call_ref_time_limit)
+                .try_invoke()
+                .map_err(|error| FlipError::OracleUnavailable(Some(error.into())))?
+                .map_err(|error| FlipError::OracleUnavailable(Some(error.into())))?
+                .ok_or(FlipError::OracleUnavailable(None))?;
+            let age = self.env().block_number().saturating_sub(quote.updated_at);
+            if age > fee.max_age {
+                return Err(FlipError::StalePrice);
+            }
+            Ok(fee.native_amount(&quote))
+        }
+    }
+
+    impl UnitTestBug {
+        /// Sets the compressed BN254 Groth16 verifying key for `flip_with_zk_proof`.
+        ///
+        /// The key is fully validated here so that proofs can skip the checks.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, set_caller}, FlipError};
+        /// let mut contract = deploy(false);
+        /// // Rejected as malformed with the `zk` feature, and as unsupported without it.
+        /// assert!(contract.set_zk_verifying_key(vec![1, 2, 3]).is_err());
+        /// set_caller(accounts().bob);
+        /// assert_eq!(contract.set_zk_verifying_key(Vec::new()), Err(FlipError::NotOwner));
+        /// ```
+        #[ink(message)]
+        pub fn set_zk_verifying_key(&mut self, key: Vec<u8>) -> FlipResult<()> {
+            self.ensure_owner()?;
+            groth16::check_verifying_key(&key)?;
+            let key = Some(key);
+            self.audit(
+                ink::selector_bytes!("set_zk_verifying_key"),
+                Self::audit_hash(&self.zk_verifying_key),
+                Self::audit_hash(&key),
+            );
+            self.zk_verifying_key = key;
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Flips once `proof` verifies against the configured key and `public_inputs`.
+        ///
+        /// `proof` is a compressed BN254 Groth16 proof and each public input a
+        /// little-endian scalar. Every rule of `flip`, including the fee, still
+        /// applies. Without the `zk` feature no proof verifies.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, FlipError};
+        /// let mut contract = deploy(false);
+        /// let result = contract.flip_with_zk_proof(Vec::new(), Vec::new());
+        /// assert_eq!(result, Err(FlipError::NoVerifyingKey));
+        /// ```
+        #[ink(message, payable)]
+        pub fn flip_with_zk_proof(
+            &mut self,
+            proof: Vec<u8>,
+            public_inputs: Vec<[u8; 32]>,
+        ) -> FlipResult<MutationReceipt> {
+            trace_call!(self, "flip_with_zk_proof", (&proof, &public_inputs));
+            let gas = self.gas_left();
+            let key = self
+                .zk_verifying_key
+                .as_ref()
+                .ok_or(FlipError::NoVerifyingKey)?;
+            groth16::verify(key, &proof, &public_inputs)?;
+            let seq = self.flip_as(self.env().caller())?;
+            Ok(self.receipt(seq, gas))
+        }
+    }
+
+    /// Groth16 verification over BN254, compiled in with the `zk` feature.
+    ///
+    /// ink! 4 dispatches every message regardless of `cfg`, so the messages
+    /// above always exist and only this module changes with the feature.
+    #[cfg(feature = "zk")]
+    mod groth16 {
+        use super::{FlipError, FlipResult};
+        use ark_bn254::{Bn254, Fr};
+        use ark_ff::PrimeField;
+        use ark_groth16::{prepare_verifying_key, Groth16, Proof, VerifyingKey};
+        use ark_serialize::CanonicalDeserialize;
+        use ink::prelude::vec::Vec;
+
+        pub fn check_verifying_key(key: &[u8]) -> FlipResult<()> {
+            VerifyingKey::<Bn254>::deserialize_compressed(key)
+                .map(|_| ())
+                .map_err(|_| FlipError::InvalidVerifyingKey)
+        }
+
+        pub fn verify(
+            key: &[u8],
+            proof: &[u8],
+            public_inputs: &[[u8; 32]],
+        ) -> FlipResult<()> {
+            // Checked once in `check_verifying_key`.
+            let key = VerifyingKey::<Bn254>::deserialize_compressed_unchecked(key)
+                .map_err(|_| FlipError::InvalidVerifyingKey)?;
+            let proof = Proof::<Bn254>::deserialize_compressed(proof)
+                .map_err(|_| FlipError::InvalidProof)?;
+            let inputs: Vec<Fr> = public_inputs
+                .iter()
+                .map(|input| Fr::from_le_bytes_mod_order(input))
+                .collect();
+            match Groth16::<Bn254>::verify_proof(&prepare_verifying_key(&key), &proof, &inputs) {
+                Ok(true) => Ok(()),
+                _ => Err(FlipError::InvalidProof),
+            }
+        }
+    }
+
+    #[cfg(not(feature = "zk"))]
+    mod groth16 {
+        use super::{FlipError, FlipResult};
+
+        pub fn check_verifying_key(_key: &[u8]) -> FlipResult<()> {
+            Err(FlipError::ZkUnsupported)
+        }
+
+        pub fn verify(
+            _key: &[u8],
+            _proof: &[u8],
+            _public_inputs: &[[u8; 32]],
+        ) -> FlipResult<()> {
+            Err(FlipError::ZkUnsupported)
+        }
+    }
+
+    impl UnitTestBug {
+        /// Replaces the validator set attesting flips.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, BlsValidatorSet, FlipError};
+        /// let mut contract = deploy(false);
+        /// let validators = BlsValidatorSet { keys: Vec::new(), threshold: 0 };
+        /// assert_eq!(contract.set_bls_validators(validators), Err(FlipError::InvalidValidatorSet));
+        /// ```
+        #[ink(message)]
+        pub fn set_bls_validators(&mut self, validators: BlsValidatorSet) -> FlipResult<()> {
+            self.ensure_owner()?;
+            if validators.keys.len() > MAX_BLS_VALIDATORS
+                || validators.threshold == 0
+                || validators.threshold as usize > validators.keys.len()
+            {
+                return Err(FlipError::InvalidValidatorSet);
+            }
+            for key in &validators.keys {
+                bls::check_public_key(key)?;
+            }
+            let validators = Some(validators);
+            self.audit(
+                ink::selector_bytes!("set_bls_validators"),
+                Self::audit_hash(&self.bls_validators),
+                Self::audit_hash(&validators),
+            );
+            self.bls_validators = validators;
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Returns the validator set attesting flips, if any.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::deploy;
+        /// let contract = deploy(false);
+        /// assert_eq!(contract.bls_validators(), None);
+        /// ```
+        #[ink(message)]
+        pub fn bls_validators(&self) -> Option<BlsValidatorSet> {
+            self.bls_validators.clone()
+        }
+
+        /// Returns the message validators must sign to attest the next flip.
+        ///
+        /// ```
+        /// # use ink::primitives::Hash;
+        /// # use unit_test_bug::test_support::deploy;
+        /// let mut contract = deploy(false);
+        /// let message = contract.bls_attestation_message();
+        /// contract.set_chain_id(Hash::from([7; 32])).unwrap();
+        /// assert_ne!(contract.bls_attestation_message(), message);
+        /// ```
+        #[ink(message)]
+        pub fn bls_attestation_message(&self) -> Vec<u8> {
+            let domain = SigningDomain {
+                contract: self.env().account_id(),
+                chain_id: self.chain_id,
+                version: SIGNING_DOMAIN_VERSION,
+                nonce: self.bls_nonce,
+            };
+            scale::Encode::encode(&(b"unit_test_bug:bls-flip", domain))
+        }
+
+        /// Flips on an aggregated BLS signature of the validators at `signers`.
+        ///
+        /// `signers` are strictly ascending indices into the validator set and
+        /// `signature` the compressed G1 sum of their signatures over
+        /// `bls_attestation_message`. The flip is privileged: it goes through
+        /// while paused and skips cooldown, flip rights and fee.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, FlipError};
+        /// let mut contract = deploy(false);
+        /// let result = contract.flip_with_bls_attestation(vec![0], vec![0; 48]);
+        /// assert_eq!(result, Err(FlipError::NoValidatorSet));
+        /// ```
+        #[ink(message)]
+        pub fn flip_with_bls_attestation(
+            &mut self,
+            signers: Vec<u32>,
+            signature: Vec<u8>,
+        ) -> FlipResult<MutationReceipt> {
+            trace_call!(self, "flip_with_bls_attestation", (&signers, &signature));
+            let gas = self.gas_left();
+            let validators = self
+                .bls_validators
+                .as_ref()
+                .ok_or(FlipError::NoValidatorSet)?;
+            if signers.windows(2).any(|pair| pair[0] >= pair[1])
+                || signers
+                    .last()
+                    .is_some_and(|&last| last as usize >= validators.keys.len())
+            {
+                return Err(FlipError::InvalidSigner);
+            }
+            if (signers.len() as u32) < validators.threshold {
+                return Err(FlipError::BelowThreshold);
+            }
+            let keys: Vec<&[u8]> = signers
+                .iter()
+                .map(|&index| &validators.keys[index as usize][..])
+                .collect();
+            bls::verify_aggregate(&keys, &self.bls_attestation_message(), &signature)?;
+
+            self.bls_nonce += 1;
+            let caller = self.env().caller();
+            self.write_value(!self.read_value());
+            let seq = self.emit_flipped(caller);
+            self.write_checkpoint();
+            self.record_flip(caller);
+            self.debug_check_invariants();
+            Ok(self.receipt(seq, gas))
+        }
+    }
+
+    /// Aggregated BLS12-381 signatures, compiled in with the `bls` feature.
+    ///
+    /// Signatures live in G1 and keys in G2, hashing with the
+    /// `BLS_SIG_BLS12381G1_XMD:SHA-256_SSWU_RO_NUL_` ciphersuite.
+    #[cfg(feature = "bls")]
+    mod bls {
+        use super::{FlipError, FlipResult};
+        use ark_bls12_381::{g1, Bls12_381, G1Affine, G1Projective, G2Affine, G2Projective};
+        use ark_ec::{
+            hashing::{
+                curve_maps::wb::WBMap, map_to_curve_hasher::MapToCurveBasedHasher, HashToCurve,
+            },
+            pairing::Pairing,
+            AffineRepr, CurveGroup,
+        };
+        use ark_ff::field_hashers::DefaultFieldHasher;
+        use ark_serialize::CanonicalDeserialize;
+        use sha2::Sha256;
+
+        const DST: &[u8] = b"BLS_SIG_BLS12381G1_XMD:SHA-256_SSWU_RO_NUL_";
+
+        pub fn check_public_key(key: &[u8]) -> FlipResult<()> {
+            match G2Affine::deserialize_compressed(key) {
+                Ok(key) if !key.is_zero() => Ok(()),
+                _ => Err(FlipError::InvalidValidatorSet),
+            }
+        }
+
+        pub fn verify_aggregate(
+            keys: &[&[u8]],
+            message: &[u8],
+            signature: &[u8],
+        ) -> FlipResult<()> {
+            let signature = match G1Affine::deserialize_compressed(signature) {
+                Ok(signature) if !signature.is_zero() => signature,
+                _ => return Err(FlipError::InvalidSignature),
+            };
+            let mut aggregate = G2Projective::default();
+            for key in keys {
+                // Checked once in `check_public_key`.
+                aggregate += G2Affine::deserialize_compressed_unchecked(*key)
+                    .map_err(|_| FlipError::InvalidValidatorSet)?;
+            }
+            let point = MapToCurveBasedHasher::<
+                G1Projective,
+                DefaultFieldHasher<Sha256, 128>,
+                WBMap<g1::Config>,
+            >::new(DST)
+            .and_then(|hasher| hasher.hash(message))
+            .map_err(|_| FlipError::InvalidSignature)?;
+            if Bls12_381::pairing(signature, G2Affine::generator())
+                == Bls12_381::pairing(point, aggregate.into_affine())
+            {
+                Ok(())
+            } else {
+                Err(FlipError::InvalidSignature)
+            }
+        }
+    }
+
+    #[cfg(not(feature = "bls"))]
+    mod bls {
+        use super::{FlipError, FlipResult};
+
+        pub fn check_public_key(_key: &[u8]) -> FlipResult<()> {
+            Err(FlipError::BlsUnsupported)
+        }
+
+        pub fn verify_aggregate(
+            _keys: &[&[u8]],
+            _message: &[u8],
+            _signature: &[u8],
+        ) -> FlipResult<()> {
+            Err(FlipError::BlsUnsupported)
+        }
+    }
+
+    impl UnitTestBug {
+        /// Returns the BLAKE2b-256 hash of `data` as the runtime computes it.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::deploy;
+        /// let contract = deploy(false);
+        /// assert_eq!(contract.hash_blake2(Vec::new())[..4], [0x0e, 0x57, 0x51, 0xc0]);
+        /// ```
+        #[ink(message)]
+        pub fn hash_blake2(&self, data: Vec<u8>) -> [u8; 32] {
+            self.env().hash_bytes::<Blake2x256>(&data)
+        }
+
+        /// Returns the Keccak-256 hash of `data` as the runtime computes it.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::deploy;
+        /// let contract = deploy(false);
+        /// assert_eq!(contract.hash_keccak(Vec::new())[..4], [0xc5, 0xd2, 0x46, 0x01]);
+        /// ```
+        #[ink(message)]
+        pub fn hash_keccak(&self, data: Vec<u8>) -> [u8; 32] {
+            self.env().hash_bytes::<Keccak256>(&data)
+        }
+
+        /// Returns the SHA2-256 hash of `data` as the runtime computes it.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::deploy;
+        /// let contract = deploy(false);
+        /// assert_eq!(contract.hash_sha2(Vec::new())[..4], [0xe3, 0xb0, 0xc4, 0x42]);
+        /// ```
+        #[ink(message)]
+        pub fn hash_sha2(&self, data: Vec<u8>) -> [u8; 32] {
+            self.env().hash_bytes::<Sha2x256>(&data)
+        }
+    }
+
+    impl UnitTestBug {
+        /// Stores `data` under `key`, replacing any previous blob.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, FlipError};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.store_blob(7, vec![1, 2, 3]), Ok(()));
+        /// let too_large = vec![0; contract.config().max_blob_size as usize + 1];
+        /// assert_eq!(contract.store_blob(7, too_large), Err(FlipError::BlobTooLarge));
+        /// ```
+        #[ink(message)]
+        pub fn store_blob(&mut self, key: u32, data: Vec<u8>) -> FlipResult<()> {
+            self.ensure_owner()?;
+            if data.len() > self.config.max_blob_size as usize {
+                return Err(FlipError::BlobTooLarge);
+            }
+            let old_chunks = self
+                .blob_lengths
+                .get(key)
+                .map_or(0, |length| length.div_ceil(BLOB_CHUNK_SIZE));
+            let mut chunks = 0;
+            for chunk in data.chunks(BLOB_CHUNK_SIZE as usize) {
+                self.blob_chunks.insert((key, chunks), &chunk.to_vec());
+                chunks += 1;
+            }
+            for index in chunks..old_chunks {
+                self.blob_chunks.remove((key, index));
+            }
+            self.audit(
+                ink::selector_bytes!("store_blob"),
+                Self::audit_hash(&(key, self.blob_lengths.get(key))),
+                Self::audit_hash(&(key, &data)),
+            );
+            self.blob_lengths.insert(key, &(data.len() as u32));
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Returns the blob stored under `key`, if any.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::deploy;
+        /// let mut contract = deploy(false);
+        /// contract.store_blob(7, vec![1, 2, 3]).unwrap();
+        /// assert_eq!(contract.read_blob(7), Some(vec![1, 2, 3]));
+        /// assert_eq!(contract.read_blob(8), None);
+        /// ```
+        #[ink(message)]
+        pub fn read_blob(&self, key: u32) -> Option<Vec<u8>> {
+            let length = self.blob_lengths.get(key)?;
+            let mut data = Vec::with_capacity(length as usize);
+            for index in 0..length.div_ceil(BLOB_CHUNK_SIZE) {
+                data.extend(self.blob_chunks.get((key, index)).unwrap_or_default());
+            }
+            Some(data)
+        }
+    }
+
+    impl UnitTestBug {
+        /// Sets the human-readable name of the deployment.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, FlipError, MAX_LABEL_LEN};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.set_label("staging".into()), Ok(()));
+        /// let too_long = "x".repeat(MAX_LABEL_LEN + 1);
+        /// assert_eq!(contract.set_label(too_long), Err(FlipError::LabelTooLong));
+        /// ```
+        #[ink(message)]
+        pub fn set_label(&mut self, label: String) -> FlipResult<()> {
+            self.ensure_owner()?;
+            if label.len() > MAX_LABEL_LEN {
+                return Err(FlipError::LabelTooLong);
+            }
+            self.audit(
+                ink::selector_bytes!("set_label"),
+                Self::audit_hash(&self.label),
+                Self::audit_hash(&label),
+            );
+            self.label = label;
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Returns the human-readable name of the deployment.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::deploy;
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.label(), "");
+        /// contract.set_label("staging".into()).unwrap();
+        /// assert_eq!(contract.label(), "staging");
+        /// ```
+        #[ink(message)]
+        pub fn label(&self) -> String {
+            self.label.clone()
+        }
+
+        /// Returns the label, or the contract's address in hex while none is
+        /// set, for wallets to name the deployment by.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::deploy;
+        /// let mut contract = deploy(false);
+        /// assert!(contract.display_label().starts_with("0x"));
+        /// contract.set_label("staging".into()).unwrap();
+        /// assert_eq!(contract.display_label(), "staging");
+        /// ```
+        #[ink(message)]
+        pub fn display_label(&self) -> String {
+            if self.label.is_empty() {
+                String::from(crate::fmt::hex(self.env().account_id().as_ref()).as_str())
+            } else {
+                self.label.clone()
+            }
+        }
+    }
+
+    impl UnitTestBug {
+        /// Replaces the flip policy.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, set_caller}, FlipError, FlipPolicy, PolicyMode};
+        /// let mut contract = deploy(false);
+        /// let policy = FlipPolicy { mode: PolicyMode::OwnerOnly, ..FlipPolicy::default() };
+        /// assert_eq!(contract.set_flip_policy(policy), Ok(()));
+        /// set_caller(accounts().bob);
+        /// assert_eq!(contract.flip(), Err(FlipError::PolicyDenied));
+        /// ```
+        #[ink(message)]
+        pub fn set_flip_policy(&mut self, policy: FlipPolicy) -> FlipResult<()> {
+            self.ensure_owner()?;
+            policy.validate()?;
+            self.audit(
+                ink::selector_bytes!("set_flip_policy"),
+                Self::audit_hash(&self.policy),
+                Self::audit_hash(&policy),
+            );
+            self.policy = policy;
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Returns the flip policy.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, FlipPolicy, PolicyMode};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.flip_policy(), FlipPolicy::default());
+        /// let policy = FlipPolicy { mode: PolicyMode::OwnerOnly, ..FlipPolicy::default() };
+        /// contract.set_flip_policy(policy.clone()).unwrap();
+        /// assert_eq!(contract.flip_policy(), policy);
+        /// ```
+        #[ink(message)]
+        pub fn flip_policy(&self) -> FlipPolicy {
+            self.policy.clone()
+        }
+    }
+
+    impl UnitTestBug {
+        /// Runs a privileged operation, tripping the circuit breaker after
+        /// `config.breaker_threshold` consecutive failures.
+        ///
+        /// A failing operation is returned inside `Ok` so that the call does not
+        /// revert and the failure count persists. Every operation leaves storage
+        /// untouched when it fails. Refused with `Paused` once tripped.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, FlipError, PrivilegedOp};
+        /// let mut contract = deploy(false);
+        /// let outcome = contract.run_privileged(PrivilegedOp::Claim);
+        /// assert_eq!(outcome, Ok(Err(FlipError::NothingToClaim)));
+        /// assert_eq!(contract.privileged_failures(), 1);
+        /// ```
+        #[ink(message)]
+        pub fn run_privileged(
+            &mut self,
+            op: PrivilegedOp,
+        ) -> FlipResult<FlipResult<Balance>> {
+            self.ensure_owner()?;
+            if self.breaker_tripped {
+                return Err(FlipError::Paused);
+            }
+            let outcome = match op {
+                PrivilegedOp::Claim => self.claim(),
+                PrivilegedOp::ForwardDonations => self.forward_donations(),
+                PrivilegedOp::WithdrawAssetFees(asset_id) => self.withdraw_asset_fees(asset_id),
+            };
+            if outcome.is_ok() {
+                self.privileged_failures = 0;
+            } else {
+                self.privileged_failures += 1;
+                let threshold = self.config.breaker_threshold;
+                if threshold > 0 && self.privileged_failures >= threshold {
+                    self.breaker_tripped = true;
+                    let seq = self.next_seq();
+                    self.env().emit_event(BreakerTripped {
+                        failures: self.privileged_failures,
+                        seq,
+                    });
+                }
+            }
+            self.debug_check_invariants();
+            Ok(outcome)
+        }
+
+        /// Resets the circuit breaker and resumes the contract.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy_with_config, set_caller}, Config, PrivilegedOp};
+        /// let mut contract = deploy_with_config(false, Config { breaker_threshold: 1, ..Config::default() });
+        /// contract.set_guardian(Some(accounts().charlie)).unwrap();
+        /// contract.run_privileged(PrivilegedOp::Claim).unwrap().unwrap_err();
+        /// assert!(contract.breaker_tripped());
+        /// set_caller(accounts().charlie);
+        /// assert_eq!(contract.reset_breaker(), Ok(()));
+        /// assert!(!contract.breaker_tripped());
+        /// ```
+        #[ink(message)]
+        pub fn reset_breaker(&mut self) -> FlipResult<()> {
+            if self.guardian != Some(self.env().caller()) {
+                return Err(FlipError::NotGuardian);
+            }
+            self.audit(
+                ink::selector_bytes!("reset_breaker"),
+                Self::audit_hash(&(self.breaker_tripped, self.privileged_failures)),
+                Self::audit_hash(&(false, 0u32)),
+            );
+            self.breaker_tripped = false;
+            self.privileged_failures = 0;
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Sets the account allowed to reset the circuit breaker.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, set_caller}, FlipError};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.set_guardian(Some(accounts().charlie)), Ok(()));
+        /// set_caller(accounts().bob);
+        /// assert_eq!(contract.set_guardian(None), Err(FlipError::NotOwner));
+        /// ```
+        #[ink(message)]
+        pub fn set_guardian(&mut self, guardian: Option<AccountId>) -> FlipResult<()> {
+            self.ensure_owner()?;
+            self.audit(
+                ink::selector_bytes!("set_guardian"),
+                Self::audit_hash(&self.guardian),
+                Self::audit_hash(&guardian),
+            );
+            self.guardian = guardian;
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Returns the account allowed to reset the circuit breaker.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.guardian(), None);
+        /// contract.set_guardian(Some(accounts().charlie)).unwrap();
+        /// assert_eq!(contract.guardian(), Some(accounts().charlie));
+        /// ```
+        #[ink(message)]
+        pub fn guardian(&self) -> Option<AccountId> {
+            self.guardian
+        }
+
+        /// Returns the number of consecutive failed privileged operations.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, PrivilegedOp};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.privileged_failures(), 0);
+        /// contract.run_privileged(PrivilegedOp::ForwardDonations).unwrap().unwrap_err();
+        /// assert_eq!(contract.privileged_failures(), 1);
+        /// ```
+        #[ink(message)]
+        pub fn privileged_failures(&self) -> u32 {
+            self.privileged_failures
+        }
+
+        /// Returns whether the circuit breaker has paused the contract.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy_with_config, Config, PrivilegedOp};
+        /// let mut contract = deploy_with_config(false, Config { breaker_threshold: 2, ..Config::default() });
+        /// contract.run_privileged(PrivilegedOp::Claim).unwrap().unwrap_err();
+        /// assert!(!contract.breaker_tripped());
+        /// contract.run_privileged(PrivilegedOp::Claim).unwrap().unwrap_err();
+        /// assert!(contract.breaker_tripped());
+        /// ```
+        #[ink(message)]
+        pub fn breaker_tripped(&self) -> bool {
+            self.breaker_tripped
+        }
+    }
+
+    impl UnitTestBug {
+        /// Returns up to `limit` audit entries, oldest first, starting at `offset`.
+        ///
+        /// Entries overwritten by newer ones are skipped and `limit` is clamped
+        /// to `AUDIT_LOG_SIZE`.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy};
+        /// let mut contract = deploy(false);
+        /// contract.set_treasury(Some(accounts().django)).unwrap();
+        /// let log = contract.audit_log(0, 10);
+        /// assert_eq!(log.len(), 1);
+        /// assert_eq!(log[0].by, accounts().alice);
+        /// assert_eq!(log[0].what, ink::selector_bytes!("set_treasury"));
+        /// ```
+        #[ink(message)]
+        pub fn audit_log(&self, offset: u32, limit: u32) -> Vec<AuditEntry> {
+            let start = offset.max(self.audit_count.saturating_sub(AUDIT_LOG_SIZE));
+            let end = start
+                .saturating_add(limit.min(AUDIT_LOG_SIZE))
+                .min(self.audit_count);
+            (start..end)
+                .map(|index| {
+                    self.audit_entries
+                        .get(index % AUDIT_LOG_SIZE)
+                        .expect("the latest `AUDIT_LOG_SIZE` entries are always present")
+                })
+                .collect()
+        }
+
+        /// Returns the number of audit entries ever recorded.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::deploy;
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.audit_count(), 0);
+        /// contract.set_label("audited".into()).unwrap();
+        /// assert_eq!(contract.audit_count(), 1);
+        /// ```
+        #[ink(message)]
+        pub fn audit_count(&self) -> u32 {
+            self.audit_count
+        }
+
+        /// Records a privileged change by the caller, from and to the `audit_hash` values.
+        fn audit(&mut self, what: [u8; 4], old: Hash, new: Hash) {
+            let index = self.audit_count;
+            let entry = AuditEntry {
+                index,
+                by: self.env().caller(),
+                what,
+                block: self.env().block_number(),
+                old,
+                new,
+            };
+            self.audit_entries.insert(index % AUDIT_LOG_SIZE, &entry);
+            self.audit_count = index + 1;
+        }
+
+        /// Hashes what a privileged operation changed, for `audit`.
+        fn audit_hash(value: &impl scale::Encode) -> Hash {
+            let mut output = [0u8; 32];
+            ink::env::hash_encoded::<Blake2x256, _>(value, &mut output);
+            Hash::from(output)
+        }
+    }
+
+    impl UnitTestBug {
+        /// Applies up to `config.max_batch_size` admin operations in order.
+        ///
+        /// Stops at the first failing operation and returns its error, which
+        /// reverts the whole call, so either every operation takes effect or none.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy}, AdminOp};
+        /// let mut contract = deploy(false);
+        /// let ops = vec![
+        ///     AdminOp::SetLabel("staging".into()),
+        ///     AdminOp::SetTreasury(Some(accounts().django)),
+        /// ];
+        /// assert_eq!(contract.multicall(ops), Ok(()));
+        /// assert_eq!(contract.label(), "staging");
+        /// assert_eq!(contract.treasury(), Some(accounts().django));
+        /// ```
+        #[ink(message)]
+        pub fn multicall(&mut self, ops: Vec<AdminOp>) -> FlipResult<()> {
+            self.ensure_owner()?;
+            if ops.len() > self.config.max_batch_size as usize {
+                return Err(FlipError::BatchTooLarge);
+            }
+            for op in ops {
+                match op {
+                    AdminOp::SetTreasury(treasury) => self.set_treasury(treasury),
+                    AdminOp::SetGuardian(guardian) => self.set_guardian(guardian),
+                    AdminOp::SetTrustedForwarder(forwarder) => {
+                        self.set_trusted_forwarder(forwarder)
+                    }
+                    AdminOp::SetChainId(chain_id) => self.set_chain_id(chain_id),
+                    AdminOp::SetLabel(label) => self.set_label(label),
+                    AdminOp::SetFlipPolicy(policy) => self.set_flip_policy(policy),
+                }?;
+            }
+            Ok(())
+        }
+    }
+
+    impl UnitTestBug {
+        /// Returns the estimated storage deposit of one more checkpoint.
+        ///
+        /// Priced at the `substrate-contracts-node` rates. Runtimes that also
+        /// charge for the storage key take more.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, STORAGE_DEPOSIT_PER_BYTE, STORAGE_DEPOSIT_PER_ITEM};
+        /// let contract = deploy(false);
+        /// // One item holding a block number and a `bool`.
+        /// assert_eq!(
+        ///     contract.deposit_for_history_record(),
+        ///     STORAGE_DEPOSIT_PER_ITEM + 5 * STORAGE_DEPOSIT_PER_BYTE
+        /// );
+        /// ```
+        #[ink(message)]
+        pub fn deposit_for_history_record(&self) -> Balance {
+            let checkpoint = Checkpoint {
+                block: 0,
+                value: false,
+            };
+            Self::storage_deposit(1, scale::Encode::encoded_size(&checkpoint))
+        }
+
+        /// Returns the estimated storage deposit of a new blob of `length` bytes.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, STORAGE_DEPOSIT_PER_BYTE, STORAGE_DEPOSIT_PER_ITEM};
+        /// let contract = deploy(false);
+        /// // An empty blob still stores its length.
+        /// assert_eq!(
+        ///     contract.deposit_for_blob(0),
+        ///     STORAGE_DEPOSIT_PER_ITEM + 4 * STORAGE_DEPOSIT_PER_BYTE
+        /// );
+        /// assert!(contract.deposit_for_blob(100) > contract.deposit_for_blob(10));
+        /// ```
+        #[ink(message)]
+        pub fn deposit_for_blob(&self, length: u32) -> Balance {
+            let chunks = length.div_ceil(BLOB_CHUNK_SIZE);
+            let prefixes = (0..chunks)
+                .map(|index| {
+                    let chunk = (length - index * BLOB_CHUNK_SIZE).min(BLOB_CHUNK_SIZE);
+                    scale::Encode::encoded_size(&scale::Compact(chunk))
+                })
+                .sum::<usize>();
+            let bytes = scale::Encode::encoded_size(&length) + length as usize + prefixes;
+            Self::storage_deposit(1 + chunks as usize, bytes)
+        }
+
+        /// Removes checkpoints no longer needed to answer `value_at` from
+        /// `before_block` on, at most `config.max_batch_size` per call.
+        ///
+        /// Returns the number removed. Their storage deposit is refunded to the caller.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{advance_blocks, deploy};
+        /// let mut contract = deploy(false);
+        /// for _ in 0..3 {
+        ///     advance_blocks(1);
+        ///     contract.flip().unwrap();
+        /// }
+        /// assert_eq!(contract.prune_history(2), Ok(2));
+        /// assert_eq!(contract.value_at(1), None);
+        /// assert_eq!(contract.value_at(2), Some(false));
+        /// ```
+        #[ink(message)]
+        pub fn prune_history(&mut self, before_block: BlockNumber) -> FlipResult<u32> {
+            self.ensure_owner()?;
+            let count = self.checkpoint_count();
+            let mut pruned = 0;
+            while pruned < self.config.max_batch_size
+                && self.first_checkpoint + 1 < count
+                && self.checkpoint(self.first_checkpoint + 1).block <= before_block
+            {
+                self.checkpoints.remove(self.first_checkpoint);
+                self.first_checkpoint += 1;
+                pruned += 1;
+            }
+            self.audit(
+                ink::selector_bytes!("prune_history"),
+                Self::audit_hash(&(self.first_checkpoint - pruned)),
+                Self::audit_hash(&self.first_checkpoint),
+            );
+            self.debug_check_invariants();
+            Ok(pruned)
+        }
+
+        /// Returns the index of the oldest kept checkpoint.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{advance_blocks, deploy};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.first_checkpoint(), 0);
+        /// advance_blocks(1);
+        /// contract.flip().unwrap();
+        /// contract.prune_history(1).unwrap();
+        /// assert_eq!(contract.first_checkpoint(), 1);
+        /// ```
+        #[ink(message)]
+        pub fn first_checkpoint(&self) -> u32 {
+            self.first_checkpoint
+        }
+
+        fn storage_deposit(items: usize, bytes: usize) -> Balance {
+            STORAGE_DEPOSIT_PER_ITEM * items as Balance
+                + STORAGE_DEPOSIT_PER_BYTE * bytes as Balance
+        }
+    }
+
+    impl UnitTestBug {
+        /// Flips the value for the duration of a callback into `borrower`.
+        ///
+        /// The borrower's `on_flash_flip` runs with the flipped value in place,
+        /// then the value is restored and the invariants are checked. A failed
+        /// or rejected callback, or a broken invariant, returns an error and so
+        /// reverts the whole call. The callback cannot re-enter the contract.
+        /// Needs `FEATURE_FLASH_FLIP`.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy}, FlipError};
+        /// let mut contract = deploy(false);
+        /// // The contract is deployed at alice's account and cannot borrow from itself.
+        /// let result = contract.flash_flip(accounts().alice, Vec::new());
+        /// assert_eq!(result, Err(FlipError::InvalidBorrower));
+        /// ```
+        #[ink(message)]
+        pub fn flash_flip(&mut self, borrower: AccountId, data: Vec<u8>) -> FlipResult<()> {
+            self.config.ensure_feature(FEATURE_FLASH_FLIP)?;
+            if self.config.paused || self.breaker_tripped {
+                return Err(FlipError::Paused);
+            }
+            if borrower == self.env().account_id() {
+                return Err(FlipError::InvalidBorrower);
+            }
+            self.ensure_trusted_callee(borrower)?;
+            self.settle_expiry();
+            let original = self.read_value();
+            self.value.set(&!original);
+            let mut callee: ink::contract_ref!(FlashBorrower) = borrower.into();
+            let accepted = callee
+                .call_mut()
+                .on_flash_flip(self.env().caller(), !original, data)
+                .gas_limit(self.config.call_ref_time_limit)
+                .try_invoke()
+                .map_err(|error| FlipError::FlashCallFailed(Some(error.into())))?
+                .map_err(|error| FlipError::FlashCallFailed(Some(error.into())))?;
+            if !accepted {
+                return Err(FlipError::FlashRejected);
+            }
+            self.value.set(&original);
+            if !self.verify_invariants().is_ok() {
+                return Err(FlipError::InvariantViolated);
+            }
+            Ok(())
+        }
+    }
+
+    impl UnitTestBug {
+        /// Flips like `flip`, recording `referrer` as having referred the caller.
+        ///
+        /// A referrer is only accepted on the caller's first flip.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, set_caller}, FlipError};
+        /// let mut contract = deploy(false);
+        /// let accounts = accounts();
+        /// set_caller(accounts.bob);
+        /// assert!(contract.flip_with_referrer(Some(accounts.charlie)).is_ok());
+        /// let again = contract.flip_with_referrer(Some(accounts.charlie));
+        /// assert_eq!(again, Err(FlipError::AlreadyReferred));
+        /// ```
+        #[ink(message, payable)]
+        pub fn flip_with_referrer(
+            &mut self,
+            referrer: Option<AccountId>,
+        ) -> FlipResult<MutationReceipt> {
+            trace_call!(self, "flip_with_referrer", referrer);
+            let gas = self.gas_left();
+            let caller = self.env().caller();
+            if let Some(referrer) = referrer {
+                if referrer == caller {
+                    return Err(FlipError::SelfReferral);
+                }
+                if self.referrers.contains(caller) || self.flip_count(caller) > 0 {
+                    return Err(FlipError::AlreadyReferred);
+                }
+            }
+            let seq = self.flip_as(caller)?;
+            if let Some(referrer) = referrer {
+                self.referrers.insert(caller, &referrer);
+                let count = self.referrals_of(referrer);
+                self.referral_counts.insert(referrer, &(count + 1));
+            }
+            Ok(self.receipt(seq, gas))
+        }
+
+        /// Returns the number of accounts `account` has referred.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy, set_caller};
+        /// let mut contract = deploy(false);
+        /// let accounts = accounts();
+        /// for referred in [accounts.bob, accounts.django] {
+        ///     set_caller(referred);
+        ///     contract.flip_with_referrer(Some(accounts.charlie)).unwrap();
+        /// }
+        /// assert_eq!(contract.referrals_of(accounts.charlie), 2);
+        /// ```
+        #[ink(message)]
+        pub fn referrals_of(&self, account: AccountId) -> u32 {
+            self.referral_counts.get(account).unwrap_or(0)
+        }
+
+        /// Returns who referred `account`, if anyone.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy, set_caller};
+        /// let mut contract = deploy(false);
+        /// let accounts = accounts();
+        /// set_caller(accounts.bob);
+        /// contract.flip_with_referrer(Some(accounts.charlie)).unwrap();
+        /// assert_eq!(contract.referrer_of(accounts.bob), Some(accounts.charlie));
+        /// assert_eq!(contract.referrer_of(accounts.charlie), None);
+        /// ```
+        #[ink(message)]
+        pub fn referrer_of(&self, account: AccountId) -> Option<AccountId> {
+            self.referrers.get(account)
+        }
+    }
+
+    impl UnitTestBug {
+        /// Replaces the fee discount tiers.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, FeeDiscounts, FeeTier, FlipError};
+        /// let mut contract = deploy(false);
+        /// let tiers = vec![
+        ///     FeeTier { min_flips: 10, discount_bps: 1_000 },
+        ///     FeeTier { min_flips: 100, discount_bps: 500 },
+        /// ];
+        /// // A later tier may never lower the discount.
+        /// assert_eq!(
+        ///     contract.set_fee_discounts(FeeDiscounts { tiers }),
+        ///     Err(FlipError::InvalidFeeDiscounts)
+        /// );
+        /// ```
+        #[ink(message)]
+        pub fn set_fee_discounts(&mut self, discounts: FeeDiscounts) -> FlipResult<()> {
+            self.ensure_owner()?;
+            discounts.validate()?;
+            self.audit(
+                ink::selector_bytes!("set_fee_discounts"),
+                Self::audit_hash(&self.fee_discounts),
+                Self::audit_hash(&discounts),
+            );
+            self.fee_discounts = discounts;
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Returns the fee discount tiers.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::deploy, FeeDiscounts, FeeTier};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.fee_discounts(), FeeDiscounts::default());
+        /// let discounts = FeeDiscounts { tiers: vec![FeeTier { min_flips: 10, discount_bps: 1_000 }] };
+        /// contract.set_fee_discounts(discounts.clone()).unwrap();
+        /// assert_eq!(contract.fee_discounts(), discounts);
+        /// ```
+        #[ink(message)]
+        pub fn fee_discounts(&self) -> FeeDiscounts {
+            self.fee_discounts.clone()
+        }
+    }
+
+    impl UnitTestBug {
+        /// Proposes setting the value to `value` and returns the proposal id.
+        ///
+        /// Votes are weighted by wrapped balances at the end of the current
+        /// block and accepted for `config.voting_period` blocks after it.
+        /// Only accounts holding a wrapped balance may propose.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, deploy, pay_as, set_caller}, FlipError};
+        /// let mut contract = deploy(false);
+        /// set_caller(accounts().bob);
+        /// assert_eq!(contract.propose_value(true), Err(FlipError::NoStake));
+        /// pay_as(accounts().bob, 100);
+        /// contract.wrap();
+        /// assert_eq!(contract.propose_value(true), Ok(0));
+        /// ```
+        #[ink(message)]
+        pub fn propose_value(&mut self, value: bool) -> FlipResult<u32> {
+            let proposer = self.env().caller();
+            if self.wrapped_balance_of(proposer) == 0 {
+                return Err(FlipError::NoStake);
+            }
+            let snapshot = self.env().block_number();
+            let id = self.proposal_count;
+            self.proposals.insert(
+                id,
+                &Proposal {
+                    proposer,
+                    value,
+                    snapshot,
+                    voting_ends: snapshot.saturating_add(self.config.voting_period),
+                    ayes: 0,
+                    nays: 0,
+                    executed: false,
+                },
+            );
+            self.proposal_count = id + 1;
+            self.debug_check_invariants();
+            Ok(id)
+        }
+
+        /// Votes on a proposal with the caller's wrapped balance at its snapshot.
+        ///
+        /// Returns the weight of the vote.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, advance_blocks, deploy, pay_as};
+        /// let mut contract = deploy(false);
+        /// pay_as(accounts().bob, 100);
+        /// contract.wrap();
+        /// let id = contract.propose_value(true).unwrap();
+        /// advance_blocks(1);
+        /// assert_eq!(contract.vote(id, true), Ok(100));
+        /// ```
+        #[ink(message)]
+        pub fn vote(&mut self, proposal_id: u32, aye: bool) -> FlipResult<Balance> {
+            let voter = self.env().caller();
+            let mut proposal = self
+                .proposal(proposal_id)
+                .ok_or(FlipError::ProposalNotFound)?;
+            let now = self.env().block_number();
+            if now <= proposal.snapshot || now > proposal.voting_ends {
+                return Err(FlipError::VotingNotOpen);
+            }
+            if self.votes.contains((proposal_id, voter)) {
+                return Err(FlipError::AlreadyVoted);
+            }
+            let weight = self.wrapped_balance_at(voter, proposal.snapshot);
+            if weight == 0 {
+                return Err(FlipError::NoStake);
+            }
+            if aye {
+                proposal.ayes += weight;
+            } else {
+                proposal.nays += weight;
+            }
+            self.votes.insert((proposal_id, voter), &aye);
+            self.proposals.insert(proposal_id, &proposal);
+            self.debug_check_invariants();
+            Ok(weight)
+        }
+
+        /// Sets the value of a proposal that won more aye than nay weight.
+        ///
+        /// Anyone may execute once the voting period is over.
+        ///
+        /// ```
+        /// # use unit_test_bug::{test_support::{accounts, advance_blocks, deploy, pay_as}, FlipError};
+        /// let mut contract = deploy(false);
+        /// pay_as(accounts().bob, 100);
+        /// contract.wrap();
+        /// let id = contract.propose_value(true).unwrap();
+        /// advance_blocks(1);
+        /// contract.vote(id, true).unwrap();
+        /// assert_eq!(contract.execute(id), Err(FlipError::VotingNotOver));
+        /// advance_blocks(contract.config().voting_period);
+        /// assert_eq!(contract.execute(id), Ok(()));
+        /// assert!(contract.get());
+        /// ```
+        #[ink(message)]
+        pub fn execute(&mut self, proposal_id: u32) -> FlipResult<()> {
+            let mut proposal = self
+                .proposal(proposal_id)
+                .ok_or(FlipError::ProposalNotFound)?;
+            if proposal.executed {
+                return Err(FlipError::ProposalExecuted);
+            }
+            if self.env().block_number() <= proposal.voting_ends {
+                return Err(FlipError::VotingNotOver);
+            }
+            if proposal.ayes <= proposal.nays {
+                return Err(FlipError::ProposalRejected);
+            }
+            proposal.executed = true;
+            self.proposals.insert(proposal_id, &proposal);
+            if proposal.value != self.read_value() {
+                self.write_value(proposal.value);
+                self.emit_flipped(self.env().caller());
+                self.write_checkpoint();
+            }
+            self.debug_check_invariants();
+            Ok(())
+        }
+
+        /// Returns the proposal with id `proposal_id`, if any.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy, pay_as};
+        /// let mut contract = deploy(false);
+        /// pay_as(accounts().bob, 100);
+        /// contract.wrap();
+        /// let id = contract.propose_value(true).unwrap();
+        /// let proposal = contract.proposal(id).unwrap();
+        /// assert_eq!((proposal.proposer, proposal.value), (accounts().bob, true));
+        /// assert_eq!(contract.proposal(id + 1), None);
+        /// ```
+        #[ink(message)]
+        pub fn proposal(&self, proposal_id: u32) -> Option<Proposal> {
+            self.proposals.get(proposal_id)
+        }
+
+        /// Returns the number of proposals made.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, deploy, pay_as};
+        /// let mut contract = deploy(false);
+        /// assert_eq!(contract.proposal_count(), 0);
+        /// pay_as(accounts().bob, 100);
+        /// contract.wrap();
+        /// contract.propose_value(true).unwrap();
+        /// assert_eq!(contract.proposal_count(), 1);
+        /// ```
+        #[ink(message)]
+        pub fn proposal_count(&self) -> u32 {
+            self.proposal_count
+        }
+
+        /// Returns how `voter` voted on a proposal, `true` for aye.
+        ///
+        /// ```
+        /// # use unit_test_bug::test_support::{accounts, advance_blocks, deploy, pay_as};
+        /// let mut contract = deploy(false);
+        /// let bob = accounts().bob;
+        /// pay_as(bob, 100);
+        /// contract.wrap();
+        /// let id = contract.propose_value(true).unwrap();
+        /// assert_eq!(contract.vote_of(id, bob), None);
+        /// advance_blocks(1);
+        /// contract.vote(id, false).unwrap();
+        /// assert_eq!(contract.vote_of(id, bob), Some(false));
+        /// ```
+        #[ink(message)]
+        pub fn vote_of(&self, proposal_id: u32, voter: AccountId) -> Option<bool> {
+            self.votes.get((proposal_id, voter))
+        }
+    }
+
+    impl IFlipQuery for UnitTestBug {
+        #[ink(message)]
+        fn get(&self) -> bool {
+            UnitTestBug::get(self)
+        }
+
+        #[ink(message)]
+        fn stats(&self) -> EpochStats {
+            self.epoch_stats()
+        }
+    }
+
+    /// Unit tests in Rust are normally defined within such a `#[cfg(test)]`
+    /// module and test functions are marked with a `#[test]` attribute.
+    /// The below code is technically just normal Rust code.
+    #[cfg(test)]
+    mod tests {
+
+        /// Imports all the definitions from the outer scope so we can use them here.
+        use super::*;
+        use crate::{
+            test_support::{
+                accounts, advance_blocks, balance_of, call_reverting, pay_as, set_balance,
+                set_caller, set_value_transferred, Snapshot,
+            },
+            AssetsError,
+        };
+
+        /// Reads stay constant or logarithmic in the history length.
+        #[ink::test]
+        fn reads_do_not_scan_storage() {
+            use crate::test_support::{op_counts, OpCounts};
+
+            let mut unit_test_bug = UnitTestBug::new(false);
+            for _ in 0..64 {
+                advance_blocks(1);
+                unit_test_bug.flip().expect("flip succeeds");
+            }
+            let read_one = OpCounts {
+                reads: 1,
+                writes: 0,
+            };
+            assert_eq!(op_counts(|| unit_test_bug.get()).1, read_one);
+            assert_eq!(op_counts(|| unit_test_bug.checkpoint_count()).1, read_one);
+            // A binary search over 65 checkpoints, plus reading their count.
+            let (value, ops) = op_counts(|| unit_test_bug.value_at(20));
+            assert_eq!(value, Some(false));
+            assert!(ops.reads <= 9, "value_at read {} cells", ops.reads);
+            assert_eq!(ops.writes, 0);
+        }
+
+        /// `API_VERSION` is bumped with the crate and reports the built features.
+        #[ink::test]
+        fn api_version_matches_crate_version() {
+            let version = UnitTestBug::new(false).api_version();
+            assert_eq!(
+                format!("{}.{}.{}", version.major, version.minor, version.patch),
+                env!("CARGO_PKG_VERSION")
+            );
+            assert_eq!(version.supports(API_FEATURE_ZK), cfg!(feature = "zk"));
+            assert_eq!(version.supports(API_FEATURE_BLS), cfg!(feature = "bls"));
+            assert_eq!(version.supports(API_FEATURE_DEBUG), cfg!(feature = "debug"));
+        }
+
+        /// `true_ratio_since` weighs each value by how long it was held.
+        #[ink::test]
+        fn true_ratio_is_time_weighted() {
+            use ink::env::test::set_block_timestamp;
+
+            let mut unit_test_bug = UnitTestBug::new(false);
+            // `true` over [1_000, 4_000) and from 6_000 on.
+            for at in [1_000, 4_000, 6_000] {
+                advance_blocks(1);
+                set_block_timestamp::<ink::env::DefaultEnvironment>(at);
+                unit_test_bug.flip().expect("flip succeeds");
+            }
+            // Flipping twice at one timestamp leaves no time to weigh.
+            unit_test_bug.flip().expect("flip succeeds");
+            unit_test_bug.flip().expect("flip succeeds");
+            set_block_timestamp::<ink::env::DefaultEnvironment>(7_000);
+
+            assert_eq!(unit_test_bug.true_ratio_since(0), Some(5_714));
+            assert_eq!(unit_test_bug.true_ratio_since(2_000), Some(6_000));
+            assert_eq!(unit_test_bug.true_ratio_since(4_000), Some(3_333));
+            assert_eq!(unit_test_bug.true_ratio_since(4_500), Some(4_000));
+            assert_eq!(unit_test_bug.true_ratio_since(6_000), Some(10_000));
+            assert_eq!(unit_test_bug.true_ratio_since(7_000), None);
+        }
+
+        /// A `flip_until` flip reverts exactly at its deadline, and the revert
+        /// reaches the history once the next flip stores it.
+        #[ink::test]
+        fn flip_until_reverts_at_deadline() {
+            use ink::env::test::set_block_timestamp;
+
+            let mut unit_test_bug = UnitTestBug::new(false);
+            assert_eq!(unit_test_bug.flip_until(0), Err(FlipError::DeadlineInPast));
+            advance_blocks(1);
+            set_block_timestamp::<ink::env::DefaultEnvironment>(1_000);
+            unit_test_bug.flip_until(2_000).expect("flip succeeds");
+            assert_eq!(unit_test_bug.value_expiry, Some(2_000));
+
+            advance_blocks(1);
+            set_block_timestamp::<ink::env::DefaultEnvironment>(1_999);
+            assert!(unit_test_bug.get());
+            set_block_timestamp::<ink::env::DefaultEnvironment>(2_000);
+            assert!(!unit_test_bug.get());
+            // Nothing was written at the deadline, so block 2 still reads the flip.
+            assert_eq!(unit_test_bug.value_at(2), Some(true));
+            assert!(unit_test_bug.verify_invariants().is_ok());
+
+            set_block_timestamp::<ink::env::DefaultEnvironment>(3_000);
+            assert_eq!(unit_test_bug.true_ratio_since(0), Some(3_333));
+            // The next flip starts from the reverted value and stores it.
+            advance_blocks(1);
+            set_block_timestamp::<ink::env::DefaultEnvironment>(3_000);
+            unit_test_bug.flip().expect("flip succeeds");
+            assert!(unit_test_bug.get());
+            assert_eq!(unit_test_bug.value_expiry, None);
+            assert_eq!(unit_test_bug.value_at(3), Some(true));
+            assert_eq!(unit_test_bug.checkpoint_count(), 3);
+            assert_eq!(unit_test_bug.true_ratio_since(0), Some(3_333));
+            set_block_timestamp::<ink::env::DefaultEnvironment>(4_000);
+            assert_eq!(unit_test_bug.true_ratio_since(0), Some(5_000));
+        }
+
+        /// Flipping again before the deadline drops it.
+        #[ink::test]
+        fn flip_before_deadline_keeps_value() {
+            use ink::env::test::set_block_timestamp;
+
+            let mut unit_test_bug = UnitTestBug::new(false);
+            unit_test_bug.flip_until(1_000).expect("flip succeeds");
+            advance_blocks(1);
+            unit_test_bug.flip().expect("flip succeeds");
+            set_block_timestamp::<ink::env::DefaultEnvironment>(5_000);
+            assert!(!unit_test_bug.get());
+            assert_eq!(unit_test_bug.value_at(1), Some(false));
+        }
+
+        /// A restored snapshot lets a second path start where the first did.
+        #[ink::test]
+        fn snapshot_branches_paths() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            set_config(&mut unit_test_bug, |config| config.flip_fee = 100);
+            let bob = accounts().bob;
+            let snapshot = Snapshot::take(&unit_test_bug);
+            let bob_start = balance_of(bob);
+
+            // Path A: bob pays and flips.
+            pay_as(bob, 100);
+            assert_eq!(unit_test_bug.flip().map(drop), Ok(()));
+            assert_eq!(unit_test_bug.fee_pot(), 100);
+
+            // Path B starts over: bob underpays.
+            snapshot.restore(&mut unit_test_bug);
+            assert_eq!(balance_of(bob), bob_start);
+            assert_eq!(unit_test_bug.fee_pot(), 0);
+            assert!(!unit_test_bug.get());
+            pay_as(bob, 10);
+            assert_eq!(unit_test_bug.flip(), Err(FlipError::InsufficientFee));
+        }
+
+        /// Every test starts from the environment `assert_pristine` expects.
+        #[ink::test]
+        fn environment_starts_pristine() {
+            crate::test_support::assert_pristine();
+        }
+
+        /// State left behind by a contract is reported as a leak.
+        #[ink::test]
+        #[should_panic(expected = "storage cells used by the callee")]
+        fn assert_pristine_reports_leaks() {
+            UnitTestBug::new(false).flip().expect("flip succeeds");
+            crate::test_support::assert_pristine();
+        }
+
+        /// We test if the default constructor does its job.
+        #[ink::test]
+        fn default_works() {
+            let unit_test_bug = UnitTestBug::default();
+            assert_eq!(unit_test_bug.get(), false);
+        }
+
+        /// We test a simple use case of our contract.
+        #[ink::test]
+        fn it_works() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            assert_eq!(unit_test_bug.get(), false);
+            // Error is returned, revert should occur, and value should remain as false
+            assert_eq!(unit_test_bug.flip_with_error(), Err(FlipError::FlipError));
+            // This test is going to FAIL because the revert did not occur
+            assert_eq!(unit_test_bug.get(), false);
+        }
+
+        type Event = <UnitTestBug as ::ink::reflect::ContractEventBase>::Type;
+
+        /// Decodes every event emitted so far.
+        fn recorded_events() -> Vec<Event> {
+            ink::env::test::recorded_events()
+                .map(|event| {
+                    <Event as scale::Decode>::decode(&mut &event.data[..]).expect("event decodes")
+                })
+                .collect()
+        }
+
+        /// Applies a configuration change directly, bypassing the timelock.
+        fn set_config(unit_test_bug: &mut UnitTestBug, change: impl FnOnce(&mut Config)) {
+            change(&mut unit_test_bug.config);
+        }
+
+        /// History queries before the first checkpoint have no answer.
+        #[ink::test]
+        fn value_at_before_first_checkpoint_is_none() {
+            advance_blocks(5);
+            let unit_test_bug = UnitTestBug::new(true);
+            assert_eq!(unit_test_bug.value_at(4), None);
+            assert_eq!(unit_test_bug.value_at(5), Some(true));
+        }
+
+        /// Queries on the exact block of a change see the new value.
+        #[ink::test]
+        fn value_at_exact_and_between_blocks() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            advance_blocks(3);
+            unit_test_bug.flip().expect("flip failed");
+            advance_blocks(4);
+            unit_test_bug.flip().expect("flip failed");
+
+            assert_eq!(unit_test_bug.value_at(0), Some(false));
+            assert_eq!(unit_test_bug.value_at(2), Some(false));
+            assert_eq!(unit_test_bug.value_at(3), Some(true));
+            assert_eq!(unit_test_bug.value_at(6), Some(true));
+            assert_eq!(unit_test_bug.value_at(7), Some(false));
+        }
+
+        /// Blocks past the latest checkpoint report the current value.
+        #[ink::test]
+        fn value_at_latest_matches_get() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            advance_blocks(2);
+            unit_test_bug.flip().expect("flip failed");
+            assert_eq!(unit_test_bug.value_at(u32::MAX), Some(unit_test_bug.get()));
+        }
+
+        /// The core storage keys are part of the upgrade contract and must never
+        /// move from the keys checked in with the golden table.
+        #[ink::test]
+        fn core_storage_keys_are_stable() {
+            use ink::storage::traits::StorageKey;
+
+            assert_eq!(<ValueCell as StorageKey>::KEY, golden_storage_key("value"));
+            assert_eq!(
+                <CheckpointsCell as StorageKey>::KEY,
+                golden_storage_key("checkpoints")
+            );
+            assert_eq!(
+                <CheckpointCountCell as StorageKey>::KEY,
+                golden_storage_key("checkpoint_count")
+            );
+        }
+
+        /// Without a subscription every flip has to pay the fee.
+        #[ink::test]
+        fn flip_requires_fee_without_subscription() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            set_config(&mut unit_test_bug, |config| config.flip_fee = 10);
+
+            set_caller(accounts().bob);
+            set_value_transferred(9);
+            assert_eq!(unit_test_bug.flip(), Err(FlipError::InsufficientFee));
+            assert!(!unit_test_bug.get());
+            assert_eq!(unit_test_bug.fee_pot(), 0);
+
+            set_value_transferred(10);
+            assert_eq!(unit_test_bug.flip().map(drop), Ok(()));
+            assert!(unit_test_bug.get());
+            assert_eq!(unit_test_bug.fee_pot(), 10);
+        }
+
+        /// Only the owner may queue or cancel configuration changes.
+        #[ink::test]
+        fn config_changes_are_owner_only() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            set_caller(accounts().bob);
+            assert_eq!(
+                unit_test_bug.propose_config(Config::default()),
+                Err(FlipError::NotOwner)
+            );
+            assert_eq!(unit_test_bug.cancel_config(), Err(FlipError::NotOwner));
+        }
+
+        /// A queued change applies only after the timelock and emits old and new values.
+        #[ink::test]
+        fn config_change_waits_for_timelock() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let old = unit_test_bug.config();
+            let new = Config { flip_fee: 5, ..old };
+            assert_eq!(
+                unit_test_bug.apply_config(),
+                Err(FlipError::NoPendingConfig)
+            );
+            assert_eq!(unit_test_bug.propose_config(new), Ok(old.timelock_delay));
+
+            advance_blocks(old.timelock_delay - 1);
+            assert_eq!(unit_test_bug.apply_config(), Err(FlipError::TimelockActive));
+            assert_eq!(unit_test_bug.config(), old);
+
+            advance_blocks(1);
+            set_caller(accounts().bob);
+            assert_eq!(unit_test_bug.apply_config(), Ok(()));
+            assert_eq!(unit_test_bug.config(), new);
+            assert_eq!(unit_test_bug.pending_config(), None);
+
+            let events = recorded_events();
+            assert_eq!(events.len(), 1);
+            let Event::ConfigChanged(changed) = &events[0] else {
+                panic!("expected ConfigChanged");
+            };
+            assert_eq!((changed.old, changed.new), (old, new));
+        }
+
+        /// An invalid change is rejected as a whole, leaving every field untouched.
+        #[ink::test]
+        fn invalid_config_change_is_atomic() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let old = unit_test_bug.config();
+            let queued = Config { flip_fee: 1, ..old };
+            unit_test_bug
+                .propose_config(queued)
+                .expect("valid proposal");
+
+            for invalid in [
+                Config {
+                    flip_fee: 99,
+                    max_batch_size: 0,
+                    ..old
+                },
+                Config {
+                    paused: true,
+                    cooldown_blocks: MAX_COOLDOWN_BLOCKS + 1,
+                    ..old
+                },
+                Config {
+                    subscription_price: 7,
+                    timelock_delay: MAX_TIMELOCK_DELAY + 1,
+                    ..old
+                },
+            ] {
+                assert_eq!(
+                    unit_test_bug.propose_config(invalid),
+                    Err(FlipError::InvalidConfig)
+                );
+                assert_eq!(unit_test_bug.config(), old);
+                assert_eq!(
+                    unit_test_bug.pending_config().map(|pending| pending.config),
+                    Some(queued)
+                );
+            }
+            assert_eq!(ink::env::test::recorded_events().count(), 0);
+        }
+
+        /// Cancelling drops the queued change.
+        #[ink::test]
+        fn cancel_config_drops_pending_change() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            assert_eq!(
+                unit_test_bug.cancel_config(),
+                Err(FlipError::NoPendingConfig)
+            );
+            unit_test_bug
+                .propose_config(Config::default())
+                .expect("valid proposal");
+            assert_eq!(unit_test_bug.cancel_config(), Ok(()));
+            advance_blocks(Config::default().timelock_delay);
+            assert_eq!(
+                unit_test_bug.apply_config(),
+                Err(FlipError::NoPendingConfig)
+            );
+        }
+
+        /// Pausing and the cooldown both stop flips.
+        #[ink::test]
+        fn paused_and_cooldown_block_flips() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            set_config(&mut unit_test_bug, |config| config.paused = true);
+            assert_eq!(unit_test_bug.flip(), Err(FlipError::Paused));
+
+            set_config(&mut unit_test_bug, |config| {
+                config.paused = false;
+                config.cooldown_blocks = 3;
+            });
+            assert_eq!(unit_test_bug.flip().map(drop), Ok(()));
+            advance_blocks(2);
+            assert_eq!(unit_test_bug.flip(), Err(FlipError::CooldownActive));
+            set_caller(accounts().bob);
+            assert_eq!(unit_test_bug.flip().map(drop), Ok(()));
+            set_caller(accounts().alice);
+            advance_blocks(1);
+            assert_eq!(unit_test_bug.flip().map(drop), Ok(()));
+        }
+
+        /// Subscribing fails until a price is set and with payments below one block.
+        #[ink::test]
+        fn subscribe_rejects_disabled_and_underpaid() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            set_value_transferred(100);
+            assert_eq!(
+                unit_test_bug.subscribe(),
+                Err(FlipError::SubscriptionsDisabled)
+            );
+
+            set_config(&mut unit_test_bug, |config| config.subscription_price = 50);
+            set_value_transferred(49);
+            assert_eq!(
+                unit_test_bug.subscribe(),
+                Err(FlipError::InsufficientPayment)
+            );
+            assert_eq!(unit_test_bug.subscription_of(accounts().alice), None);
+        }
+
+        /// A subscription covers flips up to, but not including, its expiry block.
+        #[ink::test]
+        fn subscription_expiry_transition() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            set_config(&mut unit_test_bug, |config| config.flip_fee = 10);
+            set_config(&mut unit_test_bug, |config| config.subscription_price = 5);
+
+            set_caller(accounts().bob);
+            set_value_transferred(15);
+            let subscription = unit_test_bug.subscribe().expect("subscribe failed");
+            assert_eq!(subscription.started_at, 0);
+            assert_eq!(subscription.expires_at, 3);
+            assert_eq!(unit_test_bug.fee_pot(), 15);
+
+            set_value_transferred(0);
+            advance_blocks(2);
+            assert!(unit_test_bug.is_subscribed(accounts().bob));
+            assert_eq!(unit_test_bug.flip().map(drop), Ok(()));
+
+            advance_blocks(1);
+            assert!(!unit_test_bug.is_subscribed(accounts().bob));
+            assert_eq!(unit_test_bug.flip(), Err(FlipError::InsufficientFee));
+        }
+
+        /// Renewing extends an active subscription but restarts an expired one.
+        #[ink::test]
+        fn subscription_renewal_extends_or_restarts() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            set_config(&mut unit_test_bug, |config| config.subscription_price = 5);
+            set_caller(accounts().bob);
+
+            set_value_transferred(10);
+            unit_test_bug.subscribe().expect("subscribe failed");
+            advance_blocks(1);
+            let extended = unit_test_bug.subscribe().expect("renewal failed");
+            assert_eq!((extended.started_at, extended.expires_at), (0, 4));
+            assert_eq!(extended.paid, 20);
+
+            advance_blocks(5);
+            set_value_transferred(7);
+            let restarted = unit_test_bug.subscribe().expect("restart failed");
+            assert_eq!((restarted.started_at, restarted.expires_at), (6, 7));
+            assert_eq!(restarted.paid, 7);
+            assert_eq!(unit_test_bug.fee_pot(), 27);
+        }
+
+        /// An outbid bidder gets their exact bid back and the contract keeps only the top bid.
+        #[ink::test]
+        fn outbid_bidder_is_refunded() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let contract = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            let (bob, charlie) = (accounts().bob, accounts().charlie);
+            let contract_start = balance_of(contract);
+            let (bob_start, charlie_start) = (balance_of(bob), balance_of(charlie));
+
+            pay_as(bob, 100);
+            assert_eq!(unit_test_bug.bid().map(|bid| bid.epoch), Ok(1));
+            pay_as(charlie, 150);
+            unit_test_bug.bid().expect("higher bid failed");
+
+            assert_eq!(unit_test_bug.credit_of(bob), 100);
+            assert_eq!(balance_of(charlie), charlie_start - 150);
+            assert_eq!(
+                unit_test_bug.highest_bid(),
+                Some(Bid {
+                    bidder: charlie,
+                    amount: 150,
+                    epoch: 1
+                })
+            );
+
+            set_caller(bob);
+            assert_eq!(unit_test_bug.withdraw_credit(), Ok(100));
+            assert_eq!(balance_of(bob), bob_start);
+            assert_eq!(balance_of(contract), contract_start + 150);
+            assert_eq!(
+                unit_test_bug.withdraw_credit(),
+                Err(FlipError::NothingToClaim)
+            );
+        }
+
+        /// Equal or lower bids are rejected and leave the auction untouched.
+        #[ink::test]
+        fn bid_must_exceed_highest() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            pay_as(accounts().bob, 0);
+            assert_eq!(unit_test_bug.bid(), Err(FlipError::BidTooLow));
+            pay_as(accounts().bob, 100);
+            unit_test_bug.bid().expect("first bid failed");
+            pay_as(accounts().charlie, 100);
+            assert_eq!(unit_test_bug.bid(), Err(FlipError::BidTooLow));
+            assert_eq!(
+                unit_test_bug.highest_bid().map(|bid| bid.bidder),
+                Some(accounts().bob)
+            );
+        }
+
+        /// The winner flips exclusively during their epoch and the bid lands in the fee pot.
+        #[ink::test]
+        fn auction_winner_holds_exclusive_rights_for_epoch() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            pay_as(accounts().bob, 100);
+            unit_test_bug.bid().expect("bid failed");
+            assert_eq!(unit_test_bug.rights_holder(), None);
+
+            advance_blocks(DEFAULT_EPOCH_LENGTH);
+            assert_eq!(unit_test_bug.rights_holder(), Some(accounts().bob));
+            pay_as(accounts().charlie, 0);
+            assert_eq!(unit_test_bug.flip(), Err(FlipError::NotRightsHolder));
+            pay_as(accounts().bob, 0);
+            assert_eq!(unit_test_bug.flip().map(drop), Ok(()));
+            assert_eq!(unit_test_bug.fee_pot(), 100);
+
+            advance_blocks(DEFAULT_EPOCH_LENGTH);
+            assert_eq!(unit_test_bug.rights_holder(), None);
+            pay_as(accounts().charlie, 0);
+            assert_eq!(unit_test_bug.flip().map(drop), Ok(()));
+        }
+
+        /// A bid placed in a later epoch does not refund the already settled winner.
+        #[ink::test]
+        fn settled_bid_is_not_refunded() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let bob = accounts().bob;
+            pay_as(bob, 100);
+            unit_test_bug.bid().expect("bid failed");
+            let bob_after_bid = balance_of(bob);
+
+            advance_blocks(DEFAULT_EPOCH_LENGTH);
+            pay_as(accounts().charlie, 100);
+            assert_eq!(unit_test_bug.bid().map(|bid| bid.epoch), Ok(2));
+            assert_eq!(balance_of(bob), bob_after_bid);
+            assert_eq!(unit_test_bug.rights_holder(), Some(bob));
+            assert_eq!(unit_test_bug.fee_pot(), 100);
+        }
+
+        /// Small deterministic xorshift generator for property tests.
+        struct Rng(u64);
+
+        impl Rng {
+            fn next(&mut self) -> u64 {
+                self.0 ^= self.0 << 13;
+                self.0 ^= self.0 >> 7;
+                self.0 ^= self.0 << 17;
+                self.0
+            }
+
+            fn below(&mut self, bound: u64) -> u64 {
+                self.next() % bound
+            }
+        }
+
+        /// Fees unlock per block and claiming drains exactly what is claimable.
+        #[ink::test]
+        fn vested_fees_unlock_linearly() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let alice = accounts().alice;
+            set_config(&mut unit_test_bug, |config| config.flip_fee = 100);
+            unit_test_bug.set_vesting_rate(30).expect("owner sets rate");
+            pay_as(accounts().bob, 100);
+            unit_test_bug.flip().expect("flip failed");
+
+            assert_eq!(unit_test_bug.claimable(), 0);
+            advance_blocks(2);
+            assert_eq!(unit_test_bug.claimable(), 60);
+
+            set_caller(accounts().bob);
+            assert_eq!(unit_test_bug.claim(), Err(FlipError::NotOwner));
+            set_caller(alice);
+            let alice_start = balance_of(alice);
+            assert_eq!(unit_test_bug.claim(), Ok(60));
+            assert_eq!(balance_of(alice), alice_start + 60);
+            assert_eq!(unit_test_bug.claim(), Err(FlipError::NothingToClaim));
+
+            // The schedule is capped by what the pot holds.
+            advance_blocks(10);
+            assert_eq!(unit_test_bug.claimable(), 40);
+            assert_eq!(unit_test_bug.claim(), Ok(40));
+            assert_eq!(unit_test_bug.fee_pot(), 0);
+        }
+
+        /// Changing the rate keeps what already unlocked and applies from now on.
+        #[ink::test]
+        fn vesting_rate_change_is_not_retroactive() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            pay_as(accounts().bob, 1_000);
+            set_config(&mut unit_test_bug, |config| config.subscription_price = 1);
+            unit_test_bug.subscribe().expect("subscribe failed");
+            set_caller(accounts().alice);
+
+            unit_test_bug.set_vesting_rate(10).expect("owner sets rate");
+            advance_blocks(3);
+            unit_test_bug.set_vesting_rate(1).expect("owner sets rate");
+            assert_eq!(unit_test_bug.claimable(), 30);
+            advance_blocks(5);
+            assert_eq!(unit_test_bug.claimable(), 35);
+        }
+
+        /// Property: whatever happens, total claims stay within the linear schedule and the fees paid.
+        #[test]
+        fn claims_never_exceed_linear_schedule() {
+            for seed in 1..=20 {
+                // Each run needs fresh storage, the participants outlive the contract value.
+                ink::env::test::run_test::<ink::env::DefaultEnvironment, _>(|_| {
+                    let mut rng = Rng(seed);
+                    let mut unit_test_bug = UnitTestBug::new(false);
+                    let alice = accounts().alice;
+                    let rate = Balance::from(rng.below(50) as u32);
+                    unit_test_bug
+                        .set_vesting_rate(rate)
+                        .expect("owner sets rate");
+                    set_config(&mut unit_test_bug, |config| config.flip_fee = 1);
+                    let start = ink::env::block_number::<ink::env::DefaultEnvironment>();
+                    let mut fees_paid = 0;
+
+                    for _ in 0..50 {
+                        match rng.below(3) {
+                            0 => advance_blocks(rng.below(5) as u32),
+                            1 => {
+                                let fee = Balance::from(rng.below(200) as u32) + 1;
+                                set_balance(accounts().bob, fee);
+                                pay_as(accounts().bob, fee);
+                                unit_test_bug.flip().expect("flip failed");
+                                fees_paid += fee;
+                            }
+                            _ => {
+                                set_caller(alice);
+                                set_value_transferred(0);
+                                let _ = unit_test_bug.claim();
+                            }
+                        }
+                        let now = ink::env::block_number::<ink::env::DefaultEnvironment>();
+                        let schedule = rate * Balance::from(now - start);
+                        assert!(unit_test_bug.fees_claimed() <= schedule);
+                        assert!(unit_test_bug.fees_claimed() <= fees_paid);
+                        assert_eq!(
+                            unit_test_bug.fees_claimed() + unit_test_bug.fee_pot(),
+                            fees_paid
+                        );
+                    }
+                    Ok(())
+                })
+                .expect("scenario runs");
+            }
+        }
+
+        /// Builds a four-leaf airdrop tree and returns its root and per-leaf proofs.
+        fn airdrop_tree(claimants: [(u32, AccountId); 4]) -> (Hash, Vec<Vec<Hash>>) {
+            let leaves: Vec<Hash> = claimants
+                .iter()
+                .map(|(index, account)| airdrop_leaf(*index, *account))
+                .collect();
+            let left = hash_pair(leaves[0], leaves[1]);
+            let right = hash_pair(leaves[2], leaves[3]);
+            let proofs = vec![
+                vec![leaves[1], right],
+                vec![leaves[0], right],
+                vec![leaves[3], left],
+                vec![leaves[2], left],
+            ];
+            (hash_pair(left, right), proofs)
+        }
+
+        /// Returns an airdrop over bob, charlie, django and eve funded for `claims` payouts of 10.
+        fn unit_test_bug_with_airdrop(claims: Balance) -> (UnitTestBug, Vec<Vec<Hash>>) {
+            let accounts = accounts();
+            let (root, proofs) = airdrop_tree([
+                (0, accounts.bob),
+                (1, accounts.charlie),
+                (200, accounts.django),
+                (201, accounts.eve),
+            ]);
+            let mut unit_test_bug = UnitTestBug::new(false);
+            pay_as(accounts.alice, 10 * claims);
+            unit_test_bug
+                .set_airdrop(root, 10)
+                .expect("owner sets airdrop");
+            set_value_transferred(0);
+            (unit_test_bug, proofs)
+        }
+
+        /// Each slot pays out once and repeats are rejected.
+        #[ink::test]
+        fn airdrop_double_claim_is_rejected() {
+            let (mut unit_test_bug, proofs) = unit_test_bug_with_airdrop(4);
+            let bob = accounts().bob;
+            let bob_start = balance_of(bob);
+            set_caller(bob);
+            assert_eq!(unit_test_bug.claim_airdrop(0, proofs[0].clone()), Ok(()));
+            assert_eq!(balance_of(bob), bob_start + 10);
+            assert!(unit_test_bug.is_claimed(0));
+            assert!(!unit_test_bug.is_claimed(1));
+            assert_eq!(
+                unit_test_bug.claim_airdrop(0, proofs[0].clone()),
+                Err(FlipError::AlreadyClaimed)
+            );
+            assert_eq!(balance_of(bob), bob_start + 10);
+
+            // Indexes in another bitmap word are tracked independently.
+            set_caller(accounts().eve);
+            assert_eq!(unit_test_bug.claim_airdrop(201, proofs[3].clone()), Ok(()));
+            assert!(unit_test_bug.is_claimed(201));
+            assert!(!unit_test_bug.is_claimed(200));
+            assert_eq!(unit_test_bug.airdrop_pool(), 20);
+        }
+
+        /// Proofs are bound to both the index and the caller.
+        #[ink::test]
+        fn airdrop_rejects_foreign_proofs() {
+            let (mut unit_test_bug, proofs) = unit_test_bug_with_airdrop(4);
+            set_caller(accounts().charlie);
+            assert_eq!(
+                unit_test_bug.claim_airdrop(0, proofs[0].clone()),
+                Err(FlipError::InvalidProof)
+            );
+            assert_eq!(
+                unit_test_bug.claim_airdrop(0, proofs[1].clone()),
+                Err(FlipError::InvalidProof)
+            );
+            assert!(!unit_test_bug.is_claimed(0));
+        }
+
+        /// A claim that fails after proof verification leaves the slot unmarked and claimable later.
+        #[ink::test]
+        fn reverted_airdrop_claim_does_not_mark() {
+            let (mut unit_test_bug, proofs) = unit_test_bug_with_airdrop(1);
+            set_caller(accounts().charlie);
+            assert_eq!(unit_test_bug.claim_airdrop(1, proofs[1].clone()), Ok(()));
+
+            set_caller(accounts().bob);
+            assert_eq!(
+                unit_test_bug.claim_airdrop(0, proofs[0].clone()),
+                Err(FlipError::AirdropExhausted)
+            );
+            assert!(!unit_test_bug.is_claimed(0));
+
+            let root = unit_test_bug.airdrop_root.expect("airdrop configured");
+            pay_as(accounts().alice, 10);
+            unit_test_bug
+                .set_airdrop(root, 10)
+                .expect("owner tops up airdrop");
+            set_caller(accounts().bob);
+            set_value_transferred(0);
+            assert_eq!(unit_test_bug.claim_airdrop(0, proofs[0].clone()), Ok(()));
+            assert!(unit_test_bug.is_claimed(0));
+        }
+
+        /// In-memory `pallet-assets` state behind the mocked asset extension.
+        #[derive(Default)]
+        struct AssetLedger {
+            balances: std::collections::HashMap<(u32, AccountId), Balance>,
+            /// Allowance each `(asset, owner)` has granted the contract.
+            approvals: std::collections::HashMap<(u32, AccountId), Balance>,
+        }
+
+        impl AssetLedger {
+            fn balance(&self, asset_id: u32, who: AccountId) -> Balance {
+                self.balances.get(&(asset_id, who)).copied().unwrap_or(0)
+            }
+
+            fn move_balance(
+                &mut self,
+                asset_id: u32,
+                from: AccountId,
+                to: AccountId,
+                amount: Balance,
+            ) -> Result<(), AssetsError> {
+                let from_balance = self.balance(asset_id, from);
+                if from_balance < amount {
+                    return Err(AssetsError::BalanceLow);
+                }
+                self.balances
+                    .insert((asset_id, from), from_balance - amount);
+                let to_balance = self.balance(asset_id, to);
+                self.balances.insert((asset_id, to), to_balance + amount);
+                Ok(())
+            }
+        }
+
+        /// Answers one asset extension method from a shared ledger.
+        struct MockedAssetsExtension {
+            func_id: u32,
+            /// The calling contract, captured up front as the environment is
+            /// borrowed while the extension runs.
+            contract: AccountId,
+            ledger: std::rc::Rc<std::cell::RefCell<AssetLedger>>,
+        }
+
+        impl ink::env::test::ChainExtension for MockedAssetsExtension {
+            fn func_id(&self) -> u32 {
+                self.func_id
+            }
+
+            fn call(&mut self, input: &[u8], output: &mut Vec<u8>) -> u32 {
+                let args = <Vec<u8> as scale::Decode>::decode(&mut &input[..])
+                    .expect("input is length prefixed");
+                let mut args = &args[..];
+                let mut ledger = self.ledger.borrow_mut();
+                let result = match self.func_id {
+                    1102 => {
+                        let (asset_id, who): (u32, AccountId) =
+                            scale::Decode::decode(&mut args).expect("balance input");
+                        scale::Encode::encode_to(&ledger.balance(asset_id, who), output);
+                        Ok(())
+                    }
+                    1103 => {
+                        let (asset_id, target, amount): (u32, AccountId, Balance) =
+                            scale::Decode::decode(&mut args).expect("transfer input");
+                        ledger.move_balance(asset_id, self.contract, target, amount)
+                    }
+                    _ => {
+                        let (asset_id, owner, target, amount): (
+                            u32,
+                            AccountId,
+                            AccountId,
+                            Balance,
+                        ) = scale::Decode::decode(&mut args).expect("transfer_approved input");
+                        let approved = ledger
+                            .approvals
+                            .get(&(asset_id, owner))
+                            .copied()
+                            .unwrap_or(0);
+                        if approved < amount {
+                            Err(AssetsError::Unapproved)
+                        } else {
+                            ledger
+                                .move_balance(asset_id, owner, target, amount)
+                                .map(|()| {
+                                    ledger
+                                        .approvals
+                                        .insert((asset_id, owner), approved - amount);
+                                })
+                        }
+                    }
+                };
+                result.map_or_else(AssetsError::status_code, |()| 0)
+            }
+        }
+
+        /// Registers the mocked asset extension and returns its ledger.
+        fn mock_assets() -> std::rc::Rc<std::cell::RefCell<AssetLedger>> {
+            let ledger = std::rc::Rc::<std::cell::RefCell<AssetLedger>>::default();
+            let contract = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            for func_id in [1102, 1103, 1104] {
+                ink::env::test::register_chain_extension(MockedAssetsExtension {
+                    func_id,
+                    contract,
+                    ledger: ledger.clone(),
+                });
+            }
+            ledger
+        }
+
+        /// Makes every draw roll `roll`.
+        fn mock_randomness(roll: u32) {
+            let mut random = [0u8; 32];
+            random[..4].copy_from_slice(&roll.to_le_bytes());
+            crate::test_support::set_randomness(move |_subject| Ok(random));
+        }
+
+        /// Flips once as each of `flippers`, paying a fee of 100.
+        fn flip_as_all(unit_test_bug: &mut UnitTestBug, flippers: &[AccountId]) {
+            for flipper in flippers {
+                set_balance(*flipper, balance_of(*flipper) + 100);
+                pay_as(*flipper, 100);
+                unit_test_bug.flip().expect("flip failed");
+            }
+        }
+
+        /// The mocked roll selects the ticket and the winner takes half the pot.
+        #[ink::test]
+        fn draw_pays_selected_ticket() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            flip_as_all(
+                &mut unit_test_bug,
+                &[accounts.bob, accounts.charlie, accounts.django],
+            );
+            assert_eq!(unit_test_bug.tickets(0), 3);
+            advance_blocks(DEFAULT_EPOCH_LENGTH);
+
+            // 7 % 3 selects the second ticket.
+            mock_randomness(7);
+            assert_eq!(unit_test_bug.draw(0), Ok((accounts.charlie, 150)));
+            assert_eq!(unit_test_bug.credit_of(accounts.charlie), 150);
+            assert_eq!(unit_test_bug.fee_pot(), 150);
+            assert_eq!(unit_test_bug.lottery_winner(0), Some(accounts.charlie));
+            assert_eq!(unit_test_bug.draw(0), Err(FlipError::AlreadyDrawn));
+        }
+
+        /// The same seed draws the same winners.
+        #[ink::test]
+        fn seeded_draws_are_reproducible() {
+            let accounts = accounts();
+            let flippers = [accounts.bob, accounts.charlie, accounts.django, accounts.eve];
+            let draw_epochs = |seed| {
+                let mut unit_test_bug = crate::test_support::deploy(false);
+                crate::test_support::seed_randomness(seed);
+                let winners: Vec<_> = (0..4)
+                    .map(|epoch| {
+                        flip_as_all(&mut unit_test_bug, &flippers);
+                        advance_blocks(DEFAULT_EPOCH_LENGTH);
+                        unit_test_bug.draw(epoch).expect("draw succeeds").0
+                    })
+                    .collect();
+                crate::test_support::clear_randomness();
+                winners
+            };
+            assert_eq!(draw_epochs(7), draw_epochs(7));
+        }
+
+        /// Draws need a finished epoch with tickets and a working extension.
+        #[ink::test]
+        fn draw_rejects_open_empty_and_failing() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            flip_as_all(&mut unit_test_bug, &[accounts().bob]);
+            assert_eq!(unit_test_bug.draw(0), Err(FlipError::LotteryNotFinished));
+
+            advance_blocks(2 * DEFAULT_EPOCH_LENGTH);
+            assert_eq!(unit_test_bug.draw(1), Err(FlipError::NoTickets));
+
+            struct FailingExtension;
+            impl ink::env::test::ChainExtension for FailingExtension {
+                fn func_id(&self) -> u32 {
+                    1101
+                }
+
+                fn call(&mut self, _input: &[u8], _output: &mut Vec<u8>) -> u32 {
+                    1
+                }
+            }
+            ink::env::test::register_chain_extension(FailingExtension);
+            assert_eq!(unit_test_bug.draw(0), Err(FlipError::RandomnessUnavailable));
+            assert_eq!(unit_test_bug.lottery_winner(0), None);
+        }
+
+        /// Failed flips do not issue tickets.
+        #[ink::test]
+        fn failed_flip_issues_no_ticket() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            set_config(&mut unit_test_bug, |config| config.flip_fee = 100);
+            pay_as(accounts().bob, 10);
+            assert_eq!(unit_test_bug.flip(), Err(FlipError::InsufficientFee));
+            assert_eq!(unit_test_bug.tickets(0), 0);
+        }
+
+        /// Contract info comes from the extension, and other accounts have none.
+        #[ink::test]
+        fn code_hash_of_reads_contract_info() {
+            let unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            let code_hash = Hash::from([7; 32]);
+            crate::test_support::set_contract_info(&[(accounts.django, code_hash, 500)]);
+            assert_eq!(unit_test_bug.code_hash_of(accounts.django), Some(code_hash));
+            assert_eq!(unit_test_bug.code_hash_of(accounts.eve), None);
+
+            let deposit_held = |account| {
+                <crate::PalletContracts as ink::ChainExtensionInstance>::instantiate()
+                    .deposit_held(account)
+            };
+            assert_eq!(deposit_held(accounts.django), Some(500));
+            assert_eq!(deposit_held(accounts.eve), None);
+        }
+
+        /// Once a code hash is allowlisted, callees running other code, or no
+        /// code at all, are refused before any call is made.
+        #[ink::test]
+        fn untrusted_callees_are_refused() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            let trusted = Hash::from([1; 32]);
+            crate::test_support::set_contract_info(&[
+                (accounts.django, trusted, 0),
+                (accounts.eve, Hash::from([2; 32]), 0),
+            ]);
+            unit_test_bug.set_code_hash_trusted(trusted, true).unwrap();
+            unit_test_bug.set_code_hash_trusted(trusted, true).unwrap();
+            assert!(unit_test_bug.is_code_hash_trusted(trusted));
+
+            for untrusted in [accounts.eve, accounts.frank] {
+                assert_eq!(
+                    unit_test_bug.flash_flip(untrusted, Vec::new()),
+                    Err(FlipError::UntrustedCallee)
+                );
+                let oracle = UsdFee {
+                    oracle: untrusted,
+                    usd_cents: 100,
+                    max_age: 10,
+                };
+                unit_test_bug.set_usd_fee(Some(oracle)).unwrap();
+                assert_eq!(
+                    unit_test_bug.quote_flip_fee(),
+                    Err(FlipError::UntrustedCallee)
+                );
+            }
+            assert!(!unit_test_bug.get());
+
+            // Emptying the allowlist trusts every callee again.
+            unit_test_bug.set_code_hash_trusted(trusted, false).unwrap();
+            assert!(!unit_test_bug.is_code_hash_trusted(trusted));
+            assert_eq!(unit_test_bug.ensure_trusted_callee(accounts.eve), Ok(()));
+        }
+
+        /// Flips `times` times as `account` without any fee.
+        fn flip_times(unit_test_bug: &mut UnitTestBug, account: AccountId, times: u64) {
+            set_caller(account);
+            set_value_transferred(0);
+            for _ in 0..times {
+                unit_test_bug.flip().expect("flip failed");
+            }
+        }
+
+        fn ranking(unit_test_bug: &UnitTestBug) -> Vec<(AccountId, u64)> {
+            unit_test_bug
+                .leaderboard()
+                .into_iter()
+                .map(|entry| (entry.account, entry.flips))
+                .collect()
+        }
+
+        /// New flippers are inserted in order of their counts.
+        #[ink::test]
+        fn leaderboard_insertion_orders_by_flips() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            flip_times(&mut unit_test_bug, accounts.bob, 1);
+            flip_times(&mut unit_test_bug, accounts.charlie, 3);
+            flip_times(&mut unit_test_bug, accounts.django, 2);
+            assert_eq!(
+                ranking(&unit_test_bug),
+                vec![
+                    (accounts.charlie, 3),
+                    (accounts.django, 2),
+                    (accounts.bob, 1)
+                ]
+            );
+            assert_eq!(unit_test_bug.flip_count(accounts.charlie), 3);
+        }
+
+        /// A full board drops its last entry only for a strictly higher count.
+        #[ink::test]
+        fn leaderboard_displaces_lowest_entry() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let ranked: Vec<AccountId> = (1..=LEADERBOARD_SIZE as u8)
+                .map(|i| AccountId::from([i; 32]))
+                .collect();
+            for (i, account) in ranked.iter().enumerate() {
+                flip_times(&mut unit_test_bug, *account, 2 + i as u64);
+            }
+            let newcomer = AccountId::from([0xff; 32]);
+
+            // Tying the last entry is not enough.
+            flip_times(&mut unit_test_bug, newcomer, 2);
+            assert_eq!(unit_test_bug.leaderboard().len(), LEADERBOARD_SIZE);
+            assert!(!ranking(&unit_test_bug).contains(&(newcomer, 2)));
+
+            flip_times(&mut unit_test_bug, newcomer, 1);
+            let board = ranking(&unit_test_bug);
+            assert_eq!(board.len(), LEADERBOARD_SIZE);
+            assert_eq!(board[LEADERBOARD_SIZE - 1], (newcomer, 3));
+            assert!(!board.iter().any(|(account, _)| *account == ranked[0]));
+        }
+
+        /// Equal counts keep whoever got there first ahead.
+        #[ink::test]
+        fn leaderboard_ties_favour_earlier_entrant() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            flip_times(&mut unit_test_bug, accounts.bob, 2);
+            flip_times(&mut unit_test_bug, accounts.charlie, 2);
+            assert_eq!(
+                ranking(&unit_test_bug),
+                vec![(accounts.bob, 2), (accounts.charlie, 2)]
+            );
+
+            flip_times(&mut unit_test_bug, accounts.charlie, 1);
+            flip_times(&mut unit_test_bug, accounts.bob, 1);
+            assert_eq!(
+                ranking(&unit_test_bug),
+                vec![(accounts.charlie, 3), (accounts.bob, 3)]
+            );
+        }
+
+        /// Failed flips leave counts and rankings alone.
+        #[ink::test]
+        fn failed_flips_do_not_change_rankings() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            flip_times(&mut unit_test_bug, accounts.bob, 2);
+            flip_times(&mut unit_test_bug, accounts.charlie, 1);
+            let before = ranking(&unit_test_bug);
+
+            set_caller(accounts.alice);
+            set_config(&mut unit_test_bug, |config| config.flip_fee = 100);
+            set_caller(accounts.charlie);
+            for _ in 0..3 {
+                assert_eq!(unit_test_bug.flip(), Err(FlipError::InsufficientFee));
+            }
+            assert_eq!(ranking(&unit_test_bug), before);
+            assert_eq!(unit_test_bug.flip_count(accounts.charlie), 1);
+        }
+
+        /// Relayed calls act for `from` and are accepted only from the trusted forwarder.
+        #[ink::test]
+        fn forwarded_flip_uses_effective_caller() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            set_caller(accounts.frank);
+            assert_eq!(
+                unit_test_bug.execute_forwarded(accounts.bob, ForwardedCall::Flip),
+                Err(FlipError::NotTrustedForwarder)
+            );
+
+            set_caller(accounts.alice);
+            unit_test_bug
+                .set_trusted_forwarder(Some(accounts.frank))
+                .expect("owner sets forwarder");
+            set_caller(accounts.frank);
+            assert_eq!(
+                unit_test_bug
+                    .execute_forwarded(accounts.bob, ForwardedCall::Flip)
+                    .map(drop),
+                Ok(())
+            );
+            assert!(unit_test_bug.get());
+            assert_eq!(unit_test_bug.flip_count(accounts.bob), 1);
+            assert_eq!(unit_test_bug.flip_count(accounts.frank), 0);
+
+            set_caller(accounts.alice);
+            unit_test_bug
+                .set_trusted_forwarder(None)
+                .expect("owner clears forwarder");
+            set_caller(accounts.frank);
+            assert_eq!(
+                unit_test_bug.execute_forwarded(accounts.bob, ForwardedCall::Flip),
+                Err(FlipError::NotTrustedForwarder)
+            );
+        }
+
+        /// Relayed flips are subject to the same rules as direct ones.
+        #[ink::test]
+        fn forwarded_flip_respects_fee() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            set_config(&mut unit_test_bug, |config| config.flip_fee = 10);
+            unit_test_bug
+                .set_trusted_forwarder(Some(accounts.frank))
+                .expect("owner sets forwarder");
+            set_caller(accounts.frank);
+            assert_eq!(
+                unit_test_bug.execute_forwarded(accounts.bob, ForwardedCall::Flip),
+                Err(FlipError::InsufficientFee)
+            );
+            set_caller(accounts.bob);
+            assert_eq!(
+                unit_test_bug.set_trusted_forwarder(None),
+                Err(FlipError::NotOwner)
+            );
+        }
+
+        /// Returns the account controlled by the ECDSA key derived from `seed`.
+        fn ecdsa_account(seed: u8) -> AccountId {
+            let secret = secp256k1::SecretKey::from_slice(&[seed; 32]).expect("valid secret key");
+            let public_key = secp256k1::PublicKey::from_secret_key(secp256k1::SECP256K1, &secret);
+            let mut account = [0u8; 32];
+            ink::env::hash_bytes::<Blake2x256>(&public_key.serialize(), &mut account);
+            AccountId::from(account)
+        }
+
+        /// Signs `hash` with the ECDSA key derived from `seed`.
+        fn ecdsa_sign(seed: u8, hash: [u8; 32]) -> [u8; 65] {
+            let secret = secp256k1::SecretKey::from_slice(&[seed; 32]).expect("valid secret key");
+            let message = secp256k1::Message::from_slice(&hash).expect("32 byte hash");
+            let (recovery_id, compact) = secp256k1::SECP256K1
+                .sign_ecdsa_recoverable(&message, &secret)
+                .serialize_compact();
+            let mut signature = [0u8; 65];
+            signature[..64].copy_from_slice(&compact);
+            signature[64] = recovery_id.to_i32() as u8;
+            signature
+        }
+
+        /// A permit executes once for the signer, whoever submits it.
+        #[ink::test]
+        fn permit_executes_once() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let signer = ecdsa_account(7);
+            let hash = unit_test_bug.permit_hash(signer, ForwardedCall::Flip, 0);
+            let signature = ecdsa_sign(7, hash);
+
+            set_caller(accounts().charlie);
+            assert_eq!(
+                unit_test_bug
+                    .execute_with_permit(signer, ForwardedCall::Flip, signature)
+                    .map(drop),
+                Ok(())
+            );
+            assert!(unit_test_bug.get());
+            assert_eq!(unit_test_bug.flip_count(signer), 1);
+            assert_eq!(unit_test_bug.permit_nonce(signer), 1);
+            assert_eq!(
+                unit_test_bug.execute_with_permit(signer, ForwardedCall::Flip, signature),
+                Err(FlipError::InvalidSignature)
+            );
+        }
+
+        /// Signatures by another key or for another signer are rejected.
+        #[ink::test]
+        fn permit_rejects_wrong_signer() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let signer = ecdsa_account(7);
+            let hash = unit_test_bug.permit_hash(signer, ForwardedCall::Flip, 0);
+            assert_eq!(
+                unit_test_bug.execute_with_permit(signer, ForwardedCall::Flip, ecdsa_sign(8, hash)),
+                Err(FlipError::InvalidSignature)
+            );
+            assert_eq!(
+                unit_test_bug.execute_with_permit(
+                    ecdsa_account(8),
+                    ForwardedCall::Flip,
+                    ecdsa_sign(7, hash)
+                ),
+                Err(FlipError::InvalidSignature)
+            );
+            assert_eq!(unit_test_bug.permit_nonce(signer), 0);
+        }
+
+        /// A permit signed for one deployment cannot be replayed on another.
+        #[ink::test]
+        fn permit_rejects_cross_instance_replay() {
+            let signer = ecdsa_account(7);
+            ink::env::test::set_callee::<ink::env::DefaultEnvironment>(AccountId::from([0xa1; 32]));
+            let first = UnitTestBug::new(false);
+            let first_domain = first.signing_domain(signer);
+            let signature = ecdsa_sign(7, first.permit_hash(signer, ForwardedCall::Flip, 0));
+
+            ink::env::test::set_callee::<ink::env::DefaultEnvironment>(AccountId::from([0xb2; 32]));
+            let mut second = UnitTestBug::new(false);
+            assert_ne!(
+                second.signing_domain(signer).contract,
+                first_domain.contract
+            );
+            assert_eq!(
+                second.execute_with_permit(signer, ForwardedCall::Flip, signature),
+                Err(FlipError::InvalidSignature)
+            );
+            assert!(!second.get());
+        }
+
+        /// A permit signed for one chain cannot be replayed on another.
+        #[ink::test]
+        fn permit_rejects_cross_chain_replay() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let signer = ecdsa_account(7);
+            let signature =
+                ecdsa_sign(7, unit_test_bug.permit_hash(signer, ForwardedCall::Flip, 0));
+            unit_test_bug
+                .set_chain_id(Hash::from([0x42; 32]))
+                .expect("owner sets chain id");
+            assert_eq!(
+                unit_test_bug.execute_with_permit(signer, ForwardedCall::Flip, signature),
+                Err(FlipError::InvalidSignature)
+            );
+            set_caller(accounts().bob);
+            assert_eq!(
+                unit_test_bug.set_chain_id(Hash::default()),
+                Err(FlipError::NotOwner)
+            );
+        }
+
+        /// A session key flips for its account only while it holds the permission.
+        #[ink::test]
+        fn session_key_scope_is_enforced() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            set_caller(accounts.bob);
+            unit_test_bug
+                .authorize_session_key(accounts.eve, 0, 10)
+                .expect("authorize failed");
+
+            set_caller(accounts.eve);
+            assert_eq!(
+                unit_test_bug.execute_as_session(accounts.bob, ForwardedCall::Flip),
+                Err(FlipError::OutOfScope)
+            );
+            assert_eq!(
+                unit_test_bug.execute_as_session(accounts.charlie, ForwardedCall::Flip),
+                Err(FlipError::SessionKeyNotFound)
+            );
+
+            set_caller(accounts.bob);
+            unit_test_bug
+                .authorize_session_key(accounts.eve, SESSION_FLIP, 10)
+                .expect("authorize failed");
+            set_caller(accounts.eve);
+            assert_eq!(
+                unit_test_bug
+                    .execute_as_session(accounts.bob, ForwardedCall::Flip)
+                    .map(drop),
+                Ok(())
+            );
+            assert_eq!(unit_test_bug.flip_count(accounts.bob), 1);
+            assert_eq!(unit_test_bug.flip_count(accounts.eve), 0);
+        }
+
+        /// Session keys stop working at their expiry block and cannot be issued expired.
+        #[ink::test]
+        fn session_key_expiry() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            set_caller(accounts.bob);
+            assert_eq!(
+                unit_test_bug.authorize_session_key(accounts.eve, SESSION_FLIP, 0),
+                Err(FlipError::SessionExpired)
+            );
+            unit_test_bug
+                .authorize_session_key(accounts.eve, SESSION_FLIP, 2)
+                .expect("authorize failed");
+
+            set_caller(accounts.eve);
+            advance_blocks(1);
+            assert_eq!(
+                unit_test_bug
+                    .execute_as_session(accounts.bob, ForwardedCall::Flip)
+                    .map(drop),
+                Ok(())
+            );
+            advance_blocks(1);
+            assert_eq!(
+                unit_test_bug.execute_as_session(accounts.bob, ForwardedCall::Flip),
+                Err(FlipError::SessionExpired)
+            );
+        }
+
+        /// Revoked session keys are gone and only the granter can revoke them.
+        #[ink::test]
+        fn session_key_revocation() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            set_caller(accounts.bob);
+            unit_test_bug
+                .authorize_session_key(accounts.eve, SESSION_FLIP, 10)
+                .expect("authorize failed");
+
+            set_caller(accounts.eve);
+            assert_eq!(
+                unit_test_bug.revoke_session_key(accounts.eve),
+                Err(FlipError::SessionKeyNotFound)
+            );
+            set_caller(accounts.bob);
+            assert_eq!(unit_test_bug.revoke_session_key(accounts.eve), Ok(()));
+            assert_eq!(unit_test_bug.session_key(accounts.bob, accounts.eve), None);
+
+            set_caller(accounts.eve);
+            assert_eq!(
+                unit_test_bug.execute_as_session(accounts.bob, ForwardedCall::Flip),
+                Err(FlipError::SessionKeyNotFound)
+            );
+        }
+
+        /// Accounts start with default settings.
+        #[ink::test]
+        fn settings_default_until_updated() {
+            let unit_test_bug = UnitTestBug::new(false);
+            assert_eq!(
+                unit_test_bug.get_settings(accounts().bob),
+                Settings::default()
+            );
+        }
+
+        /// Only the fields present in a patch change.
+        #[ink::test]
+        fn settings_patch_is_partial() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            set_caller(accounts.bob);
+            unit_test_bug.update_settings(SettingsPatch {
+                auto_flip: Some(true),
+                notify_hook: Some(Some(accounts.eve)),
+                fee_tier: Some(2),
+            });
+
+            let settings = unit_test_bug.update_settings(SettingsPatch {
+                fee_tier: Some(3),
+                ..SettingsPatch::default()
+            });
+            assert_eq!(
+                settings,
+                Settings {
+                    auto_flip: true,
+                    notify_hook: Some(accounts.eve),
+                    fee_tier: 3,
+                }
+            );
+            assert_eq!(unit_test_bug.get_settings(accounts.bob), settings);
+            assert_eq!(
+                unit_test_bug.update_settings(SettingsPatch::default()),
+                settings
+            );
+            assert_eq!(
+                unit_test_bug.get_settings(accounts.charlie),
+                Settings::default()
+            );
+        }
+
+        /// `Some(None)` clears the notify hook while `None` keeps it.
+        #[ink::test]
+        fn settings_patch_clears_notify_hook() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            set_caller(accounts().bob);
+            unit_test_bug.update_settings(SettingsPatch {
+                notify_hook: Some(Some(accounts().eve)),
+                ..SettingsPatch::default()
+            });
+            let kept = unit_test_bug.update_settings(SettingsPatch {
+                auto_flip: Some(true),
+                ..SettingsPatch::default()
+            });
+            assert_eq!(kept.notify_hook, Some(accounts().eve));
+            let cleared = unit_test_bug.update_settings(SettingsPatch {
+                notify_hook: Some(None),
+                ..SettingsPatch::default()
+            });
+            assert_eq!(cleared.notify_hook, None);
+            assert!(cleared.auto_flip);
+        }
+
+        /// Wrapping and unwrapping move value 1:1 between native and wrapped balances.
+        #[ink::test]
+        fn wrap_and_unwrap_round_trip() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let bob = accounts().bob;
+            let before = balance_of(bob);
+            pay_as(bob, 70);
+            assert_eq!(unit_test_bug.wrap(), 70);
+            set_value_transferred(0);
+
+            assert_eq!(
+                unit_test_bug.unwrap(71),
+                Err(FlipError::InsufficientWrapped)
+            );
+            assert_eq!(unit_test_bug.unwrap(30), Ok(()));
+            assert_eq!(unit_test_bug.wrapped_balance_of(bob), 40);
+            assert_eq!(unit_test_bug.total_wrapped(), 40);
+            assert_eq!(balance_of(bob), before - 40);
+        }
+
+        /// The flip fee can be paid from the wrapped balance, and only in full.
+        #[ink::test]
+        fn wrapped_balance_pays_flip_fee() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let bob = accounts().bob;
+            set_config(&mut unit_test_bug, |config| config.flip_fee = 25);
+            pay_as(bob, 40);
+            unit_test_bug.wrap();
+            set_value_transferred(0);
+
+            assert_eq!(unit_test_bug.flip_with_wrapped().map(drop), Ok(()));
+            assert!(unit_test_bug.get());
+            assert_eq!(
+                unit_test_bug.flip_with_wrapped(),
+                Err(FlipError::InsufficientWrapped)
+            );
+            assert!(unit_test_bug.get());
+            assert_eq!(unit_test_bug.wrapped_balance_of(bob), 15);
+            assert_eq!(unit_test_bug.fee_pot(), 25);
+        }
+
+        /// A flip rejected for another reason leaves the wrapped balance untouched.
+        #[ink::test]
+        fn failed_wrapped_flip_keeps_balance() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let bob = accounts().bob;
+            set_config(&mut unit_test_bug, |config| {
+                config.flip_fee = 10;
+                config.paused = true;
+            });
+            pay_as(bob, 10);
+            unit_test_bug.wrap();
+            set_value_transferred(0);
+
+            assert_eq!(unit_test_bug.flip_with_wrapped(), Err(FlipError::Paused));
+            assert_eq!(unit_test_bug.wrapped_balance_of(bob), 10);
+            assert_eq!(unit_test_bug.fee_pot(), 0);
+        }
+
+        /// Random wrap, unwrap and flip sequences never create or destroy value.
+        #[ink::test]
+        fn wrapped_value_is_conserved() {
+            let accounts = accounts();
+            let users = [
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve,
+            ];
+            for seed in 1..=20u64 {
+                let mut rng = Rng(seed);
+                let contract = AccountId::from([0xc0 + seed as u8; 32]);
+                ink::env::test::set_callee::<ink::env::DefaultEnvironment>(contract);
+                set_balance(contract, 0);
+                let mut unit_test_bug = UnitTestBug::new(false);
+                set_config(&mut unit_test_bug, |config| config.flip_fee = 3);
+                for user in users {
+                    set_balance(user, 1_000);
+                }
+                let total = || {
+                    users.iter().map(|user| balance_of(*user)).sum::<Balance>()
+                        + balance_of(contract)
+                };
+                let initial = total();
+
+                for _ in 0..50 {
+                    let user = users[rng.below(users.len() as u64) as usize];
+                    let amount = rng.below(60) as Balance;
+                    match rng.below(3) {
+                        0 => {
+                            pay_as(user, amount);
+                            unit_test_bug.wrap();
+                        }
+                        1 => {
+                            set_caller(user);
+                            set_value_transferred(0);
+                            let held = unit_test_bug.wrapped_balance_of(user);
+                            let result = unit_test_bug.unwrap(amount);
+                            assert_eq!(result.is_ok(), amount <= held);
+                        }
+                        _ => {
+                            set_caller(user);
+                            set_value_transferred(0);
+                            let _ = unit_test_bug.flip_with_wrapped();
+                        }
+                    }
+
+                    let wrapped = users
+                        .iter()
+                        .map(|user| unit_test_bug.wrapped_balance_of(*user))
+                        .sum::<Balance>();
+                    assert_eq!(wrapped, unit_test_bug.total_wrapped());
+                    assert_eq!(total(), initial);
+                    assert_eq!(
+                        balance_of(contract),
+                        unit_test_bug.total_wrapped() + unit_test_bug.fee_pot()
+                    );
+                }
+            }
+        }
+
+        /// A fresh contract and one that has seen a mix of activity are consistent.
+        #[ink::test]
+        fn invariants_hold_after_activity() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            assert_eq!(
+                unit_test_bug.verify_invariants(),
+                InvariantReport::default()
+            );
+
+            let accounts = accounts();
+            set_config(&mut unit_test_bug, |config| config.flip_fee = 10);
+            pay_as(accounts.bob, 10);
+            unit_test_bug.flip().expect("flip failed");
+            pay_as(accounts.charlie, 50);
+            unit_test_bug.wrap();
+            open_escrow(&mut unit_test_bug, 30);
+            advance_blocks(1);
+            set_caller(accounts.charlie);
+            set_value_transferred(0);
+            unit_test_bug
+                .flip_with_wrapped()
+                .expect("wrapped flip failed");
+
+            assert!(unit_test_bug.verify_invariants().is_ok());
+        }
+
+        /// Broken storage is reported rule by rule.
+        #[ink::test]
+        fn invariant_violations_are_reported() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            unit_test_bug.flip().expect("flip failed");
+            let contract = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            set_balance(contract, 5);
+            unit_test_bug.fee_pot = 7;
+            unit_test_bug.value.set(&false);
+            unit_test_bug.leaderboard[0].flips = 3;
+
+            assert_eq!(
+                unit_test_bug.verify_invariants().violations,
+                [
+                    Violation::ReservesExceedBalance {
+                        reserved: 7,
+                        balance: 5
+                    },
+                    Violation::StaleCheckpoint,
+                    Violation::LeaderboardMismatch(accounts().alice),
+                ]
+            );
+        }
+
+        /// Totals and counts that disagree with what they aggregate are reported.
+        #[ink::test]
+        fn aggregate_violations_are_reported() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            advance_blocks(1);
+            unit_test_bug.flip().expect("flip failed");
+            pay_as(accounts.bob, 50);
+            unit_test_bug.wrap();
+            pay_as(accounts.bob, 20);
+            unit_test_bug.bid().expect("bid failed");
+            pay_as(accounts.charlie, 30);
+            unit_test_bug.bid().expect("outbid failed");
+            assert!(unit_test_bug.verify_invariants().is_ok());
+
+            unit_test_bug.total_wrapped += 1;
+            unit_test_bug.total_credits += 2;
+            unit_test_bug.epoch_stats.flips += 3;
+            unit_test_bug.epoch_stats.flippers += 4;
+            unit_test_bug.config.max_history = 1;
+
+            assert_eq!(
+                unit_test_bug.verify_invariants().violations,
+                [
+                    Violation::HistoryTooLong {
+                        kept: 2,
+                        max_history: 1
+                    },
+                    Violation::WrappedTotalMismatch { total: 51, sum: 50 },
+                    Violation::CreditTotalMismatch { total: 22, sum: 20 },
+                    Violation::EpochFlipsMismatch {
+                        recorded: 4,
+                        sum: 1
+                    },
+                    Violation::EpochFlippersMismatch {
+                        recorded: 5,
+                        count: 1
+                    },
+                ]
+            );
+        }
+
+        /// Collects the whole storage export in pages of `limit`.
+        fn export_all(unit_test_bug: &UnitTestBug, limit: u32) -> (Vec<ExportItem>, usize) {
+            let mut items = Vec::new();
+            let mut pages = 0;
+            let mut cursor = Some(ExportCursor::default());
+            while let Some(next) = cursor {
+                let chunk = unit_test_bug.export_storage(next, limit);
+                assert!(chunk.items.len() as u32 <= limit.max(1));
+                items.extend(chunk.items);
+                cursor = chunk.next;
+                pages += 1;
+            }
+            (items, pages)
+        }
+
+        /// The export lists every section in order, whatever the page size.
+        #[ink::test]
+        fn export_covers_every_section() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            pay_as(accounts.bob, 0);
+            unit_test_bug.flip().expect("flip failed");
+            advance_blocks(1);
+            pay_as(accounts.charlie, 40);
+            unit_test_bug.wrap();
+            set_value_transferred(0);
+            unit_test_bug.flip().expect("flip failed");
+
+            let checkpoints =
+                (0..unit_test_bug.checkpoint_count()).map(|index| ExportItem::Checkpoint {
+                    index,
+                    checkpoint: unit_test_bug.checkpoint(index),
+                });
+            let expected: Vec<_> = checkpoints
+                .chain([
+                    ExportItem::Participant {
+                        index: 0,
+                        account: accounts.bob,
+                        flips: 1,
+                    },
+                    ExportItem::Participant {
+                        index: 1,
+                        account: accounts.charlie,
+                        flips: 1,
+                    },
+                    ExportItem::Balance {
+                        account: accounts.bob,
+                        wrapped: 0,
+                    },
+                    ExportItem::Balance {
+                        account: accounts.charlie,
+                        wrapped: 40,
+                    },
+                ])
+                .collect();
+
+            for limit in 0..=8 {
+                assert_eq!(export_all(&unit_test_bug, limit).0, expected);
+            }
+        }
+
+        /// Page sizes are capped by the configured batch size.
+        #[ink::test]
+        fn export_limit_is_clamped_to_batch_size() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            set_config(&mut unit_test_bug, |config| config.max_batch_size = 2);
+            for _ in 0..4 {
+                advance_blocks(1);
+                unit_test_bug.flip().expect("flip failed");
+            }
+            let chunk = unit_test_bug.export_storage(ExportCursor::default(), 100);
+            assert_eq!(chunk.items.len(), 2);
+            assert_eq!(
+                chunk.next,
+                Some(ExportCursor {
+                    section: ExportSection::History,
+                    index: 2
+                })
+            );
+            // 5 checkpoints, 1 participant and 1 balance in pages of 2.
+            assert_eq!(export_all(&unit_test_bug, 100).1, 4);
+        }
+
+        /// Only the owner may configure the token gate, and an unset gate lets anyone flip.
+        #[ink::test]
+        fn psp22_gate_is_owner_configured() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let gate = Psp22Gate {
+                token: AccountId::from([0x22; 32]),
+                min_balance: 10,
+            };
+            set_caller(accounts().bob);
+            assert_eq!(
+                unit_test_bug.set_psp22_gate(Some(gate)),
+                Err(FlipError::NotOwner)
+            );
+            assert_eq!(unit_test_bug.psp22_gate(), None);
+            assert_eq!(unit_test_bug.flip().map(drop), Ok(()));
+
+            set_caller(accounts().alice);
+            assert_eq!(unit_test_bug.set_psp22_gate(Some(gate)), Ok(()));
+            assert_eq!(unit_test_bug.psp22_gate(), Some(gate));
+            assert_eq!(unit_test_bug.set_psp22_gate(None), Ok(()));
+            assert_eq!(unit_test_bug.psp22_gate(), None);
+        }
+
+        /// Only the owner may pick the NFT collection, and permits need one.
+        #[ink::test]
+        fn psp34_collection_is_owner_configured() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let collection = AccountId::from([0x34; 32]);
+            assert_eq!(
+                unit_test_bug.flip_with_nft(Id::U8(1)),
+                Err(FlipError::NoNftCollection)
+            );
+            set_caller(accounts().bob);
+            assert_eq!(
+                unit_test_bug.set_psp34_collection(Some(collection)),
+                Err(FlipError::NotOwner)
+            );
+            set_caller(accounts().alice);
+            assert_eq!(unit_test_bug.set_psp34_collection(Some(collection)), Ok(()));
+            assert_eq!(unit_test_bug.psp34_collection(), Some(collection));
+            assert!(!unit_test_bug.get());
+        }
+
+        /// An approved delegate flips in the owner's name until the approval is revoked.
+        #[ink::test]
+        fn approved_delegate_flips_for_owner() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            set_caller(accounts.charlie);
+            assert_eq!(
+                unit_test_bug.flip_for(accounts.bob),
+                Err(FlipError::NotApproved)
+            );
+
+            set_caller(accounts.bob);
+            unit_test_bug.approve(accounts.charlie, true);
+            assert!(unit_test_bug.is_approved(accounts.bob, accounts.charlie));
+            set_caller(accounts.charlie);
+            assert_eq!(unit_test_bug.flip_for(accounts.bob).map(drop), Ok(()));
+            assert_eq!(unit_test_bug.flip_count(accounts.bob), 1);
+            assert_eq!(unit_test_bug.flip_count(accounts.charlie), 0);
+
+            set_caller(accounts.bob);
+            unit_test_bug.approve(accounts.charlie, false);
+            assert!(!unit_test_bug.is_approved(accounts.bob, accounts.charlie));
+            set_caller(accounts.charlie);
+            assert_eq!(
+                unit_test_bug.flip_for(accounts.bob),
+                Err(FlipError::NotApproved)
+            );
+            assert!(unit_test_bug.get());
+
+            let approvals: Vec<_> = recorded_events()
+                .into_iter()
+                .filter_map(|event| match event {
+                    Event::Approval(approval) => Some(approval.allowed),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(approvals, [true, false]);
+        }
+
+        /// An all-or-nothing batch stops at the first failing owner.
+        #[ink::test]
+        fn all_or_nothing_batch_stops_at_first_failure() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            set_config(&mut unit_test_bug, |config| config.flip_fee = 10);
+            let accounts = accounts();
+            for owner in [accounts.bob, accounts.django] {
+                set_caller(owner);
+                unit_test_bug.approve(accounts.charlie, true);
+            }
+            set_caller(accounts.charlie);
+            pay_as(accounts.charlie, 25);
+            assert_eq!(
+                unit_test_bug.batch_flip_for(
+                    vec![accounts.bob, accounts.django],
+                    BatchMode::AllOrNothing
+                ),
+                Ok(vec![Ok(()), Ok(())])
+            );
+            assert_eq!(unit_test_bug.flip_count(accounts.bob), 1);
+            assert_eq!(unit_test_bug.flip_count(accounts.django), 1);
+            // Both fees and the 5 left over.
+            assert_eq!(unit_test_bug.fee_pot(), 25);
+
+            advance_blocks(1);
+            pay_as(accounts.charlie, 20);
+            assert_eq!(
+                unit_test_bug.batch_flip_for(
+                    vec![accounts.bob, accounts.eve, accounts.django],
+                    BatchMode::AllOrNothing
+                ),
+                Err(FlipError::NotApproved)
+            );
+            // Only the on-chain revert undoes the flip for bob.
+            assert_eq!(unit_test_bug.flip_count(accounts.django), 1);
+
+            let too_many = vec![accounts.bob; unit_test_bug.config.max_batch_size as usize + 1];
+            assert_eq!(
+                unit_test_bug.batch_flip_for(too_many, BatchMode::BestEffort),
+                Err(FlipError::BatchTooLarge)
+            );
+        }
+
+        /// A best-effort batch reports unpaid items and flips the rest.
+        #[ink::test]
+        fn best_effort_batch_skips_failures() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            set_config(&mut unit_test_bug, |config| config.flip_fee = 10);
+            let accounts = accounts();
+            for owner in [accounts.bob, accounts.django] {
+                set_caller(owner);
+                unit_test_bug.approve(accounts.charlie, true);
+            }
+            set_caller(accounts.charlie);
+            pay_as(accounts.charlie, 15);
+            assert_eq!(
+                unit_test_bug.batch_flip_for(
+                    vec![accounts.bob, accounts.django],
+                    BatchMode::BestEffort
+                ),
+                Ok(vec![Ok(()), Err(FlipError::InsufficientFee)])
+            );
+            assert!(unit_test_bug.get());
+            assert_eq!(unit_test_bug.flip_count(accounts.django), 0);
+            assert_eq!(unit_test_bug.fee_pot(), 15);
+        }
+
+        /// Best-effort results line up with the owners, and a failing item
+        /// neither spends the budget nor stops the items after it.
+        #[ink::test]
+        fn best_effort_results_map_to_inputs() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            set_config(&mut unit_test_bug, |config| {
+                config.flip_fee = 10;
+                config.cooldown_blocks = 5;
+            });
+            let accounts = accounts();
+            for owner in [accounts.bob, accounts.django, accounts.frank] {
+                set_caller(owner);
+                unit_test_bug.approve(accounts.charlie, true);
+            }
+            set_caller(accounts.charlie);
+            pay_as(accounts.charlie, 30);
+            let results = unit_test_bug
+                .batch_flip_for(
+                    vec![
+                        accounts.bob,
+                        accounts.eve,
+                        accounts.bob,
+                        accounts.django,
+                        accounts.frank,
+                    ],
+                    BatchMode::BestEffort,
+                )
+                .expect("best-effort batches complete");
+            assert_eq!(
+                results,
+                [
+                    Ok(()),
+                    Err(FlipError::NotApproved),
+                    Err(FlipError::CooldownActive),
+                    Ok(()),
+                    Ok(()),
+                ]
+            );
+            for owner in [accounts.bob, accounts.django, accounts.frank] {
+                assert_eq!(unit_test_bug.flip_count(owner), 1);
+            }
+            assert_eq!(unit_test_bug.flip_count(accounts.eve), 0);
+            // Three flips, and the budget covered exactly the three fees.
+            assert!(unit_test_bug.get());
+            assert_eq!(unit_test_bug.fee_pot(), 30);
+
+            let summaries: Vec<_> = recorded_events()
+                .into_iter()
+                .filter_map(|event| match event {
+                    Event::BatchFlipped(summary) => Some(summary),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(summaries.len(), 1);
+            assert_eq!(summaries[0].account, accounts.charlie);
+            assert_eq!((summaries[0].succeeded, summaries[0].failed), (3, 2));
+        }
+
+        /// Accounts the recorded flips were made for, in order.
+        fn flipped_by() -> Vec<AccountId> {
+            recorded_events()
+                .into_iter()
+                .filter_map(|event| match event {
+                    Event::FlippedV2(flipped) => Some(flipped.account),
+                    _ => None,
+                })
+                .collect()
+        }
+
+        /// Queued flips run by due block, and in queue order within a block.
+        #[ink::test]
+        fn queued_flips_execute_in_due_order() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            for (account, execute_after) in [
+                (accounts.bob, 5),
+                (accounts.charlie, 2),
+                (accounts.django, 2),
+                (accounts.eve, 0),
+            ] {
+                set_balance(account, KEEPER_REWARD);
+                pay_as(account, KEEPER_REWARD);
+                unit_test_bug.queue_flip(execute_after).unwrap();
+            }
+            let order: Vec<_> = unit_test_bug
+                .queued_flips()
+                .iter()
+                .map(|queued| queued.by)
+                .collect();
+            assert_eq!(
+                order,
+                [
+                    accounts.eve,
+                    accounts.charlie,
+                    accounts.django,
+                    accounts.bob
+                ]
+            );
+
+            advance_blocks(6);
+            set_caller(accounts.frank);
+            assert_eq!(unit_test_bug.execute_due(u32::MAX), Ok(4));
+            assert_eq!(flipped_by(), order);
+            assert!(unit_test_bug.queued_flips().is_empty());
+        }
+
+        /// Keepers execute at most `limit` flips and none that are not due.
+        #[ink::test]
+        fn execute_due_stops_at_limit_and_pending() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            for execute_after in [1, 1, 10] {
+                pay_as(accounts.bob, KEEPER_REWARD);
+                unit_test_bug.queue_flip(execute_after).unwrap();
+            }
+            set_caller(accounts.eve);
+            assert_eq!(unit_test_bug.execute_due(10), Ok(0));
+
+            advance_blocks(2);
+            assert_eq!(unit_test_bug.execute_due(1), Ok(1));
+            assert_eq!(unit_test_bug.execute_due(10), Ok(1));
+            assert_eq!(unit_test_bug.execute_due(10), Ok(0));
+            assert_eq!(unit_test_bug.queued_flips().len(), 1);
+            assert_eq!(unit_test_bug.flip_count(accounts.bob), 2);
+
+            for _ in 1..MAX_QUEUED_FLIPS {
+                pay_as(accounts.bob, KEEPER_REWARD);
+                unit_test_bug.queue_flip(20).unwrap();
+            }
+            pay_as(accounts.bob, KEEPER_REWARD);
+            assert_eq!(unit_test_bug.queue_flip(20), Err(FlipError::QueueFull));
+            pay_as(accounts.bob, KEEPER_REWARD - 1);
+            assert_eq!(
+                unit_test_bug.queue_flip(20),
+                Err(FlipError::InsufficientFee)
+            );
+        }
+
+        /// The keeper earns the reward of every flip that succeeds, and a flip
+        /// that fails is credited back in full instead of paying the fee pot.
+        #[ink::test]
+        fn keeper_is_rewarded_and_failed_flips_refunded() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            set_config(&mut unit_test_bug, |config| config.flip_fee = 10);
+            let accounts = accounts();
+            pay_as(accounts.bob, KEEPER_REWARD + 10);
+            unit_test_bug.queue_flip(0).unwrap();
+            pay_as(accounts.charlie, KEEPER_REWARD + 3);
+            unit_test_bug.queue_flip(0).unwrap();
+            assert!(unit_test_bug.verify_invariants().is_ok());
+
+            advance_blocks(1);
+            let keeper_start = balance_of(accounts.eve);
+            set_caller(accounts.eve);
+            assert_eq!(unit_test_bug.execute_due(10), Ok(2));
+            assert_eq!(unit_test_bug.credit_of(accounts.eve), KEEPER_REWARD);
+            assert_eq!(unit_test_bug.credit_of(accounts.charlie), KEEPER_REWARD + 3);
+            assert_eq!(unit_test_bug.withdraw_credit(), Ok(KEEPER_REWARD));
+            assert_eq!(balance_of(accounts.eve), keeper_start + KEEPER_REWARD);
+            assert_eq!(unit_test_bug.flip_count(accounts.bob), 1);
+            assert_eq!(unit_test_bug.flip_count(accounts.charlie), 0);
+            assert_eq!(unit_test_bug.fee_pot(), 10);
+            assert!(unit_test_bug.verify_invariants().is_ok());
+        }
+
+        /// Property: every account is credited exactly the refunds, payouts and
+        /// rewards it is owed, withdrawals pay out exactly the credit, and the
+        /// contract holds every paid-in unit not yet withdrawn.
+        #[test]
+        fn credits_conserve_paid_value() {
+            for seed in 1..=20 {
+                // Each run needs fresh storage, the credits outlive the contract value.
+                ink::env::test::run_test::<ink::env::DefaultEnvironment, _>(|_| {
+                    let mut rng = Rng(seed);
+                    let mut unit_test_bug = UnitTestBug::new(false);
+                    set_config(&mut unit_test_bug, |config| config.flip_fee = 5);
+                    let accounts = accounts();
+                    let users = [
+                        accounts.bob,
+                        accounts.charlie,
+                        accounts.django,
+                        accounts.eve,
+                    ];
+                    for user in users {
+                        set_balance(user, 1_000_000);
+                    }
+                    let contract = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+                    let contract_start = balance_of(contract);
+                    let mut credited = std::collections::HashMap::<AccountId, Balance>::new();
+                    let mut withdrawn = std::collections::HashMap::<AccountId, Balance>::new();
+                    let mut open_escrows = Vec::new();
+
+                    // Fewer blocks than an epoch pass, so no bid ever wins flip rights.
+                    for _ in 0..60 {
+                        advance_blocks(rng.below(2) as u32);
+                        let user = users[rng.below(4) as usize];
+                        match rng.below(6) {
+                            0 => {
+                                let outbid = unit_test_bug.highest_bid();
+                                let amount = outbid.map_or(0, |bid| bid.amount)
+                                    + 1
+                                    + rng.below(50) as Balance;
+                                pay_as(user, amount);
+                                unit_test_bug.bid().expect("bid is highest");
+                                if let Some(outbid) = outbid {
+                                    *credited.entry(outbid.bidder).or_default() += outbid.amount;
+                                }
+                            }
+                            1 => {
+                                let beneficiary = users[rng.below(4) as usize];
+                                let amount = 1 + rng.below(100) as Balance;
+                                pay_as(user, amount);
+                                let id = unit_test_bug
+                                    .create_escrow(beneficiary, accounts.frank)
+                                    .expect("escrow is funded");
+                                open_escrows.push(id);
+                            }
+                            2 if !open_escrows.is_empty() => {
+                                let id = open_escrows
+                                    .swap_remove(rng.below(open_escrows.len() as u64) as usize);
+                                let escrow = unit_test_bug.escrow(id).expect("escrow exists");
+                                set_caller(escrow.depositor);
+                                unit_test_bug.release(id).expect("depositor releases");
+                                *credited.entry(escrow.beneficiary).or_default() += escrow.amount;
+                            }
+                            3 => {
+                                pay_as(user, KEEPER_REWARD + rng.below(10) as Balance);
+                                unit_test_bug
+                                    .queue_flip(rng.below(4) as u32)
+                                    .expect("queue has room");
+                            }
+                            4 => {
+                                let queued = unit_test_bug.queued_flips();
+                                set_caller(user);
+                                let processed = unit_test_bug
+                                    .execute_due(rng.below(4) as u32)
+                                    .expect("keeper executes");
+                                for item in &queued[..processed as usize] {
+                                    let (account, amount) = if item.paid < 5 {
+                                        (item.by, item.paid + KEEPER_REWARD)
+                                    } else {
+                                        (user, KEEPER_REWARD)
+                                    };
+                                    *credited.entry(account).or_default() += amount;
+                                }
+                            }
+                            _ => {
+                                let credit = unit_test_bug.credit_of(user);
+                                let start = balance_of(user);
+                                set_caller(user);
+                                if credit == 0 {
+                                    assert_eq!(
+                                        unit_test_bug.withdraw_credit(),
+                                        Err(FlipError::NothingToClaim)
+                                    );
+                                } else {
+                                    assert_eq!(unit_test_bug.withdraw_credit(), Ok(credit));
+                                    assert_eq!(balance_of(user), start + credit);
+                                    *withdrawn.entry(user).or_default() += credit;
+                                }
+                            }
+                        }
+                        for user in users {
+                            let owed = credited.get(&user).copied().unwrap_or(0);
+                            let paid = withdrawn.get(&user).copied().unwrap_or(0);
+                            assert_eq!(unit_test_bug.credit_of(user) + paid, owed);
+                        }
+                        assert_eq!(
+                            balance_of(contract) - contract_start,
+                            unit_test_bug.reserved_balance()
+                        );
+                        assert!(unit_test_bug.verify_invariants().is_ok());
+                    }
+                    Ok(())
+                })
+                .expect("scenario runs");
+            }
+        }
+
+        /// The audit log keeps the latest `AUDIT_LOG_SIZE` privileged changes.
+        #[ink::test]
+        fn audit_log_wraps_around() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let hash = |label: &String| UnitTestBug::audit_hash(label);
+            let labels: Vec<String> = (0..AUDIT_LOG_SIZE + 3).map(|n| n.to_string()).collect();
+            for label in &labels {
+                unit_test_bug.set_label(label.clone()).expect("owner sets the label");
+            }
+            set_caller(accounts().bob);
+            assert_eq!(
+                unit_test_bug.set_label(String::from("bob's")),
+                Err(FlipError::NotOwner)
+            );
+            assert_eq!(unit_test_bug.audit_count(), AUDIT_LOG_SIZE + 3);
+
+            // The first three were overwritten.
+            let log = unit_test_bug.audit_log(0, u32::MAX);
+            assert_eq!(log.len(), AUDIT_LOG_SIZE as usize);
+            assert_eq!(log[0].index, 3);
+            assert_eq!(log[0].old, hash(&labels[2]));
+            assert_eq!(log[0].new, hash(&labels[3]));
+            let last = log.last().expect("the log is full");
+            assert_eq!(last.index, AUDIT_LOG_SIZE + 2);
+            assert_eq!(last.new, hash(&labels[AUDIT_LOG_SIZE as usize + 2]));
+            assert!(log
+                .iter()
+                .all(|entry| entry.by == accounts().alice
+                    && entry.what == ink::selector_bytes!("set_label")));
+
+            let page = unit_test_bug.audit_log(AUDIT_LOG_SIZE, 10);
+            let indexes: Vec<u32> = page.iter().map(|entry| entry.index).collect();
+            assert_eq!(indexes, [AUDIT_LOG_SIZE, AUDIT_LOG_SIZE + 1, AUDIT_LOG_SIZE + 2]);
+            assert!(unit_test_bug.audit_log(AUDIT_LOG_SIZE + 3, 10).is_empty());
+        }
+
+        /// Each operation of a multicall is audited on its own.
+        #[ink::test]
+        fn multicall_ops_are_audited() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let treasury = Some(accounts().django);
+            let ops = vec![
+                AdminOp::SetLabel(String::from("staging")),
+                AdminOp::SetTreasury(treasury),
+            ];
+            unit_test_bug.multicall(ops).expect("multicall succeeds");
+            let log = unit_test_bug.audit_log(0, 10);
+            let what: Vec<[u8; 4]> = log.iter().map(|entry| entry.what).collect();
+            assert_eq!(
+                what,
+                [
+                    ink::selector_bytes!("set_label"),
+                    ink::selector_bytes!("set_treasury")
+                ]
+            );
+            assert_eq!(log[1].old, UnitTestBug::audit_hash(&None::<AccountId>));
+            assert_eq!(log[1].new, UnitTestBug::audit_hash(&treasury));
+        }
+
+        /// Pruning inactive participants resumes across calls and keeps funded
+        /// and recently active accounts.
+        #[ink::test]
+        fn prune_participants_resumes_across_calls() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            for account in [accounts.alice, accounts.bob] {
+                set_caller(account);
+                unit_test_bug.flip().expect("flip succeeds");
+            }
+            pay_as(accounts.charlie, 10);
+            unit_test_bug.wrap();
+            set_caller(accounts.django);
+            unit_test_bug.flip().expect("flip succeeds");
+            advance_blocks(DEFAULT_EPOCH_LENGTH * (PARTICIPANT_RETENTION_EPOCHS - 1));
+            set_caller(accounts.frank);
+            unit_test_bug.flip().expect("flip succeeds");
+            advance_blocks(DEFAULT_EPOCH_LENGTH);
+            let participants = |unit_test_bug: &UnitTestBug| -> Vec<AccountId> {
+                (0..unit_test_bug.participant_count())
+                    .map(|index| unit_test_bug.participant(index))
+                    .collect()
+            };
+
+            // [alice, bob, charlie, django, frank]: frank replaces alice.
+            assert_eq!(unit_test_bug.prune_participants(2), 1);
+            assert_eq!(
+                participants(&unit_test_bug),
+                [accounts.frank, accounts.bob, accounts.charlie, accounts.django]
+            );
+            // Bob, then django who replaced him, and charlie moves up.
+            assert_eq!(unit_test_bug.prune_participants(2), 2);
+            assert_eq!(participants(&unit_test_bug), [accounts.frank, accounts.charlie]);
+            // The end is reached and the next call starts over.
+            assert_eq!(unit_test_bug.prune_participants(2), 0);
+            assert_eq!(unit_test_bug.prune_cursor, 0);
+            assert_eq!(unit_test_bug.prune_participants(10), 0);
+
+            let pruned: Vec<_> = recorded_events()
+                .into_iter()
+                .filter_map(|event| match event {
+                    Event::Pruned(pruned) => Some(pruned.count),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(pruned, [1, 2, 0, 0]);
+
+            // A pruned account that flips again is registered anew.
+            set_caller(accounts.bob);
+            unit_test_bug.flip().expect("flip succeeds");
+            assert_eq!(unit_test_bug.participant_count(), 3);
+            assert_eq!(unit_test_bug.participant_ids.get(accounts.bob), Some(2));
+            assert_eq!(unit_test_bug.flip_count(accounts.bob), 2);
+        }
+
+        /// Approvals are directional and scoped to the approving owner.
+        #[ink::test]
+        fn approval_does_not_extend_to_other_owners() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            set_caller(accounts.bob);
+            unit_test_bug.approve(accounts.charlie, true);
+
+            assert_eq!(
+                unit_test_bug.flip_for(accounts.charlie),
+                Err(FlipError::NotApproved)
+            );
+            set_caller(accounts.charlie);
+            assert_eq!(
+                unit_test_bug.flip_for(accounts.django),
+                Err(FlipError::NotApproved)
+            );
+            assert_eq!(unit_test_bug.flip_for(accounts.charlie).map(drop), Ok(()));
+        }
+
+        /// Donations accumulate and cannot be forwarded without a treasury or funds.
+        #[ink::test]
+        fn donations_need_treasury_and_funds() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let treasury = AccountId::from([0x7e; 32]);
+            assert_eq!(
+                unit_test_bug.forward_donations(),
+                Err(FlipError::NoTreasury)
+            );
+
+            set_caller(accounts().bob);
+            assert_eq!(
+                unit_test_bug.set_treasury(Some(treasury)),
+                Err(FlipError::NotOwner)
+            );
+            set_caller(accounts().alice);
+            unit_test_bug
+                .set_treasury(Some(treasury))
+                .expect("owner sets treasury");
+            assert_eq!(unit_test_bug.treasury(), Some(treasury));
+            assert_eq!(
+                unit_test_bug.forward_donations(),
+                Err(FlipError::NothingToClaim)
+            );
+
+            pay_as(accounts().bob, 30);
+            unit_test_bug.donate();
+            pay_as(accounts().charlie, 12);
+            unit_test_bug.donate();
+            assert_eq!(unit_test_bug.donations(), 42);
+        }
+
+        /// An approved asset payment covers the fee and lands in the asset pot.
+        #[ink::test]
+        fn asset_fee_pays_for_flip() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            let ledger = mock_assets();
+            ledger.borrow_mut().balances.insert((7, accounts.bob), 50);
+            ledger.borrow_mut().approvals.insert((7, accounts.bob), 20);
+            set_config(&mut unit_test_bug, |config| config.flip_fee = 1_000);
+            unit_test_bug
+                .set_asset_fee(Some(AssetFee {
+                    asset_id: 7,
+                    amount: 20,
+                }))
+                .expect("owner sets asset fee");
+
+            set_caller(accounts.bob);
+            assert_eq!(unit_test_bug.flip_with_asset().map(drop), Ok(()));
+            assert!(unit_test_bug.get());
+            assert_eq!(unit_test_bug.fee_pot(), 0);
+            assert_eq!(unit_test_bug.asset_fee_pot(7), 20);
+            assert_eq!(ledger.borrow().balance(7, accounts.bob), 30);
+
+            set_caller(accounts.alice);
+            assert_eq!(unit_test_bug.withdraw_asset_fees(7), Ok(20));
+            assert_eq!(ledger.borrow().balance(7, accounts.alice), 20);
+            assert_eq!(
+                unit_test_bug.withdraw_asset_fees(7),
+                Err(FlipError::NothingToClaim)
+            );
+        }
+
+        /// Asset payments need a configured asset and enough of it.
+        #[ink::test]
+        fn asset_fee_requires_configuration_and_balance() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            let ledger = mock_assets();
+            assert_eq!(unit_test_bug.flip_with_asset(), Err(FlipError::NoAssetFee));
+            set_caller(accounts.bob);
+            assert_eq!(
+                unit_test_bug.set_asset_fee(Some(AssetFee {
+                    asset_id: 7,
+                    amount: 20,
+                })),
+                Err(FlipError::NotOwner)
+            );
+            set_caller(accounts.alice);
+            unit_test_bug
+                .set_asset_fee(Some(AssetFee {
+                    asset_id: 7,
+                    amount: 20,
+                }))
+                .expect("owner sets asset fee");
+
+            ledger.borrow_mut().balances.insert((7, accounts.bob), 19);
+            ledger.borrow_mut().approvals.insert((7, accounts.bob), 100);
+            set_caller(accounts.bob);
+            assert_eq!(
+                unit_test_bug.flip_with_asset(),
+                Err(FlipError::InsufficientFee)
+            );
+            assert!(!unit_test_bug.get());
+            assert_eq!(ledger.borrow().balance(7, accounts.bob), 19);
+        }
+
+        /// Without an approval the runtime refuses to move the payment.
+        #[ink::test]
+        fn unapproved_asset_fee_is_rejected() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            let ledger = mock_assets();
+            ledger.borrow_mut().balances.insert((7, accounts.bob), 50);
+            unit_test_bug
+                .set_asset_fee(Some(AssetFee {
+                    asset_id: 7,
+                    amount: 20,
+                }))
+                .expect("owner sets asset fee");
+
+            set_caller(accounts.bob);
+            assert_eq!(
+                unit_test_bug.flip_with_asset(),
+                Err(FlipError::AssetTransferFailed)
+            );
+            assert_eq!(ledger.borrow().balance(7, accounts.bob), 50);
+            assert_eq!(unit_test_bug.asset_fee_pot(7), 0);
+        }
+
+        /// Only the owner may fix the fee in dollars, and conversion rounds up.
+        #[ink::test]
+        fn usd_fee_is_owner_configured() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let fee = UsdFee {
+                oracle: AccountId::from([0x0c; 32]),
+                usd_cents: 150,
+                max_age: 10,
+            };
+            set_config(&mut unit_test_bug, |config| config.flip_fee = 9);
+            assert_eq!(unit_test_bug.quote_flip_fee(), Ok(9));
+            set_caller(accounts().bob);
+            assert_eq!(
+                unit_test_bug.set_usd_fee(Some(fee)),
+                Err(FlipError::NotOwner)
+            );
+            set_caller(accounts().alice);
+            assert_eq!(unit_test_bug.set_usd_fee(Some(fee)), Ok(()));
+            assert_eq!(unit_test_bug.usd_fee(), Some(fee));
+
+            let quote = PriceQuote {
+                units_per_usd: 333,
+                updated_at: 0,
+            };
+            // 1.50 USD at 333 units per USD is 499.5 units.
+            assert_eq!(fee.native_amount(&quote), 500);
+        }
+
+        /// Proves knowledge of a square root of the public input.
+        #[cfg(feature = "zk")]
+        struct SquareRootCircuit {
+            root: Option<ark_bn254::Fr>,
+            square: Option<ark_bn254::Fr>,
+        }
+
+        #[cfg(feature = "zk")]
+        impl ark_relations::r1cs::ConstraintSynthesizer<ark_bn254::Fr> for SquareRootCircuit {
+            fn generate_constraints(
+                self,
+                cs: ark_relations::r1cs::ConstraintSystemRef<ark_bn254::Fr>,
+            ) -> ark_relations::r1cs::Result<()> {
+                use ark_relations::{lc, r1cs::SynthesisError};
+
+                let root =
+                    cs.new_witness_variable(|| self.root.ok_or(SynthesisError::AssignmentMissing))?;
+                let square =
+                    cs.new_input_variable(|| self.square.ok_or(SynthesisError::AssignmentMissing))?;
+                cs.enforce_constraint(lc!() + root, lc!() + root, lc!() + square)
+            }
+        }
+
+        /// Fixed-seed Groth16 fixture: a compressed verifying key and a proof that 3² = 9.
+        #[cfg(feature = "zk")]
+        pub(super) fn zk_fixture() -> (Vec<u8>, Vec<u8>, Vec<[u8; 32]>) {
+            use ark_groth16::Groth16;
+            use ark_serialize::CanonicalSerialize;
+
+            let mut rng = ark_std::test_rng();
+            let setup = SquareRootCircuit {
+                root: None,
+                square: None,
+            };
+            let params = Groth16::<ark_bn254::Bn254>::generate_random_parameters_with_reduction(
+                setup, &mut rng,
+            )
+            .expect("setup succeeds");
+            let witness = SquareRootCircuit {
+                root: Some(3u64.into()),
+                square: Some(9u64.into()),
+            };
+            let proof = Groth16::<ark_bn254::Bn254>::create_random_proof_with_reduction(
+                witness, &params, &mut rng,
+            )
+            .expect("proving succeeds");
+
+            let mut key = Vec::new();
+            params
+                .vk
+                .serialize_compressed(&mut key)
+                .expect("key serializes");
+            let mut proof_bytes = Vec::new();
+            proof
+                .serialize_compressed(&mut proof_bytes)
+                .expect("proof serializes");
+            let mut nine = [0u8; 32];
+            nine[0] = 9;
+            (key, proof_bytes, vec![nine])
+        }
+
+        /// A valid proof flips, while a wrong input, a corrupt proof or no key do not.
+        #[cfg(feature = "zk")]
+        #[ink::test]
+        fn zk_proof_gates_flip() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let (key, proof, inputs) = zk_fixture();
+            assert_eq!(
+                unit_test_bug.flip_with_zk_proof(proof.clone(), inputs.clone()),
+                Err(FlipError::NoVerifyingKey)
+            );
+            assert_eq!(
+                unit_test_bug.set_zk_verifying_key(vec![1, 2, 3]),
+                Err(FlipError::InvalidVerifyingKey)
+            );
+            unit_test_bug
+                .set_zk_verifying_key(key)
+                .expect("owner sets key");
+
+            let mut ten = [0u8; 32];
+            ten[0] = 10;
+            assert_eq!(
+                unit_test_bug.flip_with_zk_proof(proof.clone(), vec![ten]),
+                Err(FlipError::InvalidProof)
+            );
+            assert_eq!(
+                unit_test_bug.flip_with_zk_proof(proof[1..].to_vec(), inputs.clone()),
+                Err(FlipError::InvalidProof)
+            );
+            assert!(!unit_test_bug.get());
+
+            assert_eq!(
+                unit_test_bug.flip_with_zk_proof(proof, inputs).map(drop),
+                Ok(())
+            );
+            assert!(unit_test_bug.get());
+        }
+
+        /// With `debug`, a reverted flip traces the value it wrote.
+        #[cfg(feature = "debug")]
+        #[ink::test]
+        fn flip_with_error_traces() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            assert_eq!(unit_test_bug.flip_with_error(), Err(FlipError::FlipError));
+            let messages = ink::env::test::recorded_debug_messages();
+            assert!(messages
+                .into_iter()
+                .any(|message| message.contains("flip_with_error: value is now true, reverting")));
+        }
+
+        /// With `debug`, a call writes its caller, message and arguments digest
+        /// ahead of reverting.
+        #[cfg(feature = "debug")]
+        #[ink::test]
+        fn reverted_call_traces_context() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            set_caller(accounts.bob);
+            assert_eq!(
+                unit_test_bug.flip_for(accounts.charlie),
+                Err(FlipError::NotApproved)
+            );
+
+            let mut digest = [0u8; 32];
+            ink::env::hash_encoded::<Blake2x256, _>(&accounts.charlie, &mut digest);
+            let expected = format!(
+                "flip_for: caller 0x{}, value 0, args 0x{}",
+                hex::encode(accounts.bob),
+                hex::encode(&digest[..8])
+            );
+            let messages = ink::env::test::recorded_debug_messages();
+            assert!(
+                messages
+                    .into_iter()
+                    .any(|message| message.trim_end() == expected),
+                "no {expected:?}"
+            );
+        }
+
+        /// Without `debug`, calls write nothing to the debug buffer.
+        #[cfg(not(feature = "debug"))]
+        #[ink::test]
+        fn calls_trace_nothing_without_debug() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            set_caller(accounts().bob);
+            unit_test_bug.flip().expect("flip succeeds");
+            assert_eq!(
+                unit_test_bug.flip_for(accounts().charlie),
+                Err(FlipError::NotApproved)
+            );
+            let messages = ink::env::test::recorded_debug_messages();
+            assert_eq!(messages.into_iter().count(), 0);
+        }
+
+        /// Without the `zk` feature no verifying key is accepted.
+        #[cfg(not(feature = "zk"))]
+        #[ink::test]
+        fn zk_unsupported_without_feature() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            assert_eq!(
+                unit_test_bug.set_zk_verifying_key(vec![1, 2, 3]),
+                Err(FlipError::ZkUnsupported)
+            );
+        }
+
+        /// Attestations need a validator set, and without the `bls` feature none is accepted.
+        #[ink::test]
+        fn bls_attestation_needs_validator_set() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            assert_eq!(
+                unit_test_bug.flip_with_bls_attestation(vec![0], vec![0; 48]),
+                Err(FlipError::NoValidatorSet)
+            );
+            if cfg!(not(feature = "bls")) {
+                assert_eq!(
+                    unit_test_bug.set_bls_validators(BlsValidatorSet {
+                        keys: vec![vec![0; 96]],
+                        threshold: 1,
+                    }),
+                    Err(FlipError::BlsUnsupported)
+                );
+            }
+        }
+
+        /// Validator keys for secret keys 0x1111, 0x2222 and 0x3333.
+        #[cfg(feature = "bls")]
+        const BLS_VALIDATOR_KEYS: [&str; 3] = [
+            "93be2b30033e7c29a414e4356d8fb1f9f6949a8c5441a830a9084f89dff1687c4599945b5b7e8fde1c713f913a57c2670ba0b623eb0de039e270d0f68478cd1cb7db577206e32a57386de1c3fabe40c53b2cdb385ee68547e2a6aba35a6d1247",
+            "8f498ba6bee5681817fa4609824697e09467e23054b40dec4ee647e928d210bddaa938cae53f83a254395b99ff42561d0bdc164c592bdbeb4d5da633d98f1abd484af8736898926e9116b1970b0257bf24e9e5e67c9af991fae824063e56797c",
+            "8f782e857aeb808a9c0f18c7e38c2b2993a55d4244e164039601fce2942b2fad250490202bd2e7e1fcf9512b203553541738c45ed68ebbe8eb63152cd589716028273041b9cfe8248d0bd55e0d50ff98e2aa95d2842c26e106224290dac2b274",
+        ];
+
+        /// Aggregated signature of validators 0 and 2 over the first
+        /// attestation message of the contract at `[0xbb; 32]`.
+        #[cfg(feature = "bls")]
+        const BLS_SIGNATURE_0_2: &str = "8da76d2e537462dea89dffdf310a34d3db3db09a79e5cb7d4675b7c6cf1e465e3dd32fe76103fcc667745088f6f4831d";
+
+        /// Deploys at `[0xbb; 32]` with the fixture validators and a threshold of two.
+        #[cfg(feature = "bls")]
+        fn bls_contract() -> UnitTestBug {
+            let contract = AccountId::from([0xbb; 32]);
+            ink::env::test::set_callee::<ink::env::DefaultEnvironment>(contract);
+            set_balance(contract, 0);
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let keys = BLS_VALIDATOR_KEYS
+                .iter()
+                .map(|key| hex::decode(key).unwrap())
+                .collect();
+            unit_test_bug
+                .set_bls_validators(BlsValidatorSet { keys, threshold: 2 })
+                .expect("owner sets validators");
+            unit_test_bug
+        }
+
+        /// An attestation flips even while paused, and only once.
+        #[cfg(feature = "bls")]
+        #[ink::test]
+        fn bls_attestation_flips_once() {
+            let mut unit_test_bug = bls_contract();
+            set_config(&mut unit_test_bug, |config| config.paused = true);
+            let signature = hex::decode(BLS_SIGNATURE_0_2).unwrap();
+
+            assert_eq!(
+                unit_test_bug
+                    .flip_with_bls_attestation(vec![0, 2], signature.clone())
+                    .map(drop),
+                Ok(())
+            );
+            assert!(unit_test_bug.get());
+            assert_eq!(
+                unit_test_bug.flip_with_bls_attestation(vec![0, 2], signature),
+                Err(FlipError::InvalidSignature)
+            );
+            assert!(unit_test_bug.get());
+        }
+
+        /// Attestations need enough distinct, in-range signers who actually signed.
+        #[cfg(feature = "bls")]
+        #[ink::test]
+        fn bls_attestation_rejects_bad_signers() {
+            let mut unit_test_bug = bls_contract();
+            let signature = hex::decode(BLS_SIGNATURE_0_2).unwrap();
+            for (signers, error) in [
+                (vec![0], FlipError::BelowThreshold),
+                (vec![2, 0], FlipError::InvalidSigner),
+                (vec![0, 0], FlipError::InvalidSigner),
+                (vec![0, 3], FlipError::InvalidSigner),
+                (vec![0, 1], FlipError::InvalidSignature),
+            ] {
+                assert_eq!(
+                    unit_test_bug.flip_with_bls_attestation(signers, signature.clone()),
+                    Err(error)
+                );
+            }
+            assert_eq!(
+                unit_test_bug.set_bls_validators(BlsValidatorSet {
+                    keys: vec![vec![0; 96]],
+                    threshold: 1,
+                }),
+                Err(FlipError::InvalidValidatorSet)
+            );
+            assert!(!unit_test_bug.get());
+        }
+
+        /// The hashing messages agree with the pure-Rust implementations.
+        #[ink::test]
+        fn hash_messages_match_reference_hashes() {
+            use blake2::{digest::consts::U32, Blake2b, Digest};
+
+            let unit_test_bug = UnitTestBug::new(false);
+            for data in [&b""[..], b"flip", &[0xab; 1000]] {
+                let blake2: [u8; 32] = Blake2b::<U32>::digest(data).into();
+                let keccak: [u8; 32] = sha3::Keccak256::digest(data).into();
+                let sha2: [u8; 32] = sha2::Sha256::digest(data).into();
+                assert_eq!(unit_test_bug.hash_blake2(data.to_vec()), blake2);
+                assert_eq!(unit_test_bug.hash_keccak(data.to_vec()), keccak);
+                assert_eq!(unit_test_bug.hash_sha2(data.to_vec()), sha2);
+            }
+            assert_eq!(
+                hex::encode(unit_test_bug.hash_keccak(Vec::new())),
+                "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+            );
+        }
+
+        /// Blobs up to the configured size round-trip, larger ones are rejected.
+        #[ink::test]
+        fn blob_respects_max_size() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let max = unit_test_bug.config().max_blob_size as usize;
+            assert_eq!(unit_test_bug.read_blob(1), None);
+            assert_eq!(unit_test_bug.store_blob(1, vec![7; max]), Ok(()));
+            assert_eq!(unit_test_bug.read_blob(1), Some(vec![7; max]));
+            assert_eq!(
+                unit_test_bug.store_blob(1, vec![8; max + 1]),
+                Err(FlipError::BlobTooLarge)
+            );
+            assert_eq!(unit_test_bug.read_blob(1), Some(vec![7; max]));
+
+            set_config(&mut unit_test_bug, |config| {
+                config.max_blob_size = MAX_BLOB_SIZE_LIMIT
+            });
+            let limit = MAX_BLOB_SIZE_LIMIT as usize;
+            assert_eq!(unit_test_bug.store_blob(2, vec![9; limit]), Ok(()));
+            assert_eq!(unit_test_bug.read_blob(2), Some(vec![9; limit]));
+            assert_eq!(
+                unit_test_bug.propose_config(Config {
+                    max_blob_size: MAX_BLOB_SIZE_LIMIT + 1,
+                    ..unit_test_bug.config()
+                }),
+                Err(FlipError::InvalidConfig)
+            );
+
+            set_caller(accounts().bob);
+            assert_eq!(
+                unit_test_bug.store_blob(3, vec![1]),
+                Err(FlipError::NotOwner)
+            );
+        }
+
+        /// Random blobs up to the size limit round-trip, including overwrites that shrink.
+        #[ink::test]
+        fn chunked_blobs_round_trip() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            set_config(&mut unit_test_bug, |config| {
+                config.max_blob_size = MAX_BLOB_SIZE_LIMIT
+            });
+            let mut rng = Rng(0x5eed);
+            let mut expected: [Option<Vec<u8>>; 4] = Default::default();
+            for _ in 0..40 {
+                let key = rng.below(4) as u32;
+                let length = match rng.below(4) {
+                    0 => rng.below(3) * BLOB_CHUNK_SIZE as u64,
+                    1 => MAX_BLOB_SIZE_LIMIT as u64,
+                    _ => rng.below(MAX_BLOB_SIZE_LIMIT as u64 + 1),
+                };
+                let data: Vec<u8> = (0..length).map(|_| rng.next() as u8).collect();
+                unit_test_bug
+                    .store_blob(key, data.clone())
+                    .expect("blob within limit");
+                expected[key as usize] = Some(data);
+                for (key, blob) in expected.iter().enumerate() {
+                    assert_eq!(&unit_test_bug.read_blob(key as u32), blob);
+                }
+            }
+            // Shrinking drops every chunk past the new end.
+            unit_test_bug
+                .store_blob(0, vec![1; 2 * BLOB_CHUNK_SIZE as usize])
+                .expect("two chunks");
+            unit_test_bug.store_blob(0, Vec::new()).expect("empty blob");
+            assert_eq!(unit_test_bug.read_blob(0), Some(Vec::new()));
+            assert!(!unit_test_bug.blob_chunks.contains((0, 0)));
+        }
+
+        /// A `store_blob` call and a stored blob at the limit fit the 16 KiB buffer.
+        #[ink::test]
+        fn blob_limit_fits_buffer() {
+            const BUFFER_SIZE: usize = 16 * 1024;
+            let blob = vec![0u8; MAX_BLOB_SIZE_LIMIT as usize];
+            let call = ExecutionInput::new(Selector::new(ink::selector_bytes!("store_blob")))
+                .push_arg(u32::MAX)
+                .push_arg(&blob);
+            assert!(scale::Encode::encoded_size(&call) <= BUFFER_SIZE);
+            let read: Result<Option<Vec<u8>>, ink::LangError> = Ok(Some(blob));
+            assert!(scale::Encode::encoded_size(&read) <= BUFFER_SIZE);
+        }
+
+        /// Labels are bounded in UTF-8 bytes rather than characters.
+        #[ink::test]
+        fn label_is_bounded_in_bytes() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            assert_eq!(unit_test_bug.label(), "");
+
+            // 16 four-byte characters sit exactly at the limit.
+            let crabs = "🦀".repeat(MAX_LABEL_LEN / 4);
+            assert_eq!(unit_test_bug.set_label(crabs.clone()), Ok(()));
+            assert_eq!(unit_test_bug.label(), crabs);
+            assert_eq!(
+                unit_test_bug.set_label(crabs + "a"),
+                Err(FlipError::LabelTooLong)
+            );
+            assert_eq!(unit_test_bug.label(), "🦀".repeat(MAX_LABEL_LEN / 4));
+
+            set_caller(accounts().bob);
+            assert_eq!(
+                unit_test_bug.set_label(String::from("mine")),
+                Err(FlipError::NotOwner)
+            );
+        }
+
+        /// Call data carrying invalid UTF-8 never decodes into a label.
+        #[ink::test]
+        fn label_rejects_invalid_utf8() {
+            let encoded = scale::Encode::encode(&vec![0xf0u8, 0x9f, 0xa6]);
+            assert!(<String as scale::Decode>::decode(&mut &encoded[..]).is_err());
+        }
+
+        /// Accounts, hashes and balances rendered by `fmt` parse back to themselves.
+        #[test]
+        fn fmt_round_trips() {
+            let hash: [u8; 32] = core::array::from_fn(|index| index as u8);
+            for bytes in [[0; 32], [0xff; 32], *accounts().bob.as_ref(), hash] {
+                let rendered = crate::fmt::hex(&bytes);
+                let digits = rendered
+                    .as_str()
+                    .strip_prefix("0x")
+                    .expect("hex is prefixed");
+                assert_eq!(hex::decode(digits), Ok(bytes.to_vec()));
+                assert_eq!(digits, hex::encode(bytes));
+            }
+            assert_eq!(crate::fmt::hex(&[]).as_str(), "0x");
+
+            let values = [0, 9, 10, 1_000, u128::from(u64::MAX), u128::MAX - 1];
+            for value in values.into_iter().chain([u128::MAX]) {
+                let rendered = crate::fmt::decimal(value);
+                assert_eq!(rendered.as_str().parse(), Ok(value));
+                assert_eq!(rendered.as_str(), value.to_string());
+            }
+        }
+
+        #[test]
+        #[should_panic(expected = "too many bytes to render")]
+        fn fmt_hex_refuses_more_than_a_hash() {
+            crate::fmt::hex(&[0; crate::fmt::MAX_HEX_BYTES + 1]);
+        }
+
+        /// Without a label, the contract is named by its address.
+        #[ink::test]
+        fn display_label_falls_back_to_the_address() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let label = unit_test_bug.display_label();
+            let address = hex::decode(&label[2..]).expect("label is hex");
+            let contract = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            assert_eq!(AccountId::try_from(&address[..]).ok(), Some(contract));
+
+            unit_test_bug.set_label("staging".into()).unwrap();
+            assert_eq!(unit_test_bug.display_label(), "staging");
+        }
+
+        /// Flip policies survive a SCALE round-trip and register every nested type.
+        #[ink::test]
+        fn flip_policy_round_trips() {
+            let policies = [
+                FlipPolicy::default(),
+                FlipPolicy {
+                    mode: PolicyMode::OwnerOnly,
+                    params: PolicyParams {
+                        max_flips_per_account: Some(3),
+                        exempt: vec![accounts().bob],
+                    },
+                },
+                FlipPolicy {
+                    mode: PolicyMode::Scheduled(Schedule {
+                        start: 10,
+                        period: 7,
+                        open_for: 2,
+                    }),
+                    params: PolicyParams::default(),
+                },
+            ];
+            for policy in policies {
+                let encoded = scale::Encode::encode(&policy);
+                let decoded = <FlipPolicy as scale::Decode>::decode(&mut &encoded[..]);
+                assert_eq!(decoded, Ok(policy));
+            }
+
+            let mut registry = scale_info::Registry::new();
+            registry.register_type(&scale_info::meta_type::<FlipPolicy>());
+            let registry = scale_info::PortableRegistry::from(registry);
+            for name in ["FlipPolicy", "PolicyMode", "Schedule", "PolicyParams"] {
+                assert!(registry.types.iter().any(|ty| ty
+                    .ty
+                    .path
+                    .segments
+                    .last()
+                    .map(String::as_str)
+                    == Some(name)));
+            }
+        }
+
+        /// Flips follow the policy's mode, schedule, limit and exemptions.
+        #[ink::test]
+        fn flip_policy_gates_flips() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let exempt = FlipPolicy {
+                mode: PolicyMode::OwnerOnly,
+                params: PolicyParams {
+                    max_flips_per_account: Some(1),
+                    exempt: vec![accounts().charlie],
+                },
+            };
+            unit_test_bug
+                .set_flip_policy(exempt.clone())
+                .expect("owner sets policy");
+            assert_eq!(unit_test_bug.flip_policy(), exempt);
+            assert_eq!(unit_test_bug.flip().map(drop), Ok(()));
+            assert_eq!(unit_test_bug.flip(), Err(FlipError::PolicyDenied));
+            set_caller(accounts().bob);
+            assert_eq!(unit_test_bug.flip(), Err(FlipError::PolicyDenied));
+            set_caller(accounts().charlie);
+            assert_eq!(unit_test_bug.flip().map(drop), Ok(()));
+            assert_eq!(unit_test_bug.flip().map(drop), Ok(()));
+
+            set_caller(accounts().alice);
+            let schedule = Schedule {
+                start: 2,
+                period: 4,
+                open_for: 1,
+            };
+            unit_test_bug
+                .set_flip_policy(FlipPolicy {
+                    mode: PolicyMode::Scheduled(schedule),
+                    params: PolicyParams::default(),
+                })
+                .expect("owner sets policy");
+            set_caller(accounts().bob);
+            let mut admitted = Vec::new();
+            for block in 0..8 {
+                if unit_test_bug.flip().is_ok() {
+                    admitted.push(block);
+                }
+                advance_blocks(1);
+            }
+            assert_eq!(admitted, vec![2, 6]);
+
+            set_caller(accounts().alice);
+            for invalid in [
+                PolicyMode::Scheduled(Schedule {
+                    open_for: 0,
+                    ..schedule
+                }),
+                PolicyMode::Scheduled(Schedule {
+                    open_for: 5,
+                    ..schedule
+                }),
+            ] {
+                assert_eq!(
+                    unit_test_bug.set_flip_policy(FlipPolicy {
+                        mode: invalid,
+                        params: PolicyParams::default(),
+                    }),
+                    Err(FlipError::InvalidPolicy)
+                );
+            }
+            assert_eq!(
+                unit_test_bug.set_flip_policy(FlipPolicy {
+                    mode: PolicyMode::Open,
+                    params: PolicyParams {
+                        max_flips_per_account: None,
+                        exempt: vec![accounts().bob; MAX_POLICY_EXEMPTIONS + 1],
+                    },
+                }),
+                Err(FlipError::InvalidPolicy)
+            );
+        }
+
+        /// Both flip events decode with the shared types, the legacy one only while enabled.
+        #[ink::test]
+        fn flip_events_decode_with_shared_types() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            advance_blocks(3);
+            unit_test_bug.flip().expect("flip succeeds");
+            set_config(&mut unit_test_bug, |config| {
+                config.emit_legacy_events = false
+            });
+            unit_test_bug.flip().expect("flip succeeds");
+
+            let data: Vec<Vec<u8>> = ink::env::test::recorded_events()
+                .map(|event| event.data)
+                .collect();
+            assert_eq!(data.len(), 3);
+            let expected = shared_types::FlippedV2 {
+                account: accounts().alice,
+                value: true,
+                block: 3,
+                seq: 0,
+            };
+            let v2 = <shared_types::FlippedV2 as scale::Decode>::decode(&mut &data[0][1..]);
+            assert_eq!(v2, Ok(expected));
+            let legacy = <shared_types::Flipped as scale::Decode>::decode(&mut &data[1][1..]);
+            assert_eq!(legacy, Ok(expected.into()));
+            let v2 = <shared_types::FlippedV2 as scale::Decode>::decode(&mut &data[2][1..]);
+            assert_eq!(v2.map(|event| event.value), Ok(false));
+        }
+
+        /// Recorded flip events round-trip through the shared decode helpers.
+        #[ink::test]
+        fn flip_events_decode_with_event_helpers() {
+            use shared_types::events::{decode_flipped, decode_flipped_v2};
+
+            let mut unit_test_bug = UnitTestBug::new(false);
+            advance_blocks(3);
+            unit_test_bug.flip().expect("flip succeeds");
+
+            let events: Vec<(Vec<[u8; 32]>, Vec<u8>)> = ink::env::test::recorded_events()
+                .map(|event| {
+                    let topics = event
+                        .topics
+                        .iter()
+                        .map(|topic| topic[..].try_into().expect("topics are 32 bytes"))
+                        .collect();
+                    (topics, event.data)
+                })
+                .collect();
+            let [(v2_topics, v2_data), (legacy_topics, legacy_data)] = &events[..] else {
+                panic!("expected FlippedV2 and Flipped, got {events:?}");
+            };
+            let expected = shared_types::FlippedV2 {
+                account: accounts().alice,
+                value: true,
+                block: 3,
+                seq: 0,
+            };
+            assert_eq!(decode_flipped_v2(v2_topics, v2_data), Some(expected));
+            assert_eq!(decode_flipped(legacy_topics, legacy_data), Some(expected.into()));
+
+            // Each helper rejects the other event and mismatched topics.
+            assert_eq!(decode_flipped(v2_topics, v2_data), None);
+            assert_eq!(decode_flipped_v2(legacy_topics, legacy_data), None);
+            assert_eq!(decode_flipped_v2(legacy_topics, v2_data), None);
+            assert_eq!(decode_flipped_v2(v2_topics, &v2_data[..v2_data.len() - 1]), None);
+        }
+
+        /// `seq` of a state-change event, `None` for the legacy `Flipped`.
+        fn event_seq(event: &Event) -> Option<u64> {
+            match event {
+                Event::Flipped(_) => None,
+                Event::FlippedV2(event) => Some(event.seq),
+                Event::Approval(event) => Some(event.seq),
+                Event::BreakerTripped(event) => Some(event.seq),
+                Event::ConfigChanged(event) => Some(event.seq),
+                Event::EpochRolledOver(event) => Some(event.seq),
+                Event::BatchFlipped(event) => Some(event.seq),
+                Event::Pruned(event) => Some(event.seq),
+                Event::Evicted(event) => Some(event.seq),
+            }
+        }
+
+        /// The events a chain would keep carry consecutive `seq`s: a reverted
+        /// call's events are dropped, and its `seq`s are reused.
+        #[ink::test]
+        fn event_seq_counts_only_calls_that_took_effect() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            set_caller(accounts.bob);
+            unit_test_bug.approve(accounts.charlie, true);
+            set_caller(accounts.charlie);
+            unit_test_bug.flip_for(accounts.bob).expect("approved");
+            let before_revert = recorded_events().len();
+
+            // Bob's flip goes through, then eve's unapproved one fails the batch.
+            advance_blocks(1);
+            let reverted = call_reverting(&mut unit_test_bug, |unit_test_bug| {
+                unit_test_bug
+                    .batch_flip_for(vec![accounts.bob, accounts.eve], BatchMode::AllOrNothing)
+            });
+            assert_eq!(reverted, Err(FlipError::NotApproved));
+            let after_revert = recorded_events().len();
+            assert!(after_revert > before_revert);
+
+            advance_blocks(1);
+            unit_test_bug.flip_for(accounts.bob).expect("approved");
+            let events = recorded_events();
+            let kept: Vec<u64> = events[..before_revert]
+                .iter()
+                .chain(&events[after_revert..])
+                .filter_map(event_seq)
+                .collect();
+            // Approval, bob's flip, then the flip after the reverted batch.
+            assert_eq!(kept, [0, 1, 2]);
+            assert_eq!(unit_test_bug.next_event_seq(), 3);
+            let dropped: Vec<u64> = events[before_revert..after_revert]
+                .iter()
+                .filter_map(event_seq)
+                .collect();
+            assert_eq!(dropped, [2]);
+        }
+
+        /// A receipt repeats the `seq` and value of the `FlippedV2` its call emitted.
+        #[ink::test]
+        fn receipts_match_emitted_events() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            set_caller(accounts.bob);
+            unit_test_bug.approve(accounts.charlie, true);
+            let receipts = [
+                unit_test_bug.flip_with_referrer(Some(accounts.django)),
+                unit_test_bug.flip(),
+                {
+                    set_caller(accounts.charlie);
+                    unit_test_bug.flip_for(accounts.bob)
+                },
+            ];
+
+            let emitted: Vec<(u64, bool)> = recorded_events()
+                .into_iter()
+                .filter_map(|event| match event {
+                    Event::FlippedV2(event) => Some((event.seq, event.value)),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(emitted, [(1, true), (2, false), (3, true)]);
+            for (receipt, (seq, value)) in receipts.into_iter().zip(emitted) {
+                let expected = MutationReceipt {
+                    seq,
+                    new_value: value,
+                    gas_hint: 0,
+                };
+                assert_eq!(receipt, Ok(expected));
+            }
+            assert!(unit_test_bug.get());
+        }
+
+        /// A retried idempotency key returns the first receipt without flipping
+        /// again, within the block and after it, and credits the retry's payment.
+        #[ink::test]
+        fn idempotency_key_replays_first_receipt() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            set_config(&mut unit_test_bug, |config| config.flip_fee = 10);
+            let accounts = accounts();
+            let key = [7; 16];
+
+            // A failed call leaves the key unused.
+            set_caller(accounts.bob);
+            assert_eq!(
+                unit_test_bug.execute_idempotent(ForwardedCall::Flip, Some(key)),
+                Err(FlipError::InsufficientFee)
+            );
+            assert_eq!(unit_test_bug.idempotent_receipt(accounts.bob, key), None);
+            pay_as(accounts.bob, 10);
+            let first = unit_test_bug
+                .execute_idempotent(ForwardedCall::Flip, Some(key))
+                .expect("flip succeeds");
+            assert_eq!(
+                unit_test_bug.idempotent_receipt(accounts.bob, key),
+                Some(first)
+            );
+
+            pay_as(accounts.bob, 10);
+            assert_eq!(
+                unit_test_bug.execute_idempotent(ForwardedCall::Flip, Some(key)),
+                Ok(first)
+            );
+            advance_blocks(1);
+            set_value_transferred(0);
+            assert_eq!(
+                unit_test_bug.execute_idempotent(ForwardedCall::Flip, Some(key)),
+                Ok(first)
+            );
+            assert!(unit_test_bug.get());
+            assert_eq!(unit_test_bug.flip_count(accounts.bob), 1);
+            assert_eq!(unit_test_bug.next_event_seq(), first.seq + 1);
+            assert_eq!(unit_test_bug.credit_of(accounts.bob), 10);
+
+            // Keys are per caller, and calls without one always run.
+            pay_as(accounts.charlie, 10);
+            let charlie = unit_test_bug.execute_idempotent(ForwardedCall::Flip, Some(key));
+            assert_eq!(charlie.map(|receipt| receipt.seq), Ok(first.seq + 1));
+            pay_as(accounts.bob, 10);
+            let unkeyed = unit_test_bug.execute_idempotent(ForwardedCall::Flip, None);
+            assert_eq!(unkeyed.map(|receipt| receipt.new_value), Ok(true));
+            assert_eq!(unit_test_bug.flip_count(accounts.bob), 2);
+        }
+
+        /// The call quota is per account and resets with every block.
+        #[ink::test]
+        fn rate_limit_resets_each_block() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            set_config(&mut unit_test_bug, |config| config.max_calls_per_block = 2);
+            unit_test_bug.wrap();
+            assert_eq!(unit_test_bug.flip().map(drop), Ok(()));
+            assert_eq!(unit_test_bug.unwrap(0), Ok(()));
+            assert_eq!(unit_test_bug.flip(), Err(FlipError::RateLimited));
+            assert_eq!(unit_test_bug.unwrap(0), Err(FlipError::RateLimited));
+
+            set_caller(accounts().bob);
+            assert_eq!(unit_test_bug.flip().map(drop), Ok(()));
+
+            set_caller(accounts().alice);
+            advance_blocks(1);
+            assert_eq!(unit_test_bug.flip().map(drop), Ok(()));
+            assert_eq!(unit_test_bug.flip().map(drop), Ok(()));
+            assert_eq!(unit_test_bug.flip(), Err(FlipError::RateLimited));
+
+            set_config(&mut unit_test_bug, |config| config.max_calls_per_block = 0);
+            assert_eq!(unit_test_bug.flip().map(drop), Ok(()));
+        }
+
+        /// Consecutive failures pause the contract until the guardian resets it.
+        #[ink::test]
+        fn breaker_trips_and_guardian_resets() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            set_config(&mut unit_test_bug, |config| config.breaker_threshold = 2);
+            unit_test_bug
+                .set_guardian(Some(accounts.charlie))
+                .expect("owner sets guardian");
+
+            assert_eq!(
+                unit_test_bug.run_privileged(PrivilegedOp::Claim),
+                Ok(Err(FlipError::NothingToClaim))
+            );
+            assert!(!unit_test_bug.breaker_tripped());
+            assert_eq!(
+                unit_test_bug.run_privileged(PrivilegedOp::ForwardDonations),
+                Ok(Err(FlipError::NoTreasury))
+            );
+            assert_eq!(unit_test_bug.privileged_failures(), 2);
+            assert!(unit_test_bug.breaker_tripped());
+            assert_eq!(unit_test_bug.flip(), Err(FlipError::Paused));
+            assert_eq!(
+                unit_test_bug.run_privileged(PrivilegedOp::Claim),
+                Err(FlipError::Paused)
+            );
+
+            assert_eq!(unit_test_bug.reset_breaker(), Err(FlipError::NotGuardian));
+            set_caller(accounts.charlie);
+            assert_eq!(unit_test_bug.reset_breaker(), Ok(()));
+            assert_eq!(unit_test_bug.privileged_failures(), 0);
+            assert_eq!(unit_test_bug.flip().map(drop), Ok(()));
+
+            let events = recorded_events();
+            let tripped = events
+                .iter()
+                .filter(|event| matches!(event, Event::BreakerTripped(e) if e.failures == 2))
+                .count();
+            assert_eq!(tripped, 1);
+        }
+
+        /// Rejected callers and successes never add to the failure count.
+        #[ink::test]
+        fn breaker_counts_only_failed_operations() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            set_config(&mut unit_test_bug, |config| {
+                config.breaker_threshold = 2;
+                config.flip_fee = 10;
+            });
+            unit_test_bug.set_vesting_rate(10).expect("owner sets rate");
+            assert_eq!(
+                unit_test_bug.run_privileged(PrivilegedOp::Claim),
+                Ok(Err(FlipError::NothingToClaim))
+            );
+            pay_as(accounts().bob, 10);
+            unit_test_bug.flip().expect("fee paid");
+            advance_blocks(1);
+
+            assert_eq!(
+                unit_test_bug.run_privileged(PrivilegedOp::Claim),
+                Err(FlipError::NotOwner)
+            );
+            assert_eq!(unit_test_bug.privileged_failures(), 1);
+
+            set_caller(accounts().alice);
+            set_value_transferred(0);
+            assert_eq!(
+                unit_test_bug.run_privileged(PrivilegedOp::Claim),
+                Ok(Ok(10))
+            );
+            assert_eq!(unit_test_bug.privileged_failures(), 0);
+            assert!(!unit_test_bug.breaker_tripped());
+        }
+
+        /// A failure counted inside a call that then reverts is rolled back with it.
+        #[ink::test]
+        fn breaker_count_rolls_back_with_reverted_calls() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            set_config(&mut unit_test_bug, |config| config.breaker_threshold = 2);
+            assert_eq!(
+                unit_test_bug.run_privileged(PrivilegedOp::Claim),
+                Ok(Err(FlipError::NothingToClaim))
+            );
+            assert_eq!(unit_test_bug.privileged_failures(), 1);
+
+            // A caller that reverts on the inner failure, after it tripped the breaker.
+            let reverted = call_reverting(&mut unit_test_bug, |contract| {
+                let outcome = contract.run_privileged(PrivilegedOp::Claim);
+                assert_eq!(contract.privileged_failures(), 2);
+                assert!(contract.breaker_tripped());
+                outcome.and_then(|outcome| outcome)
+            });
+            assert_eq!(reverted, Err(FlipError::NothingToClaim));
+            assert_eq!(unit_test_bug.privileged_failures(), 1);
+            assert!(!unit_test_bug.breaker_tripped());
+        }
+
+        /// A batch applies every operation in order.
+        #[ink::test]
+        fn multicall_applies_every_op() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            let ops = vec![
+                AdminOp::SetTreasury(Some(accounts.django)),
+                AdminOp::SetGuardian(Some(accounts.charlie)),
+                AdminOp::SetLabel(String::from("first")),
+                AdminOp::SetLabel(String::from("second")),
+            ];
+            assert_eq!(unit_test_bug.multicall(ops), Ok(()));
+            assert_eq!(unit_test_bug.treasury(), Some(accounts.django));
+            assert_eq!(unit_test_bug.guardian(), Some(accounts.charlie));
+            assert_eq!(unit_test_bug.label(), "second");
+
+            let too_many = vec![AdminOp::SetGuardian(None); MAX_BATCH_SIZE_LIMIT as usize];
+            assert_eq!(
+                unit_test_bug.multicall(too_many),
+                Err(FlipError::BatchTooLarge)
+            );
+            set_caller(accounts.bob);
+            assert_eq!(
+                unit_test_bug.multicall(Vec::new()),
+                Err(FlipError::NotOwner)
+            );
+        }
+
+        /// A failing operation undoes the ones before it once the call reverts.
+        #[ink::test]
+        fn multicall_reverts_as_a_whole() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let ops = vec![
+                AdminOp::SetTreasury(Some(accounts().django)),
+                AdminOp::SetLabel(String::from("kept?")),
+                AdminOp::SetLabel("x".repeat(MAX_LABEL_LEN + 1)),
+            ];
+
+            // Called directly, the off-chain environment keeps the partial batch.
+            let mut unreverted = UnitTestBug::new(false);
+            assert_eq!(
+                unreverted.multicall(ops.clone()),
+                Err(FlipError::LabelTooLong)
+            );
+            assert_eq!(unreverted.label(), "kept?");
+
+            assert_eq!(
+                call_reverting(&mut unit_test_bug, |contract| contract.multicall(ops)),
+                Err(FlipError::LabelTooLong)
+            );
+            assert_eq!(unit_test_bug.treasury(), None);
+            assert_eq!(unit_test_bug.label(), "");
+        }
+
+        /// A reverted call leaves the value behind its `Lazy` cell as it was.
+        #[ink::test]
+        fn call_reverting_restores_the_value() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            assert_eq!(
+                call_reverting(&mut unit_test_bug, |contract| contract.flip_with_error()),
+                Err(FlipError::FlipError)
+            );
+            assert!(!unit_test_bug.get());
+        }
+
+        /// `Mapping` entries a failed batch wrote are rolled back with it.
+        #[ink::test]
+        fn call_reverting_restores_mapping_entries() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            set_caller(accounts.bob);
+            unit_test_bug.approve(accounts.charlie, true);
+            set_caller(accounts.charlie);
+            advance_blocks(1);
+
+            let reverted = call_reverting(&mut unit_test_bug, |contract| {
+                contract.batch_flip_for(vec![accounts.bob, accounts.eve], BatchMode::AllOrNothing)
+            });
+            assert_eq!(reverted, Err(FlipError::NotApproved));
+            assert_eq!(unit_test_bug.flip_count(accounts.bob), 0);
+            assert_eq!(unit_test_bug.checkpoint_count(), 1);
+
+            // Bob's flip alone writes the entries the batch wrote before failing.
+            unit_test_bug
+                .batch_flip_for(vec![accounts.bob], BatchMode::AllOrNothing)
+                .expect("approved");
+            assert_eq!(unit_test_bug.flip_count(accounts.bob), 1);
+            assert_eq!(unit_test_bug.checkpoint_count(), 2);
+        }
+
+        /// `assert_revert!` checks the error and that the state is unchanged.
+        #[ink::test]
+        fn assert_revert_checks_error_and_state() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let ops = vec![
+                AdminOp::SetTreasury(Some(accounts().django)),
+                AdminOp::SetLabel("x".repeat(MAX_LABEL_LEN + 1)),
+            ];
+            crate::assert_revert!(
+                call_reverting(&mut unit_test_bug, |contract| contract.multicall(ops)),
+                FlipError::LabelTooLong,
+                (unit_test_bug.treasury(), unit_test_bug.label()),
+            );
+        }
+
+        /// `assert_revert!` catches a failed call that left state behind.
+        #[ink::test]
+        #[should_panic(expected = "failed call changed the state")]
+        fn assert_revert_catches_unreverted_state() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            crate::assert_revert!(
+                unit_test_bug.flip_with_error(),
+                FlipError::FlipError,
+                unit_test_bug.get(),
+            );
+        }
+
+        /// A snapshot as the state covers `Mapping` entries a reverted batch wrote.
+        #[ink::test]
+        fn assert_revert_checks_mapping_entries() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            set_caller(accounts.bob);
+            unit_test_bug.approve(accounts.charlie, true);
+            set_caller(accounts.charlie);
+            advance_blocks(1);
+            crate::assert_revert!(
+                call_reverting(&mut unit_test_bug, |contract| {
+                    contract
+                        .batch_flip_for(vec![accounts.bob, accounts.eve], BatchMode::AllOrNothing)
+                }),
+                FlipError::NotApproved,
+                Snapshot::take(&unit_test_bug),
+            );
+        }
+
+        /// A snapshot as the state catches a failed call that left only a
+        /// `Lazy` cell and a `Mapping` entry behind, with the root untouched.
+        #[ink::test]
+        #[should_panic(expected = "failed call changed the state")]
+        fn assert_revert_catches_unreverted_lazy_write() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            crate::assert_revert!(
+                unit_test_bug.flip_with_error(),
+                FlipError::FlipError,
+                Snapshot::take(&unit_test_bug),
+            );
+        }
+
+        /// Arbitrary batches succeed exactly when every operation is valid.
+        #[cfg(feature = "fuzzing")]
+        #[ink::test]
+        fn multicall_accepts_exactly_valid_arbitrary_batches() {
+            let bytes: Vec<u8> = (0..16_384u32)
+                .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+                .collect();
+            let mut input = arbitrary::Unstructured::new(&bytes);
+            while let Ok(ops) = input.arbitrary::<Vec<AdminOp>>() {
+                if input.is_empty() {
+                    break;
+                }
+                let mut unit_test_bug = UnitTestBug::new(false);
+                let valid = ops.len() <= Config::default().max_batch_size as usize
+                    && ops.iter().all(|op| match op {
+                        AdminOp::SetLabel(label) => label.len() <= MAX_LABEL_LEN,
+                        AdminOp::SetFlipPolicy(policy) => policy.validate().is_ok(),
+                        _ => true,
+                    });
+                let result =
+                    call_reverting(&mut unit_test_bug, |contract| contract.multicall(ops.clone()));
+                assert_eq!(result.is_ok(), valid, "{ops:?}");
+            }
+        }
+
+        /// Only the owner may sweep tokens.
+        #[ink::test]
+        fn sweep_token_is_owner_only() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            set_caller(accounts.bob);
+            assert_eq!(
+                unit_test_bug.sweep_token(accounts.charlie, accounts.bob),
+                Err(FlipError::NotOwner)
+            );
+        }
+
+        /// A full history page fits the output buffer and a longer one is refused.
+        #[ink::test]
+        fn history_page_is_bounded_by_the_output_buffer() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            for _ in 0..MAX_HISTORY_PAGE + 5 {
+                advance_blocks(1);
+                unit_test_bug.write_checkpoint();
+            }
+
+            let page = unit_test_bug.history_page(0, MAX_HISTORY_PAGE);
+            let page = page.expect("a full page fits");
+            assert_eq!(page.len(), MAX_HISTORY_PAGE as usize);
+            let response: ink::MessageResult<Result<Vec<Checkpoint>, FlipError>> = Ok(Ok(page));
+            assert!(scale::Encode::encoded_size(&response) <= MAX_RESPONSE_SIZE);
+            assert_eq!(
+                unit_test_bug.history_page(0, u32::MAX),
+                Err(FlipError::ResponseTooLarge)
+            );
+
+            let rest = unit_test_bug.history_page(MAX_HISTORY_PAGE, u32::MAX);
+            assert_eq!(rest.map(|page| page.len()), Ok(6));
+            assert_eq!(unit_test_bug.history_page(u32::MAX, 0), Ok(Vec::new()));
+        }
+
+        /// The limits published in `shared_types::limits` are the ones enforced.
+        #[ink::test]
+        fn published_limits_are_enforced() {
+            use shared_types::limits as published;
+
+            let at_limits = Config {
+                max_batch_size: published::MAX_BATCH_SIZE_LIMIT,
+                cooldown_blocks: published::MAX_COOLDOWN_BLOCKS,
+                timelock_delay: published::MAX_TIMELOCK_DELAY,
+                max_blob_size: published::MAX_BLOB_SIZE_LIMIT,
+                ..Config::default()
+            };
+            assert_eq!(at_limits.validate(), Ok(()));
+            let over_limits: [fn(&mut Config); 4] = [
+                |config| config.max_batch_size += 1,
+                |config| config.cooldown_blocks += 1,
+                |config| config.timelock_delay += 1,
+                |config| config.max_blob_size += 1,
+            ];
+            for exceed in over_limits {
+                let mut config = at_limits;
+                exceed(&mut config);
+                assert_eq!(config.validate(), Err(FlipError::InvalidConfig));
+            }
+
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let label = "x".repeat(published::MAX_LABEL_LEN);
+            assert_eq!(unit_test_bug.set_label(label.clone()), Ok(()));
+            assert_eq!(
+                unit_test_bug.set_label(label + "x"),
+                Err(FlipError::LabelTooLong)
+            );
+
+            for _ in 0..published::MAX_QUEUED_FLIPS {
+                pay_as(accounts().bob, KEEPER_REWARD);
+                unit_test_bug.queue_flip(20).unwrap();
+            }
+            pay_as(accounts().bob, KEEPER_REWARD);
+            assert_eq!(unit_test_bug.queue_flip(20), Err(FlipError::QueueFull));
+
+            for _ in 0..published::MAX_HISTORY_PAGE {
+                advance_blocks(1);
+                unit_test_bug.write_checkpoint();
+            }
+            let page = unit_test_bug
+                .history_page(0, published::MAX_HISTORY_PAGE)
+                .map(|page| page.len());
+            assert_eq!(page, Ok(published::MAX_HISTORY_PAGE as usize));
+        }
+
+        /// Pages start at the first checkpoint pruning kept.
+        #[ink::test]
+        fn history_page_skips_pruned_checkpoints() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            for _ in 0..3 {
+                advance_blocks(1);
+                unit_test_bug.flip().expect("flip succeeds");
+            }
+            assert_eq!(unit_test_bug.prune_history(3), Ok(3));
+
+            let page = unit_test_bug.history_page(0, 10).expect("page fits");
+            assert_eq!(
+                page,
+                vec![Checkpoint {
+                    block: 3,
+                    value: true
+                }]
+            );
+        }
+
+        /// Pruning keeps every answer from `before_block` on and nothing older.
+        #[ink::test]
+        fn prune_history_keeps_recent_answers() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            for _ in 0..5 {
+                advance_blocks(2);
+                unit_test_bug.flip().expect("flip succeeds");
+            }
+            let count = unit_test_bug.checkpoint_count();
+            let answers: Vec<_> = (0..12).map(|block| unit_test_bug.value_at(block)).collect();
+
+            set_config(&mut unit_test_bug, |config| config.max_batch_size = 2);
+            assert_eq!(unit_test_bug.prune_history(7), Ok(2));
+            assert_eq!(unit_test_bug.prune_history(7), Ok(1));
+            assert_eq!(unit_test_bug.prune_history(7), Ok(0));
+            let first = unit_test_bug.first_checkpoint();
+            assert_eq!(first, 3);
+            assert_eq!(unit_test_bug.checkpoints.get(first - 1), None);
+            assert_eq!(unit_test_bug.checkpoint_count(), count);
+            for block in 0..12 {
+                let expected = if block >= 6 {
+                    answers[block as usize]
+                } else {
+                    None
+                };
+                assert_eq!(unit_test_bug.value_at(block), expected);
+            }
+            assert!(unit_test_bug.verify_invariants().is_ok());
+            let exported = unit_test_bug.export_storage(ExportCursor::default(), 1);
+            assert!(matches!(
+                exported.items[..],
+                [ExportItem::Checkpoint { index: 3, .. }]
+            ));
+
+            set_caller(accounts().bob);
+            assert_eq!(unit_test_bug.prune_history(100), Err(FlipError::NotOwner));
+        }
+
+        /// Property: the kept history never outgrows `max_history`, and every
+        /// checkpoint not pruned was announced by an `Evicted` event.
+        #[test]
+        fn history_never_exceeds_max_history() {
+            for seed in 1..=20 {
+                // Each run needs fresh storage, the checkpoints outlive the contract value.
+                ink::env::test::run_test::<ink::env::DefaultEnvironment, _>(|_| {
+                    let mut rng = Rng(seed);
+                    let mut unit_test_bug = UnitTestBug::new(false);
+                    set_config(&mut unit_test_bug, |config| {
+                        config.timelock_delay = 0;
+                        config.max_history = rng.below(5) as u32 + 1;
+                    });
+                    let mut pruned = 0;
+
+                    for _ in 0..60 {
+                        match rng.below(4) {
+                            0 | 1 => {
+                                advance_blocks(rng.below(3) as u32);
+                                unit_test_bug.flip().expect("flip failed");
+                            }
+                            2 => {
+                                let now = ink::env::block_number::<ink::env::DefaultEnvironment>();
+                                pruned += unit_test_bug.prune_history(now).expect("owner prunes");
+                            }
+                            _ => {
+                                let config = Config {
+                                    max_history: rng.below(6) as u32,
+                                    ..unit_test_bug.config()
+                                };
+                                unit_test_bug.propose_config(config).expect("valid config");
+                                unit_test_bug.apply_config().expect("no timelock");
+                            }
+                        }
+                        let kept =
+                            unit_test_bug.checkpoint_count() - unit_test_bug.first_checkpoint();
+                        let max_history = unit_test_bug.config().max_history;
+                        assert!(max_history == 0 || kept <= max_history);
+                        assert_eq!(
+                            unit_test_bug.value_at(BlockNumber::MAX),
+                            Some(unit_test_bug.get())
+                        );
+                        assert!(unit_test_bug.verify_invariants().is_ok());
+                    }
+                    let evicted = evicted_blocks().len() as u32;
+                    assert_eq!(evicted + pruned, unit_test_bug.first_checkpoint());
+                    Ok(())
+                })
+                .expect("scenario runs");
+            }
+        }
+
+        /// Eviction drops the oldest checkpoint and reports it.
+        #[ink::test]
+        fn max_history_evicts_oldest_first() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            set_config(&mut unit_test_bug, |config| config.max_history = 2);
+            for _ in 0..3 {
+                advance_blocks(1);
+                unit_test_bug.flip().expect("flip failed");
+            }
+            assert_eq!(evicted_blocks(), [0, 1]);
+            assert_eq!(unit_test_bug.first_checkpoint(), 2);
+            assert_eq!(unit_test_bug.value_at(1), None);
+            assert_eq!(unit_test_bug.value_at(2), Some(false));
+            assert_eq!(unit_test_bug.value_at(3), Some(true));
+        }
+
+        fn evicted_blocks() -> Vec<BlockNumber> {
+            recorded_events()
+                .into_iter()
+                .filter_map(|event| match event {
+                    Event::Evicted(evicted) => Some(evicted.block),
+                    _ => None,
+                })
+                .collect()
+        }
+
+        /// Deposit estimates count one item per storage cell and every encoded byte.
+        #[ink::test]
+        fn deposit_estimates_count_items_and_bytes() {
+            let unit_test_bug = UnitTestBug::new(false);
+            assert_eq!(
+                unit_test_bug.deposit_for_history_record(),
+                STORAGE_DEPOSIT_PER_ITEM + 5 * STORAGE_DEPOSIT_PER_BYTE
+            );
+            assert_eq!(
+                unit_test_bug.deposit_for_blob(0),
+                STORAGE_DEPOSIT_PER_ITEM + 4 * STORAGE_DEPOSIT_PER_BYTE
+            );
+            let length = BLOB_CHUNK_SIZE + 10;
+            assert_eq!(
+                unit_test_bug.deposit_for_blob(length),
+                3 * STORAGE_DEPOSIT_PER_ITEM
+                    + (4 + length as Balance + 2 + 1) * STORAGE_DEPOSIT_PER_BYTE
+            );
+        }
+
+        /// A flash flip is refused while paused and cannot lend to the contract itself.
+        #[ink::test]
+        fn flash_flip_checks_before_lending() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let contract = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            assert_eq!(
+                unit_test_bug.flash_flip(contract, Vec::new()),
+                Err(FlipError::InvalidBorrower)
+            );
+            set_config(&mut unit_test_bug, |config| config.paused = true);
+            assert_eq!(
+                unit_test_bug.flash_flip(accounts().bob, Vec::new()),
+                Err(FlipError::Paused)
+            );
+            assert!(!unit_test_bug.get());
+        }
+
+        /// Each experimental message fails with `FeatureDisabled` unless its
+        /// bit is set, and the bits only change through the timelock.
+        #[ink::test]
+        fn gated_messages_follow_feature_flags() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let contract = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            let accounts = accounts();
+            flip_as_all(&mut unit_test_bug, &[accounts.bob]);
+            advance_blocks(DEFAULT_EPOCH_LENGTH);
+            mock_randomness(0);
+            set_caller(accounts.alice);
+            let set_features = |unit_test_bug: &mut UnitTestBug, features| {
+                let config = Config {
+                    features,
+                    ..unit_test_bug.config()
+                };
+                unit_test_bug.propose_config(config).expect("valid config");
+                assert_eq!(unit_test_bug.apply_config(), Err(FlipError::TimelockActive));
+                advance_blocks(config.timelock_delay);
+                unit_test_bug.apply_config().expect("timelock passed");
+            };
+
+            // Gated messages fail before any of their own checks.
+            set_features(&mut unit_test_bug, 0);
+            assert_eq!(unit_test_bug.draw(0), Err(FlipError::FeatureDisabled));
+            assert_eq!(
+                unit_test_bug.flash_flip(contract, Vec::new()),
+                Err(FlipError::FeatureDisabled)
+            );
+
+            set_features(&mut unit_test_bug, FEATURE_LOTTERY);
+            assert_eq!(unit_test_bug.draw(0), Ok((accounts.bob, 50)));
+            assert_eq!(
+                unit_test_bug.flash_flip(contract, Vec::new()),
+                Err(FlipError::FeatureDisabled)
+            );
+
+            set_features(&mut unit_test_bug, FEATURE_FLASH_FLIP);
+            assert_eq!(unit_test_bug.draw(0), Err(FlipError::FeatureDisabled));
+            assert_eq!(
+                unit_test_bug.flash_flip(contract, Vec::new()),
+                Err(FlipError::InvalidBorrower)
+            );
+
+            set_features(&mut unit_test_bug, ALL_FEATURES);
+            assert_eq!(unit_test_bug.draw(0), Err(FlipError::AlreadyDrawn));
+            assert_eq!(
+                unit_test_bug.flash_flip(contract, Vec::new()),
+                Err(FlipError::InvalidBorrower)
+            );
+
+            let unknown = Config {
+                features: ALL_FEATURES + 1,
+                ..unit_test_bug.config()
+            };
+            assert_eq!(
+                unit_test_bug.propose_config(unknown),
+                Err(FlipError::InvalidConfig)
+            );
+        }
+
+        /// A first flip records its referrer and counts the referral.
+        #[ink::test]
+        fn first_flip_records_referrer() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            set_caller(accounts.bob);
+            unit_test_bug
+                .flip_with_referrer(Some(accounts.alice))
+                .expect("referred flip succeeds");
+            set_caller(accounts.charlie);
+            unit_test_bug
+                .flip_with_referrer(Some(accounts.alice))
+                .expect("referred flip succeeds");
+            set_caller(accounts.django);
+            unit_test_bug
+                .flip_with_referrer(None)
+                .expect("unreferred flip succeeds");
+
+            assert_eq!(unit_test_bug.referrals_of(accounts.alice), 2);
+            assert_eq!(unit_test_bug.referrals_of(accounts.bob), 0);
+            assert_eq!(
+                unit_test_bug.referrer_of(accounts.bob),
+                Some(accounts.alice)
+            );
+            assert_eq!(unit_test_bug.referrer_of(accounts.django), None);
+            assert!(unit_test_bug.get());
+        }
+
+        /// Accounts cannot refer themselves or change their referrer later.
+        #[ink::test]
+        fn self_and_repeat_referrals_are_rejected() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            set_caller(accounts.bob);
+            assert_eq!(
+                unit_test_bug.flip_with_referrer(Some(accounts.bob)),
+                Err(FlipError::SelfReferral)
+            );
+            unit_test_bug
+                .flip_with_referrer(Some(accounts.alice))
+                .expect("referred flip succeeds");
+            assert_eq!(
+                unit_test_bug.flip_with_referrer(Some(accounts.charlie)),
+                Err(FlipError::AlreadyReferred)
+            );
+
+            set_caller(accounts.charlie);
+            unit_test_bug.flip().expect("flip succeeds");
+            assert_eq!(
+                unit_test_bug.flip_with_referrer(Some(accounts.alice)),
+                Err(FlipError::AlreadyReferred)
+            );
+            unit_test_bug
+                .flip_with_referrer(None)
+                .expect("later flips need no referrer");
+            assert_eq!(unit_test_bug.referrals_of(accounts.alice), 1);
+            assert_eq!(unit_test_bug.referrer_of(accounts.charlie), None);
+        }
+
+        /// Each tier applies from its flip count on, and up to the next one.
+        #[ink::test]
+        fn fee_discount_tiers_apply_from_their_boundary() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let bob = accounts().bob;
+            set_config(&mut unit_test_bug, |config| config.flip_fee = 1_000);
+            let discounts = FeeDiscounts {
+                tiers: vec![
+                    FeeTier {
+                        min_flips: 2,
+                        discount_bps: 1_000,
+                    },
+                    FeeTier {
+                        min_flips: 4,
+                        discount_bps: 5_000,
+                    },
+                ],
+            };
+            unit_test_bug
+                .set_fee_discounts(discounts)
+                .expect("owner sets discounts");
+
+            set_balance(bob, 10_000);
+            let expected = [1_000, 1_000, 900, 900, 500, 500];
+            for (flips, fee) in expected.into_iter().enumerate() {
+                assert_eq!(unit_test_bug.flip_count(bob), flips as u64);
+                assert_eq!(unit_test_bug.quote_flip_fee_for(bob), Ok(fee));
+                set_caller(bob);
+                set_value_transferred(fee - 1);
+                assert_eq!(unit_test_bug.flip(), Err(FlipError::InsufficientFee));
+                pay_as(bob, fee);
+                unit_test_bug.flip().expect("discounted flip succeeds");
+            }
+            assert_eq!(unit_test_bug.quote_flip_fee(), Ok(1_000));
+        }
+
+        /// Discount tiers apply to an asset fee from the same boundaries.
+        #[ink::test]
+        fn fee_discount_tiers_apply_to_asset_fees() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let bob = accounts().bob;
+            let ledger = mock_assets();
+            unit_test_bug
+                .set_asset_fee(Some(AssetFee {
+                    asset_id: 7,
+                    amount: 1_000,
+                }))
+                .expect("owner sets asset fee");
+            let discounts = FeeDiscounts {
+                tiers: vec![
+                    FeeTier {
+                        min_flips: 2,
+                        discount_bps: 1_000,
+                    },
+                    FeeTier {
+                        min_flips: 4,
+                        discount_bps: 5_000,
+                    },
+                ],
+            };
+            unit_test_bug
+                .set_fee_discounts(discounts)
+                .expect("owner sets discounts");
+
+            set_caller(bob);
+            let expected = [1_000, 1_000, 900, 900, 500, 500];
+            for (flips, fee) in expected.into_iter().enumerate() {
+                assert_eq!(unit_test_bug.flip_count(bob), flips as u64);
+                ledger.borrow_mut().balances.insert((7, bob), fee - 1);
+                ledger.borrow_mut().approvals.insert((7, bob), fee);
+                assert_eq!(
+                    unit_test_bug.flip_with_asset(),
+                    Err(FlipError::InsufficientFee)
+                );
+                ledger.borrow_mut().balances.insert((7, bob), fee);
+                let pot = unit_test_bug.asset_fee_pot(7);
+                unit_test_bug
+                    .flip_with_asset()
+                    .expect("discounted flip succeeds");
+                assert_eq!(unit_test_bug.asset_fee_pot(7), pot + fee);
+                assert_eq!(ledger.borrow().balance(7, bob), 0);
+            }
+        }
+
+        /// Tiers must be ordered, few, and never lower the discount.
+        #[ink::test]
+        fn fee_discounts_are_validated() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let tier = |min_flips, discount_bps| FeeTier {
+                min_flips,
+                discount_bps,
+            };
+            for tiers in [
+                vec![tier(5, 100), tier(5, 200)],
+                vec![tier(5, 200), tier(9, 100)],
+                vec![tier(5, BPS_DENOMINATOR + 1)],
+                (0..=MAX_FEE_TIERS as u64).map(|i| tier(i, 0)).collect(),
+            ] {
+                assert_eq!(
+                    unit_test_bug.set_fee_discounts(FeeDiscounts { tiers }),
+                    Err(FlipError::InvalidFeeDiscounts)
+                );
+            }
+            let full = FeeDiscounts {
+                tiers: vec![tier(0, 0), tier(1, BPS_DENOMINATOR)],
+            };
+            assert_eq!(unit_test_bug.set_fee_discounts(full.clone()), Ok(()));
+            assert_eq!(unit_test_bug.fee_discounts(), full);
+            set_caller(accounts().bob);
+            assert_eq!(
+                unit_test_bug.set_fee_discounts(FeeDiscounts::default()),
+                Err(FlipError::NotOwner)
+            );
+        }
+
+        /// Property: under any valid tiers, flipping more never raises the fee.
+        #[ink::test]
+        fn fees_never_increase_with_activity() {
+            for seed in 1..=50 {
+                let mut rng = Rng(seed);
+                let mut tiers = Vec::new();
+                let (mut min_flips, mut discount_bps) = (0, 0);
+                for _ in 0..rng.below(MAX_FEE_TIERS as u64 + 1) {
+                    min_flips += rng.below(20) + u64::from(!tiers.is_empty());
+                    discount_bps = (discount_bps
+                        + rng.below(u64::from(BPS_DENOMINATOR) / 4) as u16)
+                        .min(BPS_DENOMINATOR);
+                    tiers.push(FeeTier {
+                        min_flips,
+                        discount_bps,
+                    });
+                }
+                let discounts = FeeDiscounts { tiers };
+                assert_eq!(discounts.validate(), Ok(()));
+
+                let fee = Balance::from(rng.next()) * Balance::from(rng.next());
+                let mut previous = fee;
+                for flips in 0..200 {
+                    let discounted = discounts.apply(fee, flips);
+                    assert!(discounted <= previous);
+                    previous = discounted;
+                }
+            }
+        }
+
+        fn epoch_rollovers() -> Vec<(u32, u32)> {
+            recorded_events()
+                .into_iter()
+                .filter_map(|event| match event {
+                    Event::EpochRolledOver(rolled) => Some((rolled.from, rolled.to)),
+                    _ => None,
+                })
+                .collect()
+        }
+
+        /// The last block of an epoch still counts towards it, the next one starts afresh.
+        #[ink::test]
+        fn epoch_rolls_over_at_exact_boundary() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            flip_times(&mut unit_test_bug, accounts.bob, 1);
+            advance_blocks(DEFAULT_EPOCH_LENGTH - 1);
+            flip_times(&mut unit_test_bug, accounts.charlie, 2);
+            assert_eq!(
+                unit_test_bug.epoch_stats(),
+                EpochStats {
+                    epoch: 0,
+                    flips: 3,
+                    flippers: 2
+                }
+            );
+            assert_eq!(
+                ranking(&unit_test_bug),
+                vec![(accounts.charlie, 2), (accounts.bob, 1)]
+            );
+            assert!(epoch_rollovers().is_empty());
+
+            advance_blocks(1);
+            assert_eq!(unit_test_bug.leaderboard(), Vec::new());
+            assert_eq!(
+                unit_test_bug.epoch_stats(),
+                EpochStats {
+                    epoch: 1,
+                    ..EpochStats::default()
+                }
+            );
+            flip_times(&mut unit_test_bug, accounts.bob, 1);
+            assert_eq!(epoch_rollovers(), vec![(0, 1)]);
+            assert_eq!(ranking(&unit_test_bug), vec![(accounts.bob, 1)]);
+            assert_eq!(unit_test_bug.epoch_stats().flips, 1);
+            assert_eq!((unit_test_bug.tickets(0), unit_test_bug.tickets(1)), (3, 1));
+            assert_eq!(unit_test_bug.flip_count(accounts.bob), 2);
+            assert_eq!(unit_test_bug.epoch_flip_count(0, accounts.charlie), 2);
+            assert_eq!(unit_test_bug.epoch_flip_count(1, accounts.charlie), 0);
+            assert!(unit_test_bug.verify_invariants().is_ok());
+        }
+
+        /// Skipped epochs roll over in one step on the next flip.
+        #[ink::test]
+        fn epoch_rollover_skips_idle_epochs() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            flip_times(&mut unit_test_bug, accounts.bob, 2);
+            advance_blocks(3 * DEFAULT_EPOCH_LENGTH + DEFAULT_EPOCH_LENGTH / 2);
+            assert_eq!(unit_test_bug.epoch_stats().epoch, 3);
+            // Stale statistics are never served before the rollover is written.
+            assert_eq!(unit_test_bug.leaderboard(), Vec::new());
+            assert!(unit_test_bug.verify_invariants().is_ok());
+
+            flip_times(&mut unit_test_bug, accounts.charlie, 1);
+            assert_eq!(epoch_rollovers(), vec![(0, 3)]);
+            assert_eq!(ranking(&unit_test_bug), vec![(accounts.charlie, 1)]);
+            assert_eq!(
+                unit_test_bug.epoch_stats(),
+                EpochStats {
+                    epoch: 3,
+                    flips: 1,
+                    flippers: 1
+                }
+            );
+            assert_eq!(unit_test_bug.tickets(3), 1);
+            assert_eq!(unit_test_bug.tickets(0), 2);
+        }
+
+        /// The storage handed to a proxy is set up once by `initialize`.
+        #[ink::test]
+        fn proxy_storage_is_initialized_once() {
+            let bob = accounts().bob;
+            let storage = UnitTestBug::proxy_storage(bob);
+            let mut unit_test_bug: UnitTestBug =
+                ink::storage::traits::Storable::decode(&mut &storage[..]).expect("root decodes");
+            assert_eq!(unit_test_bug.owner(), bob);
+            assert_eq!(unit_test_bug.checkpoint_count(), 0);
+            assert_eq!(unit_test_bug.config(), Config::default());
+
+            // Whoever sees the proxy deployed first cannot take it over.
+            set_caller(accounts().eve);
+            assert_eq!(unit_test_bug.initialize(false), Err(FlipError::NotOwner));
+            assert_eq!(unit_test_bug.checkpoint_count(), 0);
+
+            set_caller(bob);
+            assert_eq!(unit_test_bug.initialize(true), Ok(()));
+            assert_eq!(unit_test_bug.owner(), bob);
+            assert!(unit_test_bug.get());
+            assert_eq!(unit_test_bug.value_at(0), Some(true));
+            assert!(unit_test_bug.verify_invariants().is_ok());
+
+            set_caller(accounts().charlie);
+            assert_eq!(
+                unit_test_bug.initialize(false),
+                Err(FlipError::AlreadyInitialized)
+            );
+            assert_eq!(unit_test_bug.owner(), bob);
+            assert!(unit_test_bug.get());
+        }
+
+        /// `IFlipQuery` answers like the inherent messages.
+        #[ink::test]
+        fn query_trait_matches_messages() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            flip_times(&mut unit_test_bug, accounts().bob, 3);
+            assert!(IFlipQuery::get(&unit_test_bug));
+            assert_eq!(
+                IFlipQuery::stats(&unit_test_bug),
+                unit_test_bug.epoch_stats()
+            );
+        }
+
+        /// Wraps `amount` for `account` in the current block.
+        fn wrap_as(unit_test_bug: &mut UnitTestBug, account: AccountId, amount: Balance) {
+            set_balance(account, balance_of(account) + amount);
+            pay_as(account, amount);
+            unit_test_bug.wrap();
+        }
+
+        /// Wrapped balances are answered as they stood at the end of each block.
+        #[ink::test]
+        fn wrapped_balance_history_tracks_block_ends() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let bob = accounts().bob;
+            advance_blocks(2);
+            wrap_as(&mut unit_test_bug, bob, 10);
+            wrap_as(&mut unit_test_bug, bob, 5);
+            advance_blocks(3);
+            set_caller(bob);
+            set_value_transferred(0);
+            unit_test_bug.unwrap(12).expect("unwrap succeeds");
+
+            let history: Vec<_> = (0..7)
+                .map(|block| unit_test_bug.wrapped_balance_at(bob, block))
+                .collect();
+            assert_eq!(history, [0, 0, 15, 15, 15, 3, 3]);
+            assert_eq!(unit_test_bug.wrapped_balance_at(accounts().charlie, 5), 0);
+        }
+
+        /// A proposal passes by snapshot-weighted votes and sets the value once.
+        #[ink::test]
+        fn proposal_lifecycle_sets_value() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            wrap_as(&mut unit_test_bug, accounts.bob, 30);
+            wrap_as(&mut unit_test_bug, accounts.charlie, 20);
+            set_caller(accounts.bob);
+            let id = unit_test_bug.propose_value(true).expect("staker proposes");
+            let proposal = unit_test_bug.proposal(id).expect("proposal is stored");
+            let period = Config::default().voting_period;
+            assert_eq!(proposal.voting_ends, proposal.snapshot + period);
+            assert_eq!(unit_test_bug.vote(id, true), Err(FlipError::VotingNotOpen));
+
+            // Stake wrapped after the snapshot carries no weight.
+            advance_blocks(1);
+            wrap_as(&mut unit_test_bug, accounts.django, 100);
+            assert_eq!(unit_test_bug.vote(id, false), Err(FlipError::NoStake));
+            set_caller(accounts.bob);
+            assert_eq!(unit_test_bug.vote(id, true), Ok(30));
+            assert_eq!(unit_test_bug.vote(id, false), Err(FlipError::AlreadyVoted));
+            // Unwrapping after the snapshot keeps the snapshot weight.
+            set_caller(accounts.charlie);
+            unit_test_bug.unwrap(20).expect("unwrap succeeds");
+            assert_eq!(unit_test_bug.vote(id, false), Ok(20));
+            assert_eq!(unit_test_bug.vote_of(id, accounts.charlie), Some(false));
+
+            assert_eq!(unit_test_bug.execute(id), Err(FlipError::VotingNotOver));
+            advance_blocks(period - 1);
+            set_caller(accounts.eve);
+            assert_eq!(unit_test_bug.execute(id), Err(FlipError::VotingNotOver));
+            advance_blocks(1);
+            assert_eq!(unit_test_bug.vote(id, true), Err(FlipError::VotingNotOpen));
+            assert_eq!(unit_test_bug.execute(id), Ok(()));
+            assert!(unit_test_bug.get());
+            assert_eq!(unit_test_bug.value_at(proposal.voting_ends + 1), Some(true));
+            assert_eq!(unit_test_bug.execute(id), Err(FlipError::ProposalExecuted));
+            assert_eq!(
+                unit_test_bug
+                    .proposal(id)
+                    .map(|proposal| (proposal.ayes, proposal.nays)),
+                Some((30, 20))
+            );
+        }
+
+        /// Proposals without a majority, proposers and voters without stake, and unknown ids are refused.
+        #[ink::test]
+        fn proposals_need_stake_and_majority() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            set_caller(accounts.bob);
+            assert_eq!(unit_test_bug.propose_value(true), Err(FlipError::NoStake));
+            assert_eq!(
+                unit_test_bug.vote(0, true),
+                Err(FlipError::ProposalNotFound)
+            );
+            assert_eq!(unit_test_bug.execute(0), Err(FlipError::ProposalNotFound));
+
+            wrap_as(&mut unit_test_bug, accounts.bob, 10);
+            wrap_as(&mut unit_test_bug, accounts.charlie, 10);
+            let id = unit_test_bug.propose_value(true).expect("staker proposes");
+            assert_eq!(unit_test_bug.proposal_count(), 1);
+            advance_blocks(1);
+            set_caller(accounts.bob);
+            unit_test_bug.vote(id, true).expect("bob votes");
+            set_caller(accounts.charlie);
+            unit_test_bug.vote(id, false).expect("charlie votes");
+            advance_blocks(Config::default().voting_period);
+            assert_eq!(unit_test_bug.execute(id), Err(FlipError::ProposalRejected));
+            assert!(!unit_test_bug.get());
+        }
+
+        extern "Rust" {
+            // Generated by `#[ink::contract]` for `std` builds; ink! reserves the name.
+            #[link_name = "__ink_generate_metadata"]
+            fn generate_metadata() -> ink::metadata::InkProject;
+        }
+
+        /// Renders the interface and storage keys in the metadata as the golden table.
+        fn abi_table() -> String {
+            // SAFETY: the generated function has exactly this signature.
+            let project = unsafe { generate_metadata() };
+            let spec = project.spec();
+            let mut table = String::new();
+            for constructor in spec.constructors() {
+                let selector = hex::encode(constructor.selector().to_bytes());
+                table += &format!("constructor {} 0x{selector}\n", constructor.label());
+            }
+            for message in spec.messages() {
+                let selector = hex::encode(message.selector().to_bytes());
+                table += &format!("message {} 0x{selector}\n", message.label());
+            }
+            for event in spec.events() {
+                let topic = hex::encode(shared_types::events::signature_topic(event.label()));
+                let topics: Vec<_> = event
+                    .args()
+                    .iter()
+                    .filter(|arg| arg.indexed())
+                    .map(|arg| arg.label().as_str())
+                    .collect();
+                table += &format!("event {} 0x{topic} [{}]\n", event.label(), topics.join(" "));
+            }
+            let registry = project.registry();
+            let flip_error = registry
+                .types
+                .iter()
+                .find(|ty| ty.ty.path.segments.last().is_some_and(|name| name == "FlipError"))
+                .expect("messages return `FlipError`");
+            let scale_info::TypeDef::Variant(variants) = &flip_error.ty.type_def else {
+                panic!("`FlipError` is an enum");
+            };
+            for variant in &variants.variants {
+                let code = shared_types::errors::error_code(&[variant.index])
+                    .expect("every variant has a status code");
+                table += &format!("error {} {code}\n", variant.name);
+            }
+            storage_keys(project.layout(), "", &mut table);
+            table
+        }
+
+        /// Adds the key of every storage cell under `layout` to the golden table.
+        fn storage_keys(
+            layout: &ink::metadata::layout::Layout<scale_info::form::PortableForm>,
+            path: &str,
+            table: &mut String,
+        ) {
+            use ink::metadata::layout::Layout;
+
+            let nested = |name: &str| match path {
+                "" => name.to_string(),
+                _ => format!("{path}.{name}"),
+            };
+            match layout {
+                Layout::Root(root) => {
+                    let name = if path.is_empty() { "root" } else { path };
+                    table.push_str(&format!("storage {name} 0x{:08x}\n", root.root_key().key()));
+                    storage_keys(root.layout(), path, table);
+                }
+                Layout::Struct(layout) => {
+                    for field in layout.fields() {
+                        storage_keys(field.layout(), &nested(field.name()), table);
+                    }
+                }
+                Layout::Enum(layout) => {
+                    for variant in layout.variants().values() {
+                        for field in variant.fields() {
+                            let name = format!("{}.{}", variant.name(), field.name());
+                            storage_keys(field.layout(), &nested(&name), table);
+                        }
+                    }
+                }
+                Layout::Hash(layout) => storage_keys(layout.layout(), path, table),
+                Layout::Array(layout) => storage_keys(layout.layout(), path, table),
+                Layout::Leaf(_) => {}
+            }
+        }
+
+        /// Returns the key the golden table records for the storage cell `field`.
+        fn golden_storage_key(field: &str) -> u32 {
+            let golden = include_str!("golden/abi.txt");
+            let prefix = format!("storage {field} 0x");
+            let key = golden
+                .lines()
+                .find_map(|line| line.strip_prefix(&prefix))
+                .unwrap_or_else(|| panic!("golden table records no `{field}` key"));
+            u32::from_str_radix(key, 16).expect("keys are hex")
+        }
+
+        /// Status codes follow the variant indexes, and no variant lacks one.
+        #[ink::test]
+        fn error_codes_follow_variant_indexes() {
+            use scale::{Decode, Encode};
+            use shared_types::errors::{error_code, error_name, ERROR_NAMES};
+
+            for (index, name) in ERROR_NAMES.iter().enumerate() {
+                // Followed by `None` for the errors with an upstream source.
+                let error = FlipError::decode(&mut &[index as u8, 0][..]).expect("variant exists");
+                let debug = format!("{error:?}");
+                assert!(debug == *name || debug == format!("{name}(None)"), "{debug} is not {name}");
+                assert_eq!(Some(error.code()), error_code(&error.encode()));
+                assert_eq!(error_name(error.code()), Some(*name));
+            }
+            assert!(FlipError::decode(&mut &[ERROR_NAMES.len() as u8][..]).is_err());
+            assert_eq!(error_code(&[ERROR_NAMES.len() as u8]), None);
+            assert_eq!(error_name(0), None);
+            // Pinned, as clients hard-code them.
+            assert_eq!(FlipError::FlipError.code(), 1);
+            assert_eq!(FlipError::Paused.code(), 29);
+            assert_eq!(FlipError::DeadlineInPast.code(), 74);
+            assert_eq!(FlipError::AlreadyInitialized.code(), 75);
+            assert_eq!(FlipError::UntrustedCallee.code(), 76);
+            assert_eq!(FlipError::QueueFull.code(), 77);
+            assert_eq!(FlipError::FeatureDisabled.code(), 78);
+        }
+
+        /// A client without the contract types reads the status code and the
+        /// upstream source from the raw return bytes.
+        #[ink::test]
+        fn upstream_errors_decode_without_contract_types() {
+            use scale::{Decode, Encode};
+            use shared_types::errors::{error_code, error_name};
+
+            let source = Some(UpstreamError::CallFailed);
+            let returned: ink::MessageResult<FlipResult<()>> =
+                Ok(Err(FlipError::FlashCallFailed(source)));
+            let bytes = returned.encode();
+            // Dispatch `Ok`, message `Err`, the variant, `Some`, `CallFailed`.
+            assert_eq!(bytes.len(), 5);
+            assert_eq!(bytes[..2], [0, 1]);
+            let code = error_code(&bytes[2..]).expect("a `FlipError` follows");
+            assert_eq!(error_name(code), Some("FlashCallFailed"));
+            let decoded = Option::<UpstreamError>::decode(&mut &bytes[3..]).expect("source decodes");
+            assert_eq!(decoded, source);
+            assert_eq!(UpstreamError::Returned(3).encode(), [2, 3]);
+            // Errors without a source stay a single byte.
+            assert_eq!(FlipError::NotOwner.encode(), [1]);
+            assert_eq!(FlipError::OracleUnavailable(None).encode().len(), 2);
+        }
+
+        /// Selectors, event topics, error codes and storage keys match the checked-in table.
+        ///
+        /// Wallets and indexers rely on these, and upgrades on the storage keys.
+        /// Regenerate the table with `UPDATE_GOLDEN=1 cargo test
+        /// abi_matches_golden_table` only for a deliberate interface change.
+        #[ink::test]
+        fn abi_matches_golden_table() {
+            const GOLDEN: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/golden/abi.txt");
+            let header = "# Selectors, event signature topics, error codes and storage keys of the contract.\n\
+                          # Checked by `abi_matches_golden_table`, do not edit by hand.\n";
+            let generated = abi_table();
+            if std::env::var_os("UPDATE_GOLDEN").is_some() {
+                std::fs::write(GOLDEN, format!("{header}{generated}")).expect("table is writable");
+            }
+            let golden = std::fs::read_to_string(GOLDEN).expect("golden table is checked in");
+            let golden: Vec<_> = golden
+                .lines()
+                .filter(|line| !line.starts_with('#'))
+                .collect();
+            let generated: Vec<_> = generated.lines().collect();
+            let removed: Vec<_> = golden
+                .iter()
+                .filter(|line| !generated.contains(line))
+                .collect();
+            let added: Vec<_> = generated
+                .iter()
+                .filter(|line| !golden.contains(line))
+                .collect();
+            assert!(
+                removed.is_empty() && added.is_empty(),
+                "interface changed, update golden/abi.txt deliberately\nremoved: {removed:#?}\nadded: {added:#?}"
+            );
+
+            // The derived topics are the ones actually emitted.
+            let mut unit_test_bug = UnitTestBug::new(false);
+            unit_test_bug.flip().expect("flip succeeds");
+            let emitted: Vec<_> = ink::env::test::recorded_events()
+                .map(|event| event.topics[0].clone())
+                .collect();
+            assert!(emitted.contains(&shared_types::events::signature_topic("FlippedV2").to_vec()));
+            assert!(emitted.contains(&shared_types::events::signature_topic("Flipped").to_vec()));
+        }
+
+        /// Each per-account event's only topic besides its signature is its
+        /// `account`, so `account_topics` selects exactly the events about that
+        /// account.
+        #[ink::test]
+        fn account_topics_select_the_accounts_events() {
+            use shared_types::events::{
+                account_topic, account_topics, signature_topic, ACCOUNT_TOPIC_EVENTS,
+            };
+
+            // SAFETY: the generated function has exactly this signature.
+            let project = unsafe { generate_metadata() };
+            let topic_fields: Vec<(&str, &str)> = project
+                .spec()
+                .events()
+                .iter()
+                .flat_map(|event| {
+                    event
+                        .args()
+                        .iter()
+                        .filter(|arg| arg.indexed())
+                        .map(move |arg| (event.label().as_str(), arg.label().as_str()))
+                })
+                .collect();
+            let expected: Vec<(&str, &str)> = ACCOUNT_TOPIC_EVENTS
+                .iter()
+                .map(|event| (*event, "account"))
+                .collect();
+            assert_eq!(topic_fields, expected);
+
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            unit_test_bug.flip().expect("flip succeeds");
+            set_caller(accounts.bob);
+            unit_test_bug.approve(accounts.charlie, true);
+            set_caller(accounts.charlie);
+            unit_test_bug.approve(accounts.bob, true);
+            unit_test_bug.flip_for(accounts.bob).expect("approved");
+            set_caller(accounts.bob);
+            unit_test_bug
+                .batch_flip_for(vec![accounts.charlie], BatchMode::BestEffort)
+                .expect("approved");
+
+            let bob_topics: Vec<Vec<u8>> = account_topics(&accounts.bob)
+                .iter()
+                .map(|topic| topic.to_vec())
+                .collect();
+            let mut selected = 0;
+            for (index, recorded) in ink::env::test::recorded_events().enumerate() {
+                let event = <Event as scale::Decode>::decode(&mut &recorded.data[..])
+                    .expect("event decodes");
+                let name = event_name(&event);
+                let account = match event {
+                    Event::FlippedV2(event) => Some(event.account),
+                    Event::Approval(event) => Some(event.account),
+                    Event::BatchFlipped(event) => Some(event.account),
+                    _ => None,
+                };
+                let mut expected = vec![signature_topic(&name).to_vec()];
+                expected.extend(account.map(|account| account_topic(&name, &account).to_vec()));
+                assert_eq!(recorded.topics, expected, "event {index}");
+                let tagged = recorded
+                    .topics
+                    .iter()
+                    .any(|topic| bob_topics.contains(topic));
+                assert_eq!(tagged, account == Some(accounts.bob), "event {index}");
+                selected += usize::from(tagged);
+            }
+            // Bob's approval, the flip for bob and bob's batch.
+            assert_eq!(selected, 3);
+        }
+
+        /// Messages accepting value, each spending it as a fee, deposit, bid or
+        /// donation. Every other message must refuse it.
+        const PAYABLE_MESSAGES: &[&str] = &[
+            "flip",
+            "flip_until",
+            "subscribe",
+            "bid",
+            "create_escrow",
+            "set_airdrop",
+            "execute_forwarded",
+            "execute_idempotent",
+            "execute_with_permit",
+            "execute_as_session",
+            "wrap",
+            "flip_with_nft",
+            "flip_for",
+            "batch_flip_for",
+            "queue_flip",
+            "donate",
+            "flip_with_zk_proof",
+            "flip_with_referrer",
+        ];
+
+        /// Zero bytes after the selector, which decode as arguments of every
+        /// type the messages take.
+        pub(super) const ZERO_ARGUMENTS: [u8; 512] = [0; 512];
+
+        /// Returns the label, selector and payability of every message.
+        pub(super) fn message_payability() -> Vec<(String, [u8; 4], bool)> {
+            // SAFETY: the generated function has exactly this signature.
+            let project = unsafe { generate_metadata() };
+            project
+                .spec()
+                .messages()
+                .iter()
+                .map(|message| {
+                    let selector = message.selector().to_bytes();
+                    let selector = [selector[0], selector[1], selector[2], selector[3]];
+                    (message.label().clone(), selector, message.payable())
+                })
+                .collect()
+        }
+
+        /// Exactly the intended messages are payable, and dispatching any other
+        /// with value fails before it runs.
+        #[ink::test]
+        fn unpayable_messages_refuse_value() {
+            use ink::reflect::{ContractMessageDecoder, DispatchError, ExecuteDispatchable};
+
+            let unit_test_bug = UnitTestBug::new(false);
+            // Dispatch loads the contract from its root cell, which an
+            // off-chain constructor leaves empty.
+            ink::env::set_contract_storage(
+                &<UnitTestBug as ink::storage::traits::StorageKey>::KEY,
+                &unit_test_bug,
+            );
+            set_value_transferred(1);
+            let mut payable = Vec::new();
+            for (label, selector, is_payable) in message_payability() {
+                if is_payable {
+                    payable.push(label);
+                    continue;
+                }
+                let input = [&selector[..], &ZERO_ARGUMENTS[..]].concat();
+                let call =
+                    <<UnitTestBug as ContractMessageDecoder>::Type as scale::Decode>::decode(
+                        &mut &input[..],
+                    )
+                    .unwrap_or_else(|_| panic!("arguments of {label} decode"));
+                assert_eq!(
+                    call.execute_dispatchable(),
+                    Err(DispatchError::PaidUnpayableMessage),
+                    "{label}"
+                );
+            }
+            assert_eq!(payable, PAYABLE_MESSAGES);
+            assert_eq!(unit_test_bug.fee_pot(), 0);
+        }
+
+        /// Creates an escrow from bob to charlie arbitrated by django.
+        fn open_escrow(unit_test_bug: &mut UnitTestBug, amount: Balance) -> u32 {
+            let accounts = accounts();
+            pay_as(accounts.bob, amount);
+            unit_test_bug
+                .create_escrow(accounts.charlie, accounts.django)
+                .expect("create_escrow failed")
+        }
+
+        /// Escrows need funds and get sequential ids.
+        #[ink::test]
+        fn create_escrow_requires_deposit() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            pay_as(accounts.bob, 0);
+            assert_eq!(
+                unit_test_bug.create_escrow(accounts.charlie, accounts.django),
+                Err(FlipError::EmptyEscrow)
+            );
+            assert_eq!(open_escrow(&mut unit_test_bug, 10), 0);
+            assert_eq!(open_escrow(&mut unit_test_bug, 10), 1);
+            let escrow = unit_test_bug.escrow(0).expect("escrow exists");
+            assert_eq!(escrow.state, EscrowState::Open);
+            assert_eq!(escrow.amount, 10);
+        }
+
+        /// Open -> Released by the depositor pays the beneficiary.
+        #[ink::test]
+        fn escrow_release_pays_beneficiary() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            let id = open_escrow(&mut unit_test_bug, 40);
+
+            for outsider in [accounts.charlie, accounts.django, accounts.eve] {
+                set_caller(outsider);
+                assert_eq!(unit_test_bug.release(id), Err(FlipError::NotEscrowParty));
+            }
+            set_caller(accounts.bob);
+            assert_eq!(unit_test_bug.release(id), Ok(()));
+            assert_eq!(unit_test_bug.credit_of(accounts.charlie), 40);
+            assert_eq!(
+                unit_test_bug.escrow(id).map(|e| e.state),
+                Some(EscrowState::Released)
+            );
+            assert_eq!(
+                unit_test_bug.release(id),
+                Err(FlipError::InvalidEscrowState)
+            );
+            assert_eq!(
+                unit_test_bug.dispute(id),
+                Err(FlipError::InvalidEscrowState)
+            );
+        }
+
+        /// Open -> Disputed is allowed for both parties but not outsiders.
+        #[ink::test]
+        fn escrow_dispute_parties() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            let by_depositor = open_escrow(&mut unit_test_bug, 1);
+            let by_beneficiary = open_escrow(&mut unit_test_bug, 1);
+
+            for outsider in [accounts.django, accounts.eve] {
+                set_caller(outsider);
+                assert_eq!(
+                    unit_test_bug.dispute(by_depositor),
+                    Err(FlipError::NotEscrowParty)
+                );
+            }
+            set_caller(accounts.bob);
+            assert_eq!(unit_test_bug.dispute(by_depositor), Ok(()));
+            set_caller(accounts.charlie);
+            assert_eq!(unit_test_bug.dispute(by_beneficiary), Ok(()));
+            assert_eq!(
+                unit_test_bug.dispute(by_beneficiary),
+                Err(FlipError::InvalidEscrowState)
+            );
+
+            // A disputed escrow can no longer be released by the depositor.
+            set_caller(accounts.bob);
+            assert_eq!(
+                unit_test_bug.release(by_depositor),
+                Err(FlipError::InvalidEscrowState)
+            );
+        }
+
+        /// Disputed -> Released or Refunded, decided by the arbiter only.
+        #[ink::test]
+        fn escrow_resolve_verdicts() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let accounts = accounts();
+            let released = open_escrow(&mut unit_test_bug, 30);
+            let refunded = open_escrow(&mut unit_test_bug, 20);
+            set_caller(accounts.bob);
+            unit_test_bug.dispute(released).expect("dispute failed");
+            unit_test_bug.dispute(refunded).expect("dispute failed");
+
+            for party in [accounts.bob, accounts.charlie] {
+                set_caller(party);
+                assert_eq!(
+                    unit_test_bug.resolve(released, true),
+                    Err(FlipError::NotEscrowParty)
+                );
+            }
+            set_caller(accounts.django);
+            assert_eq!(unit_test_bug.resolve(released, true), Ok(()));
+            assert_eq!(unit_test_bug.resolve(refunded, false), Ok(()));
+
+            assert_eq!(unit_test_bug.credit_of(accounts.charlie), 30);
+            assert_eq!(unit_test_bug.credit_of(accounts.bob), 20);
+            assert_eq!(
+                unit_test_bug.escrow(released).map(|e| e.state),
+                Some(EscrowState::Released)
+            );
+            assert_eq!(
+                unit_test_bug.escrow(refunded).map(|e| e.state),
+                Some(EscrowState::Refunded)
+            );
+            assert_eq!(
+                unit_test_bug.resolve(released, false),
+                Err(FlipError::InvalidEscrowState)
+            );
+        }
+
+        /// Resolving requires a dispute and unknown ids are reported.
+        #[ink::test]
+        fn escrow_resolve_requires_dispute() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            let id = open_escrow(&mut unit_test_bug, 5);
+            set_caller(accounts().django);
+            assert_eq!(
+                unit_test_bug.resolve(id, true),
+                Err(FlipError::InvalidEscrowState)
+            );
+            assert_eq!(
+                unit_test_bug.resolve(7, true),
+                Err(FlipError::EscrowNotFound)
+            );
+            assert_eq!(unit_test_bug.release(7), Err(FlipError::EscrowNotFound));
+            assert_eq!(unit_test_bug.dispute(7), Err(FlipError::EscrowNotFound));
+        }
+
+        /// Several changes within one block collapse into a single checkpoint.
+        #[ink::test]
+        fn flips_in_same_block_share_checkpoint() {
+            let mut unit_test_bug = UnitTestBug::new(false);
+            unit_test_bug.flip().expect("flip failed");
+            unit_test_bug.flip().expect("flip failed");
+            unit_test_bug.flip().expect("flip failed");
+            assert_eq!(unit_test_bug.checkpoint_count(), 1);
+            assert_eq!(unit_test_bug.value_at(0), Some(true));
+        }
+
+        /// An account calling messages in a scenario. Alice deploys and owns the contract.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+        #[serde(rename_all = "snake_case")]
+        pub(super) enum Actor {
+            Alice,
+            Bob,
+            Charlie,
+        }
+
+        impl Actor {
+            /// Returns the actor's off-chain account.
+            fn account_id(self) -> AccountId {
+                let accounts = accounts();
+                match self {
+                    Actor::Alice => accounts.alice,
+                    Actor::Bob => accounts.bob,
+                    Actor::Charlie => accounts.charlie,
+                }
+            }
+        }
+
+        /// A message a scenario calls.
+        #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+        #[serde(rename_all = "snake_case")]
+        pub(super) enum Call {
+            Flip,
+            FlipWithError,
+            SetLabel(String),
+            #[serde(skip)]
+            Multicall(Vec<AdminOp>),
+        }
+
+        impl Call {
+            /// Calls the message on an off-chain instance.
+            fn apply(&self, unit_test_bug: &mut UnitTestBug) -> Result<(), FlipError> {
+                match self {
+                    Call::Flip => unit_test_bug.flip().map(drop),
+                    Call::FlipWithError => unit_test_bug.flip_with_error(),
+                    Call::SetLabel(label) => unit_test_bug.set_label(label.clone()),
+                    Call::Multicall(ops) => unit_test_bug.multicall(ops.clone()),
+                }
+            }
+        }
+
+        /// Calls made against a freshly instantiated contract, and what they should lead to.
+        ///
+        /// Written in Given/When/Then form so the intent reads without knowing
+        /// the harness, e.g. `Scenario::given_deployed(false).when(Actor::Bob,
+        /// Call::Flip).then_value(true)`. The same scenario runs off-chain with
+        /// `run_unit` and on a node with `e2e_tests::run_on_chain`.
+        pub(super) struct Scenario {
+            pub name: String,
+            pub init_value: bool,
+            pub calls: Vec<(Actor, Call)>,
+            /// Expected return value of each call, `None` where any will do.
+            pub expected_returns: Vec<Option<Result<(), FlipError>>>,
+            pub expected_value: Option<bool>,
+            pub expected_label: Option<String>,
+            /// Expected names of the events emitted, in order.
+            pub expected_events: Option<Vec<String>>,
+        }
+
+        impl Scenario {
+            /// Starts a scenario on an instance alice deployed with `init_value`.
+            pub fn given_deployed(init_value: bool) -> Self {
+                Self {
+                    name: String::from("unnamed"),
+                    init_value,
+                    calls: Vec::new(),
+                    expected_returns: Vec::new(),
+                    expected_value: None,
+                    expected_label: None,
+                    expected_events: None,
+                }
+            }
+
+            /// Names the scenario in failure messages and reports.
+            pub fn named(mut self, name: &str) -> Self {
+                self.name = String::from(name);
+                self
+            }
+
+            /// Adds a call of `call` by `caller`.
+            pub fn when(mut self, caller: Actor, call: Call) -> Self {
+                self.calls.push((caller, call));
+                self.expected_returns.push(None);
+                self
+            }
+
+            /// Expects the latest call to succeed.
+            pub fn then_succeeds(self) -> Self {
+                self.then_returns(Ok(()))
+            }
+
+            /// Expects the latest call to fail with `error`.
+            pub fn then_fails_with(self, error: FlipError) -> Self {
+                self.then_returns(Err(error))
+            }
+
+            fn then_returns(mut self, result: Result<(), FlipError>) -> Self {
+                *self
+                    .expected_returns
+                    .last_mut()
+                    .expect("a `then` follows a `when`") = Some(result);
+                self
+            }
+
+            /// Expects the value the scenario ends with.
+            pub fn then_value(mut self, value: bool) -> Self {
+                self.expected_value = Some(value);
+                self
+            }
+
+            /// Expects the label the scenario ends with.
+            pub fn then_label(mut self, label: &str) -> Self {
+                self.expected_label = Some(String::from(label));
+                self
+            }
+
+            /// Expects the names of all events emitted, in order.
+            pub fn then_events(mut self, events: &[&str]) -> Self {
+                self.expected_events = Some(events.iter().map(|&name| String::from(name)).collect());
+                self
+            }
+
+            /// Asserts that `outcome`, observed on `backend`, meets every expectation.
+            ///
+            /// Event expectations are skipped on backends that do not observe events.
+            pub fn verify(&self, backend: Backend, outcome: &Outcome) {
+                let expected = self.expected_returns.iter().zip(&outcome.returns);
+                for (index, (expected, actual)) in expected.enumerate() {
+                    if let Some(expected) = expected {
+                        assert_eq!(
+                            actual, expected,
+                            "{}: when {:?} on {backend:?}",
+                            self.name, self.calls[index]
+                        );
+                    }
+                }
+                if let Some(value) = self.expected_value {
+                    assert_eq!(outcome.value, value, "{}: value on {backend:?}", self.name);
+                }
+                if let Some(label) = &self.expected_label {
+                    assert_eq!(&outcome.label, label, "{}: label on {backend:?}", self.name);
+                }
+                if let (Some(expected), Some(events)) = (&self.expected_events, &outcome.events) {
+                    assert_eq!(events, expected, "{}: events on {backend:?}", self.name);
+                }
+            }
+
+            /// Runs the scenario off-chain, emulating reverts, and verifies it.
+            pub fn run_unit(&self) {
+                let backend = Backend::OffChainReverting;
+                self.verify(backend, &run_off_chain(self, backend));
+            }
+        }
+
+        /// The return value of every call of a scenario and the state it ends in.
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub(super) struct Outcome {
+            pub returns: Vec<Result<(), FlipError>>,
+            pub value: bool,
+            pub label: String,
+            /// Names of the events emitted, `None` where the backend does not observe them.
+            pub events: Option<Vec<String>>,
+            /// Every storage cell, as a [`Snapshot`] shows it, `None` where the
+            /// backend cannot read raw storage.
+            pub storage: Option<String>,
+        }
+
+        /// An environment a scenario can run in.
+        ///
+        /// There is no drink! sandbox backend: drink! needs the ink! 5 runtime
+        /// APIs, so the sandbox tier joins once the contract moves off ink! 4.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub(super) enum Backend {
+            /// The off-chain environment, which never reverts.
+            OffChain,
+            /// The off-chain environment with reverts emulated by `call_reverting`.
+            OffChainReverting,
+            /// A live node, see `e2e_tests::run_on_chain`.
+            #[cfg_attr(not(feature = "e2e-tests"), allow(dead_code))]
+            E2e,
+        }
+
+        /// A scenario whose outcome differs between two backends.
+        #[derive(Debug, PartialEq, Eq)]
+        pub(super) struct Finding {
+            pub scenario: String,
+            pub field: &'static str,
+            pub left: (Backend, String),
+            pub right: (Backend, String),
+        }
+
+        impl core::fmt::Display for Finding {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                write!(
+                    f,
+                    "{}: `{}` is {} on {:?} but {} on {:?}",
+                    self.scenario, self.field, self.left.1, self.left.0, self.right.1, self.right.0
+                )
+            }
+        }
+
+        /// The scenarios every backend runs.
+        ///
+        /// Expectations describe the chain; off-chain runs are only diffed.
+        pub(super) fn scenarios() -> Vec<Scenario> {
+            vec![
+                Scenario::given_deployed(false)
+                    .named("flips")
+                    .when(Actor::Alice, Call::Flip)
+                    .when(Actor::Bob, Call::Flip)
+                    .when(Actor::Charlie, Call::Flip)
+                    .then_value(true),
+                Scenario::given_deployed(false)
+                    .named("flip_with_error")
+                    .when(Actor::Alice, Call::FlipWithError)
+                    .then_fails_with(FlipError::FlipError)
+                    .then_value(false),
+                label_is_owner_only(),
+                Scenario::given_deployed(true)
+                    .named("label_too_long")
+                    .when(Actor::Alice, Call::SetLabel(String::from("first")))
+                    .when(Actor::Alice, Call::SetLabel("x".repeat(MAX_LABEL_LEN + 1)))
+                    .then_fails_with(FlipError::LabelTooLong)
+                    .then_label("first"),
+                Scenario::given_deployed(false)
+                    .named("multicall_fails_midway")
+                    .when(
+                        Actor::Alice,
+                        Call::Multicall(vec![
+                            AdminOp::SetLabel(String::from("kept?")),
+                            AdminOp::SetLabel("x".repeat(MAX_LABEL_LEN + 1)),
+                        ]),
+                    )
+                    .then_fails_with(FlipError::LabelTooLong)
+                    .then_label(""),
+            ]
+        }
+
+        /// Only the owner may set the label.
+        pub(super) fn label_is_owner_only() -> Scenario {
+            Scenario::given_deployed(false)
+                .named("label_is_owner_only")
+                .when(Actor::Bob, Call::SetLabel(String::from("bob's")))
+                .then_fails_with(FlipError::NotOwner)
+                .when(Actor::Alice, Call::SetLabel(String::from("alice's")))
+                .then_succeeds()
+                .then_label("alice's")
+        }
+
+        /// Returns the name an event has in the metadata.
+        fn event_name(event: &Event) -> String {
+            let name = match event {
+                Event::Flipped(_) => "Flipped",
+                Event::FlippedV2(_) => "FlippedV2",
+                Event::Approval(_) => "Approval",
+                Event::BreakerTripped(_) => "BreakerTripped",
+                Event::ConfigChanged(_) => "ConfigChanged",
+                Event::EpochRolledOver(_) => "EpochRolledOver",
+                Event::BatchFlipped(_) => "BatchFlipped",
+                Event::Pruned(_) => "Pruned",
+                Event::Evicted(_) => "Evicted",
+            };
+            String::from(name)
+        }
+
+        /// Runs `scenario` against a fresh off-chain environment.
+        pub(super) fn run_off_chain(scenario: &Scenario, backend: Backend) -> Outcome {
+            let mut outcome = None;
+            ink::env::test::run_test::<ink::env::DefaultEnvironment, _>(|_| {
+                let mut unit_test_bug = UnitTestBug::new(scenario.init_value);
+                let returns = scenario
+                    .calls
+                    .iter()
+                    .map(|(caller, call)| {
+                        set_caller(caller.account_id());
+                        match backend {
+                            Backend::OffChain => call.apply(&mut unit_test_bug),
+                            Backend::OffChainReverting => {
+                                call_reverting(&mut unit_test_bug, |contract| call.apply(contract))
+                            }
+                            Backend::E2e => unreachable!("e2e runs on a node"),
+                        }
+                    })
+                    .collect();
+                outcome = Some(Outcome {
+                    returns,
+                    value: unit_test_bug.get(),
+                    label: unit_test_bug.label(),
+                    events: Some(recorded_events().iter().map(event_name).collect()),
+                    storage: Some(format!("{:?}", Snapshot::take(&unit_test_bug))),
+                });
+                Ok(())
+            })
+            .expect("off-chain run succeeds");
+            outcome.expect("scenario ran")
+        }
+
+        /// Lists every difference between two outcomes of `scenario`.
+        pub(super) fn diff(
+            scenario: &str,
+            left: (Backend, &Outcome),
+            right: (Backend, &Outcome),
+        ) -> Vec<Finding> {
+            let mut fields = vec![
+                ("returns", format!("{:?}", left.1.returns), format!("{:?}", right.1.returns)),
+                ("value", left.1.value.to_string(), right.1.value.to_string()),
+                ("label", format!("{:?}", left.1.label), format!("{:?}", right.1.label)),
+            ];
+            if let (Some(l), Some(r)) = (&left.1.events, &right.1.events) {
+                fields.push(("events", format!("{l:?}"), format!("{r:?}")));
+            }
+            if let (Some(l), Some(r)) = (&left.1.storage, &right.1.storage) {
+                fields.push(("storage", l.clone(), r.clone()));
+            }
+            fields
+                .into_iter()
+                .filter(|(_, l, r)| l != r)
+                .map(|(field, l, r)| Finding {
+                    scenario: String::from(scenario),
+                    field,
+                    left: (left.0, l),
+                    right: (right.0, r),
+                })
+                .collect()
+        }
+
+        /// Scenarios and fields known to differ between the plain and the
+        /// reverting off-chain environment.
+        const KNOWN_OFF_CHAIN_FINDINGS: &[(&str, &str)] = &[
+            ("flip_with_error", "value"),
+            ("flip_with_error", "storage"),
+            ("multicall_fails_midway", "label"),
+            ("multicall_fails_midway", "storage"),
+        ];
+
+        /// The off-chain environment differs from emulated reverts exactly where known.
+        ///
+        /// A new finding means a message now fails after writing state; a
+        /// vanished one means the list above is stale.
+        #[test]
+        fn off_chain_differential() {
+            let findings: Vec<_> = scenarios()
+                .iter()
+                .flat_map(|scenario| {
+                    let plain = run_off_chain(scenario, Backend::OffChain);
+                    let reverting = run_off_chain(scenario, Backend::OffChainReverting);
+                    diff(
+                        &scenario.name,
+                        (Backend::OffChain, &plain),
+                        (Backend::OffChainReverting, &reverting),
+                    )
+                })
+                .collect();
+            let found: Vec<_> = findings
+                .iter()
+                .map(|f| (f.scenario.as_str(), f.field))
+                .collect();
+            let report: Vec<_> = findings.iter().map(ToString::to_string).collect();
+            assert_eq!(found, KNOWN_OFF_CHAIN_FINDINGS, "findings:\n{}", report.join("\n"));
+        }
+
+        /// Anyone may flip an unrestricted instance.
+        #[test]
+        fn anyone_flips_scenario() {
+            Scenario::given_deployed(false)
+                .named("anyone_flips")
+                .when(Actor::Bob, Call::Flip)
+                .then_succeeds()
+                .then_value(true)
+                .when(Actor::Charlie, Call::Flip)
+                .then_succeeds()
+                .then_value(false)
+                .then_events(&["FlippedV2", "Flipped", "FlippedV2", "Flipped"])
+                .run_unit();
+        }
+
+        /// Only the owner may set the label.
+        #[test]
+        fn label_is_owner_only_scenario() {
+            label_is_owner_only().run_unit();
+        }
+
+        /// Expected result of a call in a test vector.
+        #[derive(serde::Serialize, serde::Deserialize)]
+        #[serde(rename_all = "snake_case")]
+        enum VectorResult {
+            Ok,
+            /// A `FlipError` variant, by name.
+            Err(String),
+        }
+
+        /// A call in a test vector.
+        #[derive(serde::Serialize, serde::Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct VectorCall {
+            caller: Actor,
+            call: Call,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            expect: Option<VectorResult>,
+        }
+
+        /// A test case loaded from `vectors/*.json`, shared with client SDK tests.
+        ///
+        /// Absent expectations are not checked.
+        #[derive(serde::Serialize, serde::Deserialize)]
+        #[serde(deny_unknown_fields)]
+        pub(super) struct TestVector {
+            name: String,
+            init_value: bool,
+            calls: Vec<VectorCall>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            value: Option<bool>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            label: Option<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            events: Option<Vec<String>>,
+        }
+
+        impl TestVector {
+            /// Records the calls of `scenario` and everything `outcome` observed as expectations.
+            pub(super) fn recorded(scenario: &Scenario, outcome: &Outcome) -> Self {
+                let calls = scenario
+                    .calls
+                    .iter()
+                    .zip(&outcome.returns)
+                    .map(|((caller, call), result)| VectorCall {
+                        caller: *caller,
+                        call: call.clone(),
+                        expect: Some(match result {
+                            Ok(()) => VectorResult::Ok,
+                            Err(error) => VectorResult::Err(format!("{error:?}")),
+                        }),
+                    })
+                    .collect();
+                Self {
+                    name: scenario.name.clone(),
+                    init_value: scenario.init_value,
+                    calls,
+                    value: Some(outcome.value),
+                    label: Some(outcome.label.clone()),
+                    events: outcome.events.clone(),
+                }
+            }
+
+            /// Returns the scenario the vector describes.
+            pub(super) fn scenario(&self) -> Scenario {
+                let mut scenario = Scenario::given_deployed(self.init_value).named(&self.name);
+                for step in &self.calls {
+                    scenario = scenario.when(step.caller, step.call.clone());
+                    scenario = match &step.expect {
+                        None => scenario,
+                        Some(VectorResult::Ok) => scenario.then_succeeds(),
+                        Some(VectorResult::Err(name)) => {
+                            let error = flip_error_named(name)
+                                .unwrap_or_else(|| panic!("{}: unknown error {name}", self.name));
+                            scenario.then_fails_with(error)
+                        }
+                    };
+                }
+                if let Some(value) = self.value {
+                    scenario = scenario.then_value(value);
+                }
+                if let Some(label) = &self.label {
+                    scenario = scenario.then_label(label);
+                }
+                scenario.expected_events = self.events.clone();
+                scenario
+            }
+        }
+
+        /// Lists every `FlipError` variant in declaration order, those with an
+        /// upstream source without one.
+        ///
+        /// Variants encode as their index, followed by `None` for a source, so
+        /// decoding each index in turn finds them all.
+        pub(super) fn flip_error_variants() -> Vec<FlipError> {
+            (0..=u8::MAX)
+                .map_while(|index| <FlipError as scale::Decode>::decode(&mut &[index, 0][..]).ok())
+                .collect()
+        }
+
+        /// Looks up a `FlipError` variant by name.
+        pub(super) fn flip_error_named(name: &str) -> Option<FlipError> {
+            flip_error_variants()
+                .into_iter()
+                .find(|error| format!("{error:?}") == name)
+        }
+
+        /// Writes `vectors` to `path` as a vector file.
+        pub(super) fn save_vectors(path: &std::path::Path, vectors: &[TestVector]) {
+            let json = serde_json::to_string_pretty(vectors).expect("vectors serialize");
+            std::fs::write(path, json + "\n").expect("vector file is writable");
+        }
+
+        /// Reads the vectors of one vector file.
+        pub(super) fn load_vectors(path: &std::path::Path) -> Vec<TestVector> {
+            let json = std::fs::read_to_string(path).expect("vector file is readable");
+            serde_json::from_str(&json).unwrap_or_else(|err| panic!("{}: {err}", path.display()))
+        }
+
+        /// Re-executes a recorded session off-chain and checks every recorded result.
+        ///
+        /// Sessions recorded on a node with `E2E_RECORD_DIR` set replay here; a
+        /// recording copied into `vectors/` keeps replaying with `json_vectors`.
+        pub(super) fn replay(path: &std::path::Path) {
+            for vector in load_vectors(path) {
+                vector.scenario().run_unit();
+            }
+        }
+
+        /// Loads every test vector, in file name order.
+        pub(super) fn test_vectors() -> Vec<TestVector> {
+            let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/vectors");
+            let mut paths: Vec<_> = std::fs::read_dir(dir)
+                .expect("vectors are checked in")
+                .map(|entry| entry.expect("vector entry is readable").path())
+                .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+                .collect();
+            paths.sort();
+            paths.iter().flat_map(|path| load_vectors(path)).collect()
+        }
+
+        /// Every JSON test vector holds off-chain.
+        #[test]
+        fn json_vectors() {
+            let vectors = test_vectors();
+            assert!(!vectors.is_empty());
+            for vector in vectors {
+                vector.scenario().run_unit();
+            }
+        }
+
+        /// A recorded session replays with the results it was recorded with.
+        #[test]
+        fn recorded_session_replays() {
+            let scenario = label_is_owner_only();
+            let outcome = run_off_chain(&scenario, Backend::OffChainReverting);
+            let path = std::env::temp_dir().join(format!(
+                "unit_test_bug-recording-{}.json",
+                std::process::id()
+            ));
+            save_vectors(&path, &[TestVector::recorded(&scenario, &outcome)]);
+
+            let recorded = load_vectors(&path);
+            assert_eq!(recorded.len(), 1);
+            assert_eq!(recorded[0].calls.len(), scenario.calls.len());
+            assert_eq!(recorded[0].label.as_deref(), Some("alice's"));
+            replay(&path);
+            std::fs::remove_file(path).expect("recording is removable");
+        }
+
+        /// Calls that make a message return a `FlipError` variant, run on a
+        /// contract alice just deployed.
+        type Trigger = fn(&mut UnitTestBug) -> FlipResult<()>;
+
+        /// One trigger for each variant a unit test can make a message return,
+        /// with the variant's name.
+        fn error_triggers() -> Vec<(&'static str, Trigger)> {
+            let mut triggers: Vec<(&'static str, Trigger)> = vec![
+                ("FlipError", |c| c.flip_with_error()),
+                ("NotOwner", |c| {
+                    set_caller(accounts().bob);
+                    c.set_label(String::from("bob's"))
+                }),
+                ("InsufficientFee", |c| {
+                    set_config(c, |config| config.flip_fee = 100);
+                    pay_as(accounts().bob, 10);
+                    c.flip().map(drop)
+                }),
+                ("SubscriptionsDisabled", |c| {
+                    set_value_transferred(100);
+                    c.subscribe().map(drop)
+                }),
+                ("InsufficientPayment", |c| {
+                    set_config(c, |config| config.subscription_price = 50);
+                    set_value_transferred(49);
+                    c.subscribe().map(drop)
+                }),
+                ("BidTooLow", |c| {
+                    pay_as(accounts().bob, 0);
+                    c.bid().map(drop)
+                }),
+                ("TransferFailed", |c| {
+                    let bob = accounts().bob;
+                    set_value_transferred(10);
+                    c.set_airdrop(airdrop_leaf(0, bob), 10)
+                        .expect("owner sets airdrop");
+                    // The engine fails transfers from an account it holds no
+                    // balance for, as if the contract's account had been reaped.
+                    let reaped = AccountId::from([0x42; 32]);
+                    ink::env::test::set_callee::<ink::env::DefaultEnvironment>(reaped);
+                    set_caller(bob);
+                    c.claim_airdrop(0, Vec::new())
+                }),
+                ("NotRightsHolder", |c| {
+                    pay_as(accounts().bob, 100);
+                    c.bid().expect("bid succeeds");
+                    advance_blocks(DEFAULT_EPOCH_LENGTH);
+                    pay_as(accounts().charlie, 0);
+                    c.flip().map(drop)
+                }),
+                ("EscrowNotFound", |c| c.release(7)),
+                ("InvalidEscrowState", |c| {
+                    let id = open_escrow(c, 5);
+                    set_caller(accounts().django);
+                    c.resolve(id, true)
+                }),
+                ("NotEscrowParty", |c| {
+                    let id = open_escrow(c, 5);
+                    set_caller(accounts().eve);
+                    c.release(id)
+                }),
+                ("EmptyEscrow", |c| {
+                    let accounts = accounts();
+                    pay_as(accounts.bob, 0);
+                    c.create_escrow(accounts.charlie, accounts.django).map(drop)
+                }),
+                ("NothingToClaim", |c| c.withdraw_credit().map(drop)),
+                ("AlreadyClaimed", |c| {
+                    let proofs;
+                    (*c, proofs) = unit_test_bug_with_airdrop(2);
+                    set_caller(accounts().bob);
+                    c.claim_airdrop(0, proofs[0].clone())
+                        .expect("first claim succeeds");
+                    c.claim_airdrop(0, proofs[0].clone())
+                }),
+                ("InvalidProof", |c| {
+                    let proofs;
+                    (*c, proofs) = unit_test_bug_with_airdrop(1);
+                    set_caller(accounts().charlie);
+                    c.claim_airdrop(0, proofs[0].clone())
+                }),
+                ("AirdropExhausted", |c| c.claim_airdrop(0, Vec::new())),
+                ("LotteryNotFinished", |c| {
+                    flip_as_all(c, &[accounts().bob]);
+                    c.draw(0).map(drop)
+                }),
+                ("AlreadyDrawn", |c| {
+                    flip_as_all(c, &[accounts().bob]);
+                    advance_blocks(DEFAULT_EPOCH_LENGTH);
+                    mock_randomness(0);
+                    c.draw(0).expect("first draw succeeds");
+                    c.draw(0).map(drop)
+                }),
+                ("NoTickets", |c| {
+                    advance_blocks(DEFAULT_EPOCH_LENGTH);
+                    c.draw(0).map(drop)
+                }),
+                ("RandomnessUnavailable", |c| {
+                    flip_as_all(c, &[accounts().bob]);
+                    advance_blocks(DEFAULT_EPOCH_LENGTH);
+                    crate::test_support::set_randomness(|_subject| {
+                        Err(RandomReadErr::FailGetRandomSource)
+                    });
+                    c.draw(0).map(drop)
+                }),
+                ("NotTrustedForwarder", |c| {
+                    set_caller(accounts().frank);
+                    c.execute_forwarded(accounts().bob, ForwardedCall::Flip)
+                        .map(drop)
+                }),
+                ("InvalidSignature", |c| {
+                    let signer = ecdsa_account(7);
+                    let hash = c.permit_hash(signer, ForwardedCall::Flip, 0);
+                    c.execute_with_permit(signer, ForwardedCall::Flip, ecdsa_sign(8, hash))
+                        .map(drop)
+                }),
+                ("SessionKeyNotFound", |c| {
+                    set_caller(accounts().eve);
+                    c.execute_as_session(accounts().bob, ForwardedCall::Flip)
+                        .map(drop)
+                }),
+                ("SessionExpired", |c| {
+                    c.authorize_session_key(accounts().eve, SESSION_FLIP, 0)
+                }),
+                ("OutOfScope", |c| {
+                    let accounts = accounts();
+                    set_caller(accounts.bob);
+                    c.authorize_session_key(accounts.eve, 0, 10)
+                        .expect("bob authorizes");
+                    set_caller(accounts.eve);
+                    c.execute_as_session(accounts.bob, ForwardedCall::Flip)
+                        .map(drop)
+                }),
+                ("InvalidConfig", |c| {
+                    let config = Config {
+                        max_batch_size: 0,
+                        ..c.config()
+                    };
+                    c.propose_config(config).map(drop)
+                }),
+                ("NoPendingConfig", |c| c.apply_config()),
+                ("TimelockActive", |c| {
+                    let config = Config {
+                        flip_fee: 5,
+                        ..c.config()
+                    };
+                    c.propose_config(config).expect("valid config");
+                    c.apply_config()
+                }),
+                ("Paused", |c| {
+                    set_config(c, |config| config.paused = true);
+                    c.flip().map(drop)
+                }),
+                ("CooldownActive", |c| {
+                    set_config(c, |config| config.cooldown_blocks = 3);
+                    c.flip().expect("first flip succeeds");
+                    advance_blocks(2);
+                    c.flip().map(drop)
+                }),
+                ("InsufficientWrapped", |c| c.unwrap(1)),
+                ("NoNftCollection", |c| c.flip_with_nft(Id::U8(1)).map(drop)),
+                ("NotApproved", |c| {
+                    set_caller(accounts().charlie);
+                    c.flip_for(accounts().bob).map(drop)
+                }),
+                ("NoTreasury", |c| c.forward_donations().map(drop)),
+                ("NoAssetFee", |c| c.flip_with_asset().map(drop)),
+                ("AssetTransferFailed", |c| {
+                    let bob = accounts().bob;
+                    mock_assets().borrow_mut().balances.insert((7, bob), 50);
+                    let fee = AssetFee {
+                        asset_id: 7,
+                        amount: 20,
+                    };
+                    c.set_asset_fee(Some(fee)).expect("owner sets asset fee");
+                    set_caller(bob);
+                    c.flip_with_asset().map(drop)
+                }),
+                ("NoVerifyingKey", |c| {
+                    c.flip_with_zk_proof(Vec::new(), Vec::new()).map(drop)
+                }),
+                ("NoValidatorSet", |c| {
+                    c.flip_with_bls_attestation(vec![0], vec![0; 48]).map(drop)
+                }),
+                ("BlobTooLarge", |c| {
+                    let max = c.config().max_blob_size as usize;
+                    c.store_blob(1, vec![8; max + 1])
+                }),
+                ("LabelTooLong", |c| {
+                    c.set_label("x".repeat(MAX_LABEL_LEN + 1))
+                }),
+                ("InvalidPolicy", |c| {
+                    let schedule = Schedule {
+                        start: 0,
+                        period: 4,
+                        open_for: 0,
+                    };
+                    c.set_flip_policy(FlipPolicy {
+                        mode: PolicyMode::Scheduled(schedule),
+                        params: PolicyParams::default(),
+                    })
+                }),
+                ("PolicyDenied", |c| {
+                    c.set_flip_policy(FlipPolicy {
+                        mode: PolicyMode::OwnerOnly,
+                        params: PolicyParams::default(),
+                    })
+                    .expect("owner sets policy");
+                    set_caller(accounts().bob);
+                    c.flip().map(drop)
+                }),
+                ("RateLimited", |c| {
+                    set_config(c, |config| config.max_calls_per_block = 1);
+                    c.flip().expect("first call succeeds");
+                    c.flip().map(drop)
+                }),
+                ("NotGuardian", |c| c.reset_breaker()),
+                ("BatchTooLarge", |c| {
+                    let too_many = vec![accounts().bob; c.config().max_batch_size as usize + 1];
+                    c.batch_flip_for(too_many, BatchMode::BestEffort).map(drop)
+                }),
+                ("InvalidBorrower", |c| {
+                    c.flash_flip(
+                        ink::env::test::callee::<ink::env::DefaultEnvironment>(),
+                        Vec::new(),
+                    )
+                }),
+                ("SelfReferral", |c| {
+                    set_caller(accounts().bob);
+                    c.flip_with_referrer(Some(accounts().bob)).map(drop)
+                }),
+                ("AlreadyReferred", |c| {
+                    set_caller(accounts().bob);
+                    c.flip_with_referrer(Some(accounts().alice))
+                        .expect("first referral succeeds");
+                    c.flip_with_referrer(Some(accounts().charlie)).map(drop)
+                }),
+                ("InvalidFeeDiscounts", |c| {
+                    let tier = FeeTier {
+                        min_flips: 5,
+                        discount_bps: BPS_DENOMINATOR + 1,
+                    };
+                    c.set_fee_discounts(FeeDiscounts { tiers: vec![tier] })
+                }),
+                ("ProposalNotFound", |c| c.vote(0, true).map(drop)),
+                ("VotingNotOpen", |c| {
+                    wrap_as(c, accounts().bob, 30);
+                    let id = c.propose_value(true).expect("staker proposes");
+                    c.vote(id, true).map(drop)
+                }),
+                ("AlreadyVoted", |c| {
+                    wrap_as(c, accounts().bob, 30);
+                    let id = c.propose_value(true).expect("staker proposes");
+                    advance_blocks(1);
+                    c.vote(id, true).expect("first vote counts");
+                    c.vote(id, false).map(drop)
+                }),
+                ("NoStake", |c| {
+                    set_caller(accounts().bob);
+                    c.propose_value(true).map(drop)
+                }),
+                ("VotingNotOver", |c| {
+                    wrap_as(c, accounts().bob, 30);
+                    let id = c.propose_value(true).expect("staker proposes");
+                    c.execute(id)
+                }),
+                ("ProposalRejected", |c| {
+                    wrap_as(c, accounts().bob, 30);
+                    let id = c.propose_value(true).expect("staker proposes");
+                    advance_blocks(1);
+                    c.vote(id, false).expect("vote counts");
+                    advance_blocks(Config::default().voting_period);
+                    c.execute(id)
+                }),
+                ("ProposalExecuted", |c| {
+                    wrap_as(c, accounts().bob, 30);
+                    let id = c.propose_value(true).expect("staker proposes");
+                    advance_blocks(1);
+                    c.vote(id, true).expect("vote counts");
+                    advance_blocks(Config::default().voting_period);
+                    c.execute(id).expect("proposal passed");
+                    c.execute(id)
+                }),
+                ("DeadlineInPast", |c| c.flip_until(0).map(drop)),
+                ("AlreadyInitialized", |c| c.initialize(true)),
+                ("UntrustedCallee", |c| {
+                    let trusted = Hash::from([1; 32]);
+                    crate::test_support::set_contract_info(&[(accounts().django, trusted, 0)]);
+                    c.set_code_hash_trusted(trusted, true)
+                        .expect("owner trusts code");
+                    c.flash_flip(accounts().eve, Vec::new())
+                }),
+                ("QueueFull", |c| {
+                    for _ in 0..MAX_QUEUED_FLIPS {
+                        pay_as(accounts().bob, KEEPER_REWARD);
+                        c.queue_flip(20).expect("queue has room");
+                    }
+                    pay_as(accounts().bob, KEEPER_REWARD);
+                    c.queue_flip(20).map(drop)
+                }),
+                ("ResponseTooLarge", |c| {
+                    for _ in 0..MAX_HISTORY_PAGE + 5 {
+                        advance_blocks(1);
+                        c.write_checkpoint();
+                    }
+                    c.history_page(0, u32::MAX).map(drop)
+                }),
+                ("FeatureDisabled", |c| {
+                    set_config(c, |config| config.features = 0);
+                    c.draw(0).map(drop)
+                }),
+            ];
+            #[cfg(feature = "zk")]
+            triggers.push(("InvalidVerifyingKey", |c| {
+                c.set_zk_verifying_key(vec![1, 2, 3])
+            }));
+            #[cfg(not(feature = "zk"))]
+            triggers.push(("ZkUnsupported", |c| c.set_zk_verifying_key(vec![1, 2, 3])));
+            #[cfg(feature = "bls")]
+            triggers.extend([
+                (
+                    "InvalidValidatorSet",
+                    (|c| {
+                        c.set_bls_validators(BlsValidatorSet {
+                            keys: vec![vec![0; 96]],
+                            threshold: 1,
+                        })
+                    }) as Trigger,
+                ),
+                ("InvalidSigner", |c| {
+                    *c = bls_contract();
+                    let signature = hex::decode(BLS_SIGNATURE_0_2).unwrap();
+                    c.flip_with_bls_attestation(vec![2, 0], signature).map(drop)
+                }),
+                ("BelowThreshold", |c| {
+                    *c = bls_contract();
+                    let signature = hex::decode(BLS_SIGNATURE_0_2).unwrap();
+                    c.flip_with_bls_attestation(vec![0], signature).map(drop)
+                }),
+            ]);
+            #[cfg(not(feature = "bls"))]
+            triggers.push(("BlsUnsupported", |c| {
+                c.set_bls_validators(BlsValidatorSet {
+                    keys: vec![vec![0; 96]],
+                    threshold: 1,
+                })
+            }));
+            triggers
+        }
+
+        /// Variants only calls into other contracts or the runtime return,
+        /// which the off-chain environment cannot make, each with the e2e test
+        /// that returns it.
+        const ON_CHAIN_ERRORS: &[(&str, &str)] = &[
+            ("BelowTokenMinimum", "psp22_gated_flip"),
+            ("TokenQueryFailed", "token_query_failure_is_reported"),
+            ("NotNftHolder", "psp34_permit_flip"),
+            ("RuntimeCallFailed", "failed_runtime_call_is_reported"),
+            ("OracleUnavailable", "cross_contract_calls_respect_ref_time_limit"),
+            ("StalePrice", "usd_fee_uses_fresh_oracle_price"),
+            ("TokenTransferFailed", "token_transfer_failure_is_reported"),
+            ("FlashCallFailed", "flash_flip_maps_every_callee_outcome"),
+            ("FlashRejected", "flash_flip_restores_value"),
+        ];
+
+        /// Variants no call can return in this build.
+        fn unreachable_errors() -> Vec<&'static str> {
+            let mut unreachable = vec![
+                // `flash_flip` checks the invariants after a callback that
+                // cannot re-enter the contract to break them.
+                "InvariantViolated",
+            ];
+            if cfg!(feature = "zk") {
+                unreachable.push("ZkUnsupported");
+            } else {
+                unreachable.push("InvalidVerifyingKey");
+            }
+            if cfg!(feature = "bls") {
+                unreachable.push("BlsUnsupported");
+            } else {
+                // No validator set can be stored to check signers against.
+                unreachable.extend(["InvalidValidatorSet", "InvalidSigner", "BelowThreshold"]);
+            }
+            unreachable
+        }
+
+        /// Returns the name of `error`'s variant.
+        fn variant_name(error: &FlipError) -> String {
+            let debug = format!("{error:?}");
+            debug.split('(').next().unwrap_or_default().to_owned()
+        }
+
+        /// Every error variant is returned by its trigger, by an e2e test or
+        /// is listed as unreachable, exactly one of these.
+        ///
+        /// E2E tests do not run here, so for those the test is only checked
+        /// to expect the variant.
+        #[test]
+        fn every_error_variant_is_returned() {
+            let triggers = error_triggers();
+            for (name, trigger) in &triggers {
+                let mut returned = None;
+                ink::env::test::run_test::<ink::env::DefaultEnvironment, _>(|_| {
+                    let mut unit_test_bug = UnitTestBug::new(false);
+                    returned = Some(trigger(&mut unit_test_bug));
+                    Ok(())
+                })
+                .expect("trigger runs");
+                let returned = returned.expect("trigger ran").err();
+                assert_eq!(
+                    returned.as_ref().map(variant_name).as_deref(),
+                    Some(*name),
+                    "trigger returned {returned:?}"
+                );
+            }
+
+            let source = include_str!("lib.rs");
+            for (name, test) in ON_CHAIN_ERRORS {
+                let start = source
+                    .find(&format!("async fn {test}("))
+                    .unwrap_or_else(|| panic!("no e2e test {test}"));
+                let end = source[start..].find("\n        }\n").expect("test ends") + start;
+                let path = format!("FlipError::{name}");
+                assert!(
+                    source[start..end].contains(&path),
+                    "{test} never expects {path}"
+                );
+            }
+
+            let unreachable = unreachable_errors();
+            let listed: Vec<_> = triggers
+                .iter()
+                .map(|(name, _)| *name)
+                .chain(ON_CHAIN_ERRORS.iter().map(|(name, _)| *name))
+                .chain(unreachable.iter().copied())
+                .collect();
+            let names: Vec<_> = flip_error_variants().iter().map(variant_name).collect();
+            let missing: Vec<_> = names
+                .iter()
+                .filter(|name| !listed.contains(&name.as_str()))
+                .collect();
+            let mut extra: Vec<_> = listed.clone();
+            for name in &names {
+                if let Some(index) = extra.iter().position(|listed| listed == name) {
+                    extra.remove(index);
+                }
+            }
+            assert!(missing.is_empty(), "errors nothing returns: {missing:?}");
+            assert!(extra.is_empty(), "unknown or repeated errors: {extra:?}");
+        }
+    }
+
+
+    /// This is how you'd write end-to-end (E2E) or integration tests for ink! contracts.
+    ///
+    /// When running these you need to make sure that you:
+    /// - Compile the tests with the `e2e-tests` feature flag enabled (`--features e2e-tests`)
+    /// - Are running a Substrate node which contains `pallet-contracts` in the background
+    #[cfg(all(test, feature = "e2e-tests"))]
+    mod e2e_tests {
+        /// Imports all the definitions from the outer scope so we can use them here.
+        use super::*;
+
+        use ink::codegen::TraitCallBuilder;
+        use ink_e2e::MessageBuilder;
+
+        use super::tests::{
+            diff, message_payability, run_off_chain, save_vectors, scenarios, Actor, Backend,
+            Call, Outcome, Scenario, TestVector, ZERO_ARGUMENTS,
+        };
+
+        /// A helper function used for calling contract messages.
+        ///
+        /// Mirrors `ink_e2e::build_message`, which is fixed to the default environment.
+        fn build_message<Ref>(
+            account_id: AccountId,
+        ) -> MessageBuilder<crate::CustomEnvironment, Ref>
+        where
+            Ref: TraitCallBuilder + ink::env::call::FromAccountId<crate::CustomEnvironment>,
+        {
+            MessageBuilder::from_account_id(account_id)
+        }
+
+        /// The End-to-End test `Result` type.
+        type E2EResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+        /// Asserts that a dry run wrote `needle` to the contracts pallet debug buffer.
+        ///
+        /// The contract only writes there when built with `debug`.
+        #[cfg(feature = "e2e-debug")]
+        fn assert_debug_contains<V>(
+            dry_run: &ink_e2e::CallDryRunResult<crate::CustomEnvironment, V>,
+            needle: &str,
+        ) {
+            let debug = dry_run.debug_message();
+            assert!(
+                debug.contains(needle),
+                "debug buffer lacks {needle:?}: {debug:?}"
+            );
+        }
+
+        /// Gas and storage deposit a call needs, from its dry run.
+        #[derive(Debug)]
+        struct Estimate {
+            ref_time: u64,
+            proof_size: u64,
+            storage_deposit: pallet_contracts_primitives::StorageDeposit<Balance>,
+        }
+
+        impl Estimate {
+            fn of<V>(dry_run: &ink_e2e::CallDryRunResult<crate::CustomEnvironment, V>) -> Self {
+                let gas_required = dry_run.exec_result.gas_required;
+                Self {
+                    ref_time: gas_required.ref_time(),
+                    proof_size: gas_required.proof_size(),
+                    storage_deposit: dry_run.exec_result.storage_deposit.clone(),
+                }
+            }
+
+            /// Deposit limit covering the call: its charge, or `None` for a refund.
+            fn storage_deposit_limit(&self) -> Option<Balance> {
+                match self.storage_deposit {
+                    pallet_contracts_primitives::StorageDeposit::Charge(amount) => Some(amount),
+                    pallet_contracts_primitives::StorageDeposit::Refund(_) => None,
+                }
+            }
+        }
+
+        impl<T: scale::Decode> crate::test_support::Reverted
+            for ink_e2e::CallDryRunResult<crate::CustomEnvironment, Result<T, FlipError>>
+        {
+            fn reverted_with(self) -> Option<FlipError> {
+                self.return_value().err()
+            }
+        }
+
+        /// We test that we can upload and instantiate the contract using its default constructor.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn default_works(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Given
+            let constructor = UnitTestBugRef::default();
+
+            // When
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+
+            // Then
+            let get = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.get());
+            let get_result = client.call_dry_run(&ink_e2e::alice(), &get, 0, None).await;
+            assert!(matches!(get_result.return_value(), false));
+
+            Ok(())
+        }
+
+        /// We test that we can read and write a value from the on-chain contract contract.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn it_works(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Given
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::bob(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+
+            let get = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.get());
+            let get_result = client.call_dry_run(&ink_e2e::bob(), &get, 0, None).await;
+            assert!(matches!(get_result.return_value(), false));
+
+            // When
+            let flip = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flip_with_error());
+
+            // Call flip. Result should still be false as error is returned
+            let _flip_result = client
+                .call(&ink_e2e::bob(), flip, 0, None)
+                .await;
+
+            // Then
+            let get = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.get());
+            let get_result = client.call_dry_run(&ink_e2e::bob(), &get, 0, None).await;
+            // This test does pass properly in e2e tests
+            assert!(matches!(get_result.return_value(), false));
+
+            Ok(())
+        }
+
+        /// We test that a flip submitted within its estimate succeeds.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn flip_estimate_is_sufficient(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Given
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let flip = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flip());
+            let estimate = Estimate::of(&client.call_dry_run(&ink_e2e::bob(), &flip, 0, None).await);
+            assert!(estimate.ref_time > 0 && estimate.proof_size > 0);
+            // The first flip of a block stores a checkpoint.
+            assert!(estimate.storage_deposit_limit().is_some());
+
+            // When
+            let flip_result = client
+                .call(&ink_e2e::bob(), flip, 0, estimate.storage_deposit_limit())
+                .await
+                .expect("flip within the estimated deposit failed");
+
+            // Then
+            let consumed = flip_result.dry_run.exec_result.gas_consumed;
+            assert!(consumed.ref_time() <= estimate.ref_time);
+            assert!(consumed.proof_size() <= estimate.proof_size);
+            assert_eq!(flip_result.return_value().map(drop), Ok(()));
+            let get = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.get());
+            let get_result = client.call_dry_run(&ink_e2e::bob(), &get, 0, None).await;
+            assert!(get_result.return_value());
+
+            Ok(())
+        }
+
+        /// We test that the cooldown holds on-chain until enough blocks passed.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn cooldown_passes_with_blocks(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Given
+            let config = Config {
+                cooldown_blocks: 5,
+                ..Config::default()
+            };
+            let constructor = UnitTestBugRef::with_config(false, config);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let flip = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flip());
+            client
+                .call(&ink_e2e::bob(), flip.clone(), 0, None)
+                .await
+                .expect("first flip failed");
+            let flip_result = client.call_dry_run(&ink_e2e::bob(), &flip, 0, None).await;
+            assert_eq!(flip_result.return_value(), Err(FlipError::CooldownActive));
+
+            // When
+            advance_blocks(&mut client, config.cooldown_blocks).await;
+
+            // Then
+            let flip_result = client.call_dry_run(&ink_e2e::bob(), &flip, 0, None).await;
+            assert_eq!(flip_result.return_value().map(drop), Ok(()));
+
+            Ok(())
+        }
+
+        /// We test that a queued config applies on-chain only after its timelock.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn config_applies_after_timelock(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Given
+            let old = Config {
+                timelock_delay: 5,
+                ..Config::default()
+            };
+            let new = Config { flip_fee: 5, ..old };
+            let constructor = UnitTestBugRef::with_config(false, old);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let propose = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.propose_config(new));
+            client
+                .call(&ink_e2e::alice(), propose, 0, None)
+                .await
+                .expect("propose failed");
+            let apply = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.apply_config());
+            let apply_result = client.call_dry_run(&ink_e2e::bob(), &apply, 0, None).await;
+            assert_eq!(apply_result.return_value(), Err(FlipError::TimelockActive));
+
+            // When
+            advance_blocks(&mut client, old.timelock_delay).await;
+            client
+                .call(&ink_e2e::bob(), apply, 0, None)
+                .await
+                .expect("apply failed");
+
+            // Then
+            let config = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.config());
+            let config_result = client.call_dry_run(&ink_e2e::bob(), &config, 0, None).await;
+            assert_eq!(config_result.return_value(), new);
+
+            Ok(())
+        }
+
+        /// We test that a subscription lapses on-chain once its blocks passed.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn subscription_expires_with_blocks(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Given
+            let config = Config {
+                flip_fee: 10,
+                subscription_price: 5,
+                ..Config::default()
+            };
+            let constructor = UnitTestBugRef::with_config(false, config);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let subscribe = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.subscribe());
+            client
+                .call(&ink_e2e::bob(), subscribe, 15, None)
+                .await
+                .expect("subscribe failed");
+            let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let is_subscribed = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.is_subscribed(bob));
+            let is_subscribed_result = client
+                .call_dry_run(&ink_e2e::bob(), &is_subscribed, 0, None)
+                .await;
+            assert!(is_subscribed_result.return_value());
+
+            // When
+            advance_blocks(&mut client, 3).await;
+
+            // Then
+            let is_subscribed_result = client
+                .call_dry_run(&ink_e2e::bob(), &is_subscribed, 0, None)
+                .await;
+            assert!(!is_subscribed_result.return_value());
+            let flip = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flip());
+            let flip_result = client.call_dry_run(&ink_e2e::bob(), &flip, 0, None).await;
+            assert_eq!(flip_result.return_value(), Err(FlipError::InsufficientFee));
+
+            Ok(())
+        }
+
+        /// What `outbid_refund_balances` funds each bidder with, enough for their
+        /// bids and fees.
+        const BIDDER_FUNDS: Balance = 1_000_000_000_000_000;
+
+        /// We test that outbidding credits the previous bidder on-chain, who
+        /// then withdraws the refund.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn outbid_refund_balances(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Given
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let contract_start = client
+                .balance(contract_account_id.clone())
+                .await
+                .expect("balance failed");
+            let (first, first_account) = signer(1);
+            let (second, second_account) = signer(2);
+            fund(&mut client, first_account, BIDDER_FUNDS).await;
+            fund(&mut client, second_account, BIDDER_FUNDS).await;
+
+            // When
+            let bid = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.bid());
+            client
+                .call(&first, bid, 1_000, None)
+                .await
+                .expect("bid failed");
+            let contract_balance = client
+                .balance(contract_account_id.clone())
+                .await
+                .expect("balance failed");
+            assert_eq!(contract_balance, contract_start + 1_000);
+
+            let bid = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.bid());
+            client
+                .call(&second, bid, 2_000, None)
+                .await
+                .expect("higher bid failed");
+
+            // Then the refund is held as a credit until withdrawn
+            let contract_balance = client
+                .balance(contract_account_id.clone())
+                .await
+                .expect("balance failed");
+            assert_eq!(contract_balance, contract_start + 3_000);
+            let credit = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.credit_of(first_account));
+            assert_eq!(
+                client
+                    .call_dry_run(&first, &credit, 0, None)
+                    .await
+                    .return_value(),
+                1_000
+            );
+            let withdraw = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.withdraw_credit());
+            let withdrawn = client.call_dry_run(&first, &withdraw, 0, None).await;
+            assert_eq!(withdrawn.return_value(), Ok(1_000));
+            client
+                .call(&first, withdraw, 0, None)
+                .await
+                .expect("withdraw_credit failed");
+            let contract_balance = client
+                .balance(contract_account_id.clone())
+                .await
+                .expect("balance failed");
+            assert_eq!(contract_balance, contract_start + 2_000);
+
+            Ok(())
+        }
+
+        /// We test that donations reach the treasury through `call_runtime`.
+        #[cfg(feature = "e2e-call-runtime")]
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn donations_forwarded_via_call_runtime(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            // Given
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let treasury = ink_e2e::account_id(ink_e2e::AccountKeyring::Ferdie);
+            let set_treasury = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.set_treasury(Some(treasury.clone())));
+            client
+                .call(&ink_e2e::alice(), set_treasury, 0, None)
+                .await
+                .expect("set_treasury failed");
+            let donate = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.donate());
+            client
+                .call(&ink_e2e::bob(), donate, 1_000_000, None)
+                .await
+                .expect("donate failed");
+            let treasury_before = client
+                .balance(treasury.clone())
+                .await
+                .expect("balance failed");
+
+            // When
+            let forward = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.forward_donations());
+            let forward_result = client
+                .call(&ink_e2e::charlie(), forward, 0, None)
+                .await
+                .expect("forward_donations failed");
+
+            // Then
+            assert_eq!(forward_result.return_value(), Ok(1_000_000));
+            let treasury_after = client
+                .balance(treasury.clone())
+                .await
+                .expect("balance failed");
+            assert_eq!(treasury_after, treasury_before + 1_000_000);
+
+            Ok(())
+        }
+
+        /// We test that a failed `call_runtime` is reported rather than trapping.
+        #[cfg(feature = "e2e-call-runtime")]
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn failed_runtime_call_is_reported(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            // Given a single unit donated for an account that does not exist
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let treasury = AccountId::from([0x42; 32]);
+            let set_treasury = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.set_treasury(Some(treasury)));
+            client
+                .call(&ink_e2e::alice(), set_treasury, 0, None)
+                .await
+                .expect("set_treasury failed");
+            let donate = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.donate());
+            client
+                .call(&ink_e2e::bob(), donate, 1, None)
+                .await
+                .expect("donate failed");
+
+            // When forwarding it, below the existential deposit
+            let forward = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.forward_donations());
+            let forward_result = client
+                .call_dry_run(&ink_e2e::charlie(), &forward, 0, None)
+                .await;
+
+            // Then the runtime refuses the transfer
+            assert_eq!(
+                forward_result.return_value(),
+                Err(FlipError::RuntimeCallFailed)
+            );
+
+            Ok(())
+        }
+
+        /// Largest `ref_time` a Groth16-gated flip may need.
+        #[cfg(feature = "e2e-zk")]
+        const ZK_FLIP_GAS_BUDGET: u64 = 200_000_000_000;
+
+        /// We test that a Groth16-gated flip verifies on-chain within its gas budget.
+        #[cfg(feature = "e2e-zk")]
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn zk_flip_within_gas_budget(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Given
+            let (key, proof, inputs) = super::tests::zk_fixture();
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let set_key = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.set_zk_verifying_key(key.clone()));
+            client
+                .call(&ink_e2e::alice(), set_key, 0, None)
+                .await
+                .expect("set_zk_verifying_key failed");
+
+            // When
+            let flip = build_message::<UnitTestBugRef>(contract_account_id.clone()).call(
+                |unit_test_bug| unit_test_bug.flip_with_zk_proof(proof.clone(), inputs.clone()),
+            );
+            let dry_run = client.call_dry_run(&ink_e2e::bob(), &flip, 0, None).await;
+
+            // Then
+            let gas = dry_run.exec_result.gas_required.ref_time();
+            assert_eq!(dry_run.return_value().map(drop), Ok(()));
+            assert!(
+                gas <= ZK_FLIP_GAS_BUDGET,
+                "verification took {gas} ref_time"
+            );
+
+            Ok(())
+        }
+
+        /// We test that a reverted flip traces the value it wrote before reverting.
+        #[cfg(feature = "e2e-debug")]
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn flip_with_error_traces_on_chain(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            // Given
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+
+            // When
+            let flip = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flip_with_error());
+            let dry_run = client.call_dry_run(&ink_e2e::bob(), &flip, 0, None).await;
+
+            // Then
+            assert_eq!(dry_run.return_value(), Err(FlipError::FlipError));
+            assert_debug_contains(&dry_run, "flip_with_error: value is now true, reverting");
+
+            Ok(())
+        }
+
+        /// We test a lottery draw against a node providing the randomness extension.
+        #[cfg(feature = "e2e-randomness")]
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn lottery_draw_on_chain(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Given
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let flip = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flip());
+            client
+                .call(&ink_e2e::bob(), flip, 1_000, None)
+                .await
+                .expect("flip failed");
+
+            // When
+            let draw = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.draw(0));
+            let draw_result = client.call_dry_run(&ink_e2e::bob(), &draw, 0, None).await;
+
+            // Then the sole ticket wins once epoch 0 is over
+            let epoch = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.current_epoch());
+            if client
+                .call_dry_run(&ink_e2e::bob(), &epoch, 0, None)
+                .await
+                .return_value()
+                > 0
+            {
+                let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+                assert_eq!(draw_result.return_value(), Ok((bob, 500)));
+            } else {
+                assert_eq!(
+                    draw_result.return_value(),
+                    Err(FlipError::LotteryNotFinished)
+                );
+            }
+
+            Ok(())
+        }
+
+        /// We test that a relayer can flip on a user's behalf through the forwarder.
+        #[ink_e2e::test(
+            additional_contracts = "mocks/forwarder/Cargo.toml",
+            environment = crate::CustomEnvironment
+        )]
+        async fn relayed_flip_through_forwarder(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            // Given
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let forwarder_constructor = forwarder_mock::ForwarderMockRef::new();
+            let forwarder_account_id = client
+                .instantiate(
+                    "forwarder_mock",
+                    &ink_e2e::alice(),
+                    forwarder_constructor,
+                    0,
+                    None,
+                )
+                .await
+                .expect("instantiate forwarder failed")
+                .account_id;
+            let set_forwarder = build_message::<UnitTestBugRef>(contract_account_id.clone()).call(
+                |unit_test_bug| {
+                    unit_test_bug.set_trusted_forwarder(Some(forwarder_account_id.clone()))
+                },
+            );
+            client
+                .call(&ink_e2e::alice(), set_forwarder, 0, None)
+                .await
+                .expect("set_trusted_forwarder failed");
+
+            // When charlie relays a flip for bob
+            let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let relay =
+                build_message::<forwarder_mock::ForwarderMockRef>(forwarder_account_id.clone())
+                    .call(|forwarder| {
+                        forwarder.forward_flip(contract_account_id.clone(), bob.clone())
+                    });
+            let relay_result = client
+                .call(&ink_e2e::charlie(), relay, 0, None)
+                .await
+                .expect("relay failed");
+            let receipt = relay_result.return_value().expect("flip relayed");
+            assert!(receipt.new_value);
+
+            // Then the flip is attributed to bob
+            let get = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.get());
+            assert!(client
+                .call_dry_run(&ink_e2e::bob(), &get, 0, None)
+                .await
+                .return_value());
+            let flip_count = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flip_count(bob.clone()));
+            assert_eq!(
+                client
+                    .call_dry_run(&ink_e2e::bob(), &flip_count, 0, None)
+                    .await
+                    .return_value(),
+                1
+            );
+
+            Ok(())
+        }
+
+        /// We test session key scope, expiry and revocation on-chain.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn session_keys_on_chain(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Given
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let eve = ink_e2e::account_id(ink_e2e::AccountKeyring::Eve);
+
+            // Expired grants are rejected
+            let authorize = build_message::<UnitTestBugRef>(contract_account_id.clone()).call(
+                |unit_test_bug| unit_test_bug.authorize_session_key(eve.clone(), SESSION_FLIP, 1),
+            );
+            let authorize_result = client
+                .call_dry_run(&ink_e2e::bob(), &authorize, 0, None)
+                .await;
+            assert_eq!(
+                authorize_result.return_value(),
+                Err(FlipError::SessionExpired)
+            );
+
+            // A key without the flip permission is out of scope
+            let authorize = build_message::<UnitTestBugRef>(contract_account_id.clone()).call(
+                |unit_test_bug| unit_test_bug.authorize_session_key(eve.clone(), 0, u32::MAX),
+            );
+            client
+                .call(&ink_e2e::bob(), authorize, 0, None)
+                .await
+                .expect("authorize failed");
+            let execute = build_message::<UnitTestBugRef>(contract_account_id.clone()).call(
+                |unit_test_bug| unit_test_bug.execute_as_session(bob.clone(), ForwardedCall::Flip),
+            );
+            let execute_result = client
+                .call_dry_run(&ink_e2e::eve(), &execute, 0, None)
+                .await;
+            assert_eq!(execute_result.return_value(), Err(FlipError::OutOfScope));
+
+            // When the permission is granted the key flips for bob
+            let authorize = build_message::<UnitTestBugRef>(contract_account_id.clone()).call(
+                |unit_test_bug| {
+                    unit_test_bug.authorize_session_key(eve.clone(), SESSION_FLIP, u32::MAX)
+                },
+            );
+            client
+                .call(&ink_e2e::bob(), authorize, 0, None)
+                .await
+                .expect("authorize failed");
+            let execute = build_message::<UnitTestBugRef>(contract_account_id.clone()).call(
+                |unit_test_bug| unit_test_bug.execute_as_session(bob.clone(), ForwardedCall::Flip),
+            );
+            client
+                .call(&ink_e2e::eve(), execute, 0, None)
+                .await
+                .expect("session flip failed");
+
+            // Then revoking the key stops it
+            let revoke = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.revoke_session_key(eve.clone()));
+            client
+                .call(&ink_e2e::bob(), revoke, 0, None)
+                .await
+                .expect("revoke failed");
+            let execute = build_message::<UnitTestBugRef>(contract_account_id.clone()).call(
+                |unit_test_bug| unit_test_bug.execute_as_session(bob.clone(), ForwardedCall::Flip),
+            );
+            let execute_result = client
+                .call_dry_run(&ink_e2e::eve(), &execute, 0, None)
+                .await;
+            assert_eq!(
+                execute_result.return_value(),
+                Err(FlipError::SessionKeyNotFound)
+            );
+
+            Ok(())
+        }
+
+        /// We test that an invalid configuration change leaves the on-chain config untouched.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn invalid_config_change_reverts(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Given
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+
+            // When
+            let invalid = Config {
+                flip_fee: 1,
+                max_batch_size: 0,
+                ..Config::default()
+            };
+            let propose = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.propose_config(invalid));
+            let propose_result = client.call(&ink_e2e::alice(), propose, 0, None).await;
+            assert!(propose_result.is_err());
+
+            // Then
+            let config = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.config());
+            let config_result = client
+                .call_dry_run(&ink_e2e::alice(), &config, 0, None)
+                .await;
+            assert_eq!(config_result.return_value(), Config::default());
+            let pending = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.pending_config());
+            let pending_result = client
+                .call_dry_run(&ink_e2e::alice(), &pending, 0, None)
+                .await;
+            assert_eq!(pending_result.return_value(), None);
+
+            Ok(())
+        }
+
+        /// We test that flipping requires holding the configured PSP22 minimum.
+        #[ink_e2e::test(
+            additional_contracts = "mocks/psp22/Cargo.toml",
+            environment = crate::CustomEnvironment
+        )]
+        async fn psp22_gated_flip(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Given a token held entirely by alice and a gate of 10
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let token_constructor = psp22_mock::Psp22MockRef::new(100);
+            let token_account_id = client
+                .instantiate("psp22_mock", &ink_e2e::alice(), token_constructor, 0, None)
+                .await
+                .expect("instantiate token failed")
+                .account_id;
+            let gate = Psp22Gate {
+                token: token_account_id.clone(),
+                min_balance: 10,
+            };
+            let set_gate = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.set_psp22_gate(Some(gate)));
+            client
+                .call(&ink_e2e::alice(), set_gate, 0, None)
+                .await
+                .expect("set_psp22_gate failed");
+
+            // Then bob, holding nothing, cannot flip
+            let flip = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flip());
+            let flip_result = client.call_dry_run(&ink_e2e::bob(), &flip, 0, None).await;
+            assert_eq!(
+                flip_result.return_value(),
+                Err(FlipError::BelowTokenMinimum)
+            );
+
+            // When alice sends him the minimum
+            let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let transfer = build_message::<psp22_mock::Psp22MockRef>(token_account_id.clone())
+                .call(|token| token.transfer(bob.clone(), 10, Vec::new()));
+            client
+                .call(&ink_e2e::alice(), transfer, 0, None)
+                .await
+                .expect("transfer failed");
+
+            // Then he can
+            let flip = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flip());
+            client
+                .call(&ink_e2e::bob(), flip, 0, None)
+                .await
+                .expect("gated flip failed");
+            let get = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.get());
+            assert!(client
+                .call_dry_run(&ink_e2e::bob(), &get, 0, None)
+                .await
+                .return_value());
+
+            Ok(())
+        }
+
+        /// We test that a PSP22 gate whose token cannot be queried is reported.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn token_query_failure_is_reported(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            // Given a gate on an account holding no contract
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let gate = Psp22Gate {
+                token: ink_e2e::account_id(ink_e2e::AccountKeyring::Dave),
+                min_balance: 10,
+            };
+            let set_gate = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.set_psp22_gate(Some(gate)));
+            client
+                .call(&ink_e2e::alice(), set_gate, 0, None)
+                .await
+                .expect("set_psp22_gate failed");
+
+            // When bob flips
+            let flip = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flip());
+            let flip_result = client.call_dry_run(&ink_e2e::bob(), &flip, 0, None).await;
+
+            // Then the failed balance query is reported
+            assert!(matches!(
+                flip_result.return_value(),
+                Err(FlipError::TokenQueryFailed(Some(_)))
+            ));
+
+            Ok(())
+        }
+
+        /// We test that stray PSP22 tokens are swept to the chosen account.
+        #[ink_e2e::test(
+            additional_contracts = "mocks/psp22/Cargo.toml",
+            environment = crate::CustomEnvironment
+        )]
+        async fn stray_tokens_are_swept(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Given 30 tokens sent to the contract by mistake
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let token_constructor = psp22_mock::Psp22MockRef::new(100);
+            let token_account_id = client
+                .instantiate("psp22_mock", &ink_e2e::alice(), token_constructor, 0, None)
+                .await
+                .expect("instantiate token failed")
+                .account_id;
+            let transfer = build_message::<psp22_mock::Psp22MockRef>(token_account_id.clone())
+                .call(|token| token.transfer(contract_account_id.clone(), 30, Vec::new()));
+            client
+                .call(&ink_e2e::alice(), transfer, 0, None)
+                .await
+                .expect("transfer failed");
+
+            // When the owner sweeps them to dave
+            let dave = ink_e2e::account_id(ink_e2e::AccountKeyring::Dave);
+            let sweep = build_message::<UnitTestBugRef>(contract_account_id.clone()).call(
+                |unit_test_bug| unit_test_bug.sweep_token(token_account_id.clone(), dave.clone()),
+            );
+            let sweep_result = client
+                .call(&ink_e2e::alice(), sweep, 0, None)
+                .await
+                .expect("sweep_token failed");
+            assert_eq!(sweep_result.return_value(), Ok(30));
+
+            // Then dave holds them and the contract has nothing left to sweep
+            let balance_of = build_message::<psp22_mock::Psp22MockRef>(token_account_id.clone())
+                .call(|token| token.balance_of(dave.clone()));
+            let balance_result = client
+                .call_dry_run(&ink_e2e::alice(), &balance_of, 0, None)
+                .await;
+            assert_eq!(balance_result.return_value(), 30);
+            let sweep = build_message::<UnitTestBugRef>(contract_account_id.clone()).call(
+                |unit_test_bug| unit_test_bug.sweep_token(token_account_id.clone(), dave.clone()),
+            );
+            let sweep_result = client
+                .call_dry_run(&ink_e2e::alice(), &sweep, 0, None)
+                .await;
+            assert_eq!(sweep_result.return_value(), Err(FlipError::NothingToClaim));
+
+            Ok(())
+        }
+
+        /// We test that a PSP22 transfer the token refuses is reported with its code.
+        #[ink_e2e::test(
+            additional_contracts = "mocks/psp22/Cargo.toml",
+            environment = crate::CustomEnvironment
+        )]
+        async fn token_transfer_failure_is_reported(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            // Given 30 tokens sent to the contract by mistake
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let token_constructor = psp22_mock::Psp22MockRef::new(100);
+            let token_account_id = client
+                .instantiate("psp22_mock", &ink_e2e::alice(), token_constructor, 0, None)
+                .await
+                .expect("instantiate token failed")
+                .account_id;
+            let transfer = build_message::<psp22_mock::Psp22MockRef>(token_account_id.clone())
+                .call(|token| token.transfer(contract_account_id.clone(), 30, Vec::new()));
+            client
+                .call(&ink_e2e::alice(), transfer, 0, None)
+                .await
+                .expect("transfer failed");
+
+            // When the owner sweeps them to the zero account
+            let zero = AccountId::from([0; 32]);
+            let sweep = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.sweep_token(token_account_id.clone(), zero));
+            let sweep_result = client
+                .call_dry_run(&ink_e2e::alice(), &sweep, 0, None)
+                .await;
+
+            // Then the token's refusal is passed on
+            let zero_recipient = psp22_mock::Psp22Error::ZeroRecipientAddress as u8;
+            assert_eq!(
+                sweep_result.return_value(),
+                Err(FlipError::TokenTransferFailed(Some(
+                    UpstreamError::Returned(zero_recipient)
+                )))
+            );
+
+            Ok(())
+        }
+
+        /// We test that a flash flip is visible to the borrower and always undone.
+        #[ink_e2e::test(
+            additional_contracts = "mocks/borrower/Cargo.toml",
+            environment = crate::CustomEnvironment
+        )]
+        async fn flash_flip_restores_value(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Given an accepting and a rejecting borrower
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let accepting = client
+                .instantiate(
+                    "borrower_mock",
+                    &ink_e2e::alice(),
+                    borrower_mock::BorrowerMockRef::new(true),
+                    0,
+                    None,
+                )
+                .await
+                .expect("instantiate borrower failed")
+                .account_id;
+            let rejecting = client
+                .instantiate(
+                    "borrower_mock",
+                    &ink_e2e::alice(),
+                    borrower_mock::BorrowerMockRef::new(false),
+                    0,
+                    None,
+                )
+                .await
+                .expect("instantiate borrower failed")
+                .account_id;
+
+            // When the accepting borrower takes a flash flip
+            let flash = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flash_flip(accepting.clone(), vec![1, 2]));
+            client
+                .call(&ink_e2e::bob(), flash, 0, None)
+                .await
+                .expect("flash_flip failed");
+
+            // Then it saw the flipped value and the value is back
+            let last_seen = build_message::<borrower_mock::BorrowerMockRef>(accepting.clone())
+                .call(|borrower| borrower.last_seen());
+            let seen = client
+                .call_dry_run(&ink_e2e::bob(), &last_seen, 0, None)
+                .await
+                .return_value();
+            assert_eq!(seen, Some(true));
+            let get = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.get());
+            assert!(!client
+                .call_dry_run(&ink_e2e::bob(), &get, 0, None)
+                .await
+                .return_value());
+
+            // And a rejected flash flip reverts, borrower state included
+            let flash = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flash_flip(rejecting.clone(), Vec::new()));
+            let flash_result = client.call_dry_run(&ink_e2e::bob(), &flash, 0, None).await;
+            assert_eq!(flash_result.return_value(), Err(FlipError::FlashRejected));
+            assert!(client.call(&ink_e2e::bob(), flash, 0, None).await.is_err());
+            let last_seen = build_message::<borrower_mock::BorrowerMockRef>(rejecting.clone())
+                .call(|borrower| borrower.last_seen());
+            let seen = client
+                .call_dry_run(&ink_e2e::bob(), &last_seen, 0, None)
+                .await
+                .return_value();
+            assert_eq!(seen, None);
+
+            // And an account without the callback fails the loan
+            let dave = ink_e2e::account_id(ink_e2e::AccountKeyring::Dave);
+            let flash = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flash_flip(dave.clone(), Vec::new()));
+            let flash_result = client.call_dry_run(&ink_e2e::bob(), &flash, 0, None).await;
+            assert_eq!(
+                flash_result.return_value(),
+                Err(FlipError::FlashCallFailed(Some(UpstreamError::CallFailed)))
+            );
+
+            Ok(())
+        }
+
+        /// We test that an NFT permit lets its holder past the token gate, and nobody else.
+        #[ink_e2e::test(
+            additional_contracts = "mocks/psp22/Cargo.toml mocks/psp34/Cargo.toml",
+            environment = crate::CustomEnvironment
+        )]
+        async fn psp34_permit_flip(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Given a gate nobody but alice passes and an NFT held by bob
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let token_constructor = psp22_mock::Psp22MockRef::new(100);
+            let token_account_id = client
+                .instantiate("psp22_mock", &ink_e2e::alice(), token_constructor, 0, None)
+                .await
+                .expect("instantiate token failed")
+                .account_id;
+            let collection_constructor = psp34_mock::Psp34MockRef::new();
+            let collection_account_id = client
+                .instantiate(
+                    "psp34_mock",
+                    &ink_e2e::alice(),
+                    collection_constructor,
+                    0,
+                    None,
+                )
+                .await
+                .expect("instantiate collection failed")
+                .account_id;
+            let mint = build_message::<psp34_mock::Psp34MockRef>(collection_account_id.clone())
+                .call(|collection| collection.mint(psp34_mock::Id::U8(7)));
+            client
+                .call(&ink_e2e::bob(), mint, 0, None)
+                .await
+                .expect("mint failed");
+            let gate = Psp22Gate {
+                token: token_account_id.clone(),
+                min_balance: 10,
+            };
+            let set_gate = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.set_psp22_gate(Some(gate)));
+            client
+                .call(&ink_e2e::alice(), set_gate, 0, None)
+                .await
+                .expect("set_psp22_gate failed");
+            let set_collection = build_message::<UnitTestBugRef>(contract_account_id.clone()).call(
+                |unit_test_bug| {
+                    unit_test_bug.set_psp34_collection(Some(collection_account_id.clone()))
+                },
+            );
+            client
+                .call(&ink_e2e::alice(), set_collection, 0, None)
+                .await
+                .expect("set_psp34_collection failed");
+
+            // Then charlie can neither flip nor present bob's NFT, or one that does not exist
+            let flip = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flip());
+            let flip_result = client
+                .call_dry_run(&ink_e2e::charlie(), &flip, 0, None)
+                .await;
+            assert_eq!(
+                flip_result.return_value(),
+                Err(FlipError::BelowTokenMinimum)
+            );
+            for id in [Id::U8(7), Id::U8(8)] {
+                let permit = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                    .call(|unit_test_bug| unit_test_bug.flip_with_nft(id.clone()));
+                let permit_result = client
+                    .call_dry_run(&ink_e2e::charlie(), &permit, 0, None)
+                    .await;
+                assert_eq!(permit_result.return_value(), Err(FlipError::NotNftHolder));
+            }
+
+            // When bob presents his NFT
+            let permit = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flip_with_nft(Id::U8(7)));
+            client
+                .call(&ink_e2e::bob(), permit, 0, None)
+                .await
+                .expect("permit flip failed");
+
+            // Then the flip went through
+            let get = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.get());
+            assert!(client
+                .call_dry_run(&ink_e2e::bob(), &get, 0, None)
+                .await
+                .return_value());
+
+            Ok(())
+        }
+
+        /// We test how each way a flash borrower can end maps to a result.
+        #[ink_e2e::test(
+            additional_contracts = "mocks/switch/Cargo.toml",
+            environment = crate::CustomEnvironment
+        )]
+        async fn flash_flip_maps_every_callee_outcome(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            use switch_mock::{BehaviorSwitch, SwitchMockRef};
+
+            // Given
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let switch = client
+                .instantiate(
+                    "switch_mock",
+                    &ink_e2e::alice(),
+                    SwitchMockRef::new(BehaviorSwitch::Ok),
+                    0,
+                    None,
+                )
+                .await
+                .expect("instantiate switch failed")
+                .account_id;
+            let cases = [
+                (BehaviorSwitch::Ok, Ok(())),
+                (BehaviorSwitch::Error, Err(FlipError::FlashRejected)),
+                (
+                    BehaviorSwitch::Trap,
+                    Err(FlipError::FlashCallFailed(Some(UpstreamError::CallFailed))),
+                ),
+                // Flash borrowers are called without reentry, so the contracts
+                // pallet traps the callee's call back.
+                (
+                    BehaviorSwitch::Reenter,
+                    Err(FlipError::FlashCallFailed(Some(UpstreamError::CallFailed))),
+                ),
+            ];
+
+            for (behavior, expected) in cases {
+                // When
+                let set_behavior = build_message::<SwitchMockRef>(switch.clone())
+                    .call(|switch| switch.set_behavior(behavior));
+                client
+                    .call(&ink_e2e::alice(), set_behavior, 0, None)
+                    .await
+                    .expect("set_behavior failed");
+                let flash = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                    .call(|unit_test_bug| unit_test_bug.flash_flip(switch.clone(), Vec::new()));
+                let flash_result = client.call_dry_run(&ink_e2e::bob(), &flash, 0, None).await;
+
+                // Then
+                assert_eq!(flash_result.return_value(), expected, "{behavior:?}");
+                let outcome = client.call(&ink_e2e::bob(), flash, 0, None).await;
+                assert_eq!(outcome.is_ok(), expected.is_ok(), "{behavior:?}");
+                let get = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                    .call(|unit_test_bug| unit_test_bug.get());
+                assert!(!client
+                    .call_dry_run(&ink_e2e::bob(), &get, 0, None)
+                    .await
+                    .return_value());
+            }
+
+            Ok(())
+        }
+
+        /// We test that once a code hash is allowlisted, a borrower running
+        /// other code is refused before it is called.
+        #[cfg(feature = "e2e-introspection")]
+        #[ink_e2e::test(
+            additional_contracts = "mocks/borrower/Cargo.toml mocks/switch/Cargo.toml",
+            environment = crate::CustomEnvironment
+        )]
+        async fn untrusted_callee_is_refused(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            use switch_mock::{BehaviorSwitch, SwitchMockRef};
+
+            // Given only the borrower mock's code is trusted
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let trusted = client
+                .upload("borrower_mock", &ink_e2e::alice(), None)
+                .await
+                .expect("upload failed")
+                .code_hash;
+            let trust = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.set_code_hash_trusted(trusted, true));
+            client
+                .call(&ink_e2e::alice(), trust, 0, None)
+                .await
+                .expect("set_code_hash_trusted failed");
+            let borrower = client
+                .instantiate(
+                    "borrower_mock",
+                    &ink_e2e::alice(),
+                    borrower_mock::BorrowerMockRef::new(true),
+                    0,
+                    None,
+                )
+                .await
+                .expect("instantiate borrower failed")
+                .account_id;
+            let switch = client
+                .instantiate(
+                    "switch_mock",
+                    &ink_e2e::alice(),
+                    SwitchMockRef::new(BehaviorSwitch::Ok),
+                    0,
+                    None,
+                )
+                .await
+                .expect("instantiate switch failed")
+                .account_id;
+
+            // When the unapproved switch mock is lent the value
+            let flash = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flash_flip(switch.clone(), Vec::new()));
+            let flash_result = client.call_dry_run(&ink_e2e::bob(), &flash, 0, None).await;
+
+            // Then it is refused, while the approved borrower is still served
+            assert_eq!(flash_result.return_value(), Err(FlipError::UntrustedCallee));
+            let flash = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flash_flip(borrower.clone(), Vec::new()));
+            assert_eq!(
+                client
+                    .call_dry_run(&ink_e2e::bob(), &flash, 0, None)
+                    .await
+                    .return_value(),
+                Ok(())
+            );
+
+            Ok(())
+        }
+
+        /// We test that another contract can read the value and statistics
+        /// through `IFlipQuery` alone.
+        #[ink_e2e::test(
+            additional_contracts = "mocks/query_caller/Cargo.toml",
+            environment = crate::CustomEnvironment
+        )]
+        async fn caller_contract_queries_through_trait(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            use query_caller_mock::QueryCallerMockRef;
+
+            // Given
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let caller = client
+                .instantiate(
+                    "query_caller_mock",
+                    &ink_e2e::alice(),
+                    QueryCallerMockRef::new(contract_account_id.clone()),
+                    0,
+                    None,
+                )
+                .await
+                .expect("instantiate caller failed")
+                .account_id;
+            let flip = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flip());
+            client
+                .call(&ink_e2e::bob(), flip, 0, None)
+                .await
+                .expect("flip failed");
+
+            // When
+            let value =
+                build_message::<QueryCallerMockRef>(caller.clone()).call(|caller| caller.value());
+            let value_result = client.call_dry_run(&ink_e2e::bob(), &value, 0, None).await;
+            let stats =
+                build_message::<QueryCallerMockRef>(caller.clone()).call(|caller| caller.stats());
+            let stats_result = client.call_dry_run(&ink_e2e::bob(), &stats, 0, None).await;
+
+            // Then
+            assert_eq!(value_result.return_value(), Ok(true));
+            let stats = stats_result.return_value().expect("stats query failed");
+            assert_eq!((stats.flips, stats.flippers), (1, 1));
+
+            Ok(())
+        }
+
+        /// We test that a proxy runs this contract's code against its own storage,
+        /// which `initialize` sets up once.
+        #[ink_e2e::test(
+            additional_contracts = "mocks/proxy/Cargo.toml",
+            environment = crate::CustomEnvironment
+        )]
+        async fn proxy_delegates_to_logic(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            use proxy_mock::ProxyMockRef;
+
+            // Given
+            let logic = client
+                .upload("unit_test_bug", &ink_e2e::alice(), None)
+                .await
+                .expect("upload failed")
+                .code_hash;
+            let alice = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let proxy = client
+                .instantiate(
+                    "proxy_mock",
+                    &ink_e2e::alice(),
+                    ProxyMockRef::new(logic, UnitTestBug::proxy_storage(alice)),
+                    0,
+                    None,
+                )
+                .await
+                .expect("instantiate proxy failed")
+                .account_id;
+            let initialize = build_message::<UnitTestBugRef>(proxy.clone())
+                .call(|unit_test_bug| unit_test_bug.initialize(false));
+            assert_eq!(
+                client
+                    .call_dry_run(&ink_e2e::bob(), &initialize, 0, None)
+                    .await
+                    .return_value(),
+                Err(FlipError::NotOwner)
+            );
+            client
+                .call(&ink_e2e::alice(), initialize, 0, None)
+                .await
+                .expect("initialize failed");
+
+            // When
+            let flip = build_message::<UnitTestBugRef>(proxy.clone())
+                .call(|unit_test_bug| unit_test_bug.flip());
+            let flip_result = client
+                .call(&ink_e2e::bob(), flip, 0, None)
+                .await
+                .expect("flip through proxy failed");
+
+            // Then
+            assert_eq!(flip_result.return_value().map(drop), Ok(()));
+            let get = build_message::<UnitTestBugRef>(proxy.clone())
+                .call(|unit_test_bug| unit_test_bug.get());
+            assert!(client
+                .call_dry_run(&ink_e2e::bob(), &get, 0, None)
+                .await
+                .return_value());
+            let owner = build_message::<UnitTestBugRef>(proxy.clone())
+                .call(|unit_test_bug| unit_test_bug.owner());
+            assert_eq!(
+                client
+                    .call_dry_run(&ink_e2e::bob(), &owner, 0, None)
+                    .await
+                    .return_value(),
+                alice
+            );
+            let initialize_again = build_message::<UnitTestBugRef>(proxy.clone())
+                .call(|unit_test_bug| unit_test_bug.initialize(true));
+            assert_eq!(
+                client
+                    .call_dry_run(&ink_e2e::bob(), &initialize_again, 0, None)
+                    .await
+                    .return_value(),
+                Err(FlipError::AlreadyInitialized)
+            );
+            let logic_hash =
+                build_message::<ProxyMockRef>(proxy.clone()).call(|proxy| proxy.logic());
+            assert_eq!(
+                client
+                    .call_dry_run(&ink_e2e::bob(), &logic_hash, 0, None)
+                    .await
+                    .return_value(),
+                logic
+            );
+
+            Ok(())
+        }
+
+        /// We test that swapping the logic behind a proxy keeps its storage,
+        /// and that only the admin may swap it.
+        #[ink_e2e::test(
+            additional_contracts = "mocks/proxy/Cargo.toml mocks/switch/Cargo.toml",
+            environment = crate::CustomEnvironment
+        )]
+        async fn proxy_upgrade_keeps_storage(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            use proxy_mock::{ProxyError, ProxyMockRef};
+
+            // Given a proxy whose value was flipped
+            let logic = client
+                .upload("unit_test_bug", &ink_e2e::alice(), None)
+                .await
+                .expect("upload failed")
+                .code_hash;
+            let other_logic = client
+                .upload("switch_mock", &ink_e2e::alice(), None)
+                .await
+                .expect("upload failed")
+                .code_hash;
+            let alice = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let proxy = client
+                .instantiate(
+                    "proxy_mock",
+                    &ink_e2e::alice(),
+                    ProxyMockRef::new(logic, UnitTestBug::proxy_storage(alice)),
+                    0,
+                    None,
+                )
+                .await
+                .expect("instantiate proxy failed")
+                .account_id;
+            let initialize = build_message::<UnitTestBugRef>(proxy.clone())
+                .call(|unit_test_bug| unit_test_bug.initialize(false));
+            client
+                .call(&ink_e2e::alice(), initialize, 0, None)
+                .await
+                .expect("initialize failed");
+            let flip = build_message::<UnitTestBugRef>(proxy.clone())
+                .call(|unit_test_bug| unit_test_bug.flip());
+            client
+                .call(&ink_e2e::bob(), flip, 0, None)
+                .await
+                .expect("flip through proxy failed");
+            let get = build_message::<UnitTestBugRef>(proxy.clone())
+                .call(|unit_test_bug| unit_test_bug.get());
+
+            // When bob tries to swap the logic
+            let upgrade = build_message::<ProxyMockRef>(proxy.clone())
+                .call(|proxy| proxy.upgrade_logic(other_logic));
+            let upgrade_result = client
+                .call_dry_run(&ink_e2e::bob(), &upgrade, 0, None)
+                .await;
+
+            // Then
+            assert_eq!(upgrade_result.return_value(), Err(ProxyError::NotAdmin));
+
+            // When the admin swaps it
+            client
+                .call(&ink_e2e::alice(), upgrade, 0, None)
+                .await
+                .expect("upgrade failed");
+
+            // Then the new logic answers, and does not know `get`
+            let get_result = client.call_dry_run(&ink_e2e::bob(), &get, 0, None).await;
+            assert_eq!(
+                get_result.message_result(),
+                Err(ink::LangError::CouldNotReadInput)
+            );
+
+            // When the admin swaps back
+            let downgrade = build_message::<ProxyMockRef>(proxy.clone())
+                .call(|proxy| proxy.upgrade_logic(logic));
+            client
+                .call(&ink_e2e::alice(), downgrade, 0, None)
+                .await
+                .expect("downgrade failed");
+
+            // Then the value flipped before is still there
+            assert!(client
+                .call_dry_run(&ink_e2e::bob(), &get, 0, None)
+                .await
+                .return_value());
+
+            Ok(())
+        }
+
+        /// We test that the node refuses value sent to every unpayable message.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn unpayable_messages_refuse_value_on_chain(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            // Given
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+
+            for (label, selector, _) in message_payability()
+                .into_iter()
+                .filter(|(_, _, payable)| !payable)
+            {
+                // When
+                let call =
+                    build_message::<UnitTestBugRef>(contract_account_id.clone()).call(|_| {
+                        build_call::<crate::CustomEnvironment>()
+                            .call(contract_account_id.clone())
+                            .exec_input(
+                                ExecutionInput::new(Selector::new(selector))
+                                    .push_arg(ZERO_ARGUMENTS),
+                            )
+                            .returns::<()>()
+                    });
+                let result = client.call_dry_run(&ink_e2e::bob(), &call, 1, None).await;
+
+                // Then ink! traps the call on `PaidUnpayableMessage`
+                assert!(result.is_err(), "{label} accepted value");
+            }
+
+            Ok(())
+        }
+
+        /// We test that the dollar fee follows the oracle and stale prices are refused.
+        #[ink_e2e::test(
+            additional_contracts = "mocks/oracle/Cargo.toml",
+            environment = crate::CustomEnvironment
+        )]
+        async fn usd_fee_uses_fresh_oracle_price(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            // Given a 2 USD fee converted at an oracle price of 1_000 units per USD
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let oracle_constructor = oracle_mock::OracleMockRef::new();
+            let oracle_account_id = client
+                .instantiate(
+                    "oracle_mock",
+                    &ink_e2e::alice(),
+                    oracle_constructor,
+                    0,
+                    None,
+                )
+                .await
+                .expect("instantiate oracle failed")
+                .account_id;
+            let set_price = build_message::<oracle_mock::OracleMockRef>(oracle_account_id.clone())
+                .call(|oracle| oracle.set_price(1_000));
+            client
+                .call(&ink_e2e::alice(), set_price, 0, None)
+                .await
+                .expect("set_price failed");
+            let fee = UsdFee {
+                oracle: oracle_account_id.clone(),
+                usd_cents: 200,
+                max_age: 2,
+            };
+            let set_fee = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.set_usd_fee(Some(fee)));
+            client
+                .call(&ink_e2e::alice(), set_fee, 0, None)
+                .await
+                .expect("set_usd_fee failed");
+
+            // Then the quote is 2_000 and paying less is refused
+            let quote = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.quote_flip_fee());
+            let quote_result = client.call_dry_run(&ink_e2e::bob(), &quote, 0, None).await;
+            assert_eq!(quote_result.return_value(), Ok(2_000));
+            let flip = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flip());
+            let flip_result = client
+                .call_dry_run(&ink_e2e::bob(), &flip, 1_999, None)
+                .await;
+            assert_eq!(flip_result.return_value(), Err(FlipError::InsufficientFee));
+
+            // When the price ages past `max_age`
+            for _ in 0..3 {
+                let get = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                    .call(|unit_test_bug| unit_test_bug.get());
+                client
+                    .call(&ink_e2e::alice(), get, 0, None)
+                    .await
+                    .expect("get failed");
+            }
+
+            // Then flips are refused whatever is paid
+            let flip = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flip());
+            let flip_result = client
+                .call_dry_run(&ink_e2e::bob(), &flip, 10_000, None)
+                .await;
+            assert_eq!(flip_result.return_value(), Err(FlipError::StalePrice));
+
+            Ok(())
+        }
+
+        /// We test that callees running past `call_ref_time_limit` fail the call.
+        #[ink_e2e::test(
+            additional_contracts = "mocks/oracle/Cargo.toml mocks/borrower/Cargo.toml",
+            environment = crate::CustomEnvironment
+        )]
+        async fn cross_contract_calls_respect_ref_time_limit(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            // Given an oracle and a borrower called with almost no ref_time
+            let config = Config {
+                call_ref_time_limit: 1,
+                ..Config::default()
+            };
+            let constructor = UnitTestBugRef::with_config(false, config);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let oracle_account_id = client
+                .instantiate(
+                    "oracle_mock",
+                    &ink_e2e::alice(),
+                    oracle_mock::OracleMockRef::new(),
+                    0,
+                    None,
+                )
+                .await
+                .expect("instantiate oracle failed")
+                .account_id;
+            let set_price = build_message::<oracle_mock::OracleMockRef>(oracle_account_id.clone())
+                .call(|oracle| oracle.set_price(1_000));
+            client
+                .call(&ink_e2e::alice(), set_price, 0, None)
+                .await
+                .expect("set_price failed");
+            let borrower_account_id = client
+                .instantiate(
+                    "borrower_mock",
+                    &ink_e2e::alice(),
+                    borrower_mock::BorrowerMockRef::new(true),
+                    0,
+                    None,
+                )
+                .await
+                .expect("instantiate borrower failed")
+                .account_id;
+            let fee = UsdFee {
+                oracle: oracle_account_id.clone(),
+                usd_cents: 200,
+                max_age: 100,
+            };
+            let set_fee = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.set_usd_fee(Some(fee)));
+            client
+                .call(&ink_e2e::alice(), set_fee, 0, None)
+                .await
+                .expect("set_usd_fee failed");
+
+            // Then both run out of ref_time and map to their call errors
+            let quote = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.quote_flip_fee());
+            let quote_result = client.call_dry_run(&ink_e2e::bob(), &quote, 0, None).await;
+            assert_eq!(
+                quote_result.return_value(),
+                Err(FlipError::OracleUnavailable(Some(UpstreamError::CallFailed)))
+            );
+            let flash = build_message::<UnitTestBugRef>(contract_account_id.clone()).call(
+                |unit_test_bug| unit_test_bug.flash_flip(borrower_account_id.clone(), Vec::new()),
+            );
+            let flash_result = client.call_dry_run(&ink_e2e::bob(), &flash, 0, None).await;
+            assert_eq!(
+                flash_result.return_value(),
+                Err(FlipError::FlashCallFailed(Some(UpstreamError::CallFailed)))
+            );
+
+            // When the owner raises the limit past what the callees need
+            let raised = Config {
+                call_ref_time_limit: 10_000_000_000,
+                ..config
+            };
+            let propose = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.propose_config(raised));
+            client
+                .call(&ink_e2e::alice(), propose, 0, None)
+                .await
+                .expect("propose_config failed");
+            advance_blocks(&mut client, config.timelock_delay).await;
+            let apply = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.apply_config());
+            client
+                .call(&ink_e2e::alice(), apply, 0, None)
+                .await
+                .expect("apply_config failed");
+
+            // Then both calls go through
+            let quote_result = client.call_dry_run(&ink_e2e::bob(), &quote, 0, None).await;
+            assert_eq!(quote_result.return_value(), Ok(2_000));
+            let flash_result = client.call_dry_run(&ink_e2e::bob(), &flash, 0, None).await;
+            assert_eq!(flash_result.return_value(), Ok(()));
+
+            Ok(())
+        }
+
+        /// We test that a blob at the size limit round-trips through a node.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn blob_at_limit_round_trips(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Given
+            let config = Config {
+                max_blob_size: MAX_BLOB_SIZE_LIMIT,
+                ..Config::default()
+            };
+            let constructor = UnitTestBugRef::with_config(false, config);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let blob = vec![0xa5; MAX_BLOB_SIZE_LIMIT as usize];
+
+            // When
+            let store = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.store_blob(7, blob.clone()));
+            client
+                .call(&ink_e2e::alice(), store, 0, None)
+                .await
+                .expect("store_blob failed");
+
+            // Then
+            let read = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.read_blob(7));
+            let read_result = client.call_dry_run(&ink_e2e::alice(), &read, 0, None).await;
+            assert_eq!(read_result.return_value(), Some(blob));
+
+            let oversize = vec![0; MAX_BLOB_SIZE_LIMIT as usize + 1];
+            let store = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.store_blob(8, oversize.clone()));
+            let store_result = client
+                .call_dry_run(&ink_e2e::alice(), &store, 0, None)
+                .await;
+            assert_eq!(store_result.return_value(), Err(FlipError::BlobTooLarge));
+
+            Ok(())
+        }
+
+        /// We test that rapid calls are limited within a block but not across blocks.
+        #[ink_e2e::test(
+            additional_contracts = "mocks/forwarder/Cargo.toml",
+            environment = crate::CustomEnvironment
+        )]
+        async fn rate_limit_on_chain(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Given
+            let config = Config {
+                max_calls_per_block: 2,
+                ..Config::default()
+            };
+            let constructor = UnitTestBugRef::with_config(false, config);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let forwarder_constructor = forwarder_mock::ForwarderMockRef::new();
+            let forwarder_account_id = client
+                .instantiate(
+                    "forwarder_mock",
+                    &ink_e2e::alice(),
+                    forwarder_constructor,
+                    0,
+                    None,
+                )
+                .await
+                .expect("instantiate forwarder failed")
+                .account_id;
+            let set_forwarder = build_message::<UnitTestBugRef>(contract_account_id.clone()).call(
+                |unit_test_bug| {
+                    unit_test_bug.set_trusted_forwarder(Some(forwarder_account_id.clone()))
+                },
+            );
+            client
+                .call(&ink_e2e::alice(), set_forwarder, 0, None)
+                .await
+                .expect("set_trusted_forwarder failed");
+
+            // When bob flips in rapid succession, each call lands in its own block
+            for _ in 0..3 {
+                let flip = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                    .call(|unit_test_bug| unit_test_bug.flip());
+                client
+                    .call(&ink_e2e::bob(), flip, 0, None)
+                    .await
+                    .expect("flip failed");
+            }
+
+            // Then three flips for bob within one block hit the limit
+            let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            for (count, expected) in [
+                (2, Ok(())),
+                (
+                    3,
+                    Err(forwarder_mock::ForwarderError::Rejected(
+                        FlipError::RateLimited as u8,
+                    )),
+                ),
+            ] {
+                let relay =
+                    build_message::<forwarder_mock::ForwarderMockRef>(forwarder_account_id.clone())
+                        .call(|forwarder| {
+                            forwarder.forward_flips(contract_account_id.clone(), bob.clone(), count)
+                        });
+                let relay_result = client
+                    .call_dry_run(&ink_e2e::charlie(), &relay, 0, None)
+                    .await;
+                assert_eq!(relay_result.return_value(), expected);
+            }
+
+            Ok(())
+        }
+
+        /// We test that the failure count only persists for calls that do not revert.
+        ///
+        /// No message reverts after counting a failure, so the unit test of the
+        /// same name covers a count rolled back by a reverting caller.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn breaker_count_rolls_back_with_reverted_calls(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            // Given
+            let config = Config {
+                breaker_threshold: 2,
+                ..Config::default()
+            };
+            let constructor = UnitTestBugRef::with_config(false, config);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let failures = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.privileged_failures());
+
+            // When reverted calls fail before reaching an operation
+            let run = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.run_privileged(PrivilegedOp::Claim));
+            assert!(client.call(&ink_e2e::bob(), run, 0, None).await.is_err());
+            let claim = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.claim());
+            assert!(client
+                .call(&ink_e2e::alice(), claim, 0, None)
+                .await
+                .is_err());
+
+            // Then nothing was counted
+            let failures_result = client
+                .call_dry_run(&ink_e2e::alice(), &failures, 0, None)
+                .await;
+            assert_eq!(failures_result.return_value(), 0);
+
+            // When failures come back inside `Ok`, they persist and trip the breaker
+            for expected in [1, 2] {
+                let run = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                    .call(|unit_test_bug| unit_test_bug.run_privileged(PrivilegedOp::Claim));
+                let run_result = client
+                    .call(&ink_e2e::alice(), run, 0, None)
+                    .await
+                    .expect("run_privileged failed");
+                assert_eq!(
+                    run_result.return_value(),
+                    Ok(Err(FlipError::NothingToClaim))
+                );
+                let failures_result = client
+                    .call_dry_run(&ink_e2e::alice(), &failures, 0, None)
+                    .await;
+                assert_eq!(failures_result.return_value(), expected);
+            }
+            let tripped = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.breaker_tripped());
+            let tripped_result = client
+                .call_dry_run(&ink_e2e::alice(), &tripped, 0, None)
+                .await;
+            assert!(tripped_result.return_value());
+
+            Ok(())
+        }
+
+        /// We test that only calls that take effect consume event sequence numbers.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn event_seq_skips_reverted_calls(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            // Given
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let next_seq = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.next_event_seq());
+            let flip = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flip());
+            client
+                .call(&ink_e2e::bob(), flip, 0, None)
+                .await
+                .expect("flip failed");
+
+            // When a batch flips for bob, then fails on eve, who approved nobody
+            let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let eve = ink_e2e::account_id(ink_e2e::AccountKeyring::Eve);
+            let batch = build_message::<UnitTestBugRef>(contract_account_id.clone()).call(
+                |unit_test_bug| {
+                    unit_test_bug.batch_flip_for(vec![bob, eve], BatchMode::AllOrNothing)
+                },
+            );
+            assert!(client.call(&ink_e2e::bob(), batch, 0, None).await.is_err());
+
+            // Then its flip took no sequence number
+            assert_eq!(
+                client
+                    .call_dry_run(&ink_e2e::bob(), &next_seq, 0, None)
+                    .await
+                    .return_value(),
+                1
+            );
+            let flip = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flip());
+            client
+                .call(&ink_e2e::bob(), flip, 0, None)
+                .await
+                .expect("flip failed");
+            assert_eq!(
+                client
+                    .call_dry_run(&ink_e2e::bob(), &next_seq, 0, None)
+                    .await
+                    .return_value(),
+                2
+            );
+
+            Ok(())
+        }
+
+        /// We test that the receipt a dry run previews is what the flip then does.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn dry_run_receipt_previews_flip(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Given
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let flip = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flip());
+            let preview = client
+                .call_dry_run(&ink_e2e::bob(), &flip, 0, None)
+                .await
+                .return_value()
+                .expect("flip previews");
+            assert!(preview.gas_hint > 0);
+
+            // When
+            let flip_result = client
+                .call(&ink_e2e::bob(), flip, 0, None)
+                .await
+                .expect("flip failed");
+
+            // Then the event it emitted matches the preview
+            let emitted: Vec<shared_types::FlippedV2> = flip_result
+                .events
+                .iter()
+                .filter_map(Result::ok)
+                .filter(|event| {
+                    event.pallet_name() == "Contracts" && event.variant_name() == "ContractEmitted"
+                })
+                .filter_map(|event| {
+                    let mut fields = event.field_bytes();
+                    let (emitter, data) =
+                        <(AccountId, Vec<u8>) as scale::Decode>::decode(&mut fields).ok()?;
+                    match data.split_first() {
+                        Some((&shared_types::events::FLIPPED_V2_INDEX, mut data))
+                            if emitter == contract_account_id =>
+                        {
+                            <shared_types::FlippedV2 as scale::Decode>::decode(&mut data).ok()
+                        }
+                        _ => None,
+                    }
+                })
+                .collect();
+            assert_eq!(emitted.len(), 1);
+            assert_eq!(emitted[0].seq, preview.seq);
+            assert_eq!(emitted[0].value, preview.new_value);
+            assert_eq!(flip_result.return_value(), Ok(preview));
+
+            Ok(())
+        }
+
+        /// We test that a resubmitted idempotency key returns the first receipt
+        /// in a later block without flipping again.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn idempotency_key_replays_on_chain(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            // Given
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let submit = build_message::<UnitTestBugRef>(contract_account_id.clone()).call(
+                |unit_test_bug| {
+                    unit_test_bug.execute_idempotent(ForwardedCall::Flip, Some([7; 16]))
+                },
+            );
+            let first = client
+                .call(&ink_e2e::bob(), submit.clone(), 0, None)
+                .await
+                .expect("first submission failed")
+                .return_value()
+                .expect("first submission flips");
+
+            // When the wallet retries in the next block
+            let retry = client
+                .call(&ink_e2e::bob(), submit, 0, None)
+                .await
+                .expect("retry failed");
+
+            // Then
+            assert_eq!(retry.return_value(), Ok(first));
+            let get = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.get());
+            assert!(client
+                .call_dry_run(&ink_e2e::bob(), &get, 0, None)
+                .await
+                .return_value());
+
+            Ok(())
+        }
+
+        /// We test that a batch with a failing operation leaves no trace on-chain.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn multicall_is_atomic(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Given
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+
+            // When the last operation fails
+            let failing = vec![
+                AdminOp::SetGuardian(Some(bob.clone())),
+                AdminOp::SetLabel(String::from("partial")),
+                AdminOp::SetLabel("x".repeat(MAX_LABEL_LEN + 1)),
+            ];
+            let multicall = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.multicall(failing.clone()));
+            assert!(client
+                .call(&ink_e2e::alice(), multicall, 0, None)
+                .await
+                .is_err());
+
+            // Then nothing changed
+            let label = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.label());
+            let guardian = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.guardian());
+            let label_result = client
+                .call_dry_run(&ink_e2e::alice(), &label, 0, None)
+                .await;
+            assert_eq!(label_result.return_value(), "");
+            let guardian_result = client
+                .call_dry_run(&ink_e2e::alice(), &guardian, 0, None)
+                .await;
+            assert_eq!(guardian_result.return_value(), None);
+
+            // When every operation succeeds, all of them apply
+            let ops = vec![
+                AdminOp::SetGuardian(Some(bob.clone())),
+                AdminOp::SetLabel(String::from("applied")),
+            ];
+            let multicall = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.multicall(ops.clone()));
+            client
+                .call(&ink_e2e::alice(), multicall, 0, None)
+                .await
+                .expect("multicall failed");
+            let label_result = client
+                .call_dry_run(&ink_e2e::alice(), &label, 0, None)
+                .await;
+            assert_eq!(label_result.return_value(), "applied");
+            let guardian_result = client
+                .call_dry_run(&ink_e2e::alice(), &guardian, 0, None)
+                .await;
+            assert_eq!(guardian_result.return_value(), Some(bob));
+
+            Ok(())
+        }
+
+        /// We test that every checkpoint charges at least its estimated deposit
+        /// and that pruning refunds that much, but no more than was charged.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn history_deposits_are_charged_and_refunded(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            // Given
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let estimate = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.deposit_for_history_record());
+            let estimate = client
+                .call_dry_run(&ink_e2e::alice(), &estimate, 0, None)
+                .await
+                .return_value();
+
+            // When bob flips in three new blocks
+            let mut charged = 0;
+            for _ in 0..3 {
+                // Dry runs see the last sealed block, so without a new one the
+                // dry run would overwrite the previous checkpoint.
+                advance_blocks(&mut client, 1).await;
+                let flip = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                    .call(|unit_test_bug| unit_test_bug.flip());
+                let flip_result = client
+                    .call(&ink_e2e::bob(), flip, 0, None)
+                    .await
+                    .expect("flip failed");
+                let deposit = flip_result.dry_run.exec_result.storage_deposit.clone();
+
+                // Then each new checkpoint is paid for
+                let pallet_contracts_primitives::StorageDeposit::Charge(charge) = deposit else {
+                    panic!("expected a charge, got {deposit:?}");
+                };
+                assert!(charge >= estimate, "charged {charge}, expected at least {estimate}");
+                charged += charge;
+            }
+
+            // When the owner prunes everything but the latest checkpoint
+            let prune = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.prune_history(BlockNumber::MAX));
+            let prune_result = client
+                .call(&ink_e2e::alice(), prune, 0, None)
+                .await
+                .expect("prune_history failed");
+
+            // Then the removed checkpoints' deposits flow back to the caller
+            let deposit = prune_result.dry_run.exec_result.storage_deposit.clone();
+            let pruned = prune_result.return_value().expect("prune_history failed");
+            assert!(pruned >= 3);
+            let pallet_contracts_primitives::StorageDeposit::Refund(refund) = deposit else {
+                panic!("expected a refund, got {deposit:?}");
+            };
+            assert!(refund >= estimate * pruned as Balance);
+            assert!(refund <= charged, "refunded {refund} of {charged} charged");
+
+            Ok(())
+        }
+
+        /// We test that an unbounded history limit returns a page that fits
+        /// instead of trapping.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn unbounded_history_limit_does_not_trap(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            // Given
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+
+            // When
+            let history_page = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.history_page(0, u32::MAX));
+            let dry_run = client
+                .call_dry_run(&ink_e2e::alice(), &history_page, 0, None)
+                .await;
+
+            // Then
+            assert!(!dry_run.exec_return_value().did_revert());
+            assert_eq!(dry_run.return_value().map(|page| page.len()), Ok(1));
+
+            Ok(())
+        }
+
+        /// We test that a failing batch is rolled back on chain.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn multicall_reverts_on_chain(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Given
+            let constructor = UnitTestBugRef::new(false);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let multicall = build_message::<UnitTestBugRef>(contract_account_id.clone()).call(
+                |unit_test_bug| {
+                    unit_test_bug.multicall(vec![
+                        AdminOp::SetLabel(String::from("kept?")),
+                        AdminOp::SetLabel("x".repeat(MAX_LABEL_LEN + 1)),
+                    ])
+                },
+            );
+            let label = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.label());
+
+            // When submitted, then it fails and the label is untouched
+            crate::assert_revert!(
+                {
+                    let dry_run = client
+                        .call_dry_run(&ink_e2e::alice(), &multicall, 0, None)
+                        .await;
+                    client
+                        .call(&ink_e2e::alice(), multicall, 0, None)
+                        .await
+                        .expect_err("reverted call is not included");
+                    dry_run
+                },
+                FlipError::LabelTooLong,
+                client
+                    .call_dry_run(&ink_e2e::alice(), &label, 0, None)
+                    .await
+                    .return_value(),
+            );
+
+            Ok(())
+        }
+
+        /// We test that a subscription lifts the flip fee only until it expires.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn subscription_expires_across_blocks(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            // Given
+            let config = Config {
+                flip_fee: 1_000,
+                subscription_price: 1_000,
+                ..Config::default()
+            };
+            let constructor = UnitTestBugRef::with_config(false, config);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+
+            // When
+            let subscribe = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.subscribe());
+            client
+                .call(&ink_e2e::bob(), subscribe, 2_000, None)
+                .await
+                .expect("subscribe failed");
+
+            // Then the next block is still covered
+            let flip = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flip());
+            client
+                .call(&ink_e2e::bob(), flip, 0, None)
+                .await
+                .expect("subscribed flip failed");
+
+            // And once the two paid blocks have passed the fee applies again
+            advance_blocks(&mut client, 2).await;
+            let is_subscribed = build_message::<UnitTestBugRef>(contract_account_id.clone()).call(
+                |unit_test_bug| {
+                    unit_test_bug.is_subscribed(ink_e2e::account_id(ink_e2e::AccountKeyring::Bob))
+                },
+            );
+            let is_subscribed_result = client
+                .call_dry_run(&ink_e2e::bob(), &is_subscribed, 0, None)
+                .await;
+            assert!(!is_subscribed_result.return_value());
+            let flip = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.flip());
+            let flip_result = client.call_dry_run(&ink_e2e::bob(), &flip, 0, None).await;
+            assert_eq!(flip_result.return_value(), Err(FlipError::InsufficientFee));
+
+            Ok(())
+        }
+
+        /// Client the e2e test bodies receive.
+        type Client = ink_e2e::Client<ink_e2e::PolkadotConfig, crate::CustomEnvironment>;
+
+        /// Returns the dev account standing in for `actor`.
+        fn keypair(actor: Actor) -> ink_e2e::Keypair {
+            match actor {
+                Actor::Alice => ink_e2e::alice(),
+                Actor::Bob => ink_e2e::bob(),
+                Actor::Charlie => ink_e2e::charlie(),
+            }
+        }
+
+        /// Deposit limit of the calls the helpers make, from the network profile.
+        ///
+        /// `ink_e2e` always starts its own node, so only the limit of the profile
+        /// named by `CONTRACTS_NETWORK` applies here.
+        fn storage_deposit_limit() -> Option<Balance> {
+            shared_types::network::NetworkProfile::from_env()
+                .expect("CONTRACTS_NETWORK names a network")
+                .storage_deposit_limit()
+        }
+
+        /// Produces `count` blocks on the dev node.
+        ///
+        /// The node seals a block for every extrinsic, so this submits `count`
+        /// empty `System::remark`s from alice.
+        async fn advance_blocks(client: &mut Client, count: u32) {
+            for _ in 0..count {
+                client
+                    .runtime_call(
+                        &ink_e2e::alice(),
+                        "System",
+                        "remark",
+                        vec![ink_e2e::subxt::dynamic::Value::from_bytes(Vec::new())],
+                    )
+                    .await
+                    .expect("remark failed");
+            }
+        }
+
+        /// Transfers `amount` from alice to `account`.
+        ///
+        /// Returns once the transfer is in a block, which the dev node finalizes
+        /// as it seals it.
+        async fn fund(client: &mut Client, account: AccountId, amount: Balance) {
+            client
+                .runtime_call(
+                    &ink_e2e::alice(),
+                    "Balances",
+                    "transfer_keep_alive",
+                    vec![
+                        ink_e2e::subxt::dynamic::Value::unnamed_variant(
+                            "Id",
+                            [ink_e2e::subxt::dynamic::Value::from_bytes(account)],
+                        ),
+                        ink_e2e::subxt::dynamic::Value::u128(amount),
+                    ],
+                )
+                .await
+                .expect("transfer failed");
+        }
+
+        /// An account apart from the dev ones, for tests with more actors.
+        ///
+        /// It holds nothing until [`fund`]ed.
+        fn signer(seed: u8) -> (ink_e2e::Keypair, AccountId) {
+            let keypair = ink_e2e::Keypair::from_seed([seed; 32]).expect("seed is valid");
+            let account = AccountId::from(keypair.public_key().0);
+            (keypair, account)
+        }
+
+        /// Runs a scenario against a fresh instance deployed by alice.
+        ///
+        /// With `E2E_RECORD_DIR` set, the session is also recorded there as a
+        /// vector file named after the scenario, for `tests::replay`.
+        ///
+        /// Each call is dry-run first and only submitted if it succeeds, so a
+        /// failing call leaves the chain as the pallet's revert leaves it.
+        async fn run_on_chain(client: &mut Client, scenario: &Scenario) -> E2EResult<Outcome> {
+            let constructor = UnitTestBugRef::new(scenario.init_value);
+            let contract_account_id = client
+                .instantiate("unit_test_bug", &ink_e2e::alice(), constructor, 0, storage_deposit_limit())
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            // A macro, since messages returning different types cannot share
+            // a function: `ink_e2e` does not export the type of a message.
+            macro_rules! submit_if_ok {
+                ($signer:expr, $message:expr) => {{
+                    let message = $message;
+                    let result = client
+                        .call_dry_run($signer, &message, 0, storage_deposit_limit())
+                        .await
+                        .return_value()
+                        .map(drop);
+                    if result.is_ok() {
+                        client
+                            .call($signer, message, 0, storage_deposit_limit())
+                            .await
+                            .expect("call failed");
+                    }
+                    result
+                }};
+            }
+            let mut returns = Vec::new();
+            for (caller, call) in &scenario.calls {
+                let signer = keypair(*caller);
+                let builder = build_message::<UnitTestBugRef>(contract_account_id.clone());
+                let result = match call {
+                    Call::Flip => {
+                        submit_if_ok!(&signer, builder.call(|unit_test_bug| unit_test_bug.flip()))
+                    }
+                    Call::FlipWithError => submit_if_ok!(
+                        &signer,
+                        builder.call(|unit_test_bug| unit_test_bug.flip_with_error())
+                    ),
+                    Call::SetLabel(label) => submit_if_ok!(
+                        &signer,
+                        builder.call(|unit_test_bug| unit_test_bug.set_label(label.clone()))
+                    ),
+                    Call::Multicall(ops) => submit_if_ok!(
+                        &signer,
+                        builder.call(|unit_test_bug| unit_test_bug.multicall(ops.clone()))
+                    ),
+                };
+                returns.push(result);
+            }
+            let get = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.get());
+            let label = build_message::<UnitTestBugRef>(contract_account_id.clone())
+                .call(|unit_test_bug| unit_test_bug.label());
+            let outcome = Outcome {
+                returns,
+                value: client
+                    .call_dry_run(&ink_e2e::alice(), &get, 0, storage_deposit_limit())
+                    .await
+                    .return_value(),
+                label: client
+                    .call_dry_run(&ink_e2e::alice(), &label, 0, storage_deposit_limit())
+                    .await
+                    .return_value(),
+                events: None,
+                storage: None,
+            };
+            if let Some(dir) = std::env::var_os("E2E_RECORD_DIR") {
+                let path = std::path::Path::new(&dir).join(format!("{}.json", scenario.name));
+                save_vectors(&path, &[TestVector::recorded(scenario, &outcome)]);
+            }
+            Ok(outcome)
+        }
+
+        /// Scenarios and fields where emulated off-chain reverts are known to
+        /// miss what the chain rolls back.
+        const KNOWN_ON_CHAIN_FINDINGS: &[(&str, &str)] = &[];
+
+        /// We test that the chain agrees with the off-chain environment except where known.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn differential_against_off_chain(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            // Given
+            let mut findings = Vec::new();
+
+            // When
+            for scenario in scenarios() {
+                let off_chain = run_off_chain(&scenario, Backend::OffChainReverting);
+                let on_chain = run_on_chain(&mut client, &scenario).await?;
+                scenario.verify(Backend::E2e, &on_chain);
+                findings.extend(diff(
+                    &scenario.name,
+                    (Backend::OffChainReverting, &off_chain),
+                    (Backend::E2e, &on_chain),
+                ));
+            }
+
+            // Then
+            let found: Vec<_> = findings
+                .iter()
+                .map(|f| (f.scenario.as_str(), f.field))
+                .collect();
+            let report: Vec<_> = findings.iter().map(ToString::to_string).collect();
+            assert_eq!(found, KNOWN_ON_CHAIN_FINDINGS, "findings:\n{}", report.join("\n"));
+
+            Ok(())
+        }
+
+        /// We test that every JSON test vector holds on-chain.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn json_vectors_on_chain(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            for vector in super::tests::test_vectors() {
+                let scenario = vector.scenario();
+                let outcome = run_on_chain(&mut client, &scenario).await?;
+                scenario.verify(Backend::E2e, &outcome);
+            }
+
+            Ok(())
+        }
+
+        /// We test the label scenario the unit tests also run.
+        #[ink_e2e::test(environment = crate::CustomEnvironment)]
+        async fn label_is_owner_only_scenario(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            let scenario = super::tests::label_is_owner_only();
+            let outcome = run_on_chain(&mut client, &scenario).await?;
+            scenario.verify(Backend::E2e, &outcome);
+
+            Ok(())
+        }
+    }
+
+    /// Guards the size of the optimized release code.
+    ///
+    /// Builds the contract as `cargo contract build --release` does, so it is
+    /// slow and only runs with `cargo test --features size-check`.
+    #[cfg(all(test, feature = "size-check"))]
+    mod size_tests {
+        use contract_build::{
+            BuildArtifacts, BuildMode, ExecuteArgs, ManifestPath, OptimizationPasses, Verbosity,
+        };
+
+        /// Largest optimized code size, in bytes, of the default build.
+        ///
+        /// The code was about 80 KB when this was set. Raise it deliberately
+        /// when a change is worth the extra bytes.
+        const CODE_SIZE_BUDGET: u64 = 88_000;
+
+        /// Builds the optimized release code with `features` and returns it.
+        fn release_code(features: &[&str]) -> Vec<u8> {
+            let manifest_path =
+                ManifestPath::new(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"))
+                    .expect("manifest path is valid");
+            let mut args = ExecuteArgs {
+                manifest_path,
+                verbosity: Verbosity::Quiet,
+                build_mode: BuildMode::Release,
+                build_artifact: BuildArtifacts::CodeOnly,
+                optimization_passes: Some(OptimizationPasses::default()),
+                ..ExecuteArgs::default()
+            };
+            for feature in features {
+                args.features.push(feature);
+            }
+            let result = contract_build::execute(args).expect("contract builds");
+            let code = result.dest_wasm.expect("code is generated");
+            std::fs::read(code).expect("code is readable")
+        }
+
+        #[test]
+        fn release_code_fits_budget() {
+            let size = release_code(&[]).len() as u64;
+            assert!(
+                size <= CODE_SIZE_BUDGET,
+                "optimized code is {size} bytes, {} over the {CODE_SIZE_BUDGET} byte budget",
+                size - CODE_SIZE_BUDGET
+            );
+        }
+
+        /// Call context is only written, and so only compiled in, with `debug`.
+        #[test]
+        fn call_context_only_in_debug_code() {
+            const CONTEXT: &[u8] = b": caller ";
+            let has_context =
+                |code: &[u8]| code.windows(CONTEXT.len()).any(|window| window == CONTEXT);
+            let release = release_code(&[]);
+            let debug = release_code(&["debug"]);
+            assert!(!has_context(&release));
+            assert!(has_context(&debug));
+            assert!(debug.len() > release.len());
+        }
+    }
+}
+
+/// Hex and decimal rendering of accounts, hashes and balances for the debug
+/// buffer and labels.
+///
+/// Writes into fixed buffers instead of going through `core::fmt`, whose
+/// machinery would cost more code than the contract logic using it.
+pub mod fmt {
+    const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+    /// Most bytes [`hex`] renders, enough for an `AccountId` or `Hash`.
+    pub const MAX_HEX_BYTES: usize = 32;
+
+    /// `0x`-prefixed lowercase hex digits, see [`hex`].
+    #[derive(Debug, Clone, Copy)]
+    pub struct Hex {
+        digits: [u8; 2 + 2 * MAX_HEX_BYTES],
+        len: usize,
+    }
+
+    impl Hex {
+        pub fn as_str(&self) -> &str {
+            core::str::from_utf8(&self.digits[..self.len]).expect("hex digits are ASCII")
+        }
+    }
+
+    /// Renders `bytes` as `0x`-prefixed lowercase hex.
+    ///
+    /// Panics for more than [`MAX_HEX_BYTES`] bytes.
+    ///
+    /// ```
+    /// # use unit_test_bug::fmt::hex;
+    /// assert_eq!(hex(&[0x00, 0xab]).as_str(), "0x00ab");
+    /// ```
+    pub fn hex(bytes: &[u8]) -> Hex {
+        assert!(bytes.len() <= MAX_HEX_BYTES, "too many bytes to render");
+        let mut digits = [0u8; 2 + 2 * MAX_HEX_BYTES];
+        digits[..2].copy_from_slice(b"0x");
+        for (pair, byte) in digits[2..].chunks_exact_mut(2).zip(bytes) {
+            pair[0] = HEX_DIGITS[usize::from(byte >> 4)];
+            pair[1] = HEX_DIGITS[usize::from(byte & 0x0f)];
+        }
+        Hex {
+            digits,
+            len: 2 + 2 * bytes.len(),
+        }
+    }
+
+    /// Decimal digits of a `u128`, see [`decimal`].
+    #[derive(Debug, Clone, Copy)]
+    pub struct Decimal {
+        /// Right-aligned, as many as `u128::MAX` has.
+        digits: [u8; 39],
+        start: usize,
+    }
+
+    impl Decimal {
+        pub fn as_str(&self) -> &str {
+            core::str::from_utf8(&self.digits[self.start..]).expect("decimal digits are ASCII")
+        }
+    }
+
+    /// Renders `value` in decimal, without separators.
+    ///
+    /// ```
+    /// # use unit_test_bug::fmt::decimal;
+    /// assert_eq!(decimal(1_000).as_str(), "1000");
+    /// ```
+    pub fn decimal(mut value: u128) -> Decimal {
+        let mut digits = [0u8; 39];
+        let mut start = digits.len();
+        loop {
+            start -= 1;
+            digits[start] = b'0' + (value % 10) as u8;
+            value /= 10;
+            if value == 0 {
+                break;
+            }
+        }
+        Decimal { digits, start }
+    }
+}
+
+/// Off-chain helpers for exercising the contract, used by the doc examples
+/// and the unit tests, and available to tests of contracts built on this one.
+#[cfg(feature = "std")]
+pub mod test_support {
+    use ink::{
+        env::{hash::Blake2x256, test, DefaultEnvironment, Environment},
+        primitives::{AccountId, Hash},
+    };
+
+    use crate::{Config, FlipError, FlipResult, RandomReadErr, Randomness, UnitTestBug};
+
+    type Balance = <DefaultEnvironment as Environment>::Balance;
+
+    /// Resets the off-chain environment and deploys a contract as alice.
+    ///
+    /// The contract shares alice's account, and so her balance.
+    pub fn deploy(init_value: bool) -> UnitTestBug {
+        deploy_with_config(init_value, Config::default())
+    }
+
+    /// Like [`deploy`], starting from `config` instead of the defaults.
+    pub fn deploy_with_config(init_value: bool, config: Config) -> UnitTestBug {
+        let mut contract = None;
+        test::run_test::<DefaultEnvironment, _>(|_| {
+            contract = Some(UnitTestBug::with_config(init_value, config));
+            Ok(())
+        })
+        .expect("off-chain environment resets");
+        contract.expect("contract is deployed")
+    }
+
+    pub fn accounts() -> test::DefaultAccounts<DefaultEnvironment> {
+        test::default_accounts::<DefaultEnvironment>()
+    }
+
+    pub fn advance_blocks(count: u32) {
+        for _ in 0..count {
+            test::advance_block::<DefaultEnvironment>();
+        }
+    }
+
+    pub fn set_caller(caller: AccountId) {
+        test::set_caller::<DefaultEnvironment>(caller);
+    }
+
+    pub fn set_value_transferred(value: Balance) {
+        test::set_value_transferred::<DefaultEnvironment>(value);
+    }
+
+    pub fn balance_of(account: AccountId) -> Balance {
+        test::get_account_balance::<DefaultEnvironment>(account).expect("account has a balance")
+    }
+
+    pub fn set_balance(account: AccountId, balance: Balance) {
+        test::set_account_balance::<DefaultEnvironment>(account, balance);
+    }
+
+    /// Moves `value` from `caller` into the contract and sets it as transferred.
+    ///
+    /// The off-chain environment does not move funds for payable calls itself.
+    pub fn pay_as(caller: AccountId, value: Balance) {
+        let contract = test::callee::<DefaultEnvironment>();
+        set_balance(caller, balance_of(caller) - value);
+        set_balance(contract, balance_of(contract) + value);
+        set_caller(caller);
+        set_value_transferred(value);
+    }
+
+    /// Runs `call` and, like the contracts pallet, reverts it if it returns `Err`.
+    ///
+    /// The off-chain environment never reverts, so this restores the storage
+    /// and balances from a [`Snapshot`] taken before `call`. Events `call`
+    /// emitted stay recorded.
+    pub fn call_reverting<T, E>(
+        contract: &mut UnitTestBug,
+        call: impl FnOnce(&mut UnitTestBug) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let snapshot = Snapshot::take(contract);
+        let result = call(contract);
+        if result.is_err() {
+            snapshot.restore(contract);
+        }
+        result
+    }
+
+    std::thread_local! {
+        static RANDOMNESS: std::cell::RefCell<Option<Box<dyn Randomness>>> =
+            std::cell::RefCell::new(None);
+    }
+
+    /// Registers a mock of the `pallet-contracts` chain extension knowing
+    /// exactly `contracts`, given as account, code hash and deposit held.
+    pub fn set_contract_info(contracts: &[(AccountId, Hash, Balance)]) {
+        for func_id in [1105, 1106] {
+            test::register_chain_extension(MockedContracts {
+                func_id,
+                contracts: contracts.to_vec(),
+            });
+        }
+    }
+
+    /// Answers one `pallet-contracts` extension method from a fixed list.
+    struct MockedContracts {
+        func_id: u32,
+        contracts: Vec<(AccountId, Hash, Balance)>,
+    }
+
+    impl test::ChainExtension for MockedContracts {
+        fn func_id(&self) -> u32 {
+            self.func_id
+        }
+
+        fn call(&mut self, input: &[u8], output: &mut Vec<u8>) -> u32 {
+            let args = <Vec<u8> as scale::Decode>::decode(&mut &input[..])
+                .expect("input is length prefixed");
+            let account = <AccountId as scale::Decode>::decode(&mut &args[..])
+                .expect("input is an account");
+            let contract = self.contracts.iter().find(|(known, ..)| *known == account);
+            if self.func_id == 1105 {
+                scale::Encode::encode_to(&contract.map(|(_, code_hash, _)| *code_hash), output);
+            } else {
+                scale::Encode::encode_to(&contract.map(|(.., deposit)| *deposit), output);
+            }
+            0
+        }
+    }
+
+    /// Answers the contract's randomness requests from `source` instead of
+    /// the chain extension, until [`clear_randomness`].
+    ///
+    /// ```
+    /// # use unit_test_bug::{test_support::{accounts, advance_blocks, deploy, set_randomness}, DEFAULT_EPOCH_LENGTH};
+    /// let mut contract = deploy(false);
+    /// contract.flip().unwrap();
+    /// advance_blocks(DEFAULT_EPOCH_LENGTH);
+    /// set_randomness(|_subject| Ok([0; 32]));
+    /// assert_eq!(contract.draw(0), Ok((accounts().alice, 0)));
+    /// ```
+    pub fn set_randomness(source: impl Randomness + 'static) {
+        RANDOMNESS.with(|randomness| *randomness.borrow_mut() = Some(Box::new(source)));
+    }
+
+    /// Answers the contract's randomness requests with [`SeededRandomness`].
+    pub fn seed_randomness(seed: u64) {
+        set_randomness(SeededRandomness::new(seed));
+    }
+
+    /// Sends randomness requests to the chain extension again.
+    pub fn clear_randomness() {
+        RANDOMNESS.with(|randomness| *randomness.borrow_mut() = None);
+    }
+
+    /// Randomness from the installed source, `None` if there is none.
+    pub(crate) fn installed_random(subject: [u8; 32]) -> Option<Result<[u8; 32], RandomReadErr>> {
+        RANDOMNESS.with(|randomness| {
+            let mut randomness = randomness.borrow_mut();
+            randomness.as_mut().map(|source| source.random(subject))
+        })
+    }
+
+    /// Reproducible randomness: the same seed answers the same sequence of
+    /// requests with the same bytes.
+    #[derive(Debug, Clone)]
+    pub struct SeededRandomness {
+        seed: u64,
+        requests: u64,
+    }
+
+    impl SeededRandomness {
+        pub fn new(seed: u64) -> Self {
+            Self { seed, requests: 0 }
+        }
+    }
+
+    impl Randomness for SeededRandomness {
+        fn random(&mut self, subject: [u8; 32]) -> Result<[u8; 32], RandomReadErr> {
+            let mut output = [0u8; 32];
+            ink::env::hash_encoded::<Blake2x256, _>(
+                &(self.seed, self.requests, subject),
+                &mut output,
+            );
+            self.requests += 1;
+            Ok(output)
+        }
+    }
+
+    /// Storage reads and writes the contract performed.
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct OpCounts {
+        pub reads: usize,
+        pub writes: usize,
+    }
+
+    /// Runs `call` and returns its result with the storage operations it
+    /// performed on the contract under test.
+    ///
+    /// ```
+    /// # use unit_test_bug::test_support::{deploy, op_counts, OpCounts};
+    /// let contract = deploy(false);
+    /// let (value, ops) = op_counts(|| contract.get());
+    /// assert!(!value);
+    /// assert_eq!(ops, OpCounts { reads: 1, writes: 0 });
+    /// ```
+    pub fn op_counts<T>(call: impl FnOnce() -> T) -> (T, OpCounts) {
+        let contract = test::callee::<DefaultEnvironment>();
+        let (reads, writes) = test::get_contract_storage_rw::<DefaultEnvironment>(&contract);
+        let result = call();
+        let (reads_after, writes_after) =
+            test::get_contract_storage_rw::<DefaultEnvironment>(&contract);
+        let ops = OpCounts {
+            reads: reads_after - reads,
+            writes: writes_after - writes,
+        };
+        (result, ops)
+    }
+
+    /// Indexes below this, alone or paired with an account or another index,
+    /// are the `Mapping` keys a [`Snapshot`] covers.
+    pub const SNAPSHOT_INDEXES: u32 = 16;
+
+    /// A copy of a contract's state to branch a test from: take it, try one
+    /// path, restore it, try another.
+    ///
+    /// Covers the root storage fields, every `Lazy` cell, the `Mapping`
+    /// entries keyed by the contract, the default accounts and indexes below
+    /// [`SNAPSHOT_INDEXES`], and the balances of those accounts. The
+    /// off-chain environment cannot list a contract's storage, so restoring
+    /// panics if a cell outside of these was added or removed since
+    /// [`Snapshot::take`]. The block number is not rewound.
+    ///
+    /// ```
+    /// # use unit_test_bug::test_support::{deploy, Snapshot};
+    /// let mut contract = deploy(false);
+    /// let snapshot = Snapshot::take(&contract);
+    /// contract.flip().unwrap();
+    /// contract.set_label("path a".into()).unwrap();
+    /// snapshot.restore(&mut contract);
+    /// assert!(!contract.get());
+    /// assert_eq!(contract.checkpoint_count(), 1);
+    /// assert_eq!(contract.label(), "");
+    /// ```
+    #[derive(Clone, PartialEq, Eq)]
+    pub struct Snapshot {
+        root: Vec<u8>,
+        cells: Vec<(RawCell, Option<RawCell>)>,
+        used_cells: usize,
+        balances: Vec<(AccountId, Option<Balance>)>,
+    }
+
+    impl Snapshot {
+        pub fn take(contract: &UnitTestBug) -> Self {
+            let mut root = Vec::new();
+            ink::storage::traits::Storable::encode(contract, &mut root);
+            let cells = Self::cell_keys()
+                .into_iter()
+                .map(|key| {
+                    let value = ink::env::get_contract_storage(&key).expect("cell decodes");
+                    (key, value)
+                })
+                .collect();
+            let balances = Self::accounts()
+                .into_iter()
+                .map(|account| {
+                    let balance = test::get_account_balance::<DefaultEnvironment>(account);
+                    (account, balance.ok())
+                })
+                .collect();
+            Self {
+                root,
+                cells,
+                used_cells: Self::used_cells(),
+                balances,
+            }
+        }
+
+        pub fn restore(&self, contract: &mut UnitTestBug) {
+            *contract = ink::storage::traits::Storable::decode(&mut &self.root[..])
+                .expect("snapshot decodes");
+            for (key, value) in &self.cells {
+                match value {
+                    Some(value) => {
+                        ink::env::set_contract_storage(key, value);
+                    }
+                    None => {
+                        ink::env::clear_contract_storage(key);
+                    }
+                }
+            }
+            for &(account, balance) in &self.balances {
+                set_balance(account, balance.unwrap_or(0));
+            }
+            assert_eq!(
+                Self::used_cells(),
+                self.used_cells,
+                "storage cells outside the snapshot were added or removed"
+            );
+        }
+
+        fn accounts() -> [AccountId; 7] {
+            let accounts = accounts();
+            [
+                test::callee::<DefaultEnvironment>(),
+                accounts.alice,
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve,
+                accounts.frank,
+            ]
+        }
+
+        fn used_cells() -> usize {
+            let contract = test::callee::<DefaultEnvironment>();
+            test::count_used_storage_cells::<DefaultEnvironment>(&contract).unwrap_or(0)
+        }
+
+        /// The keys of the cells a snapshot covers, apart from the root.
+        fn cell_keys() -> Vec<RawCell> {
+            use ink::{metadata::layout::Layout, storage::traits::StorageLayout};
+            use scale::Encode;
+            use scale_info::TypeDef;
+
+            let Layout::Struct(layout) = <UnitTestBug as StorageLayout>::layout(&0) else {
+                panic!("the storage is a struct");
+            };
+            let TypeDef::Composite(fields) =
+                <UnitTestBug as scale_info::TypeInfo>::type_info().type_def
+            else {
+                panic!("the storage is a struct");
+            };
+            let mut keys = Vec::new();
+            for field in layout.fields() {
+                let Layout::Root(root) = field.layout() else {
+                    continue;
+                };
+                let ty = fields
+                    .fields
+                    .iter()
+                    .find(|ty| ty.name == Some(field.name()))
+                    .expect("every field has a type")
+                    .ty
+                    .type_info();
+                let suffixes = if ty.path.ident() == Some("Mapping") {
+                    let key = ty.type_params[0].ty.expect("`Mapping` has a key type");
+                    Self::key_candidates(&key.type_info())
+                } else {
+                    vec![Vec::new()]
+                };
+                let root = root.root_key().key().encode();
+                keys.extend(
+                    suffixes
+                        .into_iter()
+                        .map(|suffix| RawCell([&root[..], &suffix].concat())),
+                );
+            }
+            keys
+        }
+
+        /// The encoded `Mapping` keys of type `ty` a snapshot covers.
+        fn key_candidates(ty: &scale_info::Type) -> Vec<Vec<u8>> {
+            use scale::Encode;
+            use scale_info::{TypeDef, TypeDefPrimitive};
+
+            match &ty.type_def {
+                TypeDef::Primitive(TypeDefPrimitive::U32) => {
+                    (0..SNAPSHOT_INDEXES).map(|index| index.encode()).collect()
+                }
+                TypeDef::Tuple(tuple) => {
+                    let mut keys = vec![Vec::new()];
+                    for field in &tuple.fields {
+                        let candidates = Self::key_candidates(&field.type_info());
+                        keys = keys
+                            .iter()
+                            .flat_map(|key| {
+                                candidates
+                                    .iter()
+                                    .map(move |candidate| [&key[..], candidate].concat())
+                            })
+                            .collect();
+                    }
+                    keys
+                }
+                _ if ty.path.ident() == Some("AccountId") => {
+                    Self::accounts().iter().map(Encode::encode).collect()
+                }
+                _ => Vec::new(),
+            }
+        }
+    }
+
+    /// Shows the root, the cells present and the balances, all in hex.
+    impl core::fmt::Debug for Snapshot {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            f.debug_struct("Snapshot")
+                .field("root", &RawCell(self.root.clone()))
+                .field("cells", &PresentCells(&self.cells))
+                .field("balances", &self.balances)
+                .finish()
+        }
+    }
+
+    /// The cells of a snapshot that hold a value, as a map.
+    struct PresentCells<'a>(&'a [(RawCell, Option<RawCell>)]);
+
+    impl core::fmt::Debug for PresentCells<'_> {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            let cells = self
+                .0
+                .iter()
+                .filter_map(|(key, value)| Some((key, value.as_ref()?)));
+            f.debug_map().entries(cells).finish()
+        }
+    }
+
+    /// A storage key or value, encoded as its bytes without a length prefix.
+    #[derive(Clone, PartialEq, Eq)]
+    struct RawCell(Vec<u8>);
+
+    impl core::fmt::Debug for RawCell {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            f.write_str("0x")?;
+            self.0.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+        }
+    }
+
+    impl scale::Encode for RawCell {
+        fn size_hint(&self) -> usize {
+            self.0.len()
+        }
+
+        fn encode_to<T: scale::Output + ?Sized>(&self, dest: &mut T) {
+            dest.write(&self.0);
+        }
+    }
+
+    impl scale::Decode for RawCell {
+        fn decode<I: scale::Input>(input: &mut I) -> Result<Self, scale::Error> {
+            let mut bytes = vec![0; input.remaining_len()?.unwrap_or_default()];
+            input.read(&mut bytes)?;
+            Ok(Self(bytes))
+        }
+    }
+
+    /// Panics listing everything in the off-chain environment that differs
+    /// from how `#[ink::test]` sets it up.
+    ///
+    /// The environment is global to the thread, so whatever a test leaves
+    /// behind outside `test::run_test` leaks into the next one run there. Call
+    /// this first to catch that instead of chasing a confusing failure.
+    pub fn assert_pristine() {
+        let accounts = accounts();
+        let mut leaks = Vec::new();
+        let events = test::recorded_events().count();
+        if events > 0 {
+            leaks.push(format!("{events} recorded events"));
+        }
+        let debug_messages = test::recorded_debug_messages().into_iter().count();
+        if debug_messages > 0 {
+            leaks.push(format!("{debug_messages} debug messages"));
+        }
+        let caller = ink::env::caller::<DefaultEnvironment>();
+        if caller != accounts.alice {
+            leaks.push(format!("caller is {caller:?}, not alice"));
+        }
+        let callee = test::callee::<DefaultEnvironment>();
+        if callee != accounts.alice {
+            leaks.push(format!("callee is {callee:?}, not alice"));
+        }
+        let block = ink::env::block_number::<DefaultEnvironment>();
+        if block != 0 {
+            leaks.push(format!("block number is {block}"));
+        }
+        let value = ink::env::transferred_value::<DefaultEnvironment>();
+        if value != 0 {
+            leaks.push(format!("{value} transferred"));
+        }
+        // The balances `test::run_test` funds the default accounts with.
+        let funded = [
+            ("alice", accounts.alice, 1_000_000),
+            ("bob", accounts.bob, 1_000),
+            ("charlie", accounts.charlie, 1_000),
+            ("django", accounts.django, 0),
+            ("eve", accounts.eve, 0),
+            ("frank", accounts.frank, 0),
+        ];
+        for (name, account, expected) in funded {
+            let balance = test::get_account_balance::<DefaultEnvironment>(account).ok();
+            if balance != Some(expected) {
+                leaks.push(format!("{name} holds {balance:?}, not {expected}"));
+            }
+        }
+        if RANDOMNESS.with(|randomness| randomness.borrow().is_some()) {
+            leaks.push("a randomness source is installed".into());
+        }
+        let cells = test::count_used_storage_cells::<DefaultEnvironment>(&callee).unwrap_or(0);
+        if cells > 0 {
+            leaks.push(format!("{cells} storage cells used by the callee"));
+        }
+        assert!(
+            leaks.is_empty(),
+            "off-chain environment is not pristine:\n  {}",
+            leaks.join("\n  ")
+        );
+    }
+
+    /// The result of a call, as far as [`assert_revert!`](crate::assert_revert) cares.
+    ///
+    /// Implemented for message return values in unit tests and, in the e2e
+    /// tests, for `ink_e2e` dry runs.
+    pub trait Reverted {
+        /// The error the call failed with, `None` if it succeeded.
+        fn reverted_with(self) -> Option<FlipError>;
+    }
+
+    impl<T> Reverted for FlipResult<T> {
+        fn reverted_with(self) -> Option<FlipError> {
+            self.err()
+        }
+    }
+
+    impl<T> Reverted for ink::MessageResult<FlipResult<T>> {
+        fn reverted_with(self) -> Option<FlipError> {
+            self.expect("message dispatches").err()
+        }
+    }
+}
+
+/// Asserts that `call` failed with `error` and that `state` reads the same
+/// before and after it.
+///
+/// `call` is anything implementing [`test_support::Reverted`], so the same
+/// assertion reads alike in unit and e2e tests, and in sandbox tests once
+/// there is a sandbox backend. `state` is evaluated on both
+/// sides of `call` and may `.await`. Unit tests should go through
+/// [`call_reverting`](test_support::call_reverting), as the off-chain
+/// environment does not revert on its own. A [`Snapshot`](test_support::Snapshot)
+/// as `state` also covers the `Lazy` cells and `Mapping` entries no getter
+/// reads.
+///
+/// ```
+/// # use unit_test_bug::{assert_revert, test_support::{call_reverting, deploy, Snapshot}, FlipError, MAX_LABEL_LEN};
+/// let mut contract = deploy(false);
+/// let label = "x".repeat(MAX_LABEL_LEN + 1);
+/// assert_revert!(
+///     call_reverting(&mut contract, |contract| contract.set_label(label)),
+///     FlipError::LabelTooLong,
+///     Snapshot::take(&contract),
+/// );
+/// ```
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! assert_revert {
+    ($call:expr, $error:expr, $state:expr $(,)?) => {{
+        let before = $state;
+        let reverted = $crate::test_support::Reverted::reverted_with($call);
+        assert_eq!(reverted, Some($error), "call did not fail as expected");
+        assert_eq!($state, before, "failed call changed the state");
+    }};
 }
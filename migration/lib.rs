@@ -0,0 +1,294 @@
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+pub use self::storage_migration::{Layout, MigrationError, StorageMigration, StorageMigrationRef};
+
+/// Code a proxy delegates to for one call while upgrading `unit_test_bug`
+/// across a root storage layout change.
+///
+/// ink! decodes the root before running any message, so `unit_test_bug`
+/// cannot read a root in another layout itself and traps instead. This
+/// contract keeps the root as bytes, so `migrate` can try each known layout.
+#[ink::contract(env = shared_types::CustomEnvironment)]
+mod storage_migration {
+    use ink::{
+        prelude::vec::Vec,
+        storage::{
+            traits::{ManualKey, Storable},
+            Lazy,
+        },
+    };
+    use scale::DecodeAll;
+    use unit_test_bug::UnitTestBug;
+
+    /// Root storage in whatever layout it was written, stored verbatim.
+    #[derive(Debug, Default, PartialEq, Eq, Clone)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct RootStorage(Vec<u8>);
+
+    impl scale::Encode for RootStorage {
+        fn size_hint(&self) -> usize {
+            self.0.len()
+        }
+
+        fn encode_to<T: scale::Output + ?Sized>(&self, dest: &mut T) {
+            dest.write(&self.0);
+        }
+    }
+
+    impl scale::Decode for RootStorage {
+        fn decode<I: scale::Input>(input: &mut I) -> Result<Self, scale::Error> {
+            let mut bytes = ink::prelude::vec![0; input.remaining_len()?.unwrap_or_default()];
+            input.read(&mut bytes)?;
+            Ok(Self(bytes))
+        }
+    }
+
+    /// Root of `unit_test_bug` as first released.
+    #[derive(scale::Decode)]
+    struct LegacyRoot {
+        value: bool,
+    }
+
+    /// Layout `migrate` found the root in.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Layout {
+        /// `{ value: bool }`, now rewritten in the current layout.
+        Legacy,
+        /// The current layout, left as it was.
+        Current,
+    }
+
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum MigrationError {
+        /// The root decodes in none of the known layouts.
+        UnknownLayout,
+        /// Only the proxy's admin may migrate.
+        NotAdmin,
+    }
+
+    #[ink(storage)]
+    pub struct StorageMigration {
+        /// Must stay the only field encoded at the root.
+        root: RootStorage,
+        /// The admin of the proxy delegating here, read from the proxy's key.
+        admin: Lazy<AccountId, ManualKey<{ proxy_mock::ADMIN_KEY }>>,
+    }
+
+    impl StorageMigration {
+        /// Holds `root` as its own storage, administered by the caller as a
+        /// proxy is. Proxies delegate to the uploaded code instead of
+        /// instantiating it.
+        #[ink(constructor)]
+        pub fn new(root: Vec<u8>) -> Self {
+            let mut migration = Self {
+                root: RootStorage(root),
+                admin: Lazy::new(),
+            };
+            migration.admin.set(&Self::env().caller());
+            migration
+        }
+
+        /// Rewrites a legacy root in the current layout, keeping its value.
+        ///
+        /// A root already in the current layout is left alone, anything else
+        /// fails with `UnknownLayout`. The legacy layout has no owner, so only
+        /// the proxy's admin may migrate, and it becomes the owner. Anyone
+        /// else fails with `NotAdmin`, so the window between switching the
+        /// proxy here and migrating cannot be used to take the contract over.
+        #[ink(message)]
+        pub fn migrate(&mut self) -> Result<Layout, MigrationError> {
+            if Some(self.env().caller()) != self.admin.get() {
+                return Err(MigrationError::NotAdmin);
+            }
+            if is_current(&self.root.0) {
+                return Ok(Layout::Current);
+            }
+            let legacy = LegacyRoot::decode_all(&mut &self.root.0[..])
+                .map_err(|_| MigrationError::UnknownLayout)?;
+            let mut root = Vec::new();
+            UnitTestBug::new(legacy.value).encode(&mut root);
+            self.root = RootStorage(root);
+            Ok(Layout::Legacy)
+        }
+
+        /// Returns the root storage as stored.
+        #[ink(message)]
+        pub fn root(&self) -> Vec<u8> {
+            self.root.0.clone()
+        }
+    }
+
+    /// Whether `root` decodes, without leftover bytes, in the current layout.
+    fn is_current(root: &[u8]) -> bool {
+        let mut input = root;
+        <UnitTestBug as Storable>::decode(&mut input).is_ok() && input.is_empty()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn decode_root(root: &[u8]) -> UnitTestBug {
+            <UnitTestBug as Storable>::decode(&mut &root[..]).expect("current layout")
+        }
+
+        /// A legacy root becomes a current one with the same value, once.
+        #[ink::test]
+        fn legacy_root_is_rewritten() {
+            let alice = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>().alice;
+            let mut migration = StorageMigration::new(scale::Encode::encode(&true));
+            assert_eq!(migration.migrate(), Ok(Layout::Legacy));
+
+            let contract = decode_root(&migration.root());
+            assert!(contract.get());
+            assert_eq!(contract.owner(), alice);
+
+            let root = migration.root();
+            assert_eq!(migration.migrate(), Ok(Layout::Current));
+            assert_eq!(migration.root(), root);
+        }
+
+        /// Only the admin migrates, so no one else becomes the owner.
+        #[ink::test]
+        fn only_the_admin_migrates() {
+            let bob = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>().bob;
+            let root = scale::Encode::encode(&true);
+            let mut migration = StorageMigration::new(root.clone());
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(bob);
+            assert_eq!(migration.migrate(), Err(MigrationError::NotAdmin));
+            assert_eq!(migration.root(), root);
+        }
+
+        /// Roots in no known layout are refused and kept as they were.
+        #[ink::test]
+        fn unknown_layouts_are_refused() {
+            for root in [Vec::new(), ink::prelude::vec![2], ink::prelude::vec![1, 0]] {
+                let mut migration = StorageMigration::new(root.clone());
+                assert_eq!(migration.migrate(), Err(MigrationError::UnknownLayout));
+                assert_eq!(migration.root(), root);
+            }
+        }
+    }
+
+    #[cfg(all(test, feature = "e2e-tests"))]
+    mod e2e_tests {
+        use super::*;
+
+        use ink::codegen::TraitCallBuilder;
+        use ink_e2e::MessageBuilder;
+        use legacy_mock::LegacyMockRef;
+        use proxy_mock::ProxyMockRef;
+        use unit_test_bug::UnitTestBugRef;
+
+        /// Mirrors `ink_e2e::build_message`, which is fixed to the default environment.
+        fn build_message<Ref>(
+            account_id: AccountId,
+        ) -> MessageBuilder<shared_types::CustomEnvironment, Ref>
+        where
+            Ref: TraitCallBuilder + ink::env::call::FromAccountId<shared_types::CustomEnvironment>,
+        {
+            MessageBuilder::from_account_id(account_id)
+        }
+
+        type E2EResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+        /// We test that an instance of the first release behind a proxy keeps
+        /// its value when upgraded to the current code through the migration.
+        #[ink_e2e::test(
+            additional_contracts = "../Cargo.toml ../mocks/legacy/Cargo.toml ../mocks/proxy/Cargo.toml",
+            environment = shared_types::CustomEnvironment
+        )]
+        async fn legacy_instance_upgrades(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Given a proxy running the first release, holding `true`
+            let mut code_hashes = Vec::new();
+            for name in ["legacy_mock", "storage_migration", "unit_test_bug"] {
+                let upload = client.upload(name, &ink_e2e::alice(), None).await;
+                code_hashes.push(upload.expect("upload failed").code_hash);
+            }
+            let [legacy, migration, current] = code_hashes[..] else {
+                unreachable!("three contracts were uploaded");
+            };
+            let proxy = client
+                .instantiate(
+                    "proxy_mock",
+                    &ink_e2e::alice(),
+                    ProxyMockRef::new(legacy, scale::Encode::encode(&true)),
+                    0,
+                    None,
+                )
+                .await
+                .expect("instantiate proxy failed")
+                .account_id;
+            let legacy_get =
+                build_message::<LegacyMockRef>(proxy.clone()).call(|legacy| legacy.get());
+            assert!(client
+                .call_dry_run(&ink_e2e::bob(), &legacy_get, 0, None)
+                .await
+                .return_value());
+
+            // When the admin switches it to the migration and migrates
+            let upgrade = build_message::<ProxyMockRef>(proxy.clone())
+                .call(|proxy| proxy.upgrade_logic(migration));
+            client
+                .call(&ink_e2e::alice(), upgrade, 0, None)
+                .await
+                .expect("upgrade failed");
+            let migrate = build_message::<StorageMigrationRef>(proxy.clone())
+                .call(|migration| migration.migrate());
+            assert_eq!(
+                client
+                    .call_dry_run(&ink_e2e::bob(), &migrate, 0, None)
+                    .await
+                    .return_value(),
+                Err(MigrationError::NotAdmin)
+            );
+            let migrate_result = client
+                .call(&ink_e2e::alice(), migrate, 0, None)
+                .await
+                .expect("migrate failed");
+            assert_eq!(migrate_result.return_value(), Ok(Layout::Legacy));
+
+            // And then to the current code
+            let upgrade = build_message::<ProxyMockRef>(proxy.clone())
+                .call(|proxy| proxy.upgrade_logic(current));
+            client
+                .call(&ink_e2e::alice(), upgrade, 0, None)
+                .await
+                .expect("upgrade failed");
+
+            // Then the value survived and the new layout works
+            let get = build_message::<UnitTestBugRef>(proxy.clone())
+                .call(|unit_test_bug| unit_test_bug.get());
+            assert!(client
+                .call_dry_run(&ink_e2e::bob(), &get, 0, None)
+                .await
+                .return_value());
+            let owner = build_message::<UnitTestBugRef>(proxy.clone())
+                .call(|unit_test_bug| unit_test_bug.owner());
+            assert_eq!(
+                client
+                    .call_dry_run(&ink_e2e::bob(), &owner, 0, None)
+                    .await
+                    .return_value(),
+                ink_e2e::account_id(ink_e2e::AccountKeyring::Alice)
+            );
+            let flip = build_message::<UnitTestBugRef>(proxy.clone())
+                .call(|unit_test_bug| unit_test_bug.flip());
+            client
+                .call(&ink_e2e::bob(), flip, 0, None)
+                .await
+                .expect("flip after upgrade failed");
+            assert!(!client
+                .call_dry_run(&ink_e2e::bob(), &get, 0, None)
+                .await
+                .return_value());
+
+            Ok(())
+        }
+    }
+}
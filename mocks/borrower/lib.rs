@@ -0,0 +1,60 @@
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+pub use self::borrower_mock::{BorrowerMock, BorrowerMockRef};
+
+/// Minimal `FlashBorrower` that accepts or rejects every loan.
+///
+/// It remembers the value it saw during the last loan so tests can check the
+/// flip was visible to the callback. A rejected loan reverts, record included.
+#[ink::contract(env = shared_types::CustomEnvironment)]
+mod borrower_mock {
+    use ink::prelude::vec::Vec;
+    use shared_types::FlashBorrower;
+
+    #[ink(storage)]
+    pub struct BorrowerMock {
+        accept: bool,
+        last_seen: Option<bool>,
+    }
+
+    impl BorrowerMock {
+        #[ink(constructor)]
+        pub fn new(accept: bool) -> Self {
+            Self {
+                accept,
+                last_seen: None,
+            }
+        }
+
+        /// Value seen during the last loan.
+        #[ink(message)]
+        pub fn last_seen(&self) -> Option<bool> {
+            self.last_seen
+        }
+    }
+
+    impl FlashBorrower for BorrowerMock {
+        #[ink(message)]
+        fn on_flash_flip(&mut self, _initiator: AccountId, value: bool, _data: Vec<u8>) -> bool {
+            self.last_seen = Some(value);
+            self.accept
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// The seen value is recorded and the configured answer returned.
+        #[ink::test]
+        fn records_seen_value() {
+            let alice = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>().alice;
+            let mut borrower = BorrowerMock::new(true);
+            assert_eq!(borrower.last_seen(), None);
+            assert!(borrower.on_flash_flip(alice, true, Vec::new()));
+            assert_eq!(borrower.last_seen(), Some(true));
+            let mut rejecting = BorrowerMock::new(false);
+            assert!(!rejecting.on_flash_flip(alice, true, Vec::new()));
+        }
+    }
+}
@@ -0,0 +1,101 @@
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+pub use self::forwarder_mock::{ForwarderError, ForwarderMock, ForwarderMockRef, MutationReceipt};
+
+/// Minimal trusted forwarder relaying calls to `unit_test_bug`.
+///
+/// It trusts whoever calls it to name the account being acted for, so it is
+/// only suitable for tests. A production forwarder verifies a signature by
+/// that account before relaying.
+#[ink::contract(env = shared_types::CustomEnvironment)]
+mod forwarder_mock {
+    use ink::env::call::{build_call, ExecutionInput, Selector};
+
+    /// Mirrors `unit_test_bug::ForwardedCall`.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum ForwardedCall {
+        Flip,
+    }
+
+    /// Mirrors `unit_test_bug::MutationReceipt`, which `execute_forwarded` returns.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct MutationReceipt {
+        pub seq: u64,
+        pub new_value: bool,
+        pub gas_hint: u64,
+    }
+
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum ForwarderError {
+        /// The call into the target could not be executed.
+        CallFailed,
+        /// The target returned the `FlipError` with this variant index.
+        Rejected(u8),
+    }
+
+    #[ink(storage)]
+    pub struct ForwarderMock {}
+
+    impl Default for ForwarderMock {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl ForwarderMock {
+        #[ink(constructor)]
+        pub fn new() -> Self {
+            Self {}
+        }
+
+        /// Relays `flip` to `target` on behalf of `from`, forwarding any transferred
+        /// value, and returns the target's receipt.
+        #[ink(message, payable)]
+        pub fn forward_flip(
+            &mut self,
+            target: AccountId,
+            from: AccountId,
+        ) -> Result<MutationReceipt, ForwarderError> {
+            self.relay_flip(target, from, self.env().transferred_value())
+        }
+
+        /// Relays `count` flips to `target` on behalf of `from` within this one call.
+        #[ink(message)]
+        pub fn forward_flips(
+            &mut self,
+            target: AccountId,
+            from: AccountId,
+            count: u32,
+        ) -> Result<(), ForwarderError> {
+            for _ in 0..count {
+                self.relay_flip(target, from, 0)?;
+            }
+            Ok(())
+        }
+
+        fn relay_flip(
+            &self,
+            target: AccountId,
+            from: AccountId,
+            value: Balance,
+        ) -> Result<MutationReceipt, ForwarderError> {
+            let result = build_call::<shared_types::CustomEnvironment>()
+                .call(target)
+                .transferred_value(value)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("execute_forwarded")))
+                        .push_arg(from)
+                        .push_arg(ForwardedCall::Flip),
+                )
+                .returns::<Result<MutationReceipt, u8>>()
+                .try_invoke();
+            match result {
+                Ok(Ok(outcome)) => outcome.map_err(ForwarderError::Rejected),
+                _ => Err(ForwarderError::CallFailed),
+            }
+        }
+    }
+}
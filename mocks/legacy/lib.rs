@@ -0,0 +1,51 @@
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+pub use self::legacy_mock::{LegacyMock, LegacyMockRef};
+
+/// `unit_test_bug` as first released, storing only `{ value: bool }` at its
+/// root, to test upgrading instances deployed before the layout grew.
+#[ink::contract(env = shared_types::CustomEnvironment)]
+mod legacy_mock {
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum FlipError {
+        FlipError,
+    }
+
+    #[ink(storage)]
+    pub struct LegacyMock {
+        value: bool,
+    }
+
+    impl LegacyMock {
+        #[ink(constructor)]
+        pub fn new(init_value: bool) -> Self {
+            Self { value: init_value }
+        }
+
+        #[ink(message)]
+        pub fn flip_with_error(&mut self) -> Result<(), FlipError> {
+            self.value = !self.value;
+            Err(FlipError::FlipError)
+        }
+
+        #[ink(message)]
+        pub fn get(&self) -> bool {
+            self.value
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// The whole root is the encoded `bool`.
+        #[ink::test]
+        fn root_is_the_value() {
+            let legacy = LegacyMock::new(true);
+            let mut root = ink::prelude::vec::Vec::new();
+            ink::storage::traits::Storable::encode(&legacy, &mut root);
+            assert_eq!(root, [1]);
+        }
+    }
+}
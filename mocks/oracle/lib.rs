@@ -0,0 +1,62 @@
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+pub use self::oracle_mock::{OracleMock, OracleMockRef};
+
+/// Minimal `PriceOracle` whose price anyone can set.
+///
+/// Each update is stamped with the current block so consumers can exercise
+/// their staleness checks.
+#[ink::contract(env = shared_types::CustomEnvironment)]
+mod oracle_mock {
+    use shared_types::{PriceOracle, PriceQuote};
+
+    #[ink(storage)]
+    #[derive(Default)]
+    pub struct OracleMock {
+        quote: Option<PriceQuote>,
+    }
+
+    impl OracleMock {
+        #[ink(constructor)]
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Publishes `units_per_usd` as the price as of the current block.
+        #[ink(message)]
+        pub fn set_price(&mut self, units_per_usd: Balance) {
+            self.quote = Some(PriceQuote {
+                units_per_usd,
+                updated_at: self.env().block_number(),
+            });
+        }
+    }
+
+    impl PriceOracle for OracleMock {
+        #[ink(message)]
+        fn latest_price(&self) -> Option<PriceQuote> {
+            self.quote
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// Prices are stamped with the block they were set in.
+        #[ink::test]
+        fn set_price_stamps_block() {
+            let mut oracle = OracleMock::new();
+            assert_eq!(oracle.latest_price(), None);
+            ink::env::test::advance_block::<ink::env::DefaultEnvironment>();
+            oracle.set_price(250);
+            assert_eq!(
+                oracle.latest_price(),
+                Some(PriceQuote {
+                    units_per_usd: 250,
+                    updated_at: 1,
+                })
+            );
+        }
+    }
+}
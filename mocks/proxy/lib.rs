@@ -0,0 +1,141 @@
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+pub use self::proxy_mock::{ProxyError, ProxyMock, ProxyMockRef};
+
+/// Storage key of the proxy's admin, for code the proxy delegates to that
+/// must check it.
+pub const ADMIN_KEY: u32 = 0x7072_0002;
+
+/// Upgradeable proxy running the code of another contract against its own
+/// storage through `delegate_call`.
+///
+/// Every call not addressed to the proxy's own messages is forwarded to the
+/// logic code hash, so the proxy's root storage cell must hold the logic's
+/// root storage. It takes those bytes as given and keeps its own fields on
+/// fixed keys the logic does not use.
+#[ink::contract(env = shared_types::CustomEnvironment)]
+mod proxy_mock {
+    use ink::{
+        env::{call::build_call, CallFlags},
+        prelude::vec::Vec,
+        storage::{traits::ManualKey, Lazy},
+    };
+
+    /// The logic's root storage, encoded by the logic itself.
+    #[derive(Debug, Default, PartialEq, Eq, Clone)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct LogicStorage(Vec<u8>);
+
+    impl scale::Encode for LogicStorage {
+        fn size_hint(&self) -> usize {
+            self.0.len()
+        }
+
+        fn encode_to<T: scale::Output + ?Sized>(&self, dest: &mut T) {
+            dest.write(&self.0);
+        }
+    }
+
+    impl scale::Decode for LogicStorage {
+        fn decode<I: scale::Input>(input: &mut I) -> Result<Self, scale::Error> {
+            let mut bytes = ink::prelude::vec![0; input.remaining_len()?.unwrap_or_default()];
+            input.read(&mut bytes)?;
+            Ok(Self(bytes))
+        }
+    }
+
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum ProxyError {
+        /// Only the admin may change the logic.
+        NotAdmin,
+    }
+
+    #[ink(storage)]
+    pub struct ProxyMock {
+        /// Must stay the only field encoded at the root.
+        logic_storage: LogicStorage,
+        logic: Lazy<Hash, ManualKey<0x7072_0001>>,
+        admin: Lazy<AccountId, ManualKey<{ crate::ADMIN_KEY }>>,
+    }
+
+    impl ProxyMock {
+        /// Proxies to `logic`, starting from the logic's encoded root `storage`.
+        #[ink(constructor)]
+        pub fn new(logic: Hash, storage: Vec<u8>) -> Self {
+            let mut proxy = Self {
+                logic_storage: LogicStorage(storage),
+                logic: Lazy::new(),
+                admin: Lazy::new(),
+            };
+            proxy.logic.set(&logic);
+            proxy.admin.set(&Self::env().caller());
+            proxy
+        }
+
+        /// Returns the code hash calls are forwarded to.
+        #[ink(message)]
+        pub fn logic(&self) -> Hash {
+            self.logic.get().expect("set by the constructor")
+        }
+
+        /// Forwards later calls to `logic`, keeping the storage.
+        #[ink(message)]
+        pub fn upgrade_logic(&mut self, logic: Hash) -> Result<(), ProxyError> {
+            if Some(self.env().caller()) != self.admin.get() {
+                return Err(ProxyError::NotAdmin);
+            }
+            self.logic.set(&logic);
+            Ok(())
+        }
+
+        /// Runs any other call with the logic's code.
+        ///
+        /// The call returns straight from the logic, so the proxy never writes
+        /// its root after the logic has.
+        #[ink(message, payable, selector = _)]
+        pub fn forward(&self) {
+            build_call::<shared_types::CustomEnvironment>()
+                .delegate(self.logic())
+                .call_flags(
+                    CallFlags::default()
+                        .set_forward_input(true)
+                        .set_tail_call(true),
+                )
+                .invoke();
+            unreachable!("tail calls do not return");
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// The logic's root is stored as given, without a length prefix.
+        #[test]
+        fn logic_storage_is_stored_verbatim() {
+            let storage = LogicStorage(ink::prelude::vec![1, 2, 3]);
+            let encoded = scale::Encode::encode(&storage);
+            assert_eq!(encoded, [1, 2, 3]);
+            assert_eq!(scale::Decode::decode(&mut &encoded[..]), Ok(storage));
+        }
+
+        /// Only the deployer may swap the logic.
+        #[ink::test]
+        fn only_admin_upgrades() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut proxy = ProxyMock::new(Hash::from([1; 32]), Vec::new());
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(
+                proxy.upgrade_logic(Hash::from([2; 32])),
+                Err(ProxyError::NotAdmin)
+            );
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(proxy.upgrade_logic(Hash::from([2; 32])), Ok(()));
+            assert_eq!(proxy.logic(), Hash::from([2; 32]));
+        }
+    }
+}
@@ -0,0 +1,115 @@
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+pub use self::psp22_mock::{Psp22Error, Psp22Mock, Psp22MockRef};
+
+/// Minimal PSP22 fungible token used to exercise token-gated flipping.
+///
+/// Implements only `total_supply`, `balance_of` and `transfer`, under their
+/// standard PSP22 selectors. Allowances, metadata and events are left out.
+#[ink::contract(env = shared_types::CustomEnvironment)]
+mod psp22_mock {
+    use ink::{prelude::vec::Vec, storage::Mapping};
+
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Psp22Error {
+        /// The sender holds less than the amount transferred.
+        InsufficientBalance,
+        /// Tokens were sent to the all-zero account, which nobody controls.
+        ZeroRecipientAddress,
+    }
+
+    #[ink(storage)]
+    pub struct Psp22Mock {
+        total_supply: Balance,
+        balances: Mapping<AccountId, Balance>,
+    }
+
+    impl Psp22Mock {
+        /// Mints `total_supply` tokens to the caller.
+        #[ink(constructor)]
+        pub fn new(total_supply: Balance) -> Self {
+            let mut balances = Mapping::default();
+            balances.insert(Self::env().caller(), &total_supply);
+            Self {
+                total_supply,
+                balances,
+            }
+        }
+
+        #[ink(message, selector = 0x162df8c2)]
+        pub fn total_supply(&self) -> Balance {
+            self.total_supply
+        }
+
+        #[ink(message, selector = 0x6568382f)]
+        pub fn balance_of(&self, owner: AccountId) -> Balance {
+            self.balances.get(owner).unwrap_or(0)
+        }
+
+        #[ink(message, selector = 0xdb20f9f5)]
+        pub fn transfer(
+            &mut self,
+            to: AccountId,
+            value: Balance,
+            _data: Vec<u8>,
+        ) -> Result<(), Psp22Error> {
+            if to == AccountId::from([0; 32]) {
+                return Err(Psp22Error::ZeroRecipientAddress);
+            }
+            let from = self.env().caller();
+            let from_balance = self.balance_of(from);
+            if from_balance < value {
+                return Err(Psp22Error::InsufficientBalance);
+            }
+            self.balances.insert(from, &(from_balance - value));
+            let to_balance = self.balance_of(to);
+            self.balances.insert(to, &(to_balance + value));
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn accounts() -> ink::env::test::DefaultAccounts<ink::env::DefaultEnvironment> {
+            ink::env::test::default_accounts::<ink::env::DefaultEnvironment>()
+        }
+
+        /// The deployer holds the whole supply.
+        #[ink::test]
+        fn new_mints_to_caller() {
+            let token = Psp22Mock::new(100);
+            assert_eq!(token.total_supply(), 100);
+            assert_eq!(token.balance_of(accounts().alice), 100);
+            assert_eq!(token.balance_of(accounts().bob), 0);
+        }
+
+        /// Transfers move balance and fail without changes when underfunded.
+        #[ink::test]
+        fn transfer_moves_balance() {
+            let mut token = Psp22Mock::new(100);
+            let accounts = accounts();
+            assert_eq!(token.transfer(accounts.bob, 30, Vec::new()), Ok(()));
+            assert_eq!(
+                token.transfer(accounts.bob, 71, Vec::new()),
+                Err(Psp22Error::InsufficientBalance)
+            );
+            assert_eq!(token.balance_of(accounts.alice), 70);
+            assert_eq!(token.balance_of(accounts.bob), 30);
+        }
+
+        /// Transfers to the zero account are refused.
+        #[ink::test]
+        fn transfer_to_zero_account_fails() {
+            let mut token = Psp22Mock::new(100);
+            let zero = AccountId::from([0; 32]);
+            assert_eq!(
+                token.transfer(zero, 10, Vec::new()),
+                Err(Psp22Error::ZeroRecipientAddress)
+            );
+            assert_eq!(token.balance_of(accounts().alice), 100);
+        }
+    }
+}
@@ -0,0 +1,124 @@
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+pub use self::psp34_mock::{Id, Psp34Error, Psp34Mock, Psp34MockRef};
+
+/// Minimal PSP34 NFT collection used to exercise NFT flip permits.
+///
+/// Implements only `owner_of` and `transfer`, under their standard PSP34
+/// selectors, plus an unrestricted `mint`. Approvals, metadata and events are
+/// left out.
+#[ink::contract(env = shared_types::CustomEnvironment)]
+mod psp34_mock {
+    use ink::{prelude::vec::Vec, storage::Mapping};
+
+    /// Token id, as defined by the PSP34 standard.
+    #[derive(Debug, PartialEq, Eq, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub enum Id {
+        U8(u8),
+        U16(u16),
+        U32(u32),
+        U64(u64),
+        U128(u128),
+        Bytes(Vec<u8>),
+    }
+
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Psp34Error {
+        /// The token already exists.
+        TokenExists,
+        /// The token does not exist.
+        TokenNotExists,
+        /// The caller does not own the token.
+        NotOwner,
+    }
+
+    #[ink(storage)]
+    #[derive(Default)]
+    pub struct Psp34Mock {
+        owners: Mapping<Id, AccountId>,
+    }
+
+    impl Psp34Mock {
+        #[ink(constructor)]
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Mints `id` to the caller.
+        #[ink(message)]
+        pub fn mint(&mut self, id: Id) -> Result<(), Psp34Error> {
+            if self.owners.contains(&id) {
+                return Err(Psp34Error::TokenExists);
+            }
+            self.owners.insert(&id, &self.env().caller());
+            Ok(())
+        }
+
+        #[ink(message, selector = 0x1168624d)]
+        pub fn owner_of(&self, id: Id) -> Option<AccountId> {
+            self.owners.get(&id)
+        }
+
+        #[ink(message, selector = 0x3128d61b)]
+        pub fn transfer(
+            &mut self,
+            to: AccountId,
+            id: Id,
+            _data: Vec<u8>,
+        ) -> Result<(), Psp34Error> {
+            let owner = self
+                .owner_of(id.clone())
+                .ok_or(Psp34Error::TokenNotExists)?;
+            if owner != self.env().caller() {
+                return Err(Psp34Error::NotOwner);
+            }
+            self.owners.insert(&id, &to);
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn accounts() -> ink::env::test::DefaultAccounts<ink::env::DefaultEnvironment> {
+            ink::env::test::default_accounts::<ink::env::DefaultEnvironment>()
+        }
+
+        /// Minting assigns the token to the caller once.
+        #[ink::test]
+        fn mint_assigns_owner() {
+            let mut collection = Psp34Mock::new();
+            assert_eq!(collection.mint(Id::U8(1)), Ok(()));
+            assert_eq!(collection.mint(Id::U8(1)), Err(Psp34Error::TokenExists));
+            assert_eq!(collection.owner_of(Id::U8(1)), Some(accounts().alice));
+            assert_eq!(collection.owner_of(Id::U8(2)), None);
+        }
+
+        /// Only the owner may transfer a token.
+        #[ink::test]
+        fn transfer_requires_owner() {
+            let mut collection = Psp34Mock::new();
+            let accounts = accounts();
+            collection.mint(Id::U8(1)).expect("mint failed");
+            assert_eq!(
+                collection.transfer(accounts.bob, Id::U8(2), Vec::new()),
+                Err(Psp34Error::TokenNotExists)
+            );
+            assert_eq!(
+                collection.transfer(accounts.bob, Id::U8(1), Vec::new()),
+                Ok(())
+            );
+            assert_eq!(
+                collection.transfer(accounts.charlie, Id::U8(1), Vec::new()),
+                Err(Psp34Error::NotOwner)
+            );
+            assert_eq!(collection.owner_of(Id::U8(1)), Some(accounts.bob));
+        }
+    }
+}
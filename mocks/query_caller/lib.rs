@@ -0,0 +1,43 @@
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+pub use self::query_caller_mock::{QueryCallerMock, QueryCallerMockRef};
+
+/// Contract reading `unit_test_bug` through the `IFlipQuery` trait only,
+/// without depending on the contract crate.
+#[ink::contract(env = shared_types::CustomEnvironment)]
+mod query_caller_mock {
+    use ink::codegen::TraitCallBuilder;
+    use shared_types::{EpochStats, IFlipQuery, UpstreamError};
+
+    #[ink(storage)]
+    pub struct QueryCallerMock {
+        target: AccountId,
+    }
+
+    impl QueryCallerMock {
+        #[ink(constructor)]
+        pub fn new(target: AccountId) -> Self {
+            Self { target }
+        }
+
+        /// Returns the value of the target.
+        #[ink(message)]
+        pub fn value(&self) -> Result<bool, UpstreamError> {
+            self.query().call().get().try_invoke()?.map_err(Into::into)
+        }
+
+        /// Returns the current epoch's statistics of the target.
+        #[ink(message)]
+        pub fn stats(&self) -> Result<EpochStats, UpstreamError> {
+            self.query()
+                .call()
+                .stats()
+                .try_invoke()?
+                .map_err(Into::into)
+        }
+
+        fn query(&self) -> ink::contract_ref!(IFlipQuery) {
+            self.target.into()
+        }
+    }
+}
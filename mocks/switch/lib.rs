@@ -0,0 +1,100 @@
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+pub use self::switch_mock::{BehaviorSwitch, SwitchMock, SwitchMockRef};
+
+/// `FlashBorrower` whose answer is switched between every way a callee can
+/// end, so tests can check how `unit_test_bug` maps each of them.
+#[ink::contract(env = shared_types::CustomEnvironment)]
+mod switch_mock {
+    use ink::{
+        env::call::{build_call, ExecutionInput, Selector},
+        prelude::vec::Vec,
+    };
+    use shared_types::FlashBorrower;
+
+    /// How the next callback ends.
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub enum BehaviorSwitch {
+        /// Accepts the loan.
+        Ok,
+        /// Rejects the loan through the return value.
+        Error,
+        /// Panics, trapping the callee.
+        Trap,
+        /// Calls `flip` back on the calling contract and accepts if that worked.
+        Reenter,
+    }
+
+    #[ink(storage)]
+    pub struct SwitchMock {
+        behavior: BehaviorSwitch,
+    }
+
+    impl SwitchMock {
+        #[ink(constructor)]
+        pub fn new(behavior: BehaviorSwitch) -> Self {
+            Self { behavior }
+        }
+
+        #[ink(message)]
+        pub fn set_behavior(&mut self, behavior: BehaviorSwitch) {
+            self.behavior = behavior;
+        }
+
+        #[ink(message)]
+        pub fn behavior(&self) -> BehaviorSwitch {
+            self.behavior
+        }
+    }
+
+    impl FlashBorrower for SwitchMock {
+        #[ink(message)]
+        fn on_flash_flip(&mut self, _initiator: AccountId, _value: bool, _data: Vec<u8>) -> bool {
+            match self.behavior {
+                BehaviorSwitch::Ok => true,
+                BehaviorSwitch::Error => false,
+                BehaviorSwitch::Trap => panic!("switched to trap"),
+                BehaviorSwitch::Reenter => {
+                    let flip = build_call::<shared_types::CustomEnvironment>()
+                        .call(self.env().caller())
+                        .exec_input(ExecutionInput::new(Selector::new(ink::selector_bytes!(
+                            "flip"
+                        ))))
+                        .returns::<Result<(), u8>>()
+                        .try_invoke();
+                    matches!(flip, Ok(Ok(Ok(()))))
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn alice() -> AccountId {
+            ink::env::test::default_accounts::<ink::env::DefaultEnvironment>().alice
+        }
+
+        /// `Ok` and `Error` answer the loan, and the switch can be flipped.
+        #[ink::test]
+        fn answers_as_switched() {
+            let mut switch = SwitchMock::new(BehaviorSwitch::Ok);
+            assert!(switch.on_flash_flip(alice(), true, Vec::new()));
+            switch.set_behavior(BehaviorSwitch::Error);
+            assert_eq!(switch.behavior(), BehaviorSwitch::Error);
+            assert!(!switch.on_flash_flip(alice(), true, Vec::new()));
+        }
+
+        /// `Trap` panics instead of answering.
+        #[ink::test]
+        #[should_panic(expected = "switched to trap")]
+        fn traps_when_switched() {
+            SwitchMock::new(BehaviorSwitch::Trap).on_flash_flip(alice(), true, Vec::new());
+        }
+    }
+}
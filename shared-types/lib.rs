@@ -0,0 +1,698 @@
+//! Types shared between the `unit_test_bug` contract, its companion mock
+//! contracts and off-chain consumers.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use ink::env::{DefaultEnvironment, Environment};
+
+/// Chain extension exposing the runtime's randomness source.
+#[ink::chain_extension]
+pub trait FetchRandom {
+    type ErrorCode = RandomReadErr;
+
+    /// Returns 32 random bytes derived from the runtime's randomness and `subject`.
+    #[ink(extension = 1101)]
+    fn fetch_random(subject: [u8; 32]) -> [u8; 32];
+}
+
+/// Failure reported by the randomness chain extension.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+pub enum RandomReadErr {
+    /// The runtime could not provide randomness.
+    FailGetRandomSource,
+}
+
+impl ink::env::chain_extension::FromStatusCode for RandomReadErr {
+    fn from_status_code(status_code: u32) -> Result<(), Self> {
+        match status_code {
+            0 => Ok(()),
+            _ => Err(Self::FailGetRandomSource),
+        }
+    }
+}
+
+/// A source of randomness, so that what draws on it can be handed a
+/// deterministic one in tests.
+pub trait Randomness {
+    /// Returns 32 random bytes derived from `subject`.
+    fn random(&mut self, subject: [u8; 32]) -> Result<[u8; 32], RandomReadErr>;
+}
+
+/// The runtime's randomness, through [`FetchRandom`].
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ChainRandomness;
+
+impl Randomness for ChainRandomness {
+    fn random(&mut self, subject: [u8; 32]) -> Result<[u8; 32], RandomReadErr> {
+        <FetchRandom as ink::ChainExtensionInstance>::instantiate().fetch_random(subject)
+    }
+}
+
+impl<F> Randomness for F
+where
+    F: FnMut([u8; 32]) -> Result<[u8; 32], RandomReadErr>,
+{
+    fn random(&mut self, subject: [u8; 32]) -> Result<[u8; 32], RandomReadErr> {
+        self(subject)
+    }
+}
+
+/// Chain extension exposing `pallet-assets` balances and transfers.
+///
+/// The contract environment can only name one chain extension, so this one is
+/// reached through `ChainExtensionInstance::instantiate` rather than
+/// `env().extension()`.
+#[ink::chain_extension]
+pub trait PalletAssets {
+    type ErrorCode = AssetsError;
+
+    /// Returns the balance of `who` in `asset_id`.
+    #[ink(extension = 1102, handle_status = false)]
+    fn balance(
+        asset_id: u32,
+        who: <DefaultEnvironment as Environment>::AccountId,
+    ) -> <DefaultEnvironment as Environment>::Balance;
+
+    /// Transfers `amount` of `asset_id` from the calling contract to `target`.
+    #[ink(extension = 1103)]
+    fn transfer(
+        asset_id: u32,
+        target: <DefaultEnvironment as Environment>::AccountId,
+        amount: <DefaultEnvironment as Environment>::Balance,
+    ) -> Result<(), AssetsError>;
+
+    /// Transfers `amount` of `asset_id` from `owner` to `target`, spending an
+    /// approval `owner` granted the calling contract.
+    #[ink(extension = 1104)]
+    fn transfer_approved(
+        asset_id: u32,
+        owner: <DefaultEnvironment as Environment>::AccountId,
+        target: <DefaultEnvironment as Environment>::AccountId,
+        amount: <DefaultEnvironment as Environment>::Balance,
+    ) -> Result<(), AssetsError>;
+}
+
+/// Failure reported by the `pallet-assets` chain extension.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+pub enum AssetsError {
+    /// The source account holds too little of the asset.
+    BalanceLow,
+    /// The approval is missing or too small.
+    Unapproved,
+    /// Any other failure, such as an unknown asset.
+    Other,
+}
+
+impl AssetsError {
+    /// Status code the runtime reports for this failure.
+    pub fn status_code(self) -> u32 {
+        match self {
+            Self::BalanceLow => 1,
+            Self::Unapproved => 2,
+            Self::Other => 3,
+        }
+    }
+}
+
+impl From<scale::Error> for AssetsError {
+    fn from(_: scale::Error) -> Self {
+        Self::Other
+    }
+}
+
+impl ink::env::chain_extension::FromStatusCode for AssetsError {
+    fn from_status_code(status_code: u32) -> Result<(), Self> {
+        match status_code {
+            0 => Ok(()),
+            1 => Err(Self::BalanceLow),
+            2 => Err(Self::Unapproved),
+            _ => Err(Self::Other),
+        }
+    }
+}
+
+/// Chain extension reading `pallet-contracts` information about any account.
+///
+/// Reached through `ChainExtensionInstance::instantiate`, as with
+/// [`PalletAssets`].
+#[ink::chain_extension]
+pub trait PalletContracts {
+    type ErrorCode = ContractsError;
+
+    /// Returns the code hash of the contract at `account`, `None` if it is not a contract.
+    #[ink(extension = 1105, handle_status = false)]
+    fn code_hash(
+        account: <DefaultEnvironment as Environment>::AccountId,
+    ) -> Option<<DefaultEnvironment as Environment>::Hash>;
+
+    /// Returns the storage deposit the contract at `account` holds, `None`
+    /// if it is not a contract.
+    #[ink(extension = 1106, handle_status = false)]
+    fn deposit_held(
+        account: <DefaultEnvironment as Environment>::AccountId,
+    ) -> Option<<DefaultEnvironment as Environment>::Balance>;
+}
+
+/// Failure reported by the `pallet-contracts` chain extension.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+pub enum ContractsError {
+    /// The runtime could not read the contract information.
+    Unavailable,
+}
+
+impl ink::env::chain_extension::FromStatusCode for ContractsError {
+    fn from_status_code(status_code: u32) -> Result<(), Self> {
+        match status_code {
+            0 => Ok(()),
+            _ => Err(Self::Unavailable),
+        }
+    }
+}
+
+/// A price published by a [`PriceOracle`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(
+    feature = "std",
+    derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+)]
+pub struct PriceQuote {
+    /// Smallest native units worth one US dollar.
+    pub units_per_usd: <DefaultEnvironment as Environment>::Balance,
+    /// Block the price was last updated in.
+    pub updated_at: <DefaultEnvironment as Environment>::BlockNumber,
+}
+
+/// A contract publishing the native token's US dollar price.
+#[ink::trait_definition]
+pub trait PriceOracle {
+    /// Returns the latest price, or `None` if none has been published.
+    #[ink(message)]
+    fn latest_price(&self) -> Option<PriceQuote>;
+}
+
+/// A contract receiving the callback of a `flash_flip`.
+#[ink::trait_definition]
+pub trait FlashBorrower {
+    /// Called while the flipped value is in place.
+    ///
+    /// `value` is the temporary value. Returning `false` rejects the loan
+    /// and reverts the whole `flash_flip`.
+    #[ink(message)]
+    fn on_flash_flip(
+        &mut self,
+        initiator: <DefaultEnvironment as Environment>::AccountId,
+        value: bool,
+        data: ink::prelude::vec::Vec<u8>,
+    ) -> bool;
+}
+
+/// Flip statistics of one epoch.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone, scale::Encode, scale::Decode)]
+#[cfg_attr(
+    feature = "std",
+    derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+)]
+pub struct EpochStats {
+    /// The epoch the statistics cover.
+    pub epoch: u32,
+    /// Successful flips in the epoch.
+    pub flips: u64,
+    /// Distinct accounts that flipped in the epoch.
+    pub flippers: u32,
+}
+
+/// Read-only view of `unit_test_bug` for other contracts.
+///
+/// Kept to the two queries most callers need, so a caller's contract ref
+/// stays small.
+#[ink::trait_definition]
+pub trait IFlipQuery {
+    /// Returns the current value.
+    #[ink(message)]
+    fn get(&self) -> bool;
+
+    /// Returns the flip statistics of the current epoch.
+    #[ink(message)]
+    fn stats(&self) -> EpochStats;
+}
+
+/// Why a call into another contract failed, attached to the contract's
+/// errors for cross-contract calls.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+pub enum UpstreamError {
+    /// The callee trapped, ran out of gas or is not a contract.
+    CallFailed,
+    /// The callee could not decode the call, e.g. it lacks the message.
+    CouldNotReadInput,
+    /// The callee returned an error, given by its first encoded byte.
+    Returned(u8),
+}
+
+impl From<ink::env::Error> for UpstreamError {
+    fn from(_: ink::env::Error) -> Self {
+        Self::CallFailed
+    }
+}
+
+impl From<ink::LangError> for UpstreamError {
+    fn from(_: ink::LangError) -> Self {
+        Self::CouldNotReadInput
+    }
+}
+
+/// Data of the contract's deprecated `Flipped` event.
+///
+/// ink! 4 prefixes event data with the index of the event in the contract's
+/// event enum; decode what follows that byte.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+pub struct Flipped {
+    /// The value after the flip.
+    pub value: bool,
+}
+
+/// Data of the contract's `FlippedV2` event, prefixed like [`Flipped`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+pub struct FlippedV2 {
+    /// The account the flip is recorded for.
+    pub account: <DefaultEnvironment as Environment>::AccountId,
+    /// The value after the flip.
+    pub value: bool,
+    /// Block the flip happened in.
+    pub block: <DefaultEnvironment as Environment>::BlockNumber,
+    /// Position among the contract's state-change events, counting from zero.
+    pub seq: u64,
+}
+
+impl From<FlippedV2> for Flipped {
+    fn from(event: FlippedV2) -> Self {
+        Self { value: event.value }
+    }
+}
+
+/// The default environment extended with [`FetchRandom`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+pub enum CustomEnvironment {}
+
+impl Environment for CustomEnvironment {
+    const MAX_EVENT_TOPICS: usize = <DefaultEnvironment as Environment>::MAX_EVENT_TOPICS;
+
+    type AccountId = <DefaultEnvironment as Environment>::AccountId;
+    type Balance = <DefaultEnvironment as Environment>::Balance;
+    type Hash = <DefaultEnvironment as Environment>::Hash;
+    type BlockNumber = <DefaultEnvironment as Environment>::BlockNumber;
+    type Timestamp = <DefaultEnvironment as Environment>::Timestamp;
+
+    type ChainExtension = FetchRandom;
+}
+
+/// Decoding of the contract's flip events for indexers and other off-chain
+/// consumers, without depending on the contract crate.
+///
+/// Pass the topics and data of a `Contracts::ContractEmitted` event record.
+#[cfg(feature = "std")]
+pub mod events {
+    use ink::{env::hash::Blake2x256, primitives::AccountId};
+    use scale::{DecodeAll, Encode};
+
+    use crate::{Flipped, FlippedV2};
+
+    /// Index of [`Flipped`] in the contract's event enum.
+    pub const FLIPPED_INDEX: u8 = 0;
+
+    /// Index of [`FlippedV2`] in the contract's event enum.
+    pub const FLIPPED_V2_INDEX: u8 = 1;
+
+    /// The first topic of every `event` the contract emits.
+    pub fn signature_topic(event: &str) -> [u8; 32] {
+        // An empty, length-prefixed topic prefix followed by the bare signature.
+        let prefixed = [&[0u8][..], format!("UnitTestBug::{event}").as_bytes()].concat();
+        let mut topic = [0u8; 32];
+        if prefixed.len() <= topic.len() {
+            topic[..prefixed.len()].copy_from_slice(&prefixed);
+        } else {
+            ink::env::hash_bytes::<Blake2x256>(&prefixed, &mut topic);
+        }
+        topic
+    }
+
+    /// Decodes a deprecated `Flipped` event, `None` for any other event.
+    ///
+    /// Use `Flipped::from` on [`decode_flipped_v2`] to handle both.
+    pub fn decode_flipped(topics: &[[u8; 32]], data: &[u8]) -> Option<Flipped> {
+        decode("Flipped", FLIPPED_INDEX, topics, data)
+    }
+
+    /// Decodes a `FlippedV2` event, `None` for any other event.
+    pub fn decode_flipped_v2(topics: &[[u8; 32]], data: &[u8]) -> Option<FlippedV2> {
+        decode("FlippedV2", FLIPPED_V2_INDEX, topics, data)
+    }
+
+    /// Events about a single account, each holding it in an `account` topic.
+    ///
+    /// ink! prefixes a topic with its event's name, so an account's activity is
+    /// filed under one topic per event, see [`account_topics`].
+    pub const ACCOUNT_TOPIC_EVENTS: &[&str] = &["FlippedV2", "Approval", "BatchFlipped"];
+
+    /// The `account` topic `event` emits for `account`.
+    pub fn account_topic(event: &str, account: &AccountId) -> [u8; 32] {
+        let prefix = format!("UnitTestBug::{event}::account");
+        let mut encoded = prefix.as_bytes().encode();
+        account.encode_to(&mut encoded);
+        let mut topic = [0u8; 32];
+        ink::env::hash_bytes::<Blake2x256>(&encoded, &mut topic);
+        topic
+    }
+
+    /// Every topic the contract files `account`'s activity under, for
+    /// indexers to subscribe to a single account.
+    pub fn account_topics(account: &AccountId) -> Vec<[u8; 32]> {
+        ACCOUNT_TOPIC_EVENTS
+            .iter()
+            .map(|event| account_topic(event, account))
+            .collect()
+    }
+
+    fn decode<T: DecodeAll>(event: &str, index: u8, topics: &[[u8; 32]], data: &[u8]) -> Option<T> {
+        if topics.first() != Some(&signature_topic(event)) {
+            return None;
+        }
+        match data.split_first() {
+            Some((&found, mut fields)) if found == index => T::decode_all(&mut fields).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Limits the contract enforces, for clients to check arguments against
+/// before calling.
+pub mod limits {
+    use ink::env::{DefaultEnvironment, Environment};
+
+    type BlockNumber = <DefaultEnvironment as Environment>::BlockNumber;
+
+    /// Largest `max_batch_size` a configuration may set.
+    pub const MAX_BATCH_SIZE_LIMIT: u32 = 64;
+
+    /// Largest `cooldown_blocks` a configuration may set.
+    pub const MAX_COOLDOWN_BLOCKS: BlockNumber = 14_400;
+
+    /// Largest `timelock_delay` a configuration may set.
+    pub const MAX_TIMELOCK_DELAY: BlockNumber = 100_800;
+
+    /// Largest `max_blob_size` a configuration may set.
+    ///
+    /// ink! and the contracts pallet move call data and storage values through
+    /// a 16 KiB buffer, which must also fit the selector, key and length prefix.
+    pub const MAX_BLOB_SIZE_LIMIT: u32 = 16_000;
+
+    /// Largest chunk, in bytes, a blob is stored in.
+    ///
+    /// Keeps every storage value well below the 9600 byte buffer the off-chain
+    /// test engine reads storage through.
+    pub const BLOB_CHUNK_SIZE: u32 = 4096;
+
+    /// Largest encoded return value, in bytes, a message can hand back.
+    ///
+    /// ink! encodes return values into the same 16 KiB buffer and traps if
+    /// they overflow it.
+    pub const MAX_RESPONSE_SIZE: usize = 16 * 1024;
+
+    /// Most checkpoints `history_page` returns at once. Longer pages are
+    /// refused with `ResponseTooLarge`.
+    ///
+    /// A checkpoint encodes as a block number and a `bool`. A page is wrapped
+    /// in `MessageResult` and `Result`, one byte each, and prefixed by its
+    /// compact length, at most five bytes.
+    pub const MAX_HISTORY_PAGE: u32 =
+        ((MAX_RESPONSE_SIZE - 7) / (core::mem::size_of::<BlockNumber>() + 1)) as u32;
+
+    /// Longest label, in UTF-8 bytes, `set_label` accepts.
+    pub const MAX_LABEL_LEN: usize = 64;
+
+    /// Largest number of accounts a flip policy may exempt.
+    pub const MAX_POLICY_EXEMPTIONS: usize = 16;
+
+    /// Largest number of fee discount tiers.
+    pub const MAX_FEE_TIERS: usize = 8;
+
+    /// Largest number of validators a BLS validator set may hold.
+    pub const MAX_BLS_VALIDATORS: usize = 64;
+
+    /// Largest number of flips waiting in the queue at once.
+    pub const MAX_QUEUED_FLIPS: usize = 32;
+
+    /// Number of accounts tracked on the leaderboard.
+    pub const LEADERBOARD_SIZE: usize = 10;
+
+    /// Number of entries the audit log keeps before overwriting the oldest.
+    pub const AUDIT_LOG_SIZE: u32 = 32;
+}
+
+/// Stable status codes of the contract's `FlipError` variants, for clients
+/// that do not decode SCALE enums.
+///
+/// A code is the variant's SCALE index, as listed in the contract metadata,
+/// plus one, so 0 is left for success. Variants are only ever appended, so a
+/// code never changes meaning.
+pub mod errors {
+    /// `FlipError` variant names, the one with status code `n` at `n - 1`.
+    pub const ERROR_NAMES: &[&str] = &[
+        "FlipError",
+        "NotOwner",
+        "InsufficientFee",
+        "SubscriptionsDisabled",
+        "InsufficientPayment",
+        "BidTooLow",
+        "TransferFailed",
+        "NotRightsHolder",
+        "EscrowNotFound",
+        "InvalidEscrowState",
+        "NotEscrowParty",
+        "EmptyEscrow",
+        "NothingToClaim",
+        "AlreadyClaimed",
+        "InvalidProof",
+        "AirdropExhausted",
+        "LotteryNotFinished",
+        "AlreadyDrawn",
+        "NoTickets",
+        "RandomnessUnavailable",
+        "NotTrustedForwarder",
+        "InvalidSignature",
+        "SessionKeyNotFound",
+        "SessionExpired",
+        "OutOfScope",
+        "InvalidConfig",
+        "NoPendingConfig",
+        "TimelockActive",
+        "Paused",
+        "CooldownActive",
+        "InsufficientWrapped",
+        "BelowTokenMinimum",
+        "TokenQueryFailed",
+        "NoNftCollection",
+        "NotNftHolder",
+        "NotApproved",
+        "NoTreasury",
+        "RuntimeCallFailed",
+        "NoAssetFee",
+        "AssetTransferFailed",
+        "OracleUnavailable",
+        "StalePrice",
+        "NoVerifyingKey",
+        "InvalidVerifyingKey",
+        "ZkUnsupported",
+        "NoValidatorSet",
+        "InvalidValidatorSet",
+        "InvalidSigner",
+        "BelowThreshold",
+        "BlsUnsupported",
+        "BlobTooLarge",
+        "LabelTooLong",
+        "InvalidPolicy",
+        "PolicyDenied",
+        "RateLimited",
+        "NotGuardian",
+        "BatchTooLarge",
+        "TokenTransferFailed",
+        "InvalidBorrower",
+        "FlashCallFailed",
+        "FlashRejected",
+        "InvariantViolated",
+        "SelfReferral",
+        "AlreadyReferred",
+        "InvalidFeeDiscounts",
+        "ProposalNotFound",
+        "VotingNotOpen",
+        "AlreadyVoted",
+        "NoStake",
+        "VotingNotOver",
+        "ProposalRejected",
+        "ProposalExecuted",
+        "ResponseTooLarge",
+        "DeadlineInPast",
+        "AlreadyInitialized",
+        "UntrustedCallee",
+        "QueueFull",
+        "FeatureDisabled",
+    ];
+
+    /// Returns the status code of a SCALE-encoded `FlipError`, `None` if it is not one.
+    ///
+    /// Only the variant index is read. A cross-contract error is followed by
+    /// an `Option<UpstreamError>`, which clients decode from the next byte.
+    pub fn error_code(encoded: &[u8]) -> Option<u16> {
+        match encoded {
+            [index, ..] if usize::from(*index) < ERROR_NAMES.len() => Some(u16::from(*index) + 1),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the `FlipError` variant with status `code`.
+    pub fn error_name(code: u16) -> Option<&'static str> {
+        ERROR_NAMES.get(usize::from(code.checked_sub(1)?)).copied()
+    }
+}
+
+/// Networks the off-chain tooling deploys to and tests against.
+#[cfg(feature = "std")]
+pub mod network {
+    use std::{fmt, str::FromStr};
+
+    use ink::env::{DefaultEnvironment, Environment};
+
+    type Balance = <DefaultEnvironment as Environment>::Balance;
+
+    /// Environment variable naming the network, see [`NetworkProfile::from_env`].
+    pub const NETWORK_ENV: &str = "CONTRACTS_NETWORK";
+
+    /// Decimals of the dev chain token, also assumed for custom endpoints.
+    const DEV_TOKEN_DECIMALS: u8 = 12;
+
+    /// A network and what scripts and tests need to know to use it.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum NetworkProfile {
+        /// A `substrate-contracts-node --dev` on this machine.
+        Local,
+        /// The Rococo contracts parachain.
+        RococoContracts,
+        /// Any other node.
+        Custom {
+            endpoint: String,
+            token_decimals: u8,
+            /// Most a single call may charge for storage, `None` for no limit.
+            storage_deposit_limit: Option<Balance>,
+        },
+    }
+
+    impl NetworkProfile {
+        /// The profile named by `CONTRACTS_NETWORK`, [`NetworkProfile::Local`] if unset.
+        pub fn from_env() -> Result<Self, UnknownNetwork> {
+            match std::env::var(NETWORK_ENV) {
+                Ok(name) => name.parse(),
+                Err(_) => Ok(Self::Local),
+            }
+        }
+
+        /// WebSocket endpoint of the node.
+        pub fn endpoint(&self) -> &str {
+            match self {
+                Self::Local => "ws://127.0.0.1:9944",
+                Self::RococoContracts => "wss://rococo-contracts-rpc.polkadot.io",
+                Self::Custom { endpoint, .. } => endpoint,
+            }
+        }
+
+        /// Decimals of the native token.
+        pub fn token_decimals(&self) -> u8 {
+            match self {
+                Self::Local | Self::RococoContracts => DEV_TOKEN_DECIMALS,
+                Self::Custom { token_decimals, .. } => *token_decimals,
+            }
+        }
+
+        /// Most a single call may charge for storage, `None` for no limit.
+        ///
+        /// Public networks cap it so a mistake cannot drain the deploying account.
+        pub fn storage_deposit_limit(&self) -> Option<Balance> {
+            match self {
+                Self::Local => None,
+                Self::RococoContracts => Some(self.units(10)),
+                Self::Custom {
+                    storage_deposit_limit,
+                    ..
+                } => *storage_deposit_limit,
+            }
+        }
+
+        /// `tokens` whole tokens in the smallest unit.
+        pub fn units(&self, tokens: Balance) -> Balance {
+            tokens * Balance::pow(10, self.token_decimals().into())
+        }
+
+        /// `amount` in whole tokens.
+        ///
+        /// ```
+        /// # use shared_types::network::NetworkProfile;
+        /// let local = NetworkProfile::Local;
+        /// assert_eq!(local.format_balance(local.units(3) / 2), "1.5");
+        /// assert_eq!(local.format_balance(local.units(2)), "2");
+        /// assert_eq!(local.format_balance(1), "0.000000000001");
+        /// ```
+        pub fn format_balance(&self, amount: Balance) -> String {
+            let unit = self.units(1);
+            let fraction = format!(
+                "{:0width$}",
+                amount % unit,
+                width = self.token_decimals().into()
+            );
+            match fraction.trim_end_matches('0') {
+                "" => format!("{}", amount / unit),
+                fraction => format!("{}.{fraction}", amount / unit),
+            }
+        }
+    }
+
+    /// Parses `local`, `rococo-contracts`, or a `ws://` or `wss://` endpoint of
+    /// a custom network with the dev token's decimals and no deposit limit.
+    impl FromStr for NetworkProfile {
+        type Err = UnknownNetwork;
+
+        fn from_str(name: &str) -> Result<Self, Self::Err> {
+            match name {
+                "local" => Ok(Self::Local),
+                "rococo-contracts" => Ok(Self::RococoContracts),
+                endpoint if endpoint.starts_with("ws://") || endpoint.starts_with("wss://") => {
+                    Ok(Self::Custom {
+                        endpoint: endpoint.into(),
+                        token_decimals: DEV_TOKEN_DECIMALS,
+                        storage_deposit_limit: None,
+                    })
+                }
+                _ => Err(UnknownNetwork(name.into())),
+            }
+        }
+    }
+
+    /// A network name [`NetworkProfile`] does not know.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct UnknownNetwork(pub String);
+
+    impl fmt::Display for UnknownNetwork {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "unknown network `{}`, expected `local`, `rococo-contracts` or a ws(s):// endpoint",
+                self.0
+            )
+        }
+    }
+
+    impl std::error::Error for UnknownNetwork {}
+}
@@ -0,0 +1,196 @@
+//! Builds and tests the contract across its feature matrix.
+//!
+//! `cargo xtask` runs the unit tests of every combination on the host and
+//! builds the `no_std` ones for each contract target, then reports which
+//! combinations break. `cargo xtask <name>...` runs only the named ones.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::{Command, ExitCode},
+};
+
+/// Targets the contract is compiled for on chain.
+///
+/// ink! 4 only supports Wasm, so the RISC-V build is expected to break until
+/// the contract moves to a version targeting `pallet-revive`.
+///
+/// The unit tests are not run on these targets: `ink_env` only compiles the
+/// off-chain engine for non-Wasm targets, so `#[ink::test]` and `test_support`
+/// cannot build for `wasm32-unknown-unknown`. The `no_std` builds below are
+/// what catches std-only code reaching the contract.
+const TARGETS: &[&str] = &["wasm32-unknown-unknown", "riscv32imac-unknown-none-elf"];
+
+/// The unit test demonstrating that the off-chain environment does not
+/// revert, which fails by design in every combination.
+const KNOWN_FAILING_TEST: &str = "tests::it_works";
+
+/// Lines of output shown for a failing step.
+const FAILURE_CONTEXT_LINES: usize = 15;
+
+struct Combination {
+    name: &'static str,
+    /// Features enabled on top of `std` on the host, or on their own on targets.
+    features: &'static [&'static str],
+    /// Whether the unit tests run on the host.
+    host: bool,
+    /// Whether the combination builds for `TARGETS` without `std`.
+    targets: bool,
+}
+
+const MATRIX: &[Combination] = &[
+    Combination {
+        name: "std",
+        features: &[],
+        host: true,
+        targets: false,
+    },
+    Combination {
+        name: "no-default",
+        features: &[],
+        host: false,
+        targets: true,
+    },
+    Combination {
+        name: "e2e-tests",
+        features: &["e2e-tests"],
+        host: true,
+        targets: false,
+    },
+    Combination {
+        name: "debug",
+        features: &["debug"],
+        host: true,
+        targets: true,
+    },
+    Combination {
+        name: "fuzzing",
+        features: &["fuzzing"],
+        host: true,
+        targets: false,
+    },
+    Combination {
+        name: "zk",
+        features: &["zk"],
+        host: true,
+        targets: true,
+    },
+    Combination {
+        name: "bls",
+        features: &["bls"],
+        host: true,
+        targets: true,
+    },
+];
+
+/// One cargo invocation of the matrix and its outcome.
+struct Step {
+    combination: &'static str,
+    target: &'static str,
+    action: &'static str,
+    passed: bool,
+}
+
+fn main() -> ExitCode {
+    let selected: Vec<String> = env::args().skip(1).collect();
+    if let Some(unknown) = selected
+        .iter()
+        .find(|name| MATRIX.iter().all(|combination| combination.name != name.as_str()))
+    {
+        eprintln!("unknown combination `{unknown}`, expected one of:");
+        for combination in MATRIX {
+            eprintln!("  {}", combination.name);
+        }
+        return ExitCode::FAILURE;
+    }
+
+    let root = workspace_root();
+    let mut steps = Vec::new();
+    let wanted = |combination: &&Combination| {
+        selected.is_empty() || selected.iter().any(|name| name == combination.name)
+    };
+    for combination in MATRIX.iter().filter(wanted) {
+        if combination.host {
+            let mut args = vec!["test", "--package", "unit_test_bug", "--lib"];
+            push_features(&mut args, combination.features);
+            args.extend(["--", "--skip", KNOWN_FAILING_TEST]);
+            steps.push(Step {
+                combination: combination.name,
+                target: "host",
+                action: "test",
+                passed: cargo(&root, &args),
+            });
+        }
+        if combination.targets {
+            for target in TARGETS {
+                let mut args = vec![
+                    "build",
+                    "--package",
+                    "unit_test_bug",
+                    "--release",
+                    "--no-default-features",
+                    "--target",
+                    target,
+                ];
+                push_features(&mut args, combination.features);
+                steps.push(Step {
+                    combination: combination.name,
+                    target,
+                    action: "build",
+                    passed: cargo(&root, &args),
+                });
+            }
+        }
+    }
+
+    println!();
+    println!("{:<12} {:<30} {:<6} result", "combination", "target", "step");
+    for step in &steps {
+        let result = if step.passed { "ok" } else { "BROKEN" };
+        println!(
+            "{:<12} {:<30} {:<6} {result}",
+            step.combination, step.target, step.action
+        );
+    }
+    if steps.iter().all(|step| step.passed) {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn push_features<'a>(args: &mut Vec<&'a str>, features: &[&'a str]) {
+    for feature in features {
+        args.extend(["--features", feature]);
+    }
+}
+
+/// Runs cargo with `args` in `root`, printing the end of its output on failure.
+fn cargo(root: &Path, args: &[&str]) -> bool {
+    eprintln!("cargo {}", args.join(" "));
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+    let output = match Command::new(cargo).args(args).current_dir(root).output() {
+        Ok(output) => output,
+        Err(error) => {
+            eprintln!("  failed to run cargo: {error}");
+            return false;
+        }
+    };
+    if output.status.success() {
+        return true;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<&str> = stdout.lines().chain(stderr.lines()).collect();
+    for line in &lines[lines.len().saturating_sub(FAILURE_CONTEXT_LINES)..] {
+        eprintln!("  | {line}");
+    }
+    false
+}
+
+fn workspace_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("xtask lives in the workspace")
+        .to_path_buf()
+}